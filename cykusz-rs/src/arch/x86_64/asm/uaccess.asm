@@ -0,0 +1,20 @@
+global asm_copy_user
+global asm_copy_user_fault
+global asm_copy_user_fixup
+
+section .text
+bits 64
+; fn asm_copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize
+; dst = rdi
+; src = rsi
+; len = rdx
+; Returns number of bytes which were not copied
+asm_copy_user:
+    mov rcx, rdx
+asm_copy_user_fault:
+    ; Page fault handler moves rip to asm_copy_user_fixup if the user address is invalid,
+    ; rcx then holds the number of bytes left to copy
+    rep movsb
+asm_copy_user_fixup:
+    mov rax, rcx
+    ret
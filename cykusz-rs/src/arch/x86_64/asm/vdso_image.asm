@@ -0,0 +1,9 @@
+; vDSO shared object built from src/arch/x86_64/vdso
+global vdso_image_start
+global vdso_image_end
+
+section .rodata.vdso
+align 4096
+vdso_image_start:
+    incbin "build/arch/x86_64/vdso/vdso.so"
+vdso_image_end:
@@ -107,9 +107,33 @@ fn enable_avx() {
     }
 }
 
+fn enable_smep_smap() {
+    let features = if let Some(f) = CpuId::new().get_extended_feature_info() {
+        f
+    } else {
+        return;
+    };
+
+    unsafe {
+        let mut cr4 = ctrlregs::cr4();
+        if features.has_smep() {
+            cr4.insert(Cr4::CR4_ENABLE_SMEP);
+            dbgln!(cpu, "SMEP enabled");
+        }
+        if features.has_smap() {
+            cr4.insert(Cr4::CR4_ENABLE_SMAP);
+            dbgln!(cpu, "SMAP enabled");
+        }
+        ctrlregs::cr4_write(cr4);
+    }
+
+    crate::arch::uaccess::set_smap_enabled(features.has_smap());
+}
+
 pub fn init() {
     enable_nxe_bit();
     enable_write_protect_bit();
+    enable_smep_smap();
     if enable_sse() {
         enable_avx();
     }
@@ -84,7 +84,16 @@ impl Hpet {
     }
 
     pub fn current_ns(&self) -> u64 {
-        self.counter_value() * self.period / 1_000_000
+        // Period is in femtoseconds, avoid overflow after few hours of uptime
+        (self.counter_value() as u128 * self.period as u128 / 1_000_000) as u64
+    }
+
+    pub fn base(&self) -> Option<PhysAddr> {
+        self.hpet_base.map(|b| b.to_phys())
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
     }
 }
 
@@ -100,6 +109,16 @@ pub fn current_ns() -> u64 {
     HPET.lock_irq().current_ns()
 }
 
+/// Physical address of the hpet registers
+pub fn base() -> Option<PhysAddr> {
+    HPET.lock_irq().base()
+}
+
+/// Counter tick period in femtoseconds
+pub fn period() -> u64 {
+    HPET.lock_irq().period()
+}
+
 pub fn busy_sleep(ns: u64) {
     let c = current_ns() + ns;
 
@@ -233,6 +233,19 @@ impl LApic {
         }
     }
 
+    /// Arms the one-shot timer to fire in ns nanoseconds
+    pub fn set_timer_ns(&mut self, ns: u64) {
+        let ticks = (self.ticks_in_1_ms as u64 * ns / 1_000_000).clamp(1, u32::MAX as u64);
+
+        if !self.x2 {
+            self.reg_write(REG_TIMINIT, ticks as u32);
+        } else {
+            unsafe {
+                msr::wrmsr(msr::IA32_X2APIC_INIT_COUNT, ticks);
+            }
+        }
+    }
+
     pub fn ticks_in_ms(&mut self, ms: u64) {
         if !self.x2 {
             self.reg_write(REG_TIMDIV, 0b11);
@@ -289,6 +302,10 @@ pub fn reset_timer_counter() {
     LAPIC.irq().reset_timer_counter();
 }
 
+pub fn set_timer_ns(ns: u64) {
+    LAPIC.irq().set_timer_ns(ns);
+}
+
 pub fn start_ap() {
     use crate::arch::smp::Trampoline;
 
@@ -6,6 +6,7 @@ pub mod pic;
 pub mod pit;
 pub mod rtc;
 pub mod serial;
+pub mod tsc;
 
 pub fn init() {
     pic::init();
@@ -39,9 +40,11 @@ pub fn init() {
 
         println!("[ OK ] HPET Enabled")
     } else {
-        panic!("[ ERROR ] HPET Not found");
+        println!("[ WARN ] HPET Not found");
     }
 
+    tsc::init();
+
     println!("[ OK ] PIT Disabled {}", crate::arch::int::is_enabled());
 
     rtc::init();
@@ -41,7 +41,51 @@ fn read_naive_date() -> chrono::NaiveDateTime {
 }
 
 fn set_date() {
-    UNIX_TX.store(read_naive_date().and_utc().timestamp(), Ordering::SeqCst);
+    let ts = read_naive_date().and_utc().timestamp();
+
+    UNIX_TX.store(ts, Ordering::SeqCst);
+
+    crate::kernel::time::rtc_update(ts);
+}
+
+/// Writes the date to the cmos, registers are in binary 24h format set up by init
+pub fn set_unix_ts(ts: i64) {
+    use chrono::{Datelike, Timelike};
+
+    let Some(date) = chrono::DateTime::from_timestamp(ts, 0).map(|d| d.naive_utc()) else {
+        return;
+    };
+
+    let _guard = IrqGuard::new();
+
+    unsafe {
+        let mut sel = cpuio::UnsafePort::<u8>::new(0x70);
+        let mut cmd = cpuio::UnsafePort::<u8>::new(0x71);
+
+        sel.write(0x8B);
+        let prev = cmd.read();
+
+        // Stop the clock updates while writing
+        sel.write(0x8B);
+        cmd.write(prev | 0x80);
+
+        for (reg, val) in [
+            (0x0, date.second()),
+            (0x2, date.minute()),
+            (0x4, date.hour()),
+            (0x7, date.day()),
+            (0x8, date.month()),
+            (0x9, (date.year() - 2000) as u32),
+        ] {
+            sel.write(reg);
+            cmd.write(val as u8);
+        }
+
+        sel.write(0x8B);
+        cmd.write(prev & !0x80);
+    }
+
+    UNIX_TX.store(ts, Ordering::SeqCst);
 }
 
 pub fn init() {
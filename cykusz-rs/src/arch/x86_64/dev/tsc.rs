@@ -0,0 +1,348 @@
+use core::arch::asm;
+use core::arch::x86_64::_rdtsc;
+use core::sync::atomic::{fence, AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+use raw_cpuid::CpuId;
+
+use crate::arch::dev::{hpet, pit};
+use crate::arch::raw::msr::{wrmsr, IA32_TSC_AUX};
+use crate::arch::smp::MAX_CPUS;
+use crate::kernel::sync::IrqGuard;
+
+/// Fixed point shift of the cycles to nanoseconds multiplier
+const SHIFT: u32 = 32;
+
+const CALIBRATE_MS: u64 = 50;
+
+/// Interval of the hpet watchdog
+const WATCHDOG_NS: u64 = 1_000_000_000;
+
+/// Rate difference from the hpet at which the tsc is considered unstable
+const WATCHDOG_MAX_PPM: u64 = 500;
+
+/// Maximum rate correction applied to converge with the hpet
+const MAX_SLEW_PPM: u64 = 100;
+
+const SYNC_SAMPLES: usize = 16;
+
+/// Cycles to nanoseconds conversion: ns = base_ns + ((tsc - base_tsc) * mult) >> SHIFT
+struct Clock {
+    seq: AtomicU64,
+    base_tsc: AtomicU64,
+    base_ns: AtomicU64,
+    mult: AtomicU64,
+}
+
+static CLOCK: Clock = Clock {
+    seq: AtomicU64::new(0),
+    base_tsc: AtomicU64::new(0),
+    base_ns: AtomicU64::new(0),
+    mult: AtomicU64::new(0),
+};
+
+/// Tsc is the clocksource, otherwise the hpet is used
+static TSC_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the time continuous after falling back to the hpet
+static HPET_OFFSET: AtomicI64 = AtomicI64::new(0);
+
+/// Tsc frequency in Hz
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+
+// Watchdog state, only touched by the boot cpu
+static WD_TSC: AtomicU64 = AtomicU64::new(0);
+static WD_HPET: AtomicU64 = AtomicU64::new(0);
+static ANCHOR_TSC: AtomicU64 = AtomicU64::new(0);
+static ANCHOR_HPET: AtomicU64 = AtomicU64::new(0);
+
+// Handshake used by the booting cpus to read the boot cpu tsc
+static SYNC_REQUEST: AtomicBool = AtomicBool::new(false);
+static SYNC_TSC: AtomicU64 = AtomicU64::new(0);
+
+/// Difference between the boot cpu tsc and the tsc of each cpu
+static TSC_OFFSETS: [AtomicI64; MAX_CPUS] = [const { AtomicI64::new(0) }; MAX_CPUS];
+
+fn rdtsc_raw() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+fn rdtsc() -> u64 {
+    rdtsc_raw().wrapping_add_signed(TSC_OFFSETS[crate::cpu_id() as usize].load(Ordering::Relaxed))
+}
+
+/// Vdso reads the cpu number of the tsc offset with rdtscp
+pub fn has_rdtscp() -> bool {
+    CpuId::new()
+        .get_extended_processor_and_feature_identifiers()
+        .map_or(false, |f| f.has_rdtscp())
+}
+
+fn set_tsc_aux() {
+    if has_rdtscp() {
+        unsafe {
+            wrmsr(IA32_TSC_AUX, crate::cpu_id() as u64);
+        }
+    }
+}
+
+fn has_hpet() -> bool {
+    hpet::base().is_some()
+}
+
+fn is_invariant() -> bool {
+    CpuId::new()
+        .get_advanced_power_mgmt_info()
+        .map_or(false, |i| i.has_invariant_tsc())
+}
+
+fn cycles_to_ns(cycles: u64, mult: u64) -> u64 {
+    ((cycles as u128 * mult as u128) >> SHIFT) as u64
+}
+
+fn mult_for(ns: u64, cycles: u64) -> u64 {
+    (((ns as u128) << SHIFT) / cycles as u128) as u64
+}
+
+fn set_clock(base_tsc: u64, base_ns: u64, mult: u64) {
+    let seq = CLOCK.seq.load(Ordering::Relaxed);
+
+    CLOCK.seq.store(seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    CLOCK.base_tsc.store(base_tsc, Ordering::Relaxed);
+    CLOCK.base_ns.store(base_ns, Ordering::Relaxed);
+    CLOCK.mult.store(mult, Ordering::Relaxed);
+
+    CLOCK.seq.store(seq + 2, Ordering::Release);
+
+    crate::arch::vdso::set_tsc_clock(base_tsc, base_ns, mult, SHIFT);
+}
+
+/// Returns base_tsc, base_ns and mult
+fn read_clock() -> (u64, u64, u64) {
+    loop {
+        let seq = CLOCK.seq.load(Ordering::Acquire);
+
+        if seq & 1 == 1 {
+            core::hint::spin_loop();
+            continue;
+        }
+
+        let base_tsc = CLOCK.base_tsc.load(Ordering::Relaxed);
+        let base_ns = CLOCK.base_ns.load(Ordering::Relaxed);
+        let mult = CLOCK.mult.load(Ordering::Relaxed);
+
+        fence(Ordering::Acquire);
+
+        if CLOCK.seq.load(Ordering::Relaxed) != seq {
+            continue;
+        }
+
+        return (base_tsc, base_ns, mult);
+    }
+}
+
+fn tsc_to_ns(tsc: u64) -> u64 {
+    let (base_tsc, base_ns, mult) = read_clock();
+
+    // Tsc of other cpus can be slightly behind the base after the clock was updated
+    if tsc >= base_tsc {
+        base_ns + cycles_to_ns(tsc - base_tsc, mult)
+    } else {
+        base_ns.saturating_sub(cycles_to_ns(base_tsc - tsc, mult))
+    }
+}
+
+/// Measures the tsc frequency against the hpet, or the pit if there is no hpet
+fn calibrate() -> u64 {
+    if has_hpet() {
+        let start_ns = hpet::current_ns();
+        let start_tsc = rdtsc_raw();
+
+        hpet::busy_sleep(CALIBRATE_MS * 1_000_000);
+
+        let end_tsc = rdtsc_raw();
+        let end_ns = hpet::current_ns();
+
+        ((end_tsc - start_tsc) as u128 * 1_000_000_000 / (end_ns - start_ns) as u128) as u64
+    } else {
+        let start_tsc = rdtsc_raw();
+
+        pit::early_busy_sleep(CALIBRATE_MS);
+
+        (rdtsc_raw() - start_tsc) * 1000 / CALIBRATE_MS
+    }
+}
+
+pub fn init() {
+    let forced_hpet = crate::kernel::params::get("clocksource").map(|s| s.as_str()) == Some("hpet");
+
+    // Without the hpet the tsc is the only clocksource, even if it is not invariant
+    if has_hpet() && (forced_hpet || !is_invariant()) {
+        println!(
+            "[ OK ] HPET Clocksource (invariant tsc: {})",
+            is_invariant()
+        );
+        return;
+    }
+
+    let hz = calibrate();
+
+    // Continue from the hpet time, the watchdog keeps the two in sync
+    let now_ns = if has_hpet() { hpet::current_ns() } else { 0 };
+    let now_tsc = rdtsc_raw();
+
+    TSC_HZ.store(hz, Ordering::Relaxed);
+
+    set_clock(now_tsc, now_ns, mult_for(1_000_000_000, hz));
+
+    WD_TSC.store(now_tsc, Ordering::Relaxed);
+    WD_HPET.store(now_ns, Ordering::Relaxed);
+    ANCHOR_TSC.store(now_tsc, Ordering::Relaxed);
+    ANCHOR_HPET.store(now_ns, Ordering::Relaxed);
+
+    set_tsc_aux();
+
+    TSC_ENABLED.store(true, Ordering::SeqCst);
+
+    println!(
+        "[ OK ] TSC Clocksource ({} kHz, watchdog: {})",
+        hz / 1000,
+        has_hpet()
+    );
+}
+
+/// Called by the boot cpu while waiting for an ap to come up
+pub fn serve_sync() {
+    if SYNC_REQUEST.load(Ordering::Acquire) {
+        SYNC_TSC.store(rdtsc_raw(), Ordering::Relaxed);
+        SYNC_REQUEST.store(false, Ordering::Release);
+    }
+}
+
+/// Returns the round trip time and the offset estimate of a single sync exchange
+fn sync_sample() -> (u64, i64) {
+    let t0 = rdtsc_raw();
+
+    SYNC_REQUEST.store(true, Ordering::Release);
+
+    while SYNC_REQUEST.load(Ordering::Acquire) {
+        unsafe {
+            asm!("pause");
+        }
+    }
+
+    let t1 = rdtsc_raw();
+    let bsp = SYNC_TSC.load(Ordering::Relaxed);
+
+    (t1 - t0, bsp.wrapping_sub(t0 + (t1 - t0) / 2) as i64)
+}
+
+/// Aligns the tsc of the booting ap with the boot cpu, which answers in its wait for the ap
+pub fn sync_ap() {
+    if !TSC_ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    // The sample with the shortest round trip has the smallest error
+    let (rtt, offset) = (0..SYNC_SAMPLES)
+        .map(|_| sync_sample())
+        .min_by_key(|(rtt, _)| *rtt)
+        .unwrap();
+
+    // Offsets within the measurement error are noise
+    if offset.unsigned_abs() > rtt / 2 {
+        TSC_OFFSETS[crate::cpu_id() as usize].store(offset, Ordering::Relaxed);
+
+        crate::arch::vdso::set_tsc_offset(crate::cpu_id() as usize, offset);
+    }
+
+    set_tsc_aux();
+
+    logln!("tsc sync offset {} rtt {}", offset, rtt);
+}
+
+fn mark_unstable(now_ns: u64, hpet_ns: u64) {
+    HPET_OFFSET.store(now_ns as i64 - hpet_ns as i64, Ordering::SeqCst);
+
+    TSC_ENABLED.store(false, Ordering::SeqCst);
+
+    crate::arch::vdso::set_hpet_clock(now_ns as i64 - hpet_ns as i64);
+
+    logln!("[ WARN ] TSC unstable, falling back to HPET");
+}
+
+/// Checks the tsc against the hpet, called periodically on the boot cpu. The rate is refined
+/// over the time since boot and slewed towards the hpet time, so the time doesn't jump when
+/// falling back to the hpet.
+pub fn watchdog() {
+    if !TSC_ENABLED.load(Ordering::Relaxed) || !has_hpet() {
+        return;
+    }
+
+    let tsc = rdtsc();
+    let last_tsc = WD_TSC.load(Ordering::Relaxed);
+
+    if tsc - last_tsc < TSC_HZ.load(Ordering::Relaxed) * (WATCHDOG_NS / 1_000_000_000) {
+        return;
+    }
+
+    let hpet_now = hpet::current_ns();
+    let now_ns = tsc_to_ns(tsc);
+
+    let hpet_delta = hpet_now - WD_HPET.load(Ordering::Relaxed);
+    let tsc_delta = cycles_to_ns(tsc - last_tsc, CLOCK.mult.load(Ordering::Relaxed));
+
+    if tsc_delta.abs_diff(hpet_delta) > hpet_delta / 1_000_000 * WATCHDOG_MAX_PPM {
+        mark_unstable(now_ns, hpet_now);
+        return;
+    }
+
+    let hz = ((tsc - ANCHOR_TSC.load(Ordering::Relaxed)) as u128 * 1_000_000_000
+        / (hpet_now - ANCHOR_HPET.load(Ordering::Relaxed)) as u128) as u64;
+
+    // Nanoseconds to pass until the next check to meet the hpet, within the slew limit
+    let max_slew = WATCHDOG_NS / 1_000_000 * MAX_SLEW_PPM;
+    let span = (hpet_now + WATCHDOG_NS)
+        .saturating_sub(now_ns)
+        .clamp(WATCHDOG_NS - max_slew, WATCHDOG_NS + max_slew);
+
+    TSC_HZ.store(hz, Ordering::Relaxed);
+
+    set_clock(tsc, now_ns, mult_for(span, hz));
+
+    WD_TSC.store(tsc, Ordering::Relaxed);
+    WD_HPET.store(hpet_now, Ordering::Relaxed);
+}
+
+/// Time since boot in nanoseconds, from the tsc or from the hpet once the tsc is found unstable
+pub fn current_ns() -> u64 {
+    if TSC_ENABLED.load(Ordering::Relaxed) {
+        tsc_to_ns(rdtsc())
+    } else {
+        (hpet::current_ns() as i64 + HPET_OFFSET.load(Ordering::Relaxed)) as u64
+    }
+}
+
+/// Publishes the current clock to the vdso, later changes are published as they happen
+pub fn publish_vdso() {
+    // Watchdog on this cpu can't update the clock in the meantime
+    let _guard = IrqGuard::new();
+
+    if !TSC_ENABLED.load(Ordering::SeqCst) {
+        crate::arch::vdso::set_hpet_clock(HPET_OFFSET.load(Ordering::SeqCst));
+        return;
+    }
+
+    for (cpu, offset) in TSC_OFFSETS
+        .iter()
+        .enumerate()
+        .take(crate::arch::smp::cpu_count())
+    {
+        crate::arch::vdso::set_tsc_offset(cpu, offset.load(Ordering::Relaxed));
+    }
+
+    let (base_tsc, base_ns, mult) = read_clock();
+
+    crate::arch::vdso::set_tsc_clock(base_tsc, base_ns, mult, SHIFT);
+}
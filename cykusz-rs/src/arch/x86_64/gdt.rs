@@ -102,12 +102,11 @@ fn init_tss(stack_top: VirtAddr, fs_base: u64) {
             gdt_high.set_raw(((&raw const TSS) as u64) >> 32);
         }
 
-        tss.ist[0] = crate::kernel::mm::allocate_order(2)
-            .unwrap()
-            .address_mapped()
-            .0 as u64
-            + 0x4000;
-        set_interrupt_stack_tss_offset(8, 0);
+        // Double fault runs on its own stack, so we can report kernel stack overflows
+        tss.ist[0] = (crate::arch::mm::kstack::allocate().expect("Out of kernel stacks")
+            + crate::arch::mm::kstack::KSTACK_SIZE)
+            .0 as u64;
+        set_interrupt_stack_tss_offset(8, 1);
         //tss.ist[1] = crate::kernel::mm::allocate_order(6).unwrap().address_mapped().0 as u64 + 0x40000;
         //set_interrupt_stack_tss_offset(14, 1);
 
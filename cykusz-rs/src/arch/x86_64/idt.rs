@@ -10,6 +10,10 @@ use crate::kernel::task::vm::PageFaultReason;
 use alloc::vec::Vec;
 use bit_field::BitField;
 use core::sync::atomic::{AtomicU64, Ordering};
+use syscall_defs::signal::{SIGBUS, SIGFPE, SIGILL, SIGSEGV};
+use syscall_defs::signal::{
+    BUS_ADRALN, FPE_FLTINV, FPE_INTDIV, FPE_INTOVF, ILL_ILLOPN, SEGV_ACCERR, SEGV_MAPERR, SI_KERNEL,
+};
 
 static IDT: Spin<idt::Idt> = Spin::new(idt::Idt::new());
 
@@ -227,6 +231,10 @@ pub extern "C" fn isr_handler(
     frame: &mut InterruptFrame,
     regs: &mut RegsFrame,
 ) {
+    if frame.is_user() {
+        current_task_ref().account_enter_kernel();
+    }
+
     let irqs = if int == 32 {
         // Special case for local timer int
         SHARED_IRQS.irqs[int].read_irq()
@@ -271,6 +279,8 @@ pub extern "C" fn isr_handler(
         crate::arch::signal::arch_int_check_signals(frame, regs);
 
         restore_user_fs();
+
+        current_task_ref().account_leave_kernel();
     }
 
     if !SHARED_IRQS.is_handler_eoi(int) {
@@ -288,6 +298,9 @@ pub fn init() {
                 idt.set_handler(i, h as usize);
             }
         }
+
+        // int3 is executed by user programs and debuggers
+        idt.set_user(3, true);
     }
 
     //Initialise exception handler routines
@@ -401,14 +414,31 @@ fn divide_by_zero(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
         let task = current_task_ref();
 
         println!("[ SIGFPE ] Task {} divide_by_zero error", task.tid());
-        task.signal(syscall_defs::signal::SIGFPE);
+        task.signal_fault(SIGFPE, FPE_INTDIV, frame.ip);
 
         return;
     }
     loop {}
 }
 
-fn debug(_frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
+fn debug(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
+    if frame.is_user() {
+        let task = current_task_ref();
+
+        dbgln!(
+            exc,
+            "[ SIGTRAP ] Task {} single step {:#x}",
+            task.tid(),
+            frame.ip
+        );
+
+        // Tracer sets the trap flag again if it keeps stepping
+        frame.cf &= !crate::arch::signal::TRAP_FLAG;
+
+        task.signal(syscall_defs::signal::SIGTRAP);
+
+        return;
+    }
     println!("INT: Debug exception! CPU: {}", crate::cpu_id());
     loop {}
 }
@@ -418,7 +448,20 @@ fn non_maskable_interrupt(_frame: &mut idt::InterruptFrame, _regs: &mut RegsFram
     loop {}
 }
 
-fn breakpoint(_frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
+fn breakpoint(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
+    if frame.is_user() {
+        let task = current_task_ref();
+
+        dbgln!(
+            exc,
+            "[ SIGTRAP ] Task {} breakpoint {:#x}",
+            task.tid(),
+            frame.ip
+        );
+        task.signal(syscall_defs::signal::SIGTRAP);
+
+        return;
+    }
     println!("INT: Breakpoint!");
     loop {}
 }
@@ -428,7 +471,7 @@ fn overflow(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
         let task = current_task_ref();
 
         logln!("[ SIGFPE ] Task {} overflow error", task.tid());
-        task.signal(syscall_defs::signal::SIGFPE);
+        task.signal_fault(SIGFPE, FPE_INTOVF, frame.ip);
 
         return;
     }
@@ -441,7 +484,7 @@ fn bound_range_exceeded(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame)
         let task = current_task_ref();
 
         logln!("[ SIGSEGV ] Task {} bound range exceeded error", task.tid());
-        task.signal(syscall_defs::signal::SIGSEGV);
+        task.signal_fault(SIGSEGV, SI_KERNEL, 0);
 
         return;
     }
@@ -458,7 +501,7 @@ fn invalid_opcode(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame) {
             task.tid(),
             frame.ip
         );
-        task.signal(syscall_defs::signal::SIGILL);
+        task.signal_fault(SIGILL, ILL_ILLOPN, frame.ip);
 
         return;
     }
@@ -476,8 +519,18 @@ fn device_not_available(_frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame)
     loop {}
 }
 
-fn double_fault(_frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame, err: u64) {
-    println!("Double Fault error! 0x{:x}", err);
+fn double_fault(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame, err: u64) {
+    // Exception frame push failed on the guard page
+    if crate::arch::mm::kstack::is_guard_page(VirtAddr(frame.sp as usize - 8)) {
+        panic!(
+            "Kernel stack overflow in task {}, ip: {:#x}, sp: {:#x}",
+            current_task_ref().tid(),
+            frame.ip,
+            frame.sp
+        );
+    }
+
+    println!("Double Fault error! 0x{:x} {:?}", err, frame);
     loop {}
 }
 
@@ -491,7 +544,7 @@ fn segment_not_present(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame, e
         let task = current_task_ref();
 
         dbgln!(exc, "[ SIGSEGV ] Task {} segment_not_present error", task.tid());
-        task.signal(syscall_defs::signal::SIGSEGV);
+        task.signal_fault(SIGSEGV, SI_KERNEL, 0);
 
         return;
     }
@@ -504,7 +557,7 @@ fn stack_segment_fault(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame, e
         let task = current_task_ref();
 
         dbgln!(exc, "[ SIGSEGV ] Task {} stack_segment error", task.tid());
-        task.signal(syscall_defs::signal::SIGSEGV);
+        task.signal_fault(SIGSEGV, SI_KERNEL, 0);
 
         return;
     }
@@ -522,7 +575,7 @@ fn general_protection_fault(frame: &mut idt::InterruptFrame, regs: &mut RegsFram
             frame.ip,
             regs
         );
-        task.signal(syscall_defs::signal::SIGBUS);
+        task.signal_fault(SIGBUS, SI_KERNEL, 0);
 
         return;
     }
@@ -539,13 +592,31 @@ fn page_fault(frame: &mut idt::InterruptFrame, regs: &mut RegsFrame, err: u64) {
 
     let reason = PageFaultReason::from_bits_truncate(err as usize);
 
-    if virt.is_user() {
+    if virt.is_user()
+        && !frame.is_user()
+        && reason.contains(PageFaultReason::PRESENT)
+        && crate::arch::uaccess::is_smap_violation(frame.cf)
+    {
+        // kernel touched user memory outside of user access window
+        if let Some(fixup) = crate::arch::uaccess::fixup_page_fault(frame.ip) {
+            frame.ip = fixup;
+
+            return;
+        }
+
+        panic!(
+            "SMAP violation addr: {}, ip: {:#x}, reason: {:?}",
+            virt, frame.ip, reason
+        );
+    } else if virt.is_user() {
         // page fault originated in userspace
         // let the task try handle it
         let task = current_task_ref();
 
         //println!("user pagefault {:#x} {} {:?} pid: {}", frame.ip, virt, reason, task.tid());
         if task.handle_pagefault(reason, virt) {
+            task.resources().account_fault();
+
             return;
         } else {
             dbgln!(
@@ -576,7 +647,30 @@ fn page_fault(frame: &mut idt::InterruptFrame, regs: &mut RegsFrame, err: u64) {
     if VirtAddr(frame.ip as usize).is_user() {
         let task = current_task_ref();
         dbgln!(exc, "[ SIGSEGV ] page fault");
-        task.signal(syscall_defs::signal::SIGSEGV);
+
+        let code = if reason.contains(PageFaultReason::PRESENT) {
+            SEGV_ACCERR
+        } else {
+            SEGV_MAPERR
+        };
+
+        task.signal_fault(SIGSEGV, code, regs.cr2);
+
+        return;
+    }
+
+    if crate::arch::mm::kstack::is_guard_page(virt) {
+        panic!(
+            "Kernel stack overflow in task {}, ip: {:#x}, addr: {}",
+            current_task_ref().tid(),
+            frame.ip,
+            virt
+        );
+    }
+
+    if let Some(fixup) = crate::arch::uaccess::fixup_page_fault(frame.ip) {
+        // kernel failed to access user memory, let the copy routine report the error
+        frame.ip = fixup;
 
         return;
     }
@@ -598,7 +692,7 @@ fn x87_floating_point_exception(frame: &mut idt::InterruptFrame, _regs: &mut Reg
         let task = current_task_ref();
 
         println!("[ SIGSEGV ] Task {} x87_floating_point error", task.tid());
-        task.signal(syscall_defs::signal::SIGFPE);
+        task.signal_fault(SIGFPE, FPE_FLTINV, frame.ip);
 
         return;
     }
@@ -611,7 +705,7 @@ fn alignment_check(frame: &mut idt::InterruptFrame, _regs: &mut RegsFrame, err:
         let task = current_task_ref();
 
         println!("[ SIGBUS ] Task {} alignment_check error", task.tid());
-        task.signal(syscall_defs::signal::SIGBUS);
+        task.signal_fault(SIGBUS, BUS_ADRALN, 0);
 
         return;
     }
@@ -629,7 +723,7 @@ fn simd_floating_point_exception(frame: &mut idt::InterruptFrame, _regs: &mut Re
         let task = current_task_ref();
 
         println!("[ SIGFPE ] Task {} simd_floating_point error", task.tid());
-        task.signal(syscall_defs::signal::SIGFPE);
+        task.signal_fault(SIGFPE, FPE_FLTINV, frame.ip);
 
         return;
     }
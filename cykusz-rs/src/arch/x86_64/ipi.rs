@@ -4,6 +4,7 @@ use crate::kernel::ipi::IpiTarget;
 pub enum IpiKind {
     IpiTask = 82,
     IpiTest = 83,
+    IpiBalance = 84,
 }
 
 impl IpiTarget {
@@ -22,6 +23,7 @@ pub fn init() {
     crate::arch::idt::set_handler_eoi(IpiKind::IpiTask as usize);
     crate::arch::idt::set_handler(IpiKind::IpiTask as usize, ipi_task);
     crate::arch::idt::set_handler(IpiKind::IpiTest as usize, ipi_test);
+    crate::arch::idt::set_handler(IpiKind::IpiBalance as usize, ipi_balance);
 }
 
 pub fn send_ipi_to(target: IpiTarget, kind: IpiKind) {
@@ -32,6 +34,10 @@ fn ipi_task() {
     crate::kernel::ipi::handle_ipi_task();
 }
 
+fn ipi_balance() {
+    crate::kernel::sched::internal().balance();
+}
+
 fn ipi_test() {
     dbgln!(ipi, "ipi on cpu {}", crate::cpu_id());
 }
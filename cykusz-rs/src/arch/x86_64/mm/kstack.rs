@@ -0,0 +1,68 @@
+use alloc::vec::Vec;
+
+use crate::arch::mm::virt::{map_flags, prepare_kernel_region};
+use crate::kernel::mm::virt::PageFlags;
+use crate::kernel::mm::{VirtAddr, PAGE_SIZE};
+use crate::kernel::sync::{LockApi, Spin};
+
+pub const KSTACK_START: VirtAddr = VirtAddr(0xfffffc0000000000);
+pub const KSTACK_END: VirtAddr = VirtAddr(KSTACK_START.0 + 0x80_0000_0000); // 512GB
+pub const KSTACK_SIZE: usize = 0x40000;
+pub const KSTACK_GUARD_SIZE: usize = PAGE_SIZE;
+
+// Each slot starts with unmapped guard page followed by the stack memory
+const KSTACK_SLOT_SIZE: usize = KSTACK_GUARD_SIZE + KSTACK_SIZE;
+
+struct KernelStacks {
+    next: VirtAddr,
+    // Released stacks stay mapped, we have no way of flushing tlb on other cpus
+    free: Vec<VirtAddr>,
+}
+
+static STACKS: Spin<KernelStacks> = Spin::new(KernelStacks {
+    next: KSTACK_START,
+    free: Vec::new(),
+});
+
+pub fn init() {
+    prepare_kernel_region(KSTACK_START);
+}
+
+/// Allocate kernel stack guarded by unmapped page, returns the lowest address of the stack
+pub fn allocate() -> Option<VirtAddr> {
+    let mut stacks = STACKS.lock_irq();
+
+    if let Some(stack) = stacks.free.pop() {
+        return Some(stack);
+    }
+
+    if stacks.next + KSTACK_SLOT_SIZE > KSTACK_END {
+        return None;
+    }
+
+    let stack = stacks.next + KSTACK_GUARD_SIZE;
+
+    stacks.next += KSTACK_SLOT_SIZE;
+
+    for addr in (stack..stack + KSTACK_SIZE).step_by(PAGE_SIZE) {
+        map_flags(addr, PageFlags::WRITABLE | PageFlags::NO_EXECUTE);
+    }
+
+    Some(stack)
+}
+
+pub fn deallocate(stack: VirtAddr) {
+    if !is_kernel_stack(stack) {
+        return;
+    }
+
+    STACKS.lock_irq().free.push(stack);
+}
+
+pub fn is_kernel_stack(addr: VirtAddr) -> bool {
+    addr >= KSTACK_START && addr < KSTACK_END
+}
+
+pub fn is_guard_page(addr: VirtAddr) -> bool {
+    is_kernel_stack(addr) && (addr.0 - KSTACK_START.0) % KSTACK_SLOT_SIZE < KSTACK_GUARD_SIZE
+}
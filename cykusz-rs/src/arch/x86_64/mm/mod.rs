@@ -2,6 +2,7 @@ pub use crate::arch::raw::mm::{MappedAddr, PhysAddr, VirtAddr};
 use crate::drivers::multiboot2;
 
 pub mod heap;
+pub mod kstack;
 pub mod pat;
 pub mod phys;
 pub mod virt;
@@ -12,6 +13,8 @@ pub const MAX_USER_ADDR: VirtAddr = VirtAddr(0x8000_0000_0000);
 pub const MMAP_USER_ADDR: VirtAddr = VirtAddr(0x7000_0000_0000);
 
 pub const PAGE_SIZE: usize = 4096;
+pub const HUGE_PAGE_SIZE: usize = 0x200000;
+pub const HUGE_PAGE_ORDER: usize = 9;
 
 impl PhysAddr {
     pub const fn to_mapped(&self) -> MappedAddr {
@@ -70,6 +73,8 @@ pub fn init(mboot: &multiboot2::Info) {
 
     virt::init(&mboot);
 
+    kstack::init();
+
     println!("[ OK ] Virtual Memory Initialised");
 
     pat::init();
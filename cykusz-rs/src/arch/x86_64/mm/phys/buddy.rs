@@ -6,7 +6,7 @@ use super::bump;
 
 type BMType = u64;
 const BMTYPE_BITS: usize = core::mem::size_of::<BMType>() * 8;
-pub const BUDDY_COUNT: usize = 7;
+pub const BUDDY_COUNT: usize = 10;
 
 pub struct BuddyAlloc {
     start: PhysAddr,
@@ -17,8 +17,9 @@ pub struct BuddyAlloc {
     num_ranges: usize,
 }
 
-pub static BSIZE: [usize; BUDDY_COUNT] =
-    [0x1000, 0x2000, 0x4000, 0x8000, 0x10000, 0x20000, 0x40000];
+pub static BSIZE: [usize; BUDDY_COUNT] = [
+    0x1000, 0x2000, 0x4000, 0x8000, 0x10000, 0x20000, 0x40000, 0x80000, 0x100000, 0x200000,
+];
 
 impl BuddyAlloc {
     pub const fn new() -> BuddyAlloc {
@@ -33,6 +34,9 @@ impl BuddyAlloc {
                 &mut [],
                 &mut [],
                 &mut [],
+                &mut [],
+                &mut [],
+                &mut [],
             ],
             freecnt: [0usize; BUDDY_COUNT],
             ranges: [(PhysAddr(0), PhysAddr(0)); 16],
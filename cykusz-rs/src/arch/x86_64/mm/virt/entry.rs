@@ -1,3 +1,4 @@
+use crate::arch::mm::HUGE_PAGE_ORDER;
 use crate::kernel::mm::Frame;
 use crate::kernel::mm::MappedAddr;
 use crate::kernel::mm::PhysAddr;
@@ -30,6 +31,14 @@ impl Entry {
     }
 
     pub fn from_kernel_flags(flags: virt::PageFlags) -> Entry {
+        Self::from_kernel_flags_level(false, flags)
+    }
+
+    pub fn from_kernel_flags_hugepage(flags: virt::PageFlags) -> Entry {
+        Self::from_kernel_flags_level(true, flags)
+    }
+
+    fn from_kernel_flags_level(is_hugepage: bool, flags: virt::PageFlags) -> Entry {
         let mut res: Entry = Entry::new_empty();
 
         if flags.contains(virt::PageFlags::NO_EXECUTE) {
@@ -42,7 +51,7 @@ impl Entry {
             res.insert(Entry::WRITABLE);
         }
 
-        res.insert(crate::arch::mm::pat::from_kernel_flags(false, flags));
+        res.insert(crate::arch::mm::pat::from_kernel_flags(is_hugepage, flags));
 
         return res;
     }
@@ -99,6 +108,35 @@ impl Entry {
         false
     }
 
+    pub fn unref_huge_phys_page(&self) -> bool {
+        if self.address() != PhysAddr(0) {
+            if let Some(page) = self.address().to_phys_page() {
+                let cnt = page.dec_vm_use_count();
+                if cnt == 0 {
+                    deallocate_order(&Frame::new(self.address()), HUGE_PAGE_ORDER);
+
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Flags of a 4k entry mapping the same memory as this 2MB entry
+    pub fn hugepage_to_page_flags(&self) -> Entry {
+        let mut flags = Entry::from_bits_retain(self.bits() & FLAG_MASK);
+
+        flags.remove(Entry::HUGE_PAGE);
+
+        if self.contains(Entry::HP_PAT) {
+            // PAT bit is at the HUGE_PAGE position in the last level entry
+            flags.insert(Entry::HUGE_PAGE);
+        }
+
+        flags
+    }
+
     pub fn ref_phys_page(&self) {
         if self.address() != PhysAddr(0) {
             if let Some(page) = self.address().to_phys_page() {
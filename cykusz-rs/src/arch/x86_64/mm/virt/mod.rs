@@ -1,6 +1,7 @@
 use crate::arch::raw::ctrlregs;
 use crate::arch::raw::mm;
 use crate::kernel::mm::virt;
+use crate::arch::mm::{HUGE_PAGE_ORDER, HUGE_PAGE_SIZE};
 use crate::kernel::mm::PAGE_SIZE;
 use crate::kernel::mm::{PhysAddr, VirtAddr};
 
@@ -58,6 +59,22 @@ pub fn update_flags(virt: VirtAddr, flags: virt::PageFlags) -> bool {
     return res.is_some();
 }
 
+pub fn insert_flags(virt: VirtAddr, flags: virt::PageFlags) -> bool {
+    let res = current_p4_table().insert_flags(virt, flags);
+
+    flush(virt);
+
+    res.is_some()
+}
+
+pub fn remove_flags(virt: VirtAddr, flags: virt::PageFlags) -> bool {
+    let res = current_p4_table().remove_flags(virt, flags);
+
+    flush(virt);
+
+    res.is_some()
+}
+
 pub fn map(virt: VirtAddr) {
     map_flags(virt, virt::PageFlags::WRITABLE);
 }
@@ -68,6 +85,72 @@ pub fn unmap(virt: VirtAddr) {
     flush(virt);
 }
 
+/// Map 2MB page at virt backed by newly allocated zeroed memory. Fails if any part of the
+/// region is already mapped or there is no contiguous physical memory available.
+pub fn map_hugepage_flags(virt: VirtAddr, flags: virt::PageFlags) -> bool {
+    let table = current_p4_table();
+
+    if !table.can_map_hugepage(virt) {
+        return false;
+    }
+
+    let Some(frame) = crate::arch::mm::phys::allocate_order(HUGE_PAGE_ORDER) else {
+        return false;
+    };
+
+    unsafe {
+        frame.address_mapped().as_bytes_mut(HUGE_PAGE_SIZE).fill(0);
+    }
+
+    if !table.map_hugepage_to_flags(virt, frame.address(), flags) {
+        crate::arch::mm::phys::deallocate_order(&frame, HUGE_PAGE_ORDER);
+
+        return false;
+    }
+
+    flush(virt);
+
+    true
+}
+
+pub fn unmap_hugepage(virt: VirtAddr) -> bool {
+    let res = current_p4_table().unmap_hugepage(virt);
+
+    flush(virt);
+
+    res
+}
+
+pub fn split_hugepage(virt: VirtAddr) -> bool {
+    let res = current_p4_table().split_hugepage(virt);
+
+    flush(virt);
+
+    res
+}
+
+pub fn collapse_hugepage(virt: VirtAddr, flags: virt::PageFlags) -> bool {
+    let res = current_p4_table().collapse_hugepage(virt, flags);
+
+    if res {
+        for addr in (virt..virt + HUGE_PAGE_SIZE).step_by(PAGE_SIZE) {
+            flush(addr);
+        }
+    }
+
+    res
+}
+
+pub fn is_hugepage(virt: VirtAddr) -> bool {
+    current_p4_table().is_hugepage(virt)
+}
+
+/// Allocate P3 table covering the kernel virtual region upfront, so that it is shared by all the
+/// address spaces created later
+pub fn prepare_kernel_region(virt: VirtAddr) {
+    current_p4_table().alloc_next_level(page::Page::new(virt).p4_index(), false);
+}
+
 #[allow(unused)]
 pub fn map_to(virt: VirtAddr, phys: PhysAddr) {
     current_p4_table().map_to(virt, phys);
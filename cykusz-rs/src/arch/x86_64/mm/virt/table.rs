@@ -1,6 +1,7 @@
 use core::marker::PhantomData;
 
 use crate::arch::mm::virt::entry::Entry;
+use crate::arch::mm::{HUGE_PAGE_ORDER, HUGE_PAGE_SIZE};
 use crate::arch::x86_64::mm::phys::PhysPage;
 use crate::kernel::mm::*;
 use crate::kernel::sync::{LockApi, Spin, SpinGuard};
@@ -137,6 +138,7 @@ impl<L> Table<L>
 where
     L: NotLastLevel,
 {
+    /// Huge page entries don't point to the next level table and are skipped
     pub fn for_entries_mut(
         &mut self,
         flags: Entry,
@@ -145,7 +147,7 @@ where
         self.entries
             .iter_mut()
             .enumerate()
-            .filter(|e| e.1.contains(flags))
+            .filter(|e| e.1.contains(flags) && !e.1.contains(Entry::HUGE_PAGE))
             .for_each(|(idx, e)| {
                 let lvl = Table::<L::NextLevel>::new_at_frame_mut(&Frame::new(e.address()));
                 fun(idx, e, lvl);
@@ -255,6 +257,151 @@ where
     }
 }
 
+impl Table<Level2> {
+    pub fn for_huge_entries_mut(&mut self, flags: Entry, mut fun: impl FnMut(usize, &mut Entry)) {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter(|e| e.1.contains(flags | Entry::HUGE_PAGE))
+            .for_each(|(idx, e)| {
+                fun(idx, e);
+            })
+    }
+
+    pub fn set_hugepage_flags(&mut self, idx: usize, frame: &Frame, flags: Entry) -> bool {
+        let entry = &mut self.entries[idx];
+
+        if !entry.contains(Entry::PRESENT) {
+            entry.set_frame(frame);
+            // Set raw bits as set_flags would drop the PAT bit of the huge page entry
+            entry.set_raw(entry.raw() | (flags | Entry::PRESENT | Entry::HUGE_PAGE).bits());
+
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Replace 2MB entry with a page table mapping the same memory using 4k pages
+    pub fn split_hugepage(&mut self, idx: usize) -> bool {
+        let entry = &mut self.entries[idx];
+
+        if !entry.contains(Entry::PRESENT | Entry::HUGE_PAGE) {
+            return false;
+        }
+
+        let base = entry.address();
+        let user = entry.contains(Entry::USER);
+        let flags = entry.hugepage_to_page_flags();
+
+        let frame = crate::arch::mm::phys::allocate().expect("Out of memory!");
+
+        let l1 = Table::<Level1>::new_at_frame_mut(&frame);
+        l1.clear();
+
+        for (i, e) in l1.entries.iter_mut().enumerate() {
+            // Each 4k page holds its own reference from now on
+            e.set_frame_flags(&Frame::new(base + i * PAGE_SIZE), flags);
+        }
+
+        // Drops the reference held by the 2MB entry on the first page
+        entry.set_frame(&frame);
+
+        let mut table_flags = Entry::PRESENT | Entry::WRITABLE;
+
+        if user {
+            table_flags |= Entry::USER;
+        }
+
+        entry.set_flags(table_flags);
+        entry.set_entry_count(ENTRIES_COUNT);
+
+        true
+    }
+
+    /// Replace fully populated page table with a single 2MB entry. If 4k pages form a
+    /// contiguous block not shared with anyone, the block is reused. Otherwise the content is
+    /// copied to a newly allocated huge page.
+    pub fn collapse_hugepage(&mut self, idx: usize, flags: Entry) -> bool {
+        let entry = &mut self.entries[idx];
+
+        if !entry.contains(Entry::PRESENT)
+            || entry.contains(Entry::HUGE_PAGE)
+            || entry.get_entry_count() != ENTRIES_COUNT
+        {
+            return false;
+        }
+
+        let l1 = Table::<Level1>::new_at_frame_mut(&Frame::new(entry.address()));
+
+        // Keep reads of the untouched memory backed by the shared zero page
+        if !l1
+            .entries
+            .iter()
+            .all(|e| e.contains(Entry::PRESENT) && !is_zero_page(e.address()))
+        {
+            return false;
+        }
+
+        let base = l1.entries[0].address();
+
+        let in_place = base.0 % HUGE_PAGE_SIZE == 0
+            && l1.entries.iter().enumerate().all(|(i, e)| {
+                e.address() == base + i * PAGE_SIZE
+                    && e.address()
+                        .to_phys_page()
+                        .is_some_and(|p| p.vm_use_count() == 1)
+            });
+
+        let huge = if in_place {
+            for e in l1.entries.iter_mut().skip(1) {
+                // The block stays alive through the reference held on its first page
+                e.address().to_phys_page().unwrap().dec_vm_use_count();
+                e.clear();
+            }
+
+            l1.entries[0].clear();
+
+            base
+        } else {
+            let Some(new) = crate::arch::mm::phys::allocate_order(HUGE_PAGE_ORDER) else {
+                return false;
+            };
+
+            for (i, e) in l1.entries.iter_mut().enumerate() {
+                unsafe {
+                    (new.address_mapped() + i * PAGE_SIZE).copy_page_from(e.address().to_mapped());
+                }
+
+                e.unref_phys_page();
+                e.clear();
+            }
+
+            new.address()
+        };
+
+        // Free the page table
+        entry.unref_phys_page();
+        entry.clear();
+
+        entry.set_raw(huge.0 | (flags | Entry::PRESENT | Entry::HUGE_PAGE).bits());
+
+        if !in_place {
+            entry.ref_phys_page();
+        }
+
+        true
+    }
+
+    pub fn split_all_hugepages(&mut self, flags: Entry) {
+        for idx in 0..ENTRIES_COUNT {
+            if self.entries[idx].contains(flags | Entry::HUGE_PAGE) {
+                self.split_hugepage(idx);
+            }
+        }
+    }
+}
+
 impl Table<Level1> {
     pub fn alloc(&mut self, idx: usize) {
         let entry = &mut self.entries[idx];
@@ -414,7 +561,7 @@ impl Table<Level4> {
     fn change_entry(
         &mut self,
         addr: VirtAddr,
-        mut fun: impl FnMut(&mut Entry),
+        mut fun: impl FnMut(&mut Entry, bool),
     ) -> Option<PhysAddr> {
         let _g = self.lock(addr.is_user());
 
@@ -424,7 +571,7 @@ impl Table<Level4> {
         let entry3 = l3.entry_at_mut(page.p3_index());
 
         let l2 = if entry3.contains(Entry::HUGE_PAGE | Entry::PRESENT) {
-            fun(entry3);
+            fun(entry3, true);
             return Some(entry3.address() + (addr.0 & 0x3FFFFFFF));
         } else {
             l3.next_level_mut(page.p3_index())?
@@ -433,7 +580,7 @@ impl Table<Level4> {
         let entry2 = l2.entry_at_mut(page.p2_index());
 
         let l1 = if entry2.contains(Entry::HUGE_PAGE | Entry::PRESENT) {
-            fun(entry2);
+            fun(entry2, true);
 
             return Some(entry2.address() + (addr.0 & 0x1FFFFF));
         } else {
@@ -443,7 +590,7 @@ impl Table<Level4> {
         let entry = l1.entry_at_mut(page.p1_index());
 
         return if entry.contains(Entry::PRESENT) {
-            fun(entry);
+            fun(entry, false);
             Some(entry.address() + (addr.0 & 0xFFF))
         } else {
             None
@@ -451,23 +598,62 @@ impl Table<Level4> {
     }
 
     pub fn update_flags(&mut self, addr: VirtAddr, flags: virt::PageFlags) -> Option<PhysAddr> {
-        self.change_entry(addr, |e| {
-            e.set_flags(Entry::PRESENT | Entry::from_kernel_flags(flags));
+        self.change_entry(addr, |e, huge| {
+            if huge {
+                e.set_flags(
+                    Entry::PRESENT | Entry::HUGE_PAGE | Entry::from_kernel_flags_hugepage(flags),
+                );
+            } else {
+                e.set_flags(Entry::PRESENT | Entry::from_kernel_flags(flags));
+            }
         })
     }
 
     pub fn remove_flags(&mut self, addr: VirtAddr, flags: virt::PageFlags) -> Option<PhysAddr> {
-        self.change_entry(addr, |e| {
-            e.remove(Entry::from_kernel_flags(flags));
+        self.change_entry(addr, |e, huge| {
+            e.remove(if huge {
+                Entry::from_kernel_flags_hugepage(flags)
+            } else {
+                Entry::from_kernel_flags(flags)
+            });
         })
     }
 
     pub fn insert_flags(&mut self, addr: VirtAddr, flags: virt::PageFlags) -> Option<PhysAddr> {
-        self.change_entry(addr, |e| {
-            e.insert(Entry::from_kernel_flags(flags));
+        self.change_entry(addr, |e, huge| {
+            e.insert(if huge {
+                Entry::from_kernel_flags_hugepage(flags)
+            } else {
+                Entry::from_kernel_flags(flags)
+            });
         })
     }
 
+    pub fn is_hugepage(&self, addr: VirtAddr) -> bool {
+        let _g = self.lock(addr.is_user());
+
+        let page = page::Page::new(addr);
+
+        self.next_level(page.p4_index())
+            .and_then(|l3| l3.next_level(page.p3_index()))
+            .is_some_and(|l2| {
+                l2.entry_at(page.p2_index())
+                    .contains(Entry::PRESENT | Entry::HUGE_PAGE)
+            })
+    }
+
+    /// Check whether 2MB region containing addr has no mappings yet
+    pub fn can_map_hugepage(&self, addr: VirtAddr) -> bool {
+        let _g = self.lock(addr.is_user());
+
+        let page = page::Page::new(addr);
+
+        !self
+            .next_level(page.p4_index())
+            .and_then(|l3| l3.next_level(page.p3_index()))
+            .is_some_and(|l2| l2.entry_at(page.p2_index()).contains(Entry::PRESENT))
+    }
+
     pub fn get_flags(&self, addr: VirtAddr) -> Option<Entry> {
         let _g = self.lock(addr.is_user());
 
@@ -495,6 +681,9 @@ impl Table<Level4> {
 
         let (was_alloc_3, l2) = l3.alloc_next_level(page.p3_index(), user);
 
+        // Mapping 4k page inside a 2MB page requires splitting it first
+        l2.split_hugepage(page.p2_index());
+
         let (was_alloc_2, l1) = l2.alloc_next_level(page.p2_index(), user);
 
         dbgln!(virt, "map_flags {} {:?}", addr, flags);
@@ -522,6 +711,9 @@ impl Table<Level4> {
 
         let (was_alloc_3, l2) = l3.alloc_next_level(page.p3_index(), user);
 
+        // Mapping 4k page inside a 2MB page requires splitting it first
+        l2.split_hugepage(page.p2_index());
+
         let (was_alloc_2, l1) = l2.alloc_next_level(page.p2_index(), user);
 
         dbgln!(
@@ -565,6 +757,9 @@ impl Table<Level4> {
 
         let (was_alloc_3, l2) = l3.alloc_next_level(page.p3_index(), user);
 
+        // Mapping 4k page inside a 2MB page requires splitting it first
+        l2.split_hugepage(page.p2_index());
+
         let (was_alloc_2, l1) = l2.alloc_next_level(page.p2_index(), user);
 
         if l1.set(page.p1_index(), &Frame::new(phys)) {
@@ -580,6 +775,93 @@ impl Table<Level4> {
         }
     }
 
+    pub fn map_hugepage_to_flags(
+        &mut self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        flags: virt::PageFlags,
+    ) -> bool {
+        let _g = self.lock(virt.is_user());
+
+        let page = page::Page::new(virt);
+
+        let user = page.p4_index() < 256;
+
+        let (_, l3) = self.alloc_next_level(page.p4_index(), user);
+
+        let (was_alloc_3, l2) = l3.alloc_next_level(page.p3_index(), user);
+
+        dbgln!(ptable, "map hugepage {} -> {}", virt, phys);
+
+        let res = l2.set_hugepage_flags(
+            page.p2_index(),
+            &Frame::new(phys),
+            Entry::from_kernel_flags_hugepage(flags),
+        );
+
+        if res {
+            l3.entries[page.p3_index()].inc_entry_count();
+        }
+
+        if was_alloc_3 {
+            self.entries[page.p4_index()].inc_entry_count();
+        }
+
+        res
+    }
+
+    pub fn unmap_hugepage(&mut self, virt: VirtAddr) -> bool {
+        let _g = self.lock(virt.is_user());
+
+        let page = page::Page::new(virt);
+
+        if let Some(l3) = self.next_level_mut(page.p4_index()) {
+            if let Some(l2) = l3.next_level_mut(page.p3_index()) {
+                let entry = l2.entry_at_mut(page.p2_index());
+
+                if entry.contains(Entry::PRESENT | Entry::HUGE_PAGE) {
+                    entry.unref_huge_phys_page();
+                    entry.clear();
+
+                    l3.do_unmap(page.p3_index());
+
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    pub fn split_hugepage(&mut self, virt: VirtAddr) -> bool {
+        let _g = self.lock(virt.is_user());
+
+        let page = page::Page::new(virt);
+
+        if let Some(l3) = self.next_level_mut(page.p4_index()) {
+            if let Some(l2) = l3.next_level_mut(page.p3_index()) {
+                return l2.split_hugepage(page.p2_index());
+            }
+        }
+
+        false
+    }
+
+    pub fn collapse_hugepage(&mut self, virt: VirtAddr, flags: virt::PageFlags) -> bool {
+        let _g = self.lock(virt.is_user());
+
+        let page = page::Page::new(virt);
+
+        if let Some(l3) = self.next_level_mut(page.p4_index()) {
+            if let Some(l2) = l3.next_level_mut(page.p3_index()) {
+                return l2
+                    .collapse_hugepage(page.p2_index(), Entry::from_kernel_flags_hugepage(flags));
+            }
+        }
+
+        false
+    }
+
     pub fn map_hugepage_to(&mut self, virt: VirtAddr, phys: PhysAddr) {
         let _g = self.lock(virt.is_user());
 
@@ -610,6 +892,9 @@ impl Table<Level4> {
 
         if let Some(l3) = self.next_level_mut(page.p4_index()) {
             if let Some(l2) = l3.next_level_mut(page.p3_index()) {
+                // Unmapping 4k page from 2MB page, keep the rest of it mapped
+                l2.split_hugepage(page.p2_index());
+
                 if let Some(l1) = l2.next_level_mut(page.p2_index()) {
                     if l1.do_unmap(page.p1_index()) {
                         if l2.do_unmap(page.p2_index()) {
@@ -628,6 +913,11 @@ impl Table<Level4> {
 
         self.for_entries_mut(flags, |_idx3, e3, l3| {
             l3.for_entries_mut(flags, |_idx2, e2, l2| {
+                l2.for_huge_entries_mut(flags, |_idx1, e1| {
+                    e1.unref_huge_phys_page();
+                    e1.clear();
+                });
+
                 l2.for_entries_mut(flags, |_idx1, e1, l1| {
                     l1.for_entries_mut(flags, |_idx, e| {
                         e.unref_phys_page();
@@ -672,6 +962,9 @@ impl Table<Level4> {
 
                 let mut count_2 = 0;
 
+                // Huge pages are never shared, split them so that the 4k pages can be COWed
+                l2.split_all_hugepages(flags);
+
                 l2.for_entries_mut(flags, |idx2, _e2, l1| {
                     let (w1, n1) = n2.alloc_next_level(idx2, true);
 
@@ -15,13 +15,16 @@ pub mod idt;
 pub mod int;
 pub mod ipi;
 pub mod mm;
+pub mod random;
 pub mod signal;
 pub mod smp;
 pub mod syscall;
 pub mod time;
 pub mod timer;
 pub mod tls;
+pub mod uaccess;
 pub mod utils;
+pub mod vdso;
 
 #[unsafe(no_mangle)]
 pub extern "C" fn x86_64_rust_main(mboot_addr: mm::PhysAddr, stack_top: VirtAddr) {
@@ -0,0 +1,40 @@
+use core::arch::asm;
+
+use raw_cpuid::CpuId;
+
+fn has_rdrand() -> bool {
+    CpuId::new()
+        .get_feature_info()
+        .map_or(false, |f| f.has_rdrand())
+}
+
+fn rdrand() -> Option<u64> {
+    // Intel recommends retrying rdrand up to 10 times before giving up
+    for _ in 0..10 {
+        let val: u64;
+        let ok: u8;
+
+        unsafe {
+            asm!("rdrand {0}", "setc {1}", out(reg) val, out(reg_byte) ok, options(nomem, nostack));
+        }
+
+        if ok == 1 {
+            return Some(val);
+        }
+    }
+
+    None
+}
+
+/// Hardware random number if supported by the cpu
+pub fn hw_random() -> Option<u64> {
+    if has_rdrand() {
+        rdrand()
+    } else {
+        None
+    }
+}
+
+pub fn timestamp() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
@@ -1,90 +1,404 @@
+use syscall_defs::signal::{MContext, SigInfo, SignalFlags, UContext};
 use syscall_defs::{SyscallFrom, SyscallInto, SyscallRestartable, SyscallResult};
 
+use crate::arch::gdt;
 use crate::arch::idt::RegsFrame;
 use crate::arch::raw::idt::InterruptFrame;
 use crate::arch::syscall::SyscallFrame;
 use crate::arch::utils::StackHelper;
+use crate::kernel::mm::uaccess::{read_user, write_user, UserFault};
+use crate::kernel::mm::VirtAddr;
 use crate::kernel::sched::current_task_ref;
-use crate::kernel::signal::DoSignalsResult;
+use crate::kernel::signal::{DoSignalsResult, SignalEntry};
 
 const SYSCALL_INSTRUCTION_SIZE: u64 = 2;
 const REDZONE_SIZE: u64 = 128;
 
+/// Frame pushed on the user stack when entering a signal handler, sigreturn restores the
+/// interrupted context from the ucontext, including changes made by the handler
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct SignalFrame {
+    uc: UContext,
+    info: SigInfo,
     restart_syscall: u64,
-    regs: RegsFrame,
-    sigmask: u64,
-    rflags: u64,
-    rip: u64,
 }
 
 impl SignalFrame {
-    fn from_interrupt(
-        frame: &mut InterruptFrame,
-        regs: &mut RegsFrame,
+    fn new(
+        regs: &RegsFrame,
+        rip: u64,
+        rflags: u64,
+        rsp: u64,
         sigmask: u64,
+        info: SigInfo,
     ) -> SignalFrame {
         SignalFrame {
+            uc: UContext {
+                uc_mcontext: MContext {
+                    r8: regs.r8,
+                    r9: regs.r9,
+                    r10: regs.r10,
+                    r11: regs.r11,
+                    r12: regs.r12,
+                    r13: regs.r13,
+                    r14: regs.r14,
+                    r15: regs.r15,
+                    rdi: regs.rdi,
+                    rsi: regs.rsi,
+                    rbp: regs.rbp,
+                    rbx: regs.rbx,
+                    rdx: regs.rdx,
+                    rax: regs.rax,
+                    rcx: regs.rcx,
+                    rsp,
+                    rip,
+                    eflags: rflags,
+                    cs: gdt::ring3_cs().bits(),
+                    ss: gdt::ring3_ds().bits(),
+                    oldmask: sigmask,
+                    cr2: regs.cr2,
+                    ..Default::default()
+                },
+                uc_sigmask: sigmask,
+                ..Default::default()
+            },
+            info,
             restart_syscall: u64::MAX,
-            regs: *regs,
-            sigmask,
-            rflags: frame.cf,
-            rip: frame.ip,
         }
     }
 
+    fn from_interrupt(
+        frame: &mut InterruptFrame,
+        regs: &mut RegsFrame,
+        sigmask: u64,
+        info: SigInfo,
+    ) -> SignalFrame {
+        SignalFrame::new(regs, frame.ip, frame.cf, frame.sp, sigmask, info)
+    }
+
     fn from_syscall(
         restart: bool,
         syscall_result: u64,
         sys_frame: &mut SyscallFrame,
         regs: &mut RegsFrame,
         sigmask: u64,
+        info: SigInfo,
     ) -> SignalFrame {
-        let mut frame = SignalFrame {
-            restart_syscall: if restart { regs.rax } else { u64::MAX },
-            regs: *regs,
+        let mut frame = SignalFrame::new(
+            regs,
+            sys_frame.rip,
+            sys_frame.rflags,
+            sys_frame.rsp,
             sigmask,
-            rflags: sys_frame.rflags,
-            rip: sys_frame.rip,
-        };
+            info,
+        );
 
-        if !restart {
-            frame.regs.rax = syscall_result;
+        if restart {
+            frame.restart_syscall = regs.rax;
+        } else {
+            frame.uc.uc_mcontext.rax = syscall_result;
         }
 
         frame
     }
+
+    fn restore_regs(&self, regs: &mut RegsFrame) {
+        let mc = &self.uc.uc_mcontext;
+
+        regs.r8 = mc.r8;
+        regs.r9 = mc.r9;
+        regs.r10 = mc.r10;
+        regs.r11 = mc.r11;
+        regs.r12 = mc.r12;
+        regs.r13 = mc.r13;
+        regs.r14 = mc.r14;
+        regs.r15 = mc.r15;
+        regs.rdi = mc.rdi;
+        regs.rsi = mc.rsi;
+        regs.rbp = mc.rbp;
+        regs.rbx = mc.rbx;
+        regs.rdx = mc.rdx;
+        regs.rax = mc.rax;
+        regs.rcx = mc.rcx;
+    }
 }
 
-pub fn arch_int_check_signals(frame: &mut InterruptFrame, regs: &mut RegsFrame) {
-    match crate::kernel::signal::do_signals(false, 0) {
-        DoSignalsResult::Entry((sig, entry)) => {
-            if let syscall_defs::signal::SignalHandler::Handle(f) = entry.handler() {
-                let signals = current_task_ref().signals();
-                let old_mask = signals.blocked_mask();
+/// User registers in the x86_64 user_regs_struct layout (core dump NT_PRSTATUS)
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct UserRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
 
-                let signal_frame = SignalFrame::from_interrupt(frame, regs, old_mask);
+impl UserRegs {
+    fn new(regs: &RegsFrame, orig_rax: u64, rip: u64, rflags: u64, rsp: u64) -> UserRegs {
+        UserRegs {
+            r15: regs.r15,
+            r14: regs.r14,
+            r13: regs.r13,
+            r12: regs.r12,
+            rbp: regs.rbp,
+            rbx: regs.rbx,
+            r11: regs.r11,
+            r10: regs.r10,
+            r9: regs.r9,
+            r8: regs.r8,
+            rax: regs.rax,
+            rcx: regs.rcx,
+            rdx: regs.rdx,
+            rsi: regs.rsi,
+            rdi: regs.rdi,
+            orig_rax,
+            rip,
+            cs: gdt::ring3_cs().bits() as u64,
+            eflags: rflags,
+            rsp,
+            ss: gdt::ring3_ds().bits() as u64,
+            fs_base: unsafe { current_task_ref().arch_task().user_fs_base } as u64,
+            ..Default::default()
+        }
+    }
 
-                signals.set_mask(
-                    syscall_defs::signal::SigProcMask::Block,
-                    Some(1u64 << (sig - 1)),
-                    None,
-                );
+    fn from_interrupt(frame: &InterruptFrame, regs: &RegsFrame) -> UserRegs {
+        UserRegs::new(regs, u64::MAX, frame.ip, frame.cf, frame.sp)
+    }
+
+    fn from_syscall(syscall_result: u64, sys_frame: &SyscallFrame, regs: &RegsFrame) -> UserRegs {
+        let mut res = UserRegs::new(
+            regs,
+            regs.rax,
+            sys_frame.rip,
+            sys_frame.rflags,
+            sys_frame.rsp,
+        );
+
+        res.rax = syscall_result;
+
+        res
+    }
+}
+
+pub const TRAP_FLAG: u64 = 1 << 8;
+// CF, PF, AF, ZF, SF, TF, DF, OF and AC can be modified by the tracer
+const USER_FLAGS: u64 = 0x40dd5;
+
+/// User state saved on the kernel stack by the syscall or interrupt entry
+pub enum UserFrame<'a> {
+    Syscall {
+        sys_frame: &'a mut SyscallFrame,
+        regs: &'a mut RegsFrame,
+        // Syscall result, regs.rax holds the syscall number
+        rax: &'a mut u64,
+    },
+    Interrupt {
+        frame: &'a mut InterruptFrame,
+        regs: &'a mut RegsFrame,
+    },
+}
+
+impl<'a> UserFrame<'a> {
+    pub fn user_regs(&self) -> UserRegs {
+        match self {
+            UserFrame::Syscall {
+                sys_frame,
+                regs,
+                rax,
+            } => UserRegs::from_syscall(**rax, sys_frame, regs),
+            UserFrame::Interrupt { frame, regs } => UserRegs::from_interrupt(frame, regs),
+        }
+    }
+
+    /// Updates saved user state, fails if rip or rsp points outside of user memory
+    pub fn set_user_regs(&mut self, user: &UserRegs) -> bool {
+        if !VirtAddr(user.rip as usize).is_user() || !VirtAddr(user.rsp as usize).is_user() {
+            return false;
+        }
+
+        let (regs, rip, rsp, rflags) = match self {
+            UserFrame::Syscall {
+                sys_frame,
+                regs,
+                rax,
+            } => {
+                **rax = user.rax;
+                regs.rax = user.orig_rax;
+
+                (
+                    regs,
+                    &mut sys_frame.rip,
+                    &mut sys_frame.rsp,
+                    &mut sys_frame.rflags,
+                )
+            }
+            UserFrame::Interrupt { frame, regs } => {
+                regs.rax = user.rax;
+
+                (regs, &mut frame.ip, &mut frame.sp, &mut frame.cf)
+            }
+        };
+
+        regs.r15 = user.r15;
+        regs.r14 = user.r14;
+        regs.r13 = user.r13;
+        regs.r12 = user.r12;
+        regs.rbp = user.rbp;
+        regs.rbx = user.rbx;
+        regs.r11 = user.r11;
+        regs.r10 = user.r10;
+        regs.r9 = user.r9;
+        regs.r8 = user.r8;
+        regs.rcx = user.rcx;
+        regs.rdx = user.rdx;
+        regs.rsi = user.rsi;
+        regs.rdi = user.rdi;
+
+        *rip = user.rip;
+        *rsp = user.rsp;
+        *rflags = (*rflags & !USER_FLAGS) | (user.eflags & USER_FLAGS);
+
+        true
+    }
+
+    /// Trap after the next user instruction
+    pub fn set_single_step(&mut self, enable: bool) {
+        let rflags = match self {
+            UserFrame::Syscall { sys_frame, .. } => &mut sys_frame.rflags,
+            UserFrame::Interrupt { frame, .. } => &mut frame.cf,
+        };
 
-                let mut writer = StackHelper::new(&mut frame.sp);
+        if enable {
+            *rflags |= TRAP_FLAG;
+        } else {
+            *rflags &= !TRAP_FLAG;
+        }
+    }
+}
+
+/// Push signal frame followed by the sigreturn address on the user stack, returns the frame
+/// address
+fn push_signal_frame(
+    sp: &mut u64,
+    frame: &SignalFrame,
+    sigreturn: usize,
+) -> Result<u64, UserFault> {
+    let mut writer = StackHelper::new(sp);
+
+    writer.skip_by(REDZONE_SIZE);
+    writer.skip_by(core::mem::size_of::<SignalFrame>() as u64);
+    writer.align_down();
+
+    let frame_addr = writer.current();
+
+    write_user(VirtAddr(frame_addr as usize), frame)?;
+    writer.skip_by(core::mem::size_of::<usize>() as u64);
+    write_user(VirtAddr(writer.current() as usize), &sigreturn)?;
+
+    Ok(frame_addr)
+}
+
+/// Pushes the signal frame, on the alternate stack if requested, and enters the handler
+fn setup_handler(
+    sig: usize,
+    entry: &SignalEntry,
+    handler: u64,
+    mut signal_frame: SignalFrame,
+    sp: &mut u64,
+    ip: &mut u64,
+    regs: &mut RegsFrame,
+) {
+    let signals = current_task_ref().signals();
+
+    let alt_stack = signals.alt_stack();
+
+    signal_frame.uc.uc_stack = alt_stack.to_user(*sp);
+
+    // Nested signals keep running on the alternate stack
+    if entry.flags().contains(SignalFlags::ONSTACK)
+        && alt_stack.is_enabled()
+        && !alt_stack.contains(*sp)
+    {
+        *sp = alt_stack.top();
+    }
+
+    signals.enter_handler(sig, entry);
+
+    let frame_addr = match push_signal_frame(sp, &signal_frame, entry.sigreturn()) {
+        Ok(addr) => addr,
+        Err(_) => bad_signal_frame(),
+    };
+
+    *ip = handler;
+
+    // Handler params, siginfo and ucontext are used by SA_SIGINFO handlers
+    regs.rdi = sig as u64;
+    regs.rsi = frame_addr + core::mem::offset_of!(SignalFrame, info) as u64;
+    regs.rdx = frame_addr + core::mem::offset_of!(SignalFrame, uc) as u64;
+}
+
+fn bad_signal_frame() -> ! {
+    logln!(
+        "[ SIGSEGV ] Task {} invalid signal frame",
+        current_task_ref().tid()
+    );
+
+    crate::kernel::sched::exit(syscall_defs::waitpid::Status::Signaled(
+        syscall_defs::signal::SIGSEGV as u64,
+    ));
+}
+
+pub fn arch_int_check_signals(frame: &mut InterruptFrame, regs: &mut RegsFrame) {
+    let res = crate::kernel::signal::do_signals(
+        false,
+        0,
+        &mut UserFrame::Interrupt {
+            frame: &mut *frame,
+            regs: &mut *regs,
+        },
+    );
 
-                writer.skip_by(REDZONE_SIZE);
-                unsafe {
-                    writer.write(signal_frame);
-                    writer.write(entry.sigreturn());
-                }
+    match res {
+        DoSignalsResult::Entry((sig, entry, info)) => {
+            if let syscall_defs::signal::SignalHandler::Handle(f) = entry.handler() {
+                let old_mask = current_task_ref().signals().blocked_mask();
 
-                frame.ip = f as u64;
+                let signal_frame = SignalFrame::from_interrupt(frame, regs, old_mask, info);
 
-                // Signal param
-                regs.rdi = sig as u64;
+                setup_handler(
+                    sig,
+                    &entry,
+                    f as u64,
+                    signal_frame,
+                    &mut frame.sp,
+                    &mut frame.ip,
+                    regs,
+                );
             }
         }
         DoSignalsResult::Default | DoSignalsResult::Ignore => {
@@ -99,17 +413,25 @@ pub fn arch_sys_check_signals(
     sys_frame: &mut SyscallFrame,
     regs: &mut RegsFrame,
 ) -> bool {
-    match crate::kernel::signal::do_signals(true, regs.rax as usize) {
-        DoSignalsResult::Entry((sig, entry)) => {
-            if let syscall_defs::signal::SignalHandler::Handle(f) = entry.handler() {
-                let signals = current_task_ref().signals();
-                let old_mask = signals.blocked_mask();
+    let mut rax = syscall_result.syscall_into() as u64;
+
+    let res = crate::kernel::signal::do_signals(
+        true,
+        regs.rax as usize,
+        &mut UserFrame::Syscall {
+            sys_frame: &mut *sys_frame,
+            regs: &mut *regs,
+            rax: &mut rax,
+        },
+    );
 
-                signals.set_mask(
-                    syscall_defs::signal::SigProcMask::Block,
-                    Some(1u64 << (sig - 1)),
-                    None,
-                );
+    // Tracer could have changed the result in the signal delivery stop
+    let syscall_result = SyscallResult::syscall_from(rax as isize);
+
+    match res {
+        DoSignalsResult::Entry((sig, entry, info)) => {
+            if let syscall_defs::signal::SignalHandler::Handle(f) = entry.handler() {
+                let old_mask = current_task_ref().signals().blocked_mask();
 
                 let restart = syscall_result.is_restart(
                     true,
@@ -124,21 +446,19 @@ pub fn arch_sys_check_signals(
                     sys_frame,
                     regs,
                     old_mask,
+                    info,
                 );
 
-                let mut writer = StackHelper::new(&mut sys_frame.rsp);
-
-                writer.skip_by(REDZONE_SIZE);
-                unsafe {
-                    writer.write(signal_frame);
-                    writer.write(entry.sigreturn());
-                }
-
                 logln_disabled!("sys_frame set rip: {:#x}", f as usize);
-                sys_frame.rip = f as u64;
-
-                // Signal param
-                regs.rdi = sig as u64;
+                setup_handler(
+                    sig,
+                    &entry,
+                    f as u64,
+                    signal_frame,
+                    &mut sys_frame.rsp,
+                    &mut sys_frame.rip,
+                    regs,
+                );
 
                 logln2!("do signal!!! {} FROM SYSCALL", sig);
             }
@@ -165,24 +485,29 @@ pub fn arch_sys_sigreturn(
     sys_frame: &mut SyscallFrame,
     user_regs: &mut RegsFrame,
 ) -> (SyscallResult, bool) {
-    let mut writer = StackHelper::new(&mut sys_frame.rsp);
+    let signal_frame = match read_user::<SignalFrame>(VirtAddr(sys_frame.rsp as usize)) {
+        Ok(frame) => frame,
+        Err(_) => bad_signal_frame(),
+    };
 
-    let signal_frame = unsafe { writer.restore::<SignalFrame>() };
+    let mc = &signal_frame.uc.uc_mcontext;
+
+    // Handler could have modified the context
+    if !VirtAddr(mc.rip as usize).is_user() || !VirtAddr(mc.rsp as usize).is_user() {
+        bad_signal_frame();
+    }
 
     current_task_ref().signals().set_mask(
         syscall_defs::signal::SigProcMask::Set,
-        Some(signal_frame.sigmask),
+        Some(signal_frame.uc.uc_sigmask),
         None,
     );
 
-    writer.restore_by(REDZONE_SIZE);
-
-    let result = signal_frame.regs.rax as isize;
+    signal_frame.restore_regs(user_regs);
 
-    *user_regs = signal_frame.regs;
-
-    sys_frame.rflags = signal_frame.rflags;
-    sys_frame.rip = signal_frame.rip;
+    sys_frame.rflags = (sys_frame.rflags & !USER_FLAGS) | (mc.eflags & USER_FLAGS);
+    sys_frame.rip = mc.rip;
+    sys_frame.rsp = mc.rsp;
 
     let was_restart = if signal_frame.restart_syscall != u64::MAX {
         sys_frame.rip -= SYSCALL_INSTRUCTION_SIZE as u64;
@@ -191,5 +516,12 @@ pub fn arch_sys_sigreturn(
         false
     };
 
+    // Restarted syscall number is kept in rax
+    let result = if was_restart {
+        signal_frame.restart_syscall
+    } else {
+        user_regs.rax
+    } as isize;
+
     return (SyscallResult::syscall_from(result), was_restart);
 }
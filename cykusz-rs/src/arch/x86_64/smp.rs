@@ -6,6 +6,9 @@ use crate::kernel::sync::LockApi;
 pub const TRAMPOLINE: PhysAddr = PhysAddr(0xE00);
 pub const AP_INIT: PhysAddr = PhysAddr(0x1000);
 
+/// Cpu numbers passed to the aps are 8 bit
+pub const MAX_CPUS: usize = 256;
+
 #[repr(C, packed)]
 pub struct Trampoline {
     pub ready: u8,
@@ -39,6 +42,9 @@ impl Trampoline {
 
         unsafe {
             while rdy.read_volatile() == 0 {
+                // Booting ap aligns its tsc with ours before reporting ready
+                crate::arch::dev::tsc::serve_sync();
+
                 asm!("pause");
             }
         }
@@ -90,6 +96,8 @@ pub fn start() {
 }
 
 pub fn notify_ap_ready() {
+    crate::arch::dev::tsc::sync_ap();
+
     let trampoline = crate::arch::smp::Trampoline::get();
 
     trampoline.notify_ready();
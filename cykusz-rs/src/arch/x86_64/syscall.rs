@@ -1,7 +1,7 @@
-use syscall_defs::{SyscallError, SyscallInto, SyscallResult, SYSCALL_STRING};
+use syscall_defs::{SyscallError, SyscallFrom, SyscallInto, SyscallResult, SYSCALL_STRING};
 
 use crate::arch::idt::RegsFrame;
-use crate::arch::signal::arch_sys_check_signals;
+use crate::arch::signal::{arch_sys_check_signals, UserFrame};
 use crate::kernel::mm::VirtAddr;
 use crate::kernel::sched::current_task_ref;
 use crate::kernel::sync::IrqGuard;
@@ -17,7 +17,8 @@ fn enable_syscall_extension() {
 
         msr::wrmsr(msr::IA32_STAR, 0x0013_0008_0000_0000);
         msr::wrmsr(msr::IA32_LSTAR, asm_syscall_handler as *const () as u64);
-        msr::wrmsr(msr::IA32_FMASK, 0x200);
+        // Clear IF, TF and AC on syscall entry, user memory access needs explicit stac
+        msr::wrmsr(msr::IA32_FMASK, 0x200 | 0x100 | (1 << 18));
     }
 }
 
@@ -39,6 +40,8 @@ pub struct SyscallFrame {
 
 #[unsafe(no_mangle)]
 pub extern "C" fn fast_syscall_handler(sys_frame: &mut SyscallFrame, regs: &mut RegsFrame) {
+    current_task_ref().account_enter_kernel();
+
     if regs.rax == syscall_defs::SYS_SIGRETURN as u64 {
         // Store syscall result in rax
         let (res, was_restart) = crate::arch::signal::arch_sys_sigreturn(sys_frame, regs);
@@ -61,10 +64,33 @@ pub extern "C" fn fast_syscall_handler(sys_frame: &mut SyscallFrame, regs: &mut
             crate::kernel::int::is_enabled()
         );
 
-        let res = crate::kernel::syscall::syscall_handler(
+        if task.ptrace().syscall_stops() {
+            // Syscall entry stop, tracer can inspect and modify the arguments
+            let mut rax = SyscallResult::Err(SyscallError::ENOSYS).syscall_into() as u64;
+
+            crate::kernel::task::ptrace::syscall_stop(&mut UserFrame::Syscall {
+                sys_frame: &mut *sys_frame,
+                regs: &mut *regs,
+                rax: &mut rax,
+            });
+        }
+
+        let mut res = crate::kernel::syscall::syscall_handler(
             regs.rax, regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9,
         );
 
+        if task.ptrace().syscall_stops() {
+            let mut rax = res.syscall_into() as u64;
+
+            crate::kernel::task::ptrace::syscall_stop(&mut UserFrame::Syscall {
+                sys_frame: &mut *sys_frame,
+                regs: &mut *regs,
+                rax: &mut rax,
+            });
+
+            res = SyscallResult::syscall_from(rax as isize);
+        }
+
         dbgln!(
             syscall,
             "done syscall [task {}] {} = {:?} int {}",
@@ -80,6 +106,8 @@ pub extern "C" fn fast_syscall_handler(sys_frame: &mut SyscallFrame, regs: &mut
             crate::kernel::syscall::sys::sys_exit_thread();
         }
     }
+
+    current_task_ref().account_leave_kernel();
 }
 
 pub fn sys_arch_prctl(cmd: u64, addr: u64) -> SyscallResult {
@@ -106,6 +134,12 @@ pub fn sys_arch_prctl(cmd: u64, addr: u64) -> SyscallResult {
 
             Ok(0)
         }
+        PrctlCmd::SetSyscallTrace => {
+            current_task_ref().strace().set_enabled(addr != 0);
+
+            Ok(0)
+        }
+        PrctlCmd::GetSyscallTrace => Ok(current_task_ref().strace().is_enabled() as usize),
         PrctlCmd::Unknown => Err(SyscallError::EINVAL),
     }
 }
@@ -6,29 +6,32 @@ use crate::arch::cpu::fpu::FpuState;
 use crate::arch::gdt;
 use crate::arch::gdt::update_tss_rps0;
 use crate::arch::idt::RegsFrame;
-use crate::arch::mm::phys::{allocate_order, deallocate_order};
+use crate::arch::mm::kstack;
 use crate::arch::mm::virt::p4_table;
 use crate::arch::mm::virt::table::P4Table;
 use crate::arch::mm::virt::{activate_table, current_p4_table, p4_table_addr};
 use crate::arch::raw::idt::InterruptFrame;
-use crate::arch::raw::mm::MappedAddr;
 use crate::arch::raw::segmentation::SegmentSelector;
+use crate::arch::signal::UserFrame;
 use crate::arch::syscall::SyscallFrame;
 use crate::arch::utils::StackHelper;
+use crate::kernel::mm::uaccess::UserAccess;
 use crate::drivers::elf::ElfHeader;
 use crate::kernel::fs::dirent::DirEntryItem;
 use crate::kernel::mm::virt::PageFlags;
 use crate::kernel::mm::{allocate, VirtAddr, PAGE_SIZE};
-use crate::kernel::mm::{Frame, PhysAddr};
+use crate::kernel::mm::PhysAddr;
+use crate::kernel::sched::current_task_ref;
 use crate::kernel::task::vm::{TlsVmInfo, VM};
+use crate::kernel::utils::types::Align;
 use syscall_defs::exec::ExeArgs;
-use syscall_defs::{MMapFlags, MMapProt, OpenFlags};
+use syscall_defs::resource::RLimitKind;
+use syscall_defs::{MMapFlags, MMapProt, OpenFlags, SyscallError};
 
 mod args;
 
 const USER_STACK_SIZE: usize = 0x64000;
-const KERN_STACK_SIZE: usize = 0x40000;
-const KERN_STACK_ORDER: usize = 6;
+const KERN_STACK_SIZE: usize = kstack::KSTACK_SIZE;
 
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -111,10 +114,27 @@ fn prepare_p4<'a>() -> &'a mut P4Table {
     new_p4
 }
 
-#[allow(unused)]
-fn prepare_tls(vm: &VM, p_table: &mut P4Table, tls: &TlsVmInfo) -> VirtAddr {
-    let mmap = vm
-        .mmap_vm(
+/// Maps the user stack and the tls area in the new vm, returns the tls address
+pub fn mmap_exec(vm: &VM, tls: Option<&TlsVmInfo>) -> Result<VirtAddr, SyscallError> {
+    let stack_top = vm.stack_top();
+
+    let stack_size = (core::cmp::min(
+        USER_STACK_SIZE as u64,
+        current_task_ref().rlimit(RLimitKind::Stack).cur,
+    ) as usize)
+        .align_down(PAGE_SIZE);
+
+    vm.mmap_vm(
+        Some(VirtAddr(stack_top.0 - stack_size)),
+        stack_size,
+        MMapProt::PROT_WRITE | MMapProt::PROT_READ,
+        MMapFlags::MAP_FIXED | MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
+        None,
+        0,
+    )?;
+
+    if let Some(tls) = tls {
+        vm.mmap_vm(
             Some(tls.mmap_addr_hint),
             tls.mem_size + 8,
             MMapProt::PROT_READ | MMapProt::PROT_WRITE,
@@ -122,8 +142,12 @@ fn prepare_tls(vm: &VM, p_table: &mut P4Table, tls: &TlsVmInfo) -> VirtAddr {
             None,
             0,
         )
-        .expect("Failed to mmap tls");
+    } else {
+        Ok(VirtAddr(0))
+    }
+}
 
+fn prepare_tls(p_table: &mut P4Table, tls: &TlsVmInfo, mmap: VirtAddr) -> VirtAddr {
     for (num, m) in (mmap..(mmap + VirtAddr(tls.mem_size + 8)).align_up(PAGE_SIZE))
         .step_by(PAGE_SIZE)
         .enumerate()
@@ -189,6 +213,11 @@ impl Task {
         self.user_stack.is_some()
     }
 
+    /// User stack pointer saved on the syscall entry, valid while in a syscall
+    pub fn syscall_user_sp(&self) -> u64 {
+        unsafe { self.syscall_frame() }.rsp
+    }
+
     pub fn assure_empty(&self) {
         if self.stack_top != 0 {
             panic!("[ ERROR ] ArchTask corrupted on init");
@@ -271,7 +300,7 @@ impl Task {
         .read_ref::<RegsFrame>()
     }}
 
-    unsafe fn fork_ctx(&self, sp: usize, cr3: usize) -> Unique<Context> { unsafe {
+    unsafe fn fork_ctx(&self, sp: usize, cr3: usize, user_stack: Option<usize>) -> Unique<Context> { unsafe {
         let parent_sys_frame = self.syscall_frame();
         let parent_regs_frame = self.syscall_regs();
 
@@ -279,7 +308,12 @@ impl Task {
 
         let mut helper = StackHelper::new(&mut sp);
 
-        *helper.next::<SyscallFrame>() = *parent_sys_frame;
+        let sys_frame = helper.next::<SyscallFrame>();
+        *sys_frame = *parent_sys_frame;
+
+        if let Some(stack) = user_stack {
+            sys_frame.rsp = stack as u64;
+        }
 
         let regs = helper.next::<RegsFrame>();
         *regs = *parent_regs_frame;
@@ -364,7 +398,7 @@ impl Task {
         user_stack: Option<usize>,
         param: usize,
     ) -> Task {
-        let sp = allocate_order(KERN_STACK_ORDER).unwrap().address_mapped().0;
+        let sp = kstack::allocate().expect("Out of kernel stacks").0;
 
         Task::new_sp(
             fun,
@@ -416,33 +450,51 @@ impl Task {
     }
 
     pub fn fork(&self) -> Task {
-        let orig_p4 = P4Table::new_mut_at_phys(PhysAddr(self.cr3));
+        self.clone_task(false, None, None)
+    }
 
-        let new_p4 = orig_p4.duplicate();
+    /// Child returns from the current syscall, optionally on a different stack and tls
+    pub fn clone_task(
+        &self,
+        share_vm: bool,
+        user_stack: Option<usize>,
+        tls: Option<usize>,
+    ) -> Task {
+        let cr3 = if share_vm {
+            p4_table(PhysAddr(self.cr3)).ref_table();
 
-        // We marked writable entries in parent and child process as readonly to enable COW
-        // Flush the pagetable of the current process
-        crate::arch::mm::virt::flush_all();
+            self.cr3
+        } else {
+            let orig_p4 = P4Table::new_mut_at_phys(PhysAddr(self.cr3));
 
-        let sp_top = allocate_order(KERN_STACK_ORDER).unwrap().address_mapped().0;
+            let new_p4 = orig_p4.duplicate();
+
+            // We marked writable entries in parent and child process as readonly to enable COW
+            // Flush the pagetable of the current process
+            crate::arch::mm::virt::flush_all();
+
+            new_p4.phys_addr().0
+        };
+
+        let sp_top = kstack::allocate().expect("Out of kernel stacks").0;
 
         let sp = sp_top + KERN_STACK_SIZE;
 
-        let new_ctx = unsafe { self.fork_ctx(sp, new_p4.phys_addr().0) };
+        let new_ctx = unsafe { self.fork_ctx(sp, cr3, user_stack) };
 
         Task {
             ctx: new_ctx,
-            cr3: new_p4.phys_addr().0,
+            cr3,
             stack_top: sp_top,
             stack_size: KERN_STACK_SIZE,
-            user_stack: self.user_stack,
-            user_fs_base: self.user_fs_base,
+            user_stack: user_stack.or(self.user_stack),
+            user_fs_base: tls.unwrap_or(self.user_fs_base),
             fpu: self.fpu,
         }
     }
 
     pub fn fork_thread(&self, entry: usize, user_stack: usize) -> Task {
-        let sp_top = allocate_order(KERN_STACK_ORDER).unwrap().address_mapped().0;
+        let sp_top = kstack::allocate().expect("Out of kernel stacks").0;
 
         let p4 = p4_table(PhysAddr(self.cr3 as usize));
         p4.ref_table();
@@ -469,6 +521,7 @@ impl Task {
         hdr: &ElfHeader,
         vm: &VM,
         tls_vm: Option<TlsVmInfo>,
+        tls_addr: VirtAddr,
         exe: DirEntryItem,
         args: Option<ExeArgs>,
         envs: Option<ExeArgs>,
@@ -480,38 +533,41 @@ impl Task {
         let args = args.map(|a| args::Args::new(a));
         let envs = envs.map(|e| args::Args::new(e));
 
+        let mut shared_p4 = None;
+
         let p_table = if self.is_user() {
             let p_table = current_p4_table();
-            p_table.deallocate_user();
-            p_table
+
+            if p_table.phys_page().is_some_and(|p| p.vm_use_count() > 1) {
+                // Address space is still used by the vfork parent, switch to a new one
+                shared_p4 = Some(p_table);
+
+                prepare_p4()
+            } else {
+                p_table.deallocate_user();
+                p_table
+            }
         } else {
             prepare_p4()
         };
 
-        let mut user_stack: u64 = 0x8000_0000_0000;
+        let stack_top = vm.stack_top();
 
-        vm.mmap_vm(
-            Some(VirtAddr(user_stack as usize - USER_STACK_SIZE)),
-            USER_STACK_SIZE,
-            MMapProt::PROT_WRITE | MMapProt::PROT_READ,
-            MMapFlags::MAP_FIXED | MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
-            None,
-            0,
-        );
+        let mut user_stack: u64 = stack_top.0 as u64;
 
         //for addr in (VirtAddr(user_stack as usize)..VirtAddr(user_stack as usize)).step_by(PAGE_SIZE) {
         //    p_table.map_flags(addr, PageFlags::USER | PageFlags::WRITABLE);
         //}
 
         let tls_ptr = if let Some(tls) = &tls_vm {
-            prepare_tls(vm, p_table, tls)
+            prepare_tls(p_table, tls, tls_addr)
         } else {
             VirtAddr(0)
         };
 
         self.cr3 = p_table.phys_addr().0;
         self.user_fs_base = tls_ptr.0;
-        self.user_stack = Some(0x8000_0000_0000);
+        self.user_stack = Some(stack_top.0);
 
         self.ctx = Unique::dangling();
 
@@ -521,7 +577,14 @@ impl Task {
             activate_table(p_table);
         }
 
-        // Prepare user stack program arguments
+        if let Some(p4) = shared_p4 {
+            p4.unref_table_with(|p| p.deallocate_user());
+        }
+
+        // Prepare user stack program arguments, the stack has just been mapped so we can write
+        // to it directly
+        let access = UserAccess::new();
+
         let mut helper = StackHelper::new(&mut user_stack);
 
         let mut envp = Vec::<u64>::new();
@@ -552,7 +615,7 @@ impl Task {
         }
 
         unsafe {
-            helper.write_aux(hdr, base_addr, path_ptr);
+            helper.write_aux(hdr, base_addr, path_ptr, vm.vdso());
         }
 
         unsafe {
@@ -573,10 +636,34 @@ impl Task {
         dbgln!(arch_task, "exec user stack: {:#x}", helper.current());
         assert_eq!(helper.current() % 16, 0);
 
+        drop(access);
+
+        let mut sys_frame = SyscallFrame {
+            rflags: 0x200,
+            rip: entry.0 as u64,
+            rsp: helper.current(),
+        };
+
+        // Traced process stops before running the new image, so the tracer can take over
+        if current_task_ref().ptrace().is_traced() {
+            let mut regs = RegsFrame::default();
+            let mut rax = 0;
+
+            crate::kernel::task::ptrace::exec_stop(&mut UserFrame::Syscall {
+                sys_frame: &mut sys_frame,
+                regs: &mut regs,
+                rax: &mut rax,
+            });
+        }
+
         unsafe {
             crate::bochs();
             dbgln!(arch_task, "asm_jmp_user");
-            asm_jmp_user(helper.current() as usize, entry.0, 0x200);
+            asm_jmp_user(
+                sys_frame.rsp as usize,
+                sys_frame.rip as usize,
+                sys_frame.rflags as usize,
+            );
         }
     }
 
@@ -600,10 +687,7 @@ impl Task {
     pub fn deallocate_kern(&mut self) {
         self.ctx = Unique::dangling();
 
-        deallocate_order(
-            &Frame::new(MappedAddr(self.stack_top).to_phys()),
-            KERN_STACK_ORDER,
-        );
+        kstack::deallocate(VirtAddr(self.stack_top));
 
         self.stack_top = 0;
     }
@@ -1,3 +1,7 @@
 pub fn unix_timestamp() -> i64 {
     crate::arch::dev::rtc::get_unix_ts()
 }
+
+pub fn set_unix_timestamp(ts: i64) {
+    crate::arch::dev::rtc::set_unix_ts(ts)
+}
@@ -24,19 +24,31 @@ pub fn reset_counter() {
     lapic::reset_timer_counter();
 }
 
+pub fn set_next_event(ns: u64) {
+    lapic::set_timer_ns(ns);
+}
+
 pub fn early_sleep(ms: u64) {
     crate::arch::dev::pit::early_busy_sleep(ms);
 }
 
 pub fn busy_sleep(ns: u64) {
-    crate::arch::dev::hpet::busy_sleep(ns)
+    let c = current_ns() + ns;
+
+    while c > current_ns() {
+        core::hint::spin_loop();
+    }
 }
 
 pub fn current_ns() -> u64 {
-    crate::arch::dev::hpet::current_ns()
+    crate::arch::dev::tsc::current_ns()
 }
 
 fn timer_handler() -> bool {
+    if unsafe { crate::CPU_ID } == 0 {
+        crate::arch::dev::tsc::watchdog();
+    }
+
     let timer = &TIMER;
     if let Some(ref f) = timer.irq().handler {
         (f)();
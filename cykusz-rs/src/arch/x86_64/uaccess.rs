@@ -0,0 +1,81 @@
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+const RFLAGS_AC: u64 = 1 << 18;
+
+pub fn set_smap_enabled(enabled: bool) {
+    SMAP_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+pub fn smap_enabled() -> bool {
+    SMAP_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether a supervisor fault with given rflags was caused by SMAP protection
+pub fn is_smap_violation(rflags: u64) -> bool {
+    smap_enabled() && rflags & RFLAGS_AC == 0
+}
+
+fn rflags() -> u64 {
+    unsafe {
+        let r: u64;
+        asm!("pushfq", "pop {r}", r = out(reg) r);
+        r
+    }
+}
+
+/// Allow kernel to access user pages, returns whether the access was already allowed
+pub fn user_access_begin() -> bool {
+    if !smap_enabled() {
+        return true;
+    }
+
+    if rflags() & RFLAGS_AC != 0 {
+        return true;
+    }
+
+    unsafe {
+        asm!("stac", options(nomem, nostack));
+    }
+
+    false
+}
+
+pub fn user_access_end() {
+    if smap_enabled() {
+        unsafe {
+            asm!("clac", options(nomem, nostack));
+        }
+    }
+}
+
+/// Copy bytes between user and kernel memory, returns number of bytes which were not copied
+/// because of an unresolvable page fault.
+pub unsafe fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    let was_allowed = user_access_begin();
+
+    let res = unsafe { asm_copy_user(dst, src, len) };
+
+    if !was_allowed {
+        user_access_end();
+    }
+
+    res
+}
+
+/// Returns the address execution should continue at if the page fault at ip is recoverable
+pub fn fixup_page_fault(ip: u64) -> Option<u64> {
+    if ip == asm_copy_user_fault as *const () as u64 {
+        Some(asm_copy_user_fixup as *const () as u64)
+    } else {
+        None
+    }
+}
+
+unsafe extern "C" {
+    fn asm_copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+    fn asm_copy_user_fault();
+    fn asm_copy_user_fixup();
+}
@@ -59,7 +59,13 @@ impl<'a> StackHelper<'a> {
         v
     }}
 
-    pub unsafe fn write_aux(&mut self, hdr: &ElfHeader, base_addr: VirtAddr, path_ptr: u64) { unsafe {
+    pub unsafe fn write_aux(
+        &mut self,
+        hdr: &ElfHeader,
+        base_addr: VirtAddr,
+        path_ptr: u64,
+        vdso: Option<VirtAddr>,
+    ) { unsafe {
         let hdr: [(AuxvType, usize); 5] = [
             (AuxvType::AtPhdr, hdr.e_phoff as usize + base_addr.0),
             (AuxvType::AtPhEnt, hdr.e_phentsize as usize),
@@ -70,6 +76,9 @@ impl<'a> StackHelper<'a> {
 
         self.write(0usize); // Make it 16 bytes aligned
         self.write(AuxvType::AtNull);
+        if let Some(vdso) = vdso {
+            self.write((AuxvType::AtSysinfoEhdr, vdso.0));
+        }
         self.write(hdr);
     }}
 }
@@ -0,0 +1,280 @@
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, AtomicU64, Ordering};
+
+use spin::Once;
+use syscall_defs::OpenFlags;
+
+use crate::arch::dev::{hpet, tsc};
+use crate::arch::smp::MAX_CPUS;
+use crate::kernel::fs::dirent::{DirEntry, DirEntryItem};
+use crate::kernel::fs::inode::INode;
+use crate::kernel::fs::pcache::{MMapPage, MMapPageStruct, MappedAccess, PageDirectItemStruct};
+use crate::kernel::mm::virt::PageFlags;
+use crate::kernel::mm::{allocate_order, PhysAddr, PAGE_SIZE};
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::task::filetable::FileHandle;
+use crate::kernel::utils::types::Align;
+
+unsafe extern "C" {
+    static vdso_image_start: u8;
+    static vdso_image_end: u8;
+}
+
+/// Timekeeping data read by the vdso code, layout must match vdso.asm
+#[repr(C)]
+struct VvarData {
+    // Odd while the kernel is updating the data
+    seq: AtomicU64,
+    clock_mode: AtomicU64,
+    // Hpet counter period in femtoseconds
+    hpet_period: AtomicU64,
+    // Difference between unix time and monotonic clock in nanoseconds
+    wall_offset: AtomicU64,
+    // Monotonic time at the last timer tick
+    coarse_ns: AtomicU64,
+    // Tsc clock, same as the kernel one: ns = base_ns + ((tsc - base_tsc) * mult) >> shift
+    tsc_base: AtomicU64,
+    tsc_base_ns: AtomicU64,
+    tsc_mult: AtomicU64,
+    tsc_shift: AtomicU64,
+    // Added to the hpet time after falling back from the tsc
+    hpet_offset: AtomicU64,
+    // Difference between the boot cpu tsc and the tsc of the cpu, indexed by the rdtscp cpu number
+    tsc_offset: [AtomicU64; MAX_CPUS],
+}
+
+// Only coarse clocks are available
+const VCLOCK_NONE: u64 = 0;
+// Monotonic clock is read from the hpet counter
+const VCLOCK_HPET: u64 = 1;
+// Monotonic clock is read from the tsc
+const VCLOCK_TSC: u64 = 2;
+
+/// vvar and hpet register pages mapped right below the vdso image
+const DATA_PAGES: usize = 2;
+
+/// Pages of the vdso mapping: data pages followed by the elf image
+struct VdsoMem {
+    pages: Vec<(PhysAddr, PageFlags)>,
+    sref: Weak<VdsoMem>,
+}
+
+impl MappedAccess for VdsoMem {
+    fn get_mmap_page(&self, offset: usize, _size_check: bool) -> Option<MMapPageStruct> {
+        let &(page, flags) = self.pages.get(offset / PAGE_SIZE)?;
+
+        Some(MMapPageStruct(MMapPage::Direct(PageDirectItemStruct::new(
+            page,
+            offset.align_down(PAGE_SIZE),
+            flags,
+        ))))
+    }
+}
+
+impl INode for VdsoMem {
+    fn as_mappable(&self) -> Option<Arc<dyn MappedAccess>> {
+        Some(self.sref.upgrade().unwrap())
+    }
+}
+
+struct Vdso {
+    mem: DirEntryItem,
+    data: &'static VvarData,
+    image_len: usize,
+    // Clock modes usable by the vdso, coarse clocks are used otherwise
+    hpet_mode: u64,
+    tsc_mode: u64,
+}
+
+static VDSO: Once<Vdso> = Once::new();
+
+static WRITER: Spin<()> = Spin::new(());
+
+pub struct VdsoMapping {
+    pub file: Arc<FileHandle>,
+    pub data_len: usize,
+    pub image_len: usize,
+}
+
+fn alloc_page() -> PhysAddr {
+    let mut frame = allocate_order(0).expect("vdso: failed to alloc page");
+    frame.clear();
+
+    // Hold an extra reference, so the page is not freed when the last process unmaps it
+    frame
+        .address()
+        .to_phys_page()
+        .expect("vdso: invalid page")
+        .inc_vm_use_count();
+
+    frame.address()
+}
+
+pub fn init() {
+    let image = unsafe {
+        let start = &vdso_image_start as *const u8;
+        let end = &vdso_image_end as *const u8;
+
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+
+    let vvar = alloc_page();
+
+    let data = unsafe { &*(vvar.to_mapped().0 as *const VvarData) };
+
+    let mut pages = Vec::new();
+
+    pages.push((vvar, PageFlags::empty()));
+
+    let hpet_mode = match hpet::base() {
+        Some(base) if base.0.is_multiple_of(PAGE_SIZE) => {
+            pages.push((base, PageFlags::NO_CACHE));
+
+            data.hpet_period.store(hpet::period(), Ordering::Relaxed);
+
+            VCLOCK_HPET
+        }
+        _ => {
+            // Keep the layout, vdso won't touch this page without hpet clock mode
+            pages.push((vvar, PageFlags::empty()));
+
+            VCLOCK_NONE
+        }
+    };
+
+    data.clock_mode.store(VCLOCK_NONE, Ordering::Relaxed);
+
+    for chunk in image.chunks(PAGE_SIZE) {
+        let page = alloc_page();
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                chunk.as_ptr(),
+                page.to_mapped().0 as *mut u8,
+                chunk.len(),
+            );
+        }
+
+        pages.push((page, PageFlags::empty()));
+    }
+
+    let now = crate::kernel::timer::current_ns();
+
+    let wall_offset = crate::kernel::time::wall_offset();
+
+    data.wall_offset
+        .store(wall_offset as u64, Ordering::Relaxed);
+    data.coarse_ns.store(now, Ordering::Relaxed);
+
+    let mem = Arc::<VdsoMem>::new_cyclic(|me| VdsoMem {
+        pages,
+        sref: me.clone(),
+    });
+
+    VDSO.call_once(|| Vdso {
+        mem: DirEntry::inode_wrap(mem),
+        data,
+        image_len: image.len().align_up(PAGE_SIZE),
+        hpet_mode,
+        tsc_mode: if tsc::has_rdtscp() {
+            VCLOCK_TSC
+        } else {
+            VCLOCK_NONE
+        },
+    });
+
+    tsc::publish_vdso();
+}
+
+/// Updates the data between the seq increments, so the vdso retries reading it meanwhile
+fn write(fun: impl FnOnce(&Vdso)) {
+    let Some(vdso) = VDSO.get() else {
+        return;
+    };
+
+    let _lock = WRITER.lock_irq();
+
+    let data = vdso.data;
+
+    let seq = data.seq.load(Ordering::Relaxed);
+
+    data.seq.store(seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    fun(vdso);
+
+    data.seq.store(seq + 2, Ordering::Release);
+}
+
+/// Updates timekeeping data, called from the timer interrupt
+pub fn update() {
+    let Some(vdso) = VDSO.get() else {
+        return;
+    };
+
+    // Data is updated on every cpu tick, skip if other cpu is doing it right now
+    let Some(_lock) = WRITER.try_lock() else {
+        return;
+    };
+
+    let data = vdso.data;
+
+    let seq = data.seq.load(Ordering::Relaxed);
+
+    data.seq.store(seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+
+    data.coarse_ns
+        .store(crate::kernel::timer::current_ns(), Ordering::Relaxed);
+
+    data.seq.store(seq + 2, Ordering::Release);
+}
+
+/// Publishes the wall clock offset after the time was set
+pub fn set_wall_offset(offset: i64) {
+    write(|vdso| {
+        vdso.data
+            .wall_offset
+            .store(offset as u64, Ordering::Relaxed);
+    });
+}
+
+/// Publishes the tsc clock, called when the kernel updates its rate
+pub fn set_tsc_clock(base_tsc: u64, base_ns: u64, mult: u64, shift: u32) {
+    write(|vdso| {
+        let data = vdso.data;
+
+        data.tsc_base.store(base_tsc, Ordering::Relaxed);
+        data.tsc_base_ns.store(base_ns, Ordering::Relaxed);
+        data.tsc_mult.store(mult, Ordering::Relaxed);
+        data.tsc_shift.store(shift as u64, Ordering::Relaxed);
+        data.clock_mode.store(vdso.tsc_mode, Ordering::Relaxed);
+    });
+}
+
+pub fn set_tsc_offset(cpu: usize, offset: i64) {
+    write(|vdso| {
+        vdso.data.tsc_offset[cpu].store(offset as u64, Ordering::Relaxed);
+    });
+}
+
+/// Switches to the hpet, offset keeps the time continuous with the tsc clock used before
+pub fn set_hpet_clock(offset: i64) {
+    write(|vdso| {
+        let data = vdso.data;
+
+        data.hpet_offset.store(offset as u64, Ordering::Relaxed);
+        data.clock_mode.store(vdso.hpet_mode, Ordering::Relaxed);
+    });
+}
+
+pub fn mapping() -> Option<VdsoMapping> {
+    let vdso = VDSO.get()?;
+
+    Some(VdsoMapping {
+        file: FileHandle::new(0, vdso.mem.clone(), OpenFlags::RDONLY, 0),
+        data_len: DATA_PAGES * PAGE_SIZE,
+        image_len: vdso.image_len,
+    })
+}
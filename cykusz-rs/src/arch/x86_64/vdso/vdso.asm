@@ -0,0 +1,195 @@
+; vDSO code, linked into a shared object with vdso.ld and embedded into the kernel image.
+; Kernel maps vvar and hpet pages right below the vdso, see arch/x86_64/vdso.rs
+
+global clock_gettime:function
+global __vdso_clock_gettime:function
+global gettimeofday:function
+global __vdso_gettimeofday:function
+
+extern vvar_page
+extern hpet_page
+
+; VvarData layout
+VVAR_SEQ            equ 0
+VVAR_CLOCK_MODE     equ 8
+VVAR_HPET_PERIOD    equ 16
+VVAR_WALL_OFFSET    equ 24
+VVAR_COARSE_NS      equ 32
+VVAR_TSC_BASE       equ 40
+VVAR_TSC_BASE_NS    equ 48
+VVAR_TSC_MULT       equ 56
+VVAR_TSC_SHIFT      equ 64
+VVAR_HPET_OFFSET    equ 72
+VVAR_TSC_OFFSET     equ 80
+
+VCLOCK_HPET         equ 1
+VCLOCK_TSC          equ 2
+
+HPET_COUNTER        equ 0xF0
+
+CLOCK_REALTIME          equ 0
+CLOCK_MONOTONIC         equ 1
+CLOCK_MONOTONIC_RAW     equ 4
+CLOCK_REALTIME_COARSE   equ 5
+CLOCK_MONOTONIC_COARSE  equ 6
+CLOCK_BOOTTIME          equ 7
+
+READ_WALL           equ 1
+READ_COARSE         equ 2
+
+EINVAL              equ 22
+
+section .text
+bits 64
+default rel
+
+; r9 - READ_* flags
+; returns time in ns in rax, clobbers rcx, rdx, r8
+read_time:
+.retry:
+    mov r8, [vvar_page + VVAR_SEQ]
+    test r8, 1
+    jnz .wait
+
+    test r9, READ_COARSE
+    jnz .coarse
+
+    cmp qword [vvar_page + VVAR_CLOCK_MODE], VCLOCK_TSC
+    je .tsc
+
+    cmp qword [vvar_page + VVAR_CLOCK_MODE], VCLOCK_HPET
+    jne .coarse
+
+    ; ns = counter * period_fs / 1000000 + hpet_offset
+    mov rax, [hpet_page + HPET_COUNTER]
+    mul qword [vvar_page + VVAR_HPET_PERIOD]
+    mov rcx, 1000000
+    div rcx
+    add rax, [vvar_page + VVAR_HPET_OFFSET]
+    jmp .wall
+
+.tsc:
+    ; tsc = rdtscp + tsc_offset[cpu], cpu number is in ecx
+    rdtscp
+    shl rdx, 32
+    or rax, rdx
+    and ecx, 0xff
+    lea rdx, [vvar_page + VVAR_TSC_OFFSET]
+    add rax, [rdx + rcx * 8]
+
+    ; ns = base_ns + ((tsc - base_tsc) * mult) >> shift
+    sub rax, [vvar_page + VVAR_TSC_BASE]
+    jb .tsc_behind
+
+    mul qword [vvar_page + VVAR_TSC_MULT]
+    mov rcx, [vvar_page + VVAR_TSC_SHIFT]
+    shrd rax, rdx, cl
+    add rax, [vvar_page + VVAR_TSC_BASE_NS]
+    jmp .wall
+
+.tsc_behind:
+    ; tsc of this cpu can be slightly behind the base: ns = base_ns - ((base_tsc - tsc) * mult) >> shift
+    neg rax
+    mul qword [vvar_page + VVAR_TSC_MULT]
+    mov rcx, [vvar_page + VVAR_TSC_SHIFT]
+    shrd rax, rdx, cl
+    mov rdx, [vvar_page + VVAR_TSC_BASE_NS]
+    sub rdx, rax
+    mov eax, 0
+    cmovae rax, rdx
+    jmp .wall
+
+.coarse:
+    mov rax, [vvar_page + VVAR_COARSE_NS]
+
+.wall:
+    test r9, READ_WALL
+    jz .check
+
+    add rax, [vvar_page + VVAR_WALL_OFFSET]
+
+.check:
+    ; kernel updated the data while we were reading it
+    cmp r8, [vvar_page + VVAR_SEQ]
+    jne .retry
+
+    ret
+
+.wait:
+    pause
+    jmp .retry
+
+; int clock_gettime(clockid_t clk, struct timespec *ts)
+clock_gettime:
+__vdso_clock_gettime:
+    xor r9, r9
+
+    cmp edi, CLOCK_REALTIME
+    je .realtime
+    cmp edi, CLOCK_MONOTONIC
+    je .read
+    cmp edi, CLOCK_MONOTONIC_RAW
+    je .read
+    cmp edi, CLOCK_BOOTTIME
+    je .read
+    cmp edi, CLOCK_REALTIME_COARSE
+    je .realtime_coarse
+    cmp edi, CLOCK_MONOTONIC_COARSE
+    je .coarse
+
+    mov rax, -EINVAL
+    ret
+
+.realtime_coarse:
+    or r9, READ_COARSE
+.realtime:
+    or r9, READ_WALL
+    jmp .read
+.coarse:
+    or r9, READ_COARSE
+
+.read:
+    call read_time
+
+    xor edx, edx
+    mov rcx, 1000000000
+    div rcx
+
+    mov [rsi], rax      ; tv_sec
+    mov [rsi + 8], rdx  ; tv_nsec
+
+    xor eax, eax
+    ret
+
+; int gettimeofday(struct timeval *tv, struct timezone *tz)
+gettimeofday:
+__vdso_gettimeofday:
+    test rdi, rdi
+    jz .tz
+
+    mov r9, READ_WALL
+    call read_time
+
+    xor edx, edx
+    mov rcx, 1000000000
+    div rcx
+
+    mov [rdi], rax      ; tv_sec
+
+    mov rax, rdx
+    xor edx, edx
+    mov rcx, 1000
+    div rcx
+
+    mov [rdi + 8], rax  ; tv_usec
+
+.tz:
+    test rsi, rsi
+    jz .done
+
+    ; tz_minuteswest and tz_dsttime, we are always in UTC
+    mov qword [rsi], 0
+
+.done:
+    xor eax, eax
+    ret
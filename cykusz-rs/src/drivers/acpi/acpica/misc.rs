@@ -7,7 +7,7 @@ use acpica::*;
 #[linkage = "external"]
 extern "C" fn AcpiOsGetTimer() -> UINT64 {
     //100s ns
-    crate::arch::dev::hpet::current_ns() as i64 / 100
+    crate::kernel::timer::current_ns() as i64 / 100
 }
 
 #[unsafe(no_mangle)]
@@ -90,6 +90,7 @@ pub enum AuxvType {
     AtPhNum = 5,
     AtEntry = 9,
     AtExecFn = 31,
+    AtSysinfoEhdr = 33,
 }
 
 impl Default for AuxvType {
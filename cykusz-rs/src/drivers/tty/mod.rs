@@ -19,13 +19,13 @@ use crate::kernel::fs::poll::PollTable;
 use crate::kernel::fs::vfs;
 use crate::kernel::fs::vfs::FsError;
 use crate::kernel::kbd::KeyListener;
+use crate::kernel::mm::uaccess::{read_user, write_user};
 use crate::kernel::mm::VirtAddr;
 use crate::kernel::sched::current_task_ref;
 use crate::kernel::session::{sessions, Group};
 use crate::kernel::sync::{LockApi, Spin, SpinGuard};
 use crate::kernel::task::ArcTask;
 use crate::kernel::tty::TerminalDevice;
-use crate::kernel::utils::types::Prefault;
 use crate::kernel::utils::wait_queue::{WaitQueue, WaitQueueFlags};
 
 use self::output::OutputBuffer;
@@ -596,16 +596,16 @@ impl INode for Tty {
                     return Err(FsError::NoPermission);
                 }
 
-                let gid = unsafe { VirtAddr(arg).read_mut::<u32>() };
+                let gid = tty().fg_group.lock().as_ref().map(|fg| fg.id() as u32);
 
-                if let Some(fg) = &*tty().fg_group.lock() {
-                    *gid = fg.id() as u32;
+                if let Some(gid) = gid {
+                    write_user(VirtAddr(arg), &gid)?;
                 }
 
                 return Ok(0);
             }
             tty::TIOCSPGRP => {
-                let gid = unsafe { VirtAddr(arg).read::<u32>() };
+                let gid = read_user::<u32>(VirtAddr(arg))?;
 
                 let task = current_task_ref();
 
@@ -633,19 +633,19 @@ impl INode for Tty {
             }
             tty::TIOCSWINSZ => Err(FsError::NoTty),
             tty::TIOCGWINSZ => {
-                let winsize =
-                    unsafe { VirtAddr(arg).read_mut::<syscall_defs::ioctl::tty::WinSize>() };
+                let mut winsize = read_user::<syscall_defs::ioctl::tty::WinSize>(VirtAddr(arg))?;
 
                 let (cols, rows) = video().dimensions();
 
                 winsize.ws_col = cols as u16;
                 winsize.ws_row = rows as u16;
 
+                write_user(VirtAddr(arg), &winsize)?;
+
                 Ok(0)
             }
             ioctl @ (tty::TCSETS | tty::TCSETSW | tty::TCSETSF) => {
-                let termios =
-                    unsafe { VirtAddr(arg).read_ref::<syscall_defs::ioctl::tty::Termios>() };
+                let termios = read_user::<syscall_defs::ioctl::tty::Termios>(VirtAddr(arg))?;
 
                 if let Some(fg) = &*self.fg_group.lock_irq() {
                     let task = current_task_ref();
@@ -659,7 +659,7 @@ impl INode for Tty {
                 logln3!("termios TCSETS 0x{:x}", termios.c_lflag);
                 logln3!("{:?}", termios);
 
-                *self.termios.lock_irq() = *termios;
+                *self.termios.lock_irq() = termios;
 
                 if ioctl == tty::TCSETSF {
                     self.buffer.lock_irq().flush();
@@ -668,15 +668,12 @@ impl INode for Tty {
                 Ok(0)
             }
             tty::TCGETS => {
-                let termios =
-                    unsafe { VirtAddr(arg).read_mut::<syscall_defs::ioctl::tty::Termios>() };
-
-                (termios as &tty::Termios).prefault();
+                let termios = *self.termios.lock_irq();
 
                 logln!("termios TCGETS 0x{:x}", termios.c_lflag);
                 logln!("{:?}", termios);
 
-                *termios = *self.termios.lock_irq();
+                write_user(VirtAddr(arg), &termios)?;
 
                 Ok(0)
             }
@@ -14,6 +14,7 @@ use crate::kernel::device::Device;
 use crate::kernel::fs::inode::INode;
 use crate::kernel::fs::pcache::{MMapPage, MMapPageStruct, MappedAccess, PageDirectItemStruct};
 use crate::kernel::fs::vfs::FsError;
+use crate::kernel::mm::uaccess::write_user;
 use crate::kernel::mm::virt::PageFlags;
 use crate::kernel::mm::{map_to_flags, virt, MappedAddr, PhysAddr, PAGE_SIZE};
 use crate::kernel::sync::{LockApi, Spin};
@@ -617,15 +618,19 @@ pub struct FbDevice {
 impl INode for FbDevice {
     fn ioctl(&self, cmd: usize, arg: usize) -> crate::kernel::fs::vfs::Result<usize> {
         if cmd == syscall_defs::ioctl::fb::GFBINFO {
-            let info = unsafe { VirtAddr(arg).read_mut::<syscall_defs::ioctl::fb::FbInfo>() };
-
-            let fb = fb();
-            let state = fb.state.lock();
+            let info = {
+                let fb = fb();
+                let state = fb.state.lock();
+
+                syscall_defs::ioctl::fb::FbInfo {
+                    bpp: 32,
+                    pitch: state.pitch as u64 * 4,
+                    width: state.width as u64,
+                    height: state.height as u64,
+                }
+            };
 
-            info.bpp = 32;
-            info.pitch = state.pitch as u64 * 4;
-            info.width = state.width as u64;
-            info.height = state.height as u64;
+            write_user(VirtAddr(arg), &info)?;
 
             Ok(0)
         } else {
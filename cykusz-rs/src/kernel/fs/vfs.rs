@@ -19,6 +19,9 @@ pub enum FsError {
     Interrupted,
     NoSuchDevice,
     WouldBlock,
+    Fault,
+    TooManyFiles,
+    FileTooBig,
 }
 
 impl From<FsError> for syscall_defs::SyscallError {
@@ -39,6 +42,9 @@ impl From<FsError> for syscall_defs::SyscallError {
             FsError::Interrupted => SyscallError::EINTR,
             FsError::NoSuchDevice => SyscallError::ENXIO,
             FsError::WouldBlock => SyscallError::EAGAIN,
+            FsError::Fault => SyscallError::EFAULT,
+            FsError::TooManyFiles => SyscallError::EMFILE,
+            FsError::FileTooBig => SyscallError::EFBIG,
         }
     }
 }
@@ -51,8 +51,14 @@ impl FutexContainer {
     }
 
     pub fn wait(&self, addr: VirtAddr, expected: u32) -> SyscallResult {
+        if !addr.is_user() {
+            return Err(SyscallError::EFAULT);
+        }
+
         if let Some(phys) = addr.to_phys_pagewalk() {
-            let atom = unsafe { addr.read_ref::<AtomicU32>() };
+            // Access the futex through kernel mapping of the physical page, user pages are
+            // protected by SMAP
+            let atom = unsafe { phys.to_virt().read_ref::<AtomicU32>() };
 
             if atom.load(Ordering::SeqCst) == expected {
                 let futex = self.get_alloc(phys);
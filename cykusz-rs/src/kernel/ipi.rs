@@ -21,6 +21,7 @@ pub enum TaskIpiOperation {
     WakeUp,
     WakeUpNext,
     Continue,
+    Requeue,
 }
 
 intrusive_adapter!(TaskIpiAdapter = Box<TaskIpi> : TaskIpi { link => LinkedListLink });
@@ -114,6 +115,16 @@ pub fn queue(task: &ArcTask) {
     }
 }
 
+pub fn requeue(task: &ArcTask) {
+    let cpu = task.on_cpu();
+
+    if cpu == crate::cpu_id() as usize {
+        kernel::sched::internal().requeue(task.clone());
+    } else {
+        task_ipi(TaskIpiOperation::Requeue, task);
+    }
+}
+
 static COUNT: AtomicU64 = AtomicU64::new(0);
 
 fn handle_ipi_task_exec(cmd: TaskIpiOperation, task: &ArcTask) {
@@ -130,6 +141,9 @@ fn handle_ipi_task_exec(cmd: TaskIpiOperation, task: &ArcTask) {
         TaskIpiOperation::Continue => {
             kernel::sched::internal().cont(task.clone());
         }
+        TaskIpiOperation::Requeue => {
+            kernel::sched::internal().requeue(task.clone());
+        }
     }
 }
 
@@ -131,7 +131,7 @@ pub fn heap_mem() -> usize {
     let heap = heap();
     let used = heap.lock_irq().used();
 
-    used
+    used + slab::used_mem()
 }
 
 impl LockedHeap {
@@ -202,10 +202,14 @@ unsafe impl GlobalAlloc for LockedHeap {
         //} else {
         //    None
         //};
-        let ptr = self
-            .allocate(&mut self.0.lock_irq(), layout)
-            .ok()
-            .map_or(0 as *mut u8, |alloc| alloc.as_ptr());
+        let ptr = if slab::is_slab_layout(&layout) {
+            ALLOCED_MEM.fetch_add(layout.size(), Ordering::SeqCst);
+            slab::allocate(layout)
+        } else {
+            self.allocate(&mut self.0.lock_irq(), layout)
+                .ok()
+                .map_or(0 as *mut u8, |alloc| alloc.as_ptr())
+        };
 
         leak_catcher().track_alloc(ptr as usize, layout);
         if HEAP_DEBUG.load(Ordering::SeqCst) {
@@ -226,9 +230,13 @@ unsafe impl GlobalAlloc for LockedHeap {
             println!("Dealloc {:p} {}", ptr, layout.size());
         };
         ALLOCED_MEM.fetch_sub(layout.size(), Ordering::SeqCst);
-        self.0
-            .lock_irq()
-            .deallocate(NonNull::new_unchecked(ptr), layout)
+        if slab::is_slab_layout(&layout) {
+            slab::deallocate(ptr, layout)
+        } else {
+            self.0
+                .lock_irq()
+                .deallocate(NonNull::new_unchecked(ptr), layout)
+        }
     }}
 }
 
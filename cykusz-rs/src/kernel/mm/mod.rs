@@ -4,25 +4,62 @@ pub use crate::arch::mm::phys::deallocate;
 pub use crate::arch::mm::phys::deallocate_order;
 pub use crate::arch::mm::phys::free_mem;
 pub use crate::arch::mm::phys::used_mem;
+pub use crate::arch::mm::virt::collapse_hugepage;
 pub use crate::arch::mm::virt::get_flags;
+pub use crate::arch::mm::virt::insert_flags;
+pub use crate::arch::mm::virt::is_hugepage;
 pub use crate::arch::mm::virt::map;
 pub use crate::arch::mm::virt::map_flags;
+pub use crate::arch::mm::virt::map_hugepage_flags;
 pub use crate::arch::mm::virt::map_to;
 pub use crate::arch::mm::virt::map_to_flags;
+pub use crate::arch::mm::virt::remove_flags;
+pub use crate::arch::mm::virt::split_hugepage;
 pub use crate::arch::mm::virt::to_phys;
 pub use crate::arch::mm::virt::unmap;
+pub use crate::arch::mm::virt::unmap_hugepage;
 pub use crate::arch::mm::virt::update_flags;
+pub use crate::arch::mm::HUGE_PAGE_SIZE;
 pub use crate::arch::mm::MAX_USER_ADDR;
 pub use crate::arch::mm::MMAP_USER_ADDR;
 pub use crate::arch::mm::PAGE_SIZE;
 pub use crate::arch::mm::{MappedAddr, PhysAddr, VirtAddr};
 
+use spin::Once;
+
 pub use self::frame::Frame;
 
 mod frame;
 pub mod heap;
+pub mod slab;
+pub mod uaccess;
 pub mod virt;
 
 pub fn init() {
     heap::init();
 }
+
+static ZERO_PAGE: Once<PhysAddr> = Once::new();
+
+/// Shared read only page of zeros, mapped on read faults of private anonymous memory
+pub fn zero_page() -> PhysAddr {
+    *ZERO_PAGE.call_once(|| {
+        let mut frame = allocate_order(0).expect("Failed to allocate zero page");
+        frame.clear();
+
+        // Hold an extra reference, so the page is never freed and always copied on write
+        frame
+            .address()
+            .to_phys_page()
+            .expect("Invalid zero page")
+            .inc_vm_use_count();
+
+        frame.address()
+    })
+}
+
+pub fn is_zero_page(addr: PhysAddr) -> bool {
+    ZERO_PAGE
+        .get()
+        .is_some_and(|&zero| zero == addr.align_down(PAGE_SIZE))
+}
@@ -0,0 +1,363 @@
+use core::alloc::Layout;
+use core::ptr::null_mut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Once;
+
+use crate::kernel::mm::{allocate_order, deallocate_order, Frame, MappedAddr, PAGE_SIZE};
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::utils::PerCpu;
+
+/// Object sizes served by slab caches, bigger allocations go to the heap
+const SLAB_SIZES: [usize; 7] = [16, 32, 64, 128, 256, 512, 1024];
+/// Physical allocation order of a single slab for each size class
+const SLAB_ORDERS: [usize; 7] = [0, 0, 0, 0, 0, 2, 2];
+const SLAB_CLASSES: usize = SLAB_SIZES.len();
+
+/// Number of completely free slabs kept in the cache before returning memory to the system
+const MAX_EMPTY_SLABS: usize = 2;
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// Slab header placed at the beginning of the slab memory, followed by the objects
+struct Slab {
+    cache: *const SlabCache,
+    free: *mut FreeObject,
+    inuse: usize,
+    prev: *mut Slab,
+    next: *mut Slab,
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct SlabStats {
+    pub obj_size: usize,
+    pub slabs: usize,
+    pub inuse: usize,
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+struct SlabCacheData {
+    // Slabs with at least one free object
+    partial: *mut Slab,
+    empty: usize,
+    stats: SlabStats,
+}
+
+unsafe impl Send for SlabCacheData {}
+
+struct SlabCache {
+    order: usize,
+    data: Spin<SlabCacheData>,
+}
+
+impl SlabCache {
+    const fn new(class: usize) -> SlabCache {
+        SlabCache {
+            order: SLAB_ORDERS[class],
+            data: Spin::new(SlabCacheData {
+                partial: null_mut(),
+                empty: 0,
+                stats: SlabStats {
+                    obj_size: SLAB_SIZES[class],
+                    slabs: 0,
+                    inuse: 0,
+                    allocs: 0,
+                    frees: 0,
+                },
+            }),
+        }
+    }
+
+    fn slab_size(&self) -> usize {
+        PAGE_SIZE << self.order
+    }
+
+    fn first_object_offset(obj_size: usize) -> usize {
+        // Keep objects naturally aligned to their size
+        size_of::<Slab>().next_multiple_of(obj_size)
+    }
+
+    fn new_slab(&self, data: &mut SlabCacheData) -> Option<*mut Slab> {
+        let frame = allocate_order(self.order)?;
+
+        let start = frame.address_mapped().0;
+        let obj_size = data.stats.obj_size;
+
+        let slab = start as *mut Slab;
+
+        let mut free: *mut FreeObject = null_mut();
+
+        // Build free list so objects are handed out in address order
+        let first = start + Self::first_object_offset(obj_size);
+        let count = (self.slab_size() - (first - start)) / obj_size;
+
+        for i in (0..count).rev() {
+            let obj = (first + i * obj_size) as *mut FreeObject;
+
+            unsafe {
+                (*obj).next = free;
+            }
+
+            free = obj;
+        }
+
+        unsafe {
+            slab.write(Slab {
+                cache: self as *const SlabCache,
+                free,
+                inuse: 0,
+                prev: null_mut(),
+                next: null_mut(),
+            });
+        }
+
+        data.stats.slabs += 1;
+
+        Some(slab)
+    }
+
+    fn free_slab(&self, data: &mut SlabCacheData, slab: *mut Slab) {
+        data.stats.slabs -= 1;
+
+        deallocate_order(&Frame::new(MappedAddr(slab as usize).to_phys()), self.order);
+    }
+
+    fn link(data: &mut SlabCacheData, slab: *mut Slab) {
+        unsafe {
+            (*slab).prev = null_mut();
+            (*slab).next = data.partial;
+
+            if !data.partial.is_null() {
+                (*data.partial).prev = slab;
+            }
+        }
+
+        data.partial = slab;
+    }
+
+    fn unlink(data: &mut SlabCacheData, slab: *mut Slab) {
+        unsafe {
+            if (*slab).prev.is_null() {
+                data.partial = (*slab).next;
+            } else {
+                (*(*slab).prev).next = (*slab).next;
+            }
+
+            if !(*slab).next.is_null() {
+                (*(*slab).next).prev = (*slab).prev;
+            }
+
+            (*slab).prev = null_mut();
+            (*slab).next = null_mut();
+        }
+    }
+
+    fn alloc(&self) -> *mut u8 {
+        let mut data = self.data.lock_irq();
+
+        if data.partial.is_null() {
+            let Some(slab) = self.new_slab(&mut data) else {
+                return null_mut();
+            };
+
+            Self::link(&mut data, slab);
+
+            data.empty += 1;
+        }
+
+        let slab = data.partial;
+
+        unsafe {
+            let obj = (*slab).free;
+
+            (*slab).free = (*obj).next;
+
+            if (*slab).inuse == 0 {
+                data.empty -= 1;
+            }
+
+            (*slab).inuse += 1;
+
+            if (*slab).free.is_null() {
+                Self::unlink(&mut data, slab);
+            }
+
+            data.stats.inuse += 1;
+            data.stats.allocs += 1;
+
+            obj as *mut u8
+        }
+    }
+
+    fn dealloc(&self, slab: *mut Slab, ptr: *mut u8) {
+        let mut data = self.data.lock_irq();
+
+        unsafe {
+            let obj = ptr as *mut FreeObject;
+
+            let was_full = (*slab).free.is_null();
+
+            (*obj).next = (*slab).free;
+            (*slab).free = obj;
+
+            if was_full {
+                Self::link(&mut data, slab);
+            }
+
+            (*slab).inuse -= 1;
+
+            data.stats.inuse -= 1;
+            data.stats.frees += 1;
+
+            if (*slab).inuse == 0 {
+                if data.empty >= MAX_EMPTY_SLABS {
+                    Self::unlink(&mut data, slab);
+
+                    self.free_slab(&mut data, slab);
+                } else {
+                    data.empty += 1;
+                }
+            }
+        }
+    }
+
+    fn stats(&self) -> SlabStats {
+        self.data.lock_irq().stats
+    }
+}
+
+struct SlabCaches {
+    caches: [SlabCache; SLAB_CLASSES],
+}
+
+impl SlabCaches {
+    const fn new() -> SlabCaches {
+        SlabCaches {
+            caches: [
+                SlabCache::new(0),
+                SlabCache::new(1),
+                SlabCache::new(2),
+                SlabCache::new(3),
+                SlabCache::new(4),
+                SlabCache::new(5),
+                SlabCache::new(6),
+            ],
+        }
+    }
+}
+
+// Used until per cpu caches are available (all cpus need to have their TLS initialised)
+static GLOBAL_CACHES: SlabCaches = SlabCaches::new();
+
+struct PerCpuCaches {
+    caches: PerCpu<SlabCaches>,
+}
+
+unsafe impl Sync for PerCpuCaches {}
+unsafe impl Send for PerCpuCaches {}
+
+static PERCPU_CACHES: Once<PerCpuCaches> = Once::new();
+
+static SLAB_MEM: AtomicUsize = AtomicUsize::new(0);
+
+fn size_class(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+
+    SLAB_SIZES.iter().position(|&s| size <= s)
+}
+
+fn caches() -> &'static SlabCaches {
+    if let Some(percpu) = PERCPU_CACHES.get() {
+        percpu.caches.this_cpu()
+    } else {
+        &GLOBAL_CACHES
+    }
+}
+
+/// Switch to per cpu caches, must be called after all the cpus are started
+pub fn init_percpu() {
+    PERCPU_CACHES.call_once(|| PerCpuCaches {
+        caches: PerCpu::new_fn(SlabCaches::new),
+    });
+}
+
+/// Whether allocation with given layout is served by the slab allocator
+pub fn is_slab_layout(layout: &Layout) -> bool {
+    size_class(layout).is_some()
+}
+
+pub fn allocate(layout: Layout) -> *mut u8 {
+    let class = size_class(&layout).expect("slab: invalid size");
+
+    let ptr = caches().caches[class].alloc();
+
+    if !ptr.is_null() {
+        SLAB_MEM.fetch_add(SLAB_SIZES[class], Ordering::Relaxed);
+    }
+
+    ptr
+}
+
+pub fn deallocate(ptr: *mut u8, layout: Layout) {
+    let class = size_class(&layout).expect("slab: invalid size");
+
+    // Slabs are aligned to their size, so header is at the slab start
+    let slab_size = PAGE_SIZE << SLAB_ORDERS[class];
+    let slab = (ptr as usize & !(slab_size - 1)) as *mut Slab;
+
+    // Object might have been allocated on a different cpu, return it to the owning cache
+    let cache = unsafe { &*(*slab).cache };
+
+    cache.dealloc(slab, ptr);
+
+    SLAB_MEM.fetch_sub(SLAB_SIZES[class], Ordering::Relaxed);
+}
+
+/// Memory used by allocated slab objects
+pub fn used_mem() -> usize {
+    SLAB_MEM.load(Ordering::Relaxed)
+}
+
+/// Per size class statistics summed over all the caches
+pub fn stats() -> [SlabStats; SLAB_CLASSES] {
+    let mut res = [SlabStats::default(); SLAB_CLASSES];
+
+    let mut add = |caches: &SlabCaches| {
+        for (r, c) in res.iter_mut().zip(caches.caches.iter()) {
+            let s = c.stats();
+
+            r.obj_size = s.obj_size;
+            r.slabs += s.slabs;
+            r.inuse += s.inuse;
+            r.allocs += s.allocs;
+            r.frees += s.frees;
+        }
+    };
+
+    add(&GLOBAL_CACHES);
+
+    if let Some(percpu) = PERCPU_CACHES.get() {
+        for cpu in 0..crate::kernel::smp::cpu_count() {
+            add(percpu.caches.cpu(cpu as isize));
+        }
+    }
+
+    res
+}
+
+pub fn print_stats() {
+    logln!("Slab usage:");
+    for s in stats().iter() {
+        logln!(
+            "size {:>5} slabs {:>6} inuse {:>8} allocs {:>10} frees {:>10}",
+            s.obj_size,
+            s.slabs,
+            s.inuse,
+            s.allocs,
+            s.frees
+        );
+    }
+}
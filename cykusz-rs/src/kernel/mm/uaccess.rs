@@ -0,0 +1,136 @@
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+
+use syscall_defs::SyscallError;
+
+use crate::arch::uaccess::{copy_user, user_access_begin, user_access_end};
+use crate::kernel::fs::vfs::FsError;
+use crate::kernel::mm::{VirtAddr, MAX_USER_ADDR};
+use crate::kernel::sched::current_task_ref;
+
+/// Invalid user memory was accessed
+#[derive(Debug, Copy, Clone)]
+pub struct UserFault;
+
+impl From<UserFault> for SyscallError {
+    fn from(_: UserFault) -> Self {
+        SyscallError::EFAULT
+    }
+}
+
+impl From<UserFault> for FsError {
+    fn from(_: UserFault) -> Self {
+        FsError::Fault
+    }
+}
+
+pub type Result<T> = core::result::Result<T, UserFault>;
+
+/// Allows kernel to access user memory directly (stac) while in scope.
+/// Must only be used on memory ranges verified by check_user_range
+pub struct UserAccess {
+    was_allowed: bool,
+}
+
+impl UserAccess {
+    pub fn new() -> UserAccess {
+        UserAccess {
+            was_allowed: user_access_begin(),
+        }
+    }
+}
+
+impl Drop for UserAccess {
+    fn drop(&mut self) {
+        if !self.was_allowed {
+            user_access_end();
+        }
+    }
+}
+
+pub fn check_user_range(addr: VirtAddr, len: usize) -> Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+
+    let end = addr.0.checked_add(len).ok_or(UserFault)?;
+
+    if !addr.is_user() || end > MAX_USER_ADDR.0 {
+        Err(UserFault)
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks whether user range is mapped in the current address space with required permissions
+pub fn check_user_access(addr: VirtAddr, len: usize, write: bool) -> Result<()> {
+    check_user_range(addr, len)?;
+
+    if !current_task_ref().vm().check_access(addr, len, write) {
+        return Err(UserFault);
+    }
+
+    Ok(())
+}
+
+pub fn copy_from_user(dst: &mut [u8], src: VirtAddr) -> Result<()> {
+    check_user_range(src, dst.len())?;
+
+    match unsafe { copy_user(dst.as_mut_ptr(), src.0 as *const u8, dst.len()) } {
+        0 => Ok(()),
+        _ => Err(UserFault),
+    }
+}
+
+pub fn copy_to_user(dst: VirtAddr, src: &[u8]) -> Result<()> {
+    check_user_range(dst, src.len())?;
+
+    match unsafe { copy_user(dst.0 as *mut u8, src.as_ptr(), src.len()) } {
+        0 => Ok(()),
+        _ => Err(UserFault),
+    }
+}
+
+pub fn read_user<T: Copy>(src: VirtAddr) -> Result<T> {
+    let mut val = MaybeUninit::<T>::uninit();
+
+    copy_from_user(
+        unsafe { core::slice::from_raw_parts_mut(val.as_mut_ptr() as *mut u8, size_of::<T>()) },
+        src,
+    )?;
+
+    Ok(unsafe { val.assume_init() })
+}
+
+pub fn write_user<T: Copy>(dst: VirtAddr, val: &T) -> Result<()> {
+    copy_to_user(dst, unsafe {
+        core::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>())
+    })
+}
+
+pub fn read_user_slice<T: Copy>(src: VirtAddr, count: usize) -> Result<Vec<T>> {
+    let bytes = count.checked_mul(size_of::<T>()).ok_or(UserFault)?;
+
+    let mut res = Vec::<T>::with_capacity(count);
+
+    copy_from_user(
+        unsafe { core::slice::from_raw_parts_mut(res.as_mut_ptr() as *mut u8, bytes) },
+        src,
+    )?;
+
+    unsafe {
+        res.set_len(count);
+    }
+
+    Ok(res)
+}
+
+pub fn write_user_slice<T: Copy>(dst: VirtAddr, src: &[T]) -> Result<()> {
+    copy_to_user(dst, unsafe {
+        core::slice::from_raw_parts(src.as_ptr() as *const u8, size_of_val(src))
+    })
+}
+
+pub fn read_user_bytes(src: VirtAddr, len: usize) -> Result<Vec<u8>> {
+    read_user_slice::<u8>(src, len)
+}
@@ -11,6 +11,7 @@ pub mod mm;
 pub mod module;
 pub mod net;
 pub mod params;
+pub mod random;
 #[macro_use]
 pub mod sched;
 pub mod session;
@@ -23,4 +24,5 @@ pub mod time;
 pub mod timer;
 pub mod tls;
 pub mod tty;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod vdso;
\ No newline at end of file
@@ -5,6 +5,7 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use crate::kernel::fs::vfs::FsError;
+use crate::kernel::mm::uaccess::{read_user, write_user};
 use crate::kernel::mm::VirtAddr;
 use crate::kernel::net::eth::Eth;
 use crate::kernel::net::ip::Ip4;
@@ -103,11 +104,15 @@ impl NetDevice {
     pub fn ioctl(&self, cmd: usize, arg: usize) -> Result<usize, FsError> {
         match cmd {
             syscall_defs::ioctl::net::SIOCGIFCONF => {
-                let ifreq = unsafe { VirtAddr(arg).read_mut::<IfConf>() };
-                ifreq.ifc_len = core::mem::size_of::<IfReq>() as i32;
-                let buf = ifreq.get_req_array().ok_or(FsError::InvalidParam)?;
+                let mut ifconf = read_user::<IfConf>(VirtAddr(arg))?;
+                if (ifconf.ifc_len as usize) < core::mem::size_of::<IfReq>() {
+                    return Err(FsError::InvalidParam);
+                }
+                ifconf.ifc_len = core::mem::size_of::<IfReq>() as i32;
 
-                let elem = &mut buf[0];
+                let req = VirtAddr(unsafe { ifconf.ifc_ifcu.ifcu_req } as usize);
+
+                let mut elem = read_user::<IfReq>(req)?;
 
                 elem.ifr_name.fill(0);
                 elem.ifr_name[..4].copy_from_slice(b"eth0");
@@ -116,11 +121,15 @@ impl NetDevice {
                 addr.sin_family = SockDomain::AfInet;
                 addr.sin_addr.s_addr = self.ip().into();
 
+                write_user(req, &elem)?;
+                write_user(VirtAddr(arg), &ifconf)?;
+
                 Ok(0)
             }
             syscall_defs::ioctl::net::SIOCGIFFLAGS => {
-                let ifreq = unsafe { VirtAddr(arg).read_mut::<IfReq>() };
+                let mut ifreq = read_user::<IfReq>(VirtAddr(arg))?;
                 ifreq.ifrequ.ifr_flags = IfrFlags::IFF_UP;
+                write_user(VirtAddr(arg), &ifreq)?;
                 Ok(0)
             }
             _ => Err(FsError::NoTty),
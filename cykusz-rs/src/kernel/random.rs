@@ -0,0 +1,42 @@
+use rand::{RngCore, SeedableRng};
+use spin::Once;
+
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::timer::current_ns;
+
+static RNG: Once<Spin<rand::prelude::StdRng>> = Once::new();
+
+fn seed() -> [u8; 32] {
+    let mut seed: [u8; 32] = [0; 32];
+
+    for s in seed.chunks_mut(8) {
+        let val = crate::arch::random::hw_random().unwrap_or(0)
+            ^ crate::arch::random::timestamp()
+            ^ current_ns().rotate_left(32);
+
+        s.copy_from_slice(&val.to_ne_bytes());
+    }
+
+    seed
+}
+
+fn rng() -> &'static Spin<rand::prelude::StdRng> {
+    RNG.call_once(|| Spin::new(rand::prelude::StdRng::from_seed(seed())))
+}
+
+pub fn fill_bytes(buf: &mut [u8]) {
+    rng().lock().fill_bytes(buf);
+}
+
+pub fn next_u64() -> u64 {
+    rng().lock().next_u64()
+}
+
+/// Random number in range [0, max)
+pub fn next_below(max: u64) -> u64 {
+    if max == 0 {
+        0
+    } else {
+        next_u64() % max
+    }
+}
@@ -0,0 +1,217 @@
+use crate::kernel::sched::prio::nice_weight;
+use crate::kernel::sched::queues::{QueueScheduler, RunQueue};
+use crate::kernel::task::{ArcTask, Task, TaskState};
+use crate::kernel::timer::current_ns;
+use alloc::collections::BTreeMap;
+
+/// Period in which every runnable task should get to run once
+const SCHED_LATENCY_NS: u64 = 6_000_000;
+/// Minimum time a task runs before it can be preempted by the tick
+const MIN_GRANULARITY_NS: u64 = 750_000;
+/// Woken task needs to be this much behind the current one to preempt it
+const WAKEUP_GRANULARITY_NS: u64 = 1_000_000;
+
+const NICE_0_WEIGHT: u64 = 1024;
+
+pub type FairScheduler = QueueScheduler<Fair>;
+
+/// Runnable task with the lowest vruntime runs next
+#[derive(Default)]
+pub struct Fair {
+    // Runnable tasks ordered by vruntime with their weight at the time they were queued,
+    // the current task is not in the tree
+    runnable: BTreeMap<(u64, usize), (ArcTask, u64)>,
+
+    // Sum of the weights of the runnable tasks
+    load: u64,
+
+    min_vruntime: u64,
+
+    // Time up to which the current task was charged
+    exec_start: u64,
+
+    // Time at which the current task was switched in
+    slice_start: u64,
+}
+
+impl Fair {
+    fn leftmost_vruntime(&self) -> Option<u64> {
+        self.runnable
+            .first_key_value()
+            .map(|(&(vruntime, _), _)| vruntime)
+    }
+
+    fn update_min_vruntime(&mut self, current: &Task) {
+        let current = if current.state() == TaskState::Runnable {
+            Some(current.vruntime())
+        } else {
+            None
+        };
+
+        let vruntime = match (current, self.leftmost_vruntime()) {
+            (Some(c), Some(l)) => c.min(l),
+            (Some(v), None) | (None, Some(v)) => v,
+            (None, None) => return,
+        };
+
+        // min_vruntime never goes backwards
+        self.min_vruntime = self.min_vruntime.max(vruntime);
+    }
+
+    /// Share of the scheduling period the current task is entitled to
+    fn ideal_slice(&self, current: &Task) -> u64 {
+        let weight = nice_weight(current.nice());
+
+        let nr_running = self.runnable.len() as u64 + 1;
+
+        let period = SCHED_LATENCY_NS.max(nr_running * MIN_GRANULARITY_NS);
+
+        period * weight / (self.load + weight)
+    }
+}
+
+impl RunQueue for Fair {
+    fn is_empty(&self) -> bool {
+        self.runnable.is_empty()
+    }
+
+    fn push(&mut self, task: ArcTask, front: bool) {
+        if front {
+            let front = self.leftmost_vruntime().unwrap_or(self.min_vruntime);
+
+            task.set_vruntime(task.vruntime().min(front.saturating_sub(1)));
+        }
+
+        let weight = nice_weight(task.nice());
+
+        self.load += weight;
+
+        if let Some((prev, _)) = self
+            .runnable
+            .insert((task.vruntime(), task.tid()), (task, weight))
+        {
+            panic!("task {} queued twice", prev.tid());
+        }
+    }
+
+    /// Current task keeps running unless the leftmost task is behind it
+    fn pop(&mut self, current: Option<&Task>) -> Option<ArcTask> {
+        let leftmost = self.leftmost_vruntime()?;
+
+        if current.is_some_and(|c| c.vruntime() <= leftmost) {
+            return None;
+        }
+
+        let (_, (task, weight)) = self.runnable.pop_first()?;
+
+        self.load -= weight;
+
+        Some(task)
+    }
+
+    fn remove(&mut self, task: &Task) -> Option<ArcTask> {
+        let (task, weight) = self.runnable.remove(&(task.vruntime(), task.tid()))?;
+
+        self.load -= weight;
+
+        Some(task)
+    }
+
+    /// Takes the task furthest from running here, it loses the least by moving
+    fn steal(&mut self, cpu: usize) -> Option<ArcTask> {
+        let key = *self
+            .runnable
+            .iter()
+            .rev()
+            .find(|(_, (task, _))| task.can_run_on(cpu))?
+            .0;
+
+        let (task, weight) = self.runnable.remove(&key)?;
+
+        self.load -= weight;
+
+        Some(task)
+    }
+
+    /// Charges the time since the last update to the current task, scaled by its weight
+    fn update_curr(&mut self, current: &Task) {
+        let now = current_ns();
+
+        let delta = now.saturating_sub(self.exec_start);
+
+        self.exec_start = now;
+
+        if current.state() == TaskState::Idle {
+            return;
+        }
+
+        current
+            .set_vruntime(current.vruntime() + delta * NICE_0_WEIGHT / nice_weight(current.nice()));
+
+        self.update_min_vruntime(current);
+    }
+
+    fn preempt_tick(&mut self, current: &Task, _elapsed_ns: u64) -> bool {
+        // Task is not allowed on this cpu anymore, switch so it gets migrated
+        if !current.can_run_on(crate::cpu_id() as usize) {
+            return true;
+        }
+
+        let Some(leftmost) = self.leftmost_vruntime() else {
+            return false;
+        };
+
+        let ideal = self.ideal_slice(current);
+
+        let ran = current_ns().saturating_sub(self.slice_start);
+
+        if ran >= ideal {
+            return true;
+        }
+
+        if ran < MIN_GRANULARITY_NS {
+            return false;
+        }
+
+        current.vruntime().saturating_sub(leftmost) > ideal
+    }
+
+    /// Queued tasks are new or migrated, their vruntime is relative to min_vruntime,
+    /// so new tasks can't monopolise the cpu
+    fn enter(&mut self, task: &Task) {
+        task.set_vruntime(self.min_vruntime + task.vruntime());
+    }
+
+    /// vruntime is made relative to min_vruntime, target cpu rebases it in enter
+    fn leave(&mut self, task: &Task) {
+        task.set_vruntime(task.vruntime().saturating_sub(self.min_vruntime));
+    }
+
+    /// Sleepers get credit of half the latency period, but no more than that,
+    /// so a long sleep doesn't turn into a long cpu burst after wakeup
+    fn woken(&mut self, task: &Task, current: &Task) {
+        let credit = self.min_vruntime.saturating_sub(SCHED_LATENCY_NS / 2);
+
+        task.set_vruntime(task.vruntime().max(credit));
+
+        if current.state() == TaskState::Idle || current.is_rt() {
+            return;
+        }
+
+        if task.vruntime() + WAKEUP_GRANULARITY_NS < current.vruntime() {
+            current.set_to_reschedule(true);
+        }
+    }
+
+    fn switched(&mut self) {
+        self.slice_start = current_ns();
+    }
+
+    fn debug(&self) {
+        dbgln!(sched_v, "min_vruntime: {}", self.min_vruntime);
+
+        for (&(vruntime, tid), &(_, weight)) in &self.runnable {
+            dbgln!(sched_v, "{} vruntime: {} weight: {}", tid, vruntime, weight);
+        }
+    }
+}
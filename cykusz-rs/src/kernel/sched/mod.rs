@@ -9,12 +9,14 @@ use syscall_defs::exec::ExeArgs;
 use syscall_defs::SyscallError;
 
 use crate::kernel::fs::dirent::DirEntryItem;
+use crate::kernel::ipi::TaskIpiOperation;
 use crate::kernel::mm::VirtAddr;
+use crate::kernel::sched::fair::FairScheduler;
 use crate::kernel::sched::round_robin::RRScheduler;
 use crate::kernel::sched::task_container::TaskContainer;
 use crate::kernel::session::sessions;
 use crate::kernel::signal::SignalResult;
-use crate::kernel::task::{ArcTask, Task};
+use crate::kernel::task::{ArcTask, CloneArgs, Task, TaskState};
 
 #[macro_export]
 macro_rules! switch {
@@ -36,7 +38,11 @@ bitflags! {
     }
 }
 
+pub mod prio;
+mod fair;
+mod queues;
 mod round_robin;
+mod rt;
 mod task_container;
 
 static NEW_TASK_ID: AtomicUsize = AtomicUsize::new(1);
@@ -51,6 +57,8 @@ pub fn new_task_tid() -> usize {
 pub trait SchedulerInterface: Send + Sync + DowncastSync {
     fn init(&self) {}
     fn reschedule(&self) -> bool;
+    /// Charges the timer tick to the current task, returns true if it should be preempted
+    fn preempt_tick(&self, elapsed_ns: u64) -> bool;
     fn current_task<'a>(&self) -> &'a Task;
     fn current_id(&self) -> isize {
         self.current_task().tid() as isize
@@ -60,10 +68,14 @@ pub trait SchedulerInterface: Send + Sync + DowncastSync {
     fn wake(&self, task: ArcTask);
     fn wake_as_next(&self, task: ArcTask);
     fn cont(&self, task: ArcTask);
+    /// Moves the task to the queue of its new scheduling policy and priority
+    fn requeue(&self, task: ArcTask);
     fn stop(&self, sig: usize);
     fn exit(&self, status: syscall_defs::waitpid::Status) -> !;
     fn exit_thread(&self) -> !;
     fn debug(&self);
+    /// Handles load balancing requests from other cpus
+    fn balance(&self) {}
 }
 
 impl_downcast!(sync SchedulerInterface);
@@ -76,8 +88,15 @@ struct Scheduler {
 
 impl Scheduler {
     pub fn new() -> Scheduler {
+        // Selected with sched= kernel param, round robin is the default
+        let sched: Arc<dyn SchedulerInterface> =
+            match crate::kernel::params::get("sched").map(|s| s.as_str()) {
+                Some("fair") => FairScheduler::new(),
+                _ => RRScheduler::new(),
+            };
+
         Scheduler {
-            sched: RRScheduler::new(),
+            sched,
 
             tasks: TaskContainer::default(),
         }
@@ -159,6 +178,10 @@ impl Scheduler {
         self.sched.wake_as_next(task);
     }
 
+    fn requeue(&self, task: ArcTask) {
+        self.sched.requeue(task);
+    }
+
     fn stop(&self, sig: usize) {
         let current = current_task_ref();
 
@@ -199,19 +222,25 @@ impl Scheduler {
     }
 
     pub fn fork(&self) -> ArcTask {
+        self.clone_task(&CloneArgs::default())
+    }
+
+    pub fn clone_task(&self, args: &CloneArgs) -> ArcTask {
         let current = self.sched.current_task();
 
-        let forked = current.fork();
+        let cloned = current.clone_task(args);
 
-        dbgln!(task, "fork {} -> {}", current.tid(), forked.tid());
+        dbgln!(task, "clone {} -> {}", current.tid(), cloned.tid());
 
-        self.tasks.register_task(forked.clone());
+        self.tasks.register_task(cloned.clone());
 
-        sessions().register_process(forked.clone());
+        if cloned.is_process_leader() {
+            sessions().register_process(cloned.clone());
+        }
 
-        self.sched.queue_task(forked.clone(), true);
+        self.sched.queue_task(cloned.clone(), true);
 
-        forked
+        cloned
     }
 
     pub fn exec(
@@ -229,6 +258,8 @@ impl Scheduler {
 
             current.exec(exe, args, envs)
         } else {
+            current.strace_finish(Ok(0));
+
             if current
                 .process_leader()
                 .signals()
@@ -273,6 +304,14 @@ impl Scheduler {
         if current.is_process_leader() {
             current.terminate_threads();
 
+            current.release_vm();
+
+            current.release_ptrace();
+
+            current.strace().close();
+
+            current.timers().disarm_all();
+
             current.close_all_files();
 
             self.tasks.remove_task(current.tid());
@@ -306,6 +345,8 @@ impl Scheduler {
 
             self.exit(syscall_defs::waitpid::Status::Exited(0));
         } else {
+            task.release_vm();
+
             self.tasks.remove_task(task.tid());
 
             self.sched.exit_thread();
@@ -316,6 +357,10 @@ impl Scheduler {
         self.tasks.get(tid)
     }
 
+    pub fn user_task_count(&self) -> usize {
+        self.tasks.user_count()
+    }
+
     pub fn queue(&self, task: ArcTask, alloc_cpu: bool) {
         self.sched.queue_task(task, alloc_cpu);
     }
@@ -368,6 +413,12 @@ pub(in crate::kernel::sched) fn finalize() {
     crate::kernel::timer::reset_counter();
 }
 
+/// Idle cpu sleeps until the earliest sleep deadline or timer expiry, without the periodic tick
+pub(in crate::kernel::sched) fn finalize_idle() {
+    crate::kernel::int::finish();
+    crate::kernel::timer::stop_tick();
+}
+
 pub(in crate::kernel::sched) fn register_task(task: &ArcTask) {
     scheduler().register_task(task);
 }
@@ -388,6 +439,67 @@ pub fn reschedule() -> bool {
     scheduler.reschedule()
 }
 
+/// Called from the timer interrupt, preempts the current task once its time slice is used up
+pub fn tick(elapsed_ns: u64) -> bool {
+    let scheduler = scheduler();
+
+    // Idle task gives up the cpu as soon as there is anything else to run
+    if scheduler.current_task().state() != TaskState::Idle
+        && !scheduler.sched.preempt_tick(elapsed_ns)
+    {
+        finalize();
+        return false;
+    }
+
+    reschedule()
+}
+
+/// Mask of the cpus that are online
+pub fn online_cpu_mask() -> u64 {
+    match crate::kernel::smp::cpu_count() {
+        c if c >= 64 => u64::MAX,
+        c => (1u64 << c) - 1,
+    }
+}
+
+/// Picks a cpu allowed by the task mask, tasks are spread over the allowed cpus by tid
+pub(in crate::kernel::sched) fn select_cpu(task: &Task) -> usize {
+    let mask = match task.cpu_mask() & online_cpu_mask() {
+        0 => online_cpu_mask(),
+        m => m,
+    };
+
+    let mut m = mask;
+
+    for _ in 0..task.tid() % mask.count_ones() as usize {
+        m &= m - 1;
+    }
+
+    m.trailing_zeros() as usize
+}
+
+/// Moves a task that is not running on any cpu to a cpu allowed by its mask
+pub(in crate::kernel::sched) fn migrate(task: ArcTask) {
+    let cpu = select_cpu(&task);
+
+    migrate_to(task, cpu);
+}
+
+pub(in crate::kernel::sched) fn migrate_to(task: ArcTask, cpu: usize) {
+    task.set_on_cpu(cpu);
+    // Task is in flight, wakeups only set the pending io flag
+    task.set_state(TaskState::Runnable);
+
+    dbgln!(
+        sched,
+        "migrate task {} to cpu {}",
+        task.tid(),
+        task.on_cpu()
+    );
+
+    crate::kernel::ipi::task_ipi(TaskIpiOperation::Queue, &task);
+}
+
 pub fn internal() -> Arc<dyn SchedulerInterface> {
     scheduler().internal().clone()
 }
@@ -396,6 +508,11 @@ pub fn get_task(tid: usize) -> Option<ArcTask> {
     scheduler().get_task(tid)
 }
 
+/// Number of user tasks (including threads) in the system
+pub fn user_task_count() -> usize {
+    scheduler().user_task_count()
+}
+
 pub fn current_task() -> ArcTask {
     scheduler().current_task().me()
 }
@@ -440,6 +557,10 @@ pub fn wake_as_next(task: ArcTask) {
     scheduler().wake_as_next(task);
 }
 
+pub fn requeue(task: ArcTask) {
+    scheduler().requeue(task);
+}
+
 pub fn stop(sig: usize) {
     scheduler().stop(sig);
 }
@@ -464,6 +585,10 @@ pub fn fork() -> ArcTask {
     scheduler().fork()
 }
 
+pub fn clone_task(args: &CloneArgs) -> ArcTask {
+    scheduler().clone_task(args)
+}
+
 pub fn exec(
     exe: DirEntryItem,
     args: Option<ExeArgs>,
@@ -0,0 +1,61 @@
+use syscall_defs::resource::{RLimitKind, RLIM_INFINITY};
+use syscall_defs::sched::{SchedPolicy, NICE_MAX, NICE_MIN};
+
+use crate::kernel::task::Task;
+
+/// Time slice of a nice 0 task
+const BASE_SLICE_NS: u64 = 4_000_000;
+const MIN_SLICE_NS: u64 = 1_000_000;
+const MAX_SLICE_NS: u64 = 100_000_000;
+
+/// Time slice of SCHED_RR tasks, FIFO tasks have no time slice
+pub const RR_SLICE_NS: u64 = 100_000_000;
+
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// Each nice level changes the cpu share by ~10% (same weights as linux)
+const NICE_WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, // -20
+    29154, 23254, 18705, 14949, 11916, // -15
+    9548, 7620, 6100, 4904, 3906, // -10
+    3121, 2501, 1991, 1586, 1277, // -5
+    1024, 820, 655, 526, 423, // 0
+    335, 272, 215, 172, 137, // 5
+    110, 87, 70, 56, 45, // 10
+    36, 29, 23, 18, 15, // 15
+];
+
+pub fn clamp_nice(nice: isize) -> isize {
+    nice.clamp(NICE_MIN, NICE_MAX)
+}
+
+pub fn nice_weight(nice: isize) -> u64 {
+    NICE_WEIGHTS[(clamp_nice(nice) - NICE_MIN) as usize]
+}
+
+/// Time slice scaled by the nice weight
+pub fn time_slice(nice: isize) -> u64 {
+    (BASE_SLICE_NS * nice_weight(nice) / NICE_0_WEIGHT).clamp(MIN_SLICE_NS, MAX_SLICE_NS)
+}
+
+/// Lowering the nice value is limited by RLIMIT_NICE: nice can go down to 20 - rlim_cur
+pub fn can_nice(task: &Task, nice: isize) -> bool {
+    if nice >= task.nice() {
+        return true;
+    }
+
+    let limit = task.rlimit(RLimitKind::Nice).cur;
+
+    limit == RLIM_INFINITY || (20 - nice) as u64 <= limit
+}
+
+/// Raising the real-time priority is limited by RLIMIT_RTPRIO, lowering it is always allowed
+pub fn can_set_sched(task: &Task, policy: SchedPolicy, rt_priority: usize) -> bool {
+    if !policy.is_rt() || (task.is_rt() && rt_priority <= task.rt_priority()) {
+        return true;
+    }
+
+    let limit = task.rlimit(RLimitKind::RTPrio).cur;
+
+    limit == RLIM_INFINITY || rt_priority as u64 <= limit
+}
@@ -0,0 +1,981 @@
+use crate::arch::ipi::IpiKind;
+use crate::kernel::ipi::IpiTarget;
+use crate::kernel::sched::rt::RtQueue;
+use crate::kernel::sched::{migrate_to, select_cpu, SchedulerInterface, SleepFlags};
+use crate::kernel::signal::{SignalError, SignalResult};
+use crate::kernel::sync::{IrqGuard, LockApi, Spin, SpinGuard};
+use crate::kernel::task::{ArcTask, SchedTaskAdapter, Task, TaskState};
+use crate::kernel::timer::current_ns;
+use crate::kernel::utils::PerCpu;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use intrusive_collections::LinkedList;
+
+/// How often busy cpus look for a less loaded cpu
+const BALANCE_INTERVAL_NS: u64 = 20_000_000;
+
+/// Scheduling policy of the normal (not real-time) runnable tasks of a cpu. The rest of the
+/// task states, real-time tasks and load balancing are handled by [`Queues`]
+pub trait RunQueue: Default + 'static {
+    fn is_empty(&self) -> bool;
+
+    /// Queues the task, at the front if it should run next
+    fn push(&mut self, task: ArcTask, front: bool);
+
+    /// Next task to run, None if the current task (if runnable) should keep running
+    fn pop(&mut self, current: Option<&Task>) -> Option<ArcTask>;
+
+    fn remove(&mut self, task: &Task) -> Option<ArcTask>;
+
+    /// Task that can run on the cpu to give to it by the load balancing
+    fn steal(&mut self, cpu: usize) -> Option<ArcTask>;
+
+    /// Charges the time since the last update to the current task
+    fn update_curr(&mut self, _current: &Task) {}
+
+    /// Returns true if the current task should be preempted by the tick
+    fn preempt_tick(&mut self, current: &Task, elapsed_ns: u64) -> bool;
+
+    /// Task is new or migrated to this cpu
+    fn enter(&mut self, _task: &Task) {}
+
+    /// Task is migrated to another cpu
+    fn leave(&mut self, _task: &Task) {}
+
+    /// Task woke up from sleep and is going to be queued
+    fn woken(&mut self, _task: &Task, _current: &Task) {}
+
+    /// Another task was switched in
+    fn switched(&mut self) {}
+
+    fn debug(&self);
+}
+
+#[thread_local]
+static mut CURRENT_TASK: Option<ArcTask> = None;
+
+fn set_current(current: ArcTask) {
+    let _guard = IrqGuard::new();
+
+    unsafe {
+        let t = &raw mut CURRENT_TASK;
+        drop(t.read()); //run destructor of previous entry
+        t.write(Some(current));
+    }
+}
+
+fn get_current<'a>() -> &'a Task {
+    let _guard = IrqGuard::new();
+
+    unsafe {
+        let t = &raw const CURRENT_TASK;
+        t.as_ref().unwrap().as_deref().unwrap()
+    }
+}
+
+/// Load of the cpu, read by other cpus without taking the queue lock
+#[derive(Default)]
+struct CpuLoad {
+    // Tasks waiting in the runnable queues
+    nr_queued: AtomicUsize,
+    // Pull request sent to a busier cpu and not answered yet
+    pull_pending: AtomicBool,
+    // Mask of cpus asking this cpu for a task
+    pull_requests: AtomicU64,
+}
+
+struct Queues<P: RunQueue> {
+    idle_task: ArcTask,
+
+    load: CpuLoad,
+
+    last_balance: u64,
+
+    runnable: P,
+
+    // Real-time tasks, always picked before the normal ones
+    rt: RtQueue,
+
+    deadline_awaiting: LinkedList<SchedTaskAdapter>,
+
+    awaiting: LinkedList<SchedTaskAdapter>,
+
+    stopped: LinkedList<SchedTaskAdapter>,
+
+    // Tasks switched out from a cpu they are no longer allowed on, migrated once not running
+    migrating: Vec<ArcTask>,
+}
+
+impl<P: RunQueue> Default for Queues<P> {
+    fn default() -> Queues<P> {
+        let idle = Task::this();
+        idle.set_state(TaskState::Idle);
+
+        dbgln!(sched, "idle task: {}", idle.tid());
+
+        Queues {
+            idle_task: idle,
+
+            load: CpuLoad::default(),
+            last_balance: 0,
+
+            runnable: P::default(),
+            rt: RtQueue::default(),
+            deadline_awaiting: LinkedList::new(SchedTaskAdapter::new()),
+            awaiting: LinkedList::new(SchedTaskAdapter::new()),
+            stopped: LinkedList::new(SchedTaskAdapter::new()),
+            migrating: Vec::new(),
+        }
+    }
+}
+
+impl<P: RunQueue> Queues<P> {
+    fn switch<F: FnOnce()>(&self, to: ArcTask, lock: SpinGuard<()>, f: Option<F>) {
+        let idle = to.state() == TaskState::Idle;
+
+        crate::kernel::timer::set_sleep_deadline(self.next_deadline());
+
+        let _irq = lock.to_irq_guard();
+
+        let prev = get_current();
+
+        let prev_id = prev.tid();
+
+        set_current(to);
+
+        if let Some(f) = f {
+            f();
+        }
+
+        // Careful with leaving Arcs on the stack as we may never be back on this frame
+        // after switching... Should this happen, we would never drop strong count to 0
+        let to = get_current();
+
+        assert_eq!(prev_id, prev.tid());
+
+        if idle {
+            crate::kernel::sched::finalize_idle();
+        } else {
+            crate::kernel::sched::finalize();
+        }
+
+        dbgln!(sched, "{} -> {}", prev.tid(), to.tid());
+
+        if prev.tid() == to.tid() {
+            return;
+        }
+
+        prev.account_switch_out();
+        to.account_switch_in();
+
+        unsafe {
+            if prev.state() == TaskState::Unused {
+                // prev task is not gonna be scheduled again
+                activate_task!(to);
+            } else {
+                switch!(prev, to);
+            }
+        }
+    }
+
+    /// Earliest wakeup time of the tasks sleeping with a timeout
+    fn next_deadline(&self) -> Option<u64> {
+        self.deadline_awaiting
+            .iter()
+            .map(|t| t.sleep_until() as u64)
+            .min()
+    }
+
+    fn schedule_check_deadline(&mut self) {
+        if self.deadline_awaiting.is_empty() {
+            return;
+        }
+
+        let time = current_ns() as usize;
+
+        let mut woken = Vec::new();
+
+        let mut cursor = self.deadline_awaiting.front_mut();
+
+        while let Some(c) = cursor.get() {
+            if c.sleep_until() <= time {
+                let ptr = cursor.remove().unwrap();
+
+                assert_eq!(ptr.sched.is_linked(), false);
+                ptr.set_sleep_until(0);
+
+                woken.push(ptr);
+            } else {
+                cursor.move_next();
+            }
+        }
+
+        if !woken.is_empty() {
+            crate::kernel::timer::set_sleep_deadline(self.next_deadline());
+        }
+
+        for task in woken {
+            self.push_woken(task, false);
+        }
+    }
+
+    #[allow(dead_code)]
+    fn debug_sched(&self, cpu: usize) {
+        dbgln!(sched_v, "CPU: {}, c: {}| ", cpu, get_current().tid());
+
+        dbgln!(sched_v, "runnable");
+        self.runnable.debug();
+        dbgln!(sched_v, "awaiting");
+        for t in &self.awaiting {
+            let ptr = unsafe { t.arch_task().ctx.as_ptr() };
+            dbgln!(
+                sched_v,
+                "{} {:x} {:p}",
+                t.tid(),
+                unsafe { t.arch_task().stack_top + t.arch_task().stack_size },
+                ptr
+            );
+            unsafe {
+                t.arch_task().ctx.as_ref().debug_stacktrace();
+            }
+        }
+        dbgln!(sched_v, "deadline awaiting");
+        for t in &self.deadline_awaiting {
+            dbgln!(sched_v, "{} ", t.tid());
+        }
+    }
+
+    fn migrate(&mut self, task: ArcTask) {
+        self.runnable.leave(&task);
+
+        crate::kernel::sched::migrate(task);
+    }
+
+    fn migrate_pending(&mut self) {
+        let current = get_current().tid();
+
+        for task in self
+            .migrating
+            .extract_if(.., |t| t.tid() != current)
+            .collect::<Vec<_>>()
+        {
+            self.migrate(task);
+        }
+    }
+
+    fn pop_runnable(&mut self, current: Option<&Task>) -> Option<ArcTask> {
+        let cpu = crate::cpu_id() as usize;
+
+        while let Some(task) = self.runnable.pop(current) {
+            self.load.nr_queued.fetch_sub(1, Ordering::Relaxed);
+
+            if task.can_run_on(cpu) {
+                return Some(task);
+            }
+
+            self.migrate(task);
+        }
+
+        None
+    }
+
+    fn pop_rt(&mut self) -> Option<ArcTask> {
+        let cpu = crate::cpu_id() as usize;
+
+        while let Some(task) = self.rt.pop() {
+            self.load.nr_queued.fetch_sub(1, Ordering::Relaxed);
+
+            if task.can_run_on(cpu) {
+                return Some(task);
+            }
+
+            self.migrate(task);
+        }
+
+        None
+    }
+
+    /// Real-time tasks go first, unless throttled. Throttled real-time tasks run only if
+    /// there is no normal task, including the current one
+    fn pop_next(&mut self, current: Option<&Task>) -> Option<ArcTask> {
+        if !self.rt.is_throttled() {
+            if let Some(task) = self.pop_rt() {
+                return Some(task);
+            }
+        }
+
+        match self.pop_runnable(current) {
+            None if current.is_none() => self.pop_rt(),
+            task => task,
+        }
+    }
+
+    fn preempt_tick(&mut self, elapsed_ns: u64) -> bool {
+        let current = get_current();
+
+        self.runnable.update_curr(current);
+
+        // Sleepers are woken up on time even if the current task keeps running
+        self.schedule_check_deadline();
+
+        if let Some(preempt) = self
+            .rt
+            .preempt_tick(current, elapsed_ns, !self.runnable.is_empty())
+        {
+            return preempt;
+        }
+
+        self.runnable.preempt_tick(current, elapsed_ns) || current.to_reschedule()
+    }
+
+    fn schedule_next<F: FnOnce()>(&mut self, lock: SpinGuard<()>, f: Option<F>) -> bool {
+        self.schedule_check_deadline();
+
+        self.migrate_pending();
+
+        let current = get_current();
+
+        self.runnable.update_curr(current);
+
+        let prev_id = current.tid();
+
+        let allowed = current.can_run_on(crate::cpu_id() as usize);
+
+        let current_runnable = current.tid() != self.idle_task.tid()
+            && current.state() == TaskState::Runnable
+            && allowed;
+
+        // Real-time task competes with the queued ones by priority
+        let rt_requeued = current_runnable && current.is_rt();
+
+        if rt_requeued {
+            self.rt.push_current(current.me());
+            self.load.nr_queued.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let to_run = self
+            .pop_next((current_runnable && !current.is_rt()).then_some(current))
+            .unwrap_or_else(|| {
+                if current_runnable {
+                    current.me()
+                } else {
+                    self.idle_task.clone()
+                }
+            });
+
+        if current.tid() != to_run.tid()
+            && current.tid() != self.idle_task.tid()
+            && !current.sched.is_linked()
+            && current.state() == TaskState::Runnable
+            && !rt_requeued
+        {
+            if allowed {
+                self.push_runnable(current.me(), false);
+            } else {
+                self.migrating.push(current.me());
+            }
+        }
+
+        current.set_to_reschedule(false);
+
+        let to_run_tid = to_run.tid();
+
+        if prev_id != to_run_tid {
+            self.runnable.switched();
+        }
+
+        if to_run_tid == self.idle_task.tid() {
+            self.idle_balance();
+        } else {
+            self.periodic_balance();
+        }
+
+        self.switch(to_run, lock, f);
+
+        prev_id != to_run_tid
+    }
+
+    /// Asks the busiest cpu with more than min_queued waiting tasks to give us one
+    fn request_pull(&mut self, min_queued: usize) {
+        if self.load.pull_pending.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let this = crate::cpu_id() as usize;
+
+        let sched = QueueScheduler::<P>::get();
+
+        let busiest = (0..crate::kernel::smp::cpu_count())
+            .filter(|&cpu| cpu != this)
+            .map(|cpu| (cpu, sched.load(cpu).nr_queued.load(Ordering::Relaxed)))
+            .filter(|&(_, queued)| queued > min_queued)
+            .max_by_key(|&(_, queued)| queued);
+
+        if let Some((cpu, queued)) = busiest {
+            dbgln!(
+                balance,
+                "cpu {} pull from {} ({} queued)",
+                this,
+                cpu,
+                queued
+            );
+
+            self.load.pull_pending.store(true, Ordering::Relaxed);
+
+            sched
+                .load(cpu)
+                .pull_requests
+                .fetch_or(1 << this, Ordering::Relaxed);
+
+            crate::kernel::ipi::exec_on_cpu(IpiTarget::Cpu(cpu), IpiKind::IpiBalance);
+        }
+    }
+
+    fn idle_balance(&mut self) {
+        self.request_pull(0);
+    }
+
+    fn periodic_balance(&mut self) {
+        let now = current_ns();
+
+        if now - self.last_balance < BALANCE_INTERVAL_NS {
+            return;
+        }
+
+        self.last_balance = now;
+
+        // Moving a task makes sense if the other cpu has at least 2 tasks more
+        self.request_pull(self.load.nr_queued.load(Ordering::Relaxed) + 1);
+    }
+
+    /// Gives runnable tasks to the cpus that asked for them
+    fn balance(&mut self, _lock: SpinGuard<()>) {
+        let requests = self.load.pull_requests.swap(0, Ordering::Relaxed);
+
+        let sched = QueueScheduler::<P>::get();
+
+        for cpu in (0..64).filter(|cpu| requests & (1 << cpu) != 0) {
+            let load = sched.load(cpu);
+
+            let queued = self.load.nr_queued.load(Ordering::Relaxed);
+
+            if queued > load.nr_queued.load(Ordering::Relaxed) {
+                if let Some(task) = self.runnable.steal(cpu) {
+                    self.load.nr_queued.fetch_sub(1, Ordering::Relaxed);
+
+                    dbgln!(balance, "task {} pulled to cpu {}", task.tid(), cpu);
+
+                    self.runnable.leave(&task);
+
+                    migrate_to(task, cpu);
+                }
+            }
+
+            load.pull_pending.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn reschedule(&mut self, lock: SpinGuard<()>) -> bool {
+        self.schedule_next(lock, Option::<fn()>::None)
+    }
+
+    fn reschedule_exec<F: FnOnce()>(&mut self, lock: SpinGuard<()>, fun: F) -> bool {
+        self.schedule_next(lock, Some(fun))
+    }
+
+    fn queue_task(&mut self, task: ArcTask, _lock: SpinGuard<()>) {
+        self.runnable.enter(&task);
+
+        self.push_runnable(task, false);
+    }
+
+    fn sleep(
+        &mut self,
+        time_ns: Option<usize>,
+        flags: SleepFlags,
+        lock: SpinGuard<()>,
+    ) -> SignalResult<()> {
+        let task = get_current();
+        if task.locks() > 0 {
+            dbgln!(
+                warn,
+                "sleeping while holding {} locks, tid: {}",
+                task.locks(),
+                task.tid()
+            );
+        }
+
+        if task.has_pending_io() {
+            task.set_has_pending_io(false);
+            return Ok(());
+        }
+
+        if !flags.contains(SleepFlags::NON_INTERRUPTIBLE) && task.signals().has_pending() {
+            return Err(SignalError::Interrupted);
+        }
+
+        if task.is_parent_terminating() {
+            return Err(SignalError::Interrupted);
+        }
+
+        let pending = task.signals().pending();
+
+        if pending > 0 {
+            logln!("WARN sleep with pending signals: {:#x}", pending);
+        }
+
+        assert_ne!(
+            task.tid(),
+            self.idle_task.tid(),
+            "Idle task should not sleep"
+        );
+
+        // TODO: mark task as uninterruptible and dont wake it up on signals
+        if let Some(time_ns) = time_ns {
+            //dbgln!(task, "task {} pushed deadline awaiting", task.tid());
+            self.push_deadline_awaiting(task.me(), time_ns);
+        } else {
+            //dbgln!(task, "task {} pushed awaiting", task.tid());
+            self.push_awaiting(task.me());
+        }
+
+        self.reschedule(lock);
+
+        if task.signals().pending() != pending {
+            Err(SignalError::Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn wake(&mut self, task: ArcTask, _lock: SpinGuard<()>) {
+        if !task.is_on_this_cpu() {
+            // Task migrated since the wakeup was sent
+            crate::kernel::ipi::wake_up(&task);
+        } else if task.state() == TaskState::AwaitingIo {
+            let mut cursor = if task.sleep_until() > 0 {
+                unsafe { self.deadline_awaiting.cursor_mut_from_ptr(task.as_ref()) }
+            } else {
+                unsafe { self.awaiting.cursor_mut_from_ptr(task.as_ref()) }
+            };
+
+            if let Some(task) = cursor.remove() {
+                self.push_woken(task, false);
+            }
+        } else {
+            if task.state() == TaskState::Unused {
+                dbgln!(task, "WARN: set pending io on UNUSED task {}", task.tid());
+            }
+            task.set_has_pending_io(true);
+        }
+    }
+
+    fn wake_as_next(&mut self, task: ArcTask, _lock: SpinGuard<()>) {
+        if !task.is_on_this_cpu() {
+            crate::kernel::ipi::wake_up_next(&task);
+        } else if task.state() == TaskState::AwaitingIo {
+            let mut cursor = if task.sleep_until() > 0 {
+                unsafe { self.deadline_awaiting.cursor_mut_from_ptr(task.as_ref()) }
+            } else {
+                unsafe { self.awaiting.cursor_mut_from_ptr(task.as_ref()) }
+            };
+
+            if let Some(task) = cursor.remove() {
+                self.push_woken(task, true);
+            }
+        } else if task.state() == TaskState::Runnable {
+            task.set_has_pending_io(true);
+
+            // Running and migrating tasks are not queued
+            if let Some(task) = self.remove_queued(&task) {
+                self.push_runnable(task, true);
+            }
+        } else {
+            task.set_has_pending_io(true);
+            //let mut cursor = unsafe { self.stopped.cursor_mut_from_ptr(task.as_ref()) };
+            //if let Some(task) = cursor.remove() {
+            //    self.push_runnable_front(task, false);
+            //}
+        }
+    }
+
+    fn requeue(&mut self, task: ArcTask, _lock: SpinGuard<()>) {
+        if let Some(task) = self.remove_queued(&task) {
+            self.push_runnable(task, false);
+        } else if task.tid() == get_current().tid() {
+            // Current task gives way if it's no longer the most important one
+            task.set_to_reschedule(true);
+        }
+    }
+
+    fn stop(&mut self, sig: usize, lock: SpinGuard<()>) {
+        let task = get_current();
+
+        assert_ne!(
+            task.tid(),
+            self.idle_task.tid(),
+            "Idle task should not sleep"
+        );
+
+        dbgln!(task_stop, "Stopped task {}", task.tid());
+
+        self.push_stopped(sig, task.me());
+
+        self.reschedule_exec(lock, || {
+            task.notify_stopped(sig);
+        });
+    }
+
+    fn cont(&mut self, task: ArcTask, _lock: SpinGuard<()>) {
+        if !task.is_on_this_cpu() {
+            crate::kernel::ipi::cont(&task);
+        } else if task.state() == TaskState::Stopped {
+            assert!(task.sched.is_linked());
+            let mut cursor = unsafe { self.stopped.cursor_mut_from_ptr(task.as_ref()) };
+
+            if let Some(task) = cursor.remove() {
+                dbgln!(task_stop, "Continued task {}", task.tid());
+
+                self.push_woken(task, true);
+            }
+        } else {
+            // if process was not stopped, wake it up in case it has some pending signals to process
+            self.wake(task, _lock)
+        }
+    }
+
+    fn exit(&mut self, status: syscall_defs::waitpid::Status, lock: SpinGuard<()>) -> ! {
+        let current = get_current();
+
+        dbgln!(
+            task,
+            "exit tid: {}, sc: {}, wc: {}, st: {:?} {}",
+            current.tid(),
+            ArcTask::strong_count(&current.me()),
+            ArcTask::weak_count(&current.me()),
+            status,
+            current.exe().unwrap().full_path()
+        );
+
+        current.set_state(TaskState::Unused);
+
+        assert_eq!(current.state(), TaskState::Unused);
+        assert_eq!(current.sched.is_linked(), false);
+        assert!(current.is_process_leader());
+
+        dbgln!(
+            mem,
+            "FREE MEM h:{} p:{}",
+            crate::kernel::mm::heap::heap_mem(),
+            crate::arch::mm::phys::used_mem()
+        );
+
+        self.reschedule_exec(lock, || {
+            current.make_zombie(status);
+        });
+
+        unreachable!()
+    }
+
+    fn exit_thread(&mut self, _lock: SpinGuard<()>) -> ! {
+        let task = get_current();
+
+        task.set_state(TaskState::Unused);
+
+        assert_eq!(task.state(), TaskState::Unused);
+        assert_eq!(task.sched.is_linked(), false);
+        assert!(!task.is_process_leader());
+
+        dbgln!(
+            task,
+            "exit_thread tid: {}, sc: {}, wc: {}",
+            task.tid(),
+            ArcTask::strong_count(&task.me()),
+            ArcTask::weak_count(&task.me()),
+        );
+
+        self.reschedule_exec(_lock, || {
+            task.make_zombie(syscall_defs::waitpid::Status::Exited(0));
+        });
+
+        //logln!("UNEXPECTED EXIT THREAD TID {}", task.tid());
+
+        unreachable!()
+    }
+
+    fn push_awaiting(&mut self, task: ArcTask) {
+        assert_eq!(task.sched.is_linked(), false);
+        assert_ne!(task.tid(), self.idle_task.tid());
+
+        task.set_state(TaskState::AwaitingIo);
+        task.set_sleep_until(0);
+
+        self.awaiting.push_back(task);
+    }
+
+    fn push_stopped(&mut self, _sig: usize, task: ArcTask) {
+        assert_eq!(task.sched.is_linked(), false);
+        assert_ne!(task.tid(), self.idle_task.tid());
+
+        task.set_state(TaskState::Stopped);
+        //task.set_sleep_until(0);
+
+        self.stopped.push_back(task);
+    }
+
+    fn push_deadline_awaiting(&mut self, task: ArcTask, time_ns: usize) {
+        assert_eq!(task.sched.is_linked(), false);
+        assert_ne!(task.tid(), self.idle_task.tid());
+
+        task.set_state(TaskState::AwaitingIo);
+        task.set_sleep_until(current_ns() as usize + time_ns);
+
+        self.deadline_awaiting.push_back(task);
+    }
+
+    /// Queues the woken up or continued task, tasks no longer allowed on this cpu are migrated
+    fn push_woken(&mut self, task: ArcTask, front: bool) {
+        if !task.can_run_on(crate::cpu_id() as usize) {
+            return self.migrate(task);
+        }
+
+        if !front && !task.is_rt() {
+            self.runnable.woken(&task, get_current());
+        }
+
+        self.push_runnable(task, front);
+    }
+
+    fn push_runnable(&mut self, task: ArcTask, front: bool) {
+        assert_eq!(task.sched.is_linked(), false);
+        assert_ne!(task.tid(), self.idle_task.tid());
+
+        task.set_state(TaskState::Runnable);
+        //task.set_sleep_until(0);
+        if task.is_rt() {
+            if front {
+                self.rt.push_front(task);
+            } else {
+                self.rt.push(task);
+            }
+            self.check_preempt_rt();
+        } else {
+            self.runnable.push(task, front);
+        }
+        self.load.nr_queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn remove_queued(&mut self, task: &Task) -> Option<ArcTask> {
+        let task = self
+            .rt
+            .remove(task)
+            .or_else(|| self.runnable.remove(task))?;
+
+        self.load.nr_queued.fetch_sub(1, Ordering::Relaxed);
+
+        Some(task)
+    }
+
+    /// Current task is preempted on the next tick
+    fn check_preempt_rt(&mut self) {
+        let current = get_current();
+
+        if self.rt.should_preempt(current) {
+            current.set_to_reschedule(true);
+        }
+    }
+}
+
+/// Per cpu queues with the normal tasks ordered by the policy P
+pub struct QueueScheduler<P: RunQueue> {
+    queues: PerCpu<(Spin<()>, Queues<P>)>,
+}
+
+unsafe impl<P: RunQueue> Send for QueueScheduler<P> {}
+unsafe impl<P: RunQueue> Sync for QueueScheduler<P> {}
+
+impl<P: RunQueue> SchedulerInterface for QueueScheduler<P> {
+    fn init(&self) {
+        let (_, queue) = self.queues.this_cpu();
+
+        set_current(queue.idle_task.clone());
+
+        crate::kernel::sched::register_task(&queue.idle_task);
+    }
+
+    fn reschedule(&self) -> bool {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let lock = lock.lock_irq();
+
+        queue.reschedule(lock)
+    }
+
+    fn preempt_tick(&self, elapsed_ns: u64) -> bool {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let _lock = lock.lock_irq();
+
+        queue.preempt_tick(elapsed_ns)
+    }
+
+    fn current_task<'a>(&self) -> &'a Task {
+        get_current()
+    }
+
+    fn queue_task(&self, task: ArcTask, alloc_cpu: bool) {
+        if alloc_cpu || !task.can_run_on(task.on_cpu()) {
+            Self::alloc_cpu(&task);
+        }
+
+        if Self::maybe_do_ipi(&task, crate::kernel::ipi::queue) {
+            return;
+        }
+
+        //assert!(task.is_on_this_cpu());
+
+        let (lock, queue) = self.queues.cpu_mut(task.on_cpu() as isize);
+
+        let lock = lock.lock_irq();
+
+        queue.queue_task(task, lock);
+    }
+
+    fn sleep(&self, until: Option<usize>, flags: SleepFlags) -> SignalResult<()> {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let lock = lock.lock_irq();
+
+        queue.sleep(until, flags, lock)
+    }
+
+    fn wake(&self, task: ArcTask) {
+        if Self::maybe_do_ipi(&task, crate::kernel::ipi::wake_up) {
+            return;
+        }
+
+        //assert!(task.is_on_this_cpu());
+
+        let (lock, queue) = self.queues.cpu_mut(task.on_cpu() as isize);
+
+        let lock = lock.lock_irq();
+
+        queue.wake(task, lock);
+    }
+
+    fn wake_as_next(&self, task: ArcTask) {
+        if Self::maybe_do_ipi(&task, crate::kernel::ipi::wake_up_next) {
+            return;
+        }
+
+        //assert!(task.is_on_this_cpu());
+
+        let (lock, queue) = self.queues.cpu_mut(task.on_cpu() as isize);
+
+        let lock = lock.lock_irq();
+
+        queue.wake_as_next(task, lock);
+    }
+
+    fn cont(&self, task: ArcTask) {
+        if Self::maybe_do_ipi(&task, crate::kernel::ipi::cont) {
+            return;
+        }
+
+        //assert!(task.is_on_this_cpu());
+        if task.is_process_leader() {
+            task.cont_threads();
+        }
+
+        let (lock, queue) = self.queues.cpu_mut(task.on_cpu() as isize);
+
+        let lock = lock.lock_irq();
+
+        queue.cont(task.clone(), lock);
+    }
+
+    fn requeue(&self, task: ArcTask) {
+        if Self::maybe_do_ipi(&task, crate::kernel::ipi::requeue) {
+            return;
+        }
+
+        let (lock, queue) = self.queues.cpu_mut(task.on_cpu() as isize);
+
+        let lock = lock.lock_irq();
+
+        queue.requeue(task, lock);
+    }
+
+    fn stop(&self, sig: usize) {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let lock = lock.lock_irq();
+
+        queue.stop(sig, lock);
+    }
+
+    fn exit(&self, status: syscall_defs::waitpid::Status) -> ! {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let lock = lock.lock_irq();
+
+        queue.exit(status, lock);
+    }
+
+    fn exit_thread(&self) -> ! {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let lock = lock.lock_irq();
+
+        queue.exit_thread(lock);
+    }
+
+    fn balance(&self) {
+        let (lock, queue) = self.queues.this_cpu_mut();
+
+        let lock = lock.lock_irq();
+
+        queue.balance(lock);
+    }
+
+    fn debug(&self) {
+        let cpu_count = crate::kernel::smp::cpu_count();
+
+        for i in 0..cpu_count {
+            let (l, q) = self.queues.cpu_mut(i as isize);
+
+            let _lock = l.lock_irq();
+            q.debug_sched(i);
+        }
+    }
+}
+
+impl<P: RunQueue> QueueScheduler<P> {
+    pub fn new() -> Arc<QueueScheduler<P>> {
+        Arc::new(QueueScheduler {
+            queues: PerCpu::new_fn(|| (Spin::new(()), Queues::default())),
+        })
+    }
+
+    fn get() -> &'static QueueScheduler<P> {
+        crate::kernel::sched::scheduler().as_impl::<QueueScheduler<P>>()
+    }
+
+    fn load(&self, cpu: usize) -> &CpuLoad {
+        &self.queues.cpu(cpu as isize).1.load
+    }
+
+    fn alloc_cpu(task: &ArcTask) {
+        task.set_on_cpu(select_cpu(task));
+    }
+
+    fn maybe_do_ipi(task: &ArcTask, fun: fn(&ArcTask)) -> bool {
+        if task.is_on_this_cpu() {
+            return false;
+        }
+
+        fun(task);
+        true
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::collections::BTreeMap;
+use core::cmp::Reverse;
+
+use crate::kernel::task::{ArcTask, Task};
+use crate::kernel::timer::current_ns;
+
+/// Real-time tasks can use RT_RUNTIME_NS of every RT_PERIOD_NS, the rest is left for normal tasks
+const RT_PERIOD_NS: u64 = 1_000_000_000;
+const RT_RUNTIME_NS: u64 = 950_000_000;
+
+/// Runnable real-time tasks of a cpu, highest priority first and FIFO within a priority
+#[derive(Default)]
+pub struct RtQueue {
+    runnable: BTreeMap<(Reverse<usize>, i64), ArcTask>,
+
+    // Sequence numbers of tasks pushed to the back and to the front of their priority
+    back_seq: i64,
+    front_seq: i64,
+
+    period_start: u64,
+
+    // Time used by real-time tasks in the current period
+    runtime: u64,
+}
+
+impl RtQueue {
+    pub fn push(&mut self, task: ArcTask) {
+        self.back_seq += 1;
+
+        self.runnable
+            .insert((Reverse(task.rt_priority()), self.back_seq), task);
+    }
+
+    pub fn push_front(&mut self, task: ArcTask) {
+        self.front_seq -= 1;
+
+        self.runnable
+            .insert((Reverse(task.rt_priority()), self.front_seq), task);
+    }
+
+    /// Requeues the task switched out from the cpu, a task preempted by a higher priority
+    /// task stays at the front of its priority
+    pub fn push_current(&mut self, task: ArcTask) {
+        if self
+            .highest_priority()
+            .is_some_and(|prio| prio > task.rt_priority())
+        {
+            self.push_front(task);
+        } else {
+            self.push(task);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<ArcTask> {
+        self.runnable.pop_first().map(|(_, task)| task)
+    }
+
+    /// Removes the task by tid, its priority may have changed while it was queued
+    pub fn remove(&mut self, task: &Task) -> Option<ArcTask> {
+        let key = *self.runnable.iter().find(|(_, t)| t.tid() == task.tid())?.0;
+
+        self.runnable.remove(&key)
+    }
+
+    fn highest_priority(&self) -> Option<usize> {
+        self.runnable
+            .first_key_value()
+            .map(|(&(prio, _), _)| prio.0)
+    }
+
+    fn update_period(&mut self) {
+        let now = current_ns();
+
+        if now - self.period_start >= RT_PERIOD_NS {
+            self.period_start = now;
+            self.runtime = 0;
+        }
+    }
+
+    /// Real-time tasks used up their budget for the current period
+    pub fn is_throttled(&mut self) -> bool {
+        self.update_period();
+
+        self.runtime >= RT_RUNTIME_NS
+    }
+
+    /// Queued real-time task has a higher priority than the current task
+    pub fn should_preempt(&mut self, current: &Task) -> bool {
+        !self.is_throttled()
+            && self
+                .highest_priority()
+                .is_some_and(|prio| !current.is_rt() || prio > current.rt_priority())
+    }
+
+    /// Handles the tick for real-time tasks, returns None if it's up to the normal scheduling
+    /// class to decide whether current task is preempted
+    pub fn preempt_tick(
+        &mut self,
+        current: &Task,
+        elapsed_ns: u64,
+        normal_queued: bool,
+    ) -> Option<bool> {
+        if !current.is_rt() {
+            return self.should_preempt(current).then_some(true);
+        }
+
+        self.update_period();
+
+        if self.runtime < RT_RUNTIME_NS && self.runtime + elapsed_ns >= RT_RUNTIME_NS {
+            dbgln!(sched, "rt throttling on cpu {}", crate::cpu_id());
+        }
+
+        self.runtime += elapsed_ns;
+
+        // Throttled real-time tasks only keep the cpu if there is nothing else to run
+        let throttled = self.is_throttled() && normal_queued;
+
+        Some(
+            throttled
+                || self.should_preempt(current)
+                || current.consume_slice(elapsed_ns)
+                || current.to_reschedule(),
+        )
+    }
+}
@@ -19,6 +19,15 @@ impl TaskContainer {
         self.tasks.lock().get(&id).cloned()
     }
 
+    /// Number of tasks running in userspace, kernel threads are not counted
+    pub fn user_count(&self) -> usize {
+        self.tasks
+            .lock()
+            .values()
+            .filter(|t| unsafe { t.arch_task() }.is_user())
+            .count()
+    }
+
     pub fn remove_task(&self, id: usize) {
         if let Some(_) = self.tasks.lock().remove(&id) {
             dbgln!(task, "task {} removed from container", id);
@@ -3,6 +3,7 @@ use alloc::sync::{Arc, Weak};
 use hashbrown::HashMap;
 use spin::Once;
 
+use syscall_defs::signal::{SigInfo, SI_KERNEL};
 use syscall_defs::{SyscallError, SyscallResult};
 
 use crate::kernel::sched::{current_task, get_task};
@@ -82,6 +83,10 @@ impl Group {
     }
 
     pub fn signal(&self, sig: usize) {
+        self.signal_info(sig, SigInfo::new(sig, SI_KERNEL));
+    }
+
+    pub fn signal_info(&self, sig: usize, info: SigInfo) {
         let procs = self.processes.lock();
 
         dbgln!(
@@ -93,7 +98,7 @@ impl Group {
         );
 
         for (_pid, proc) in procs.iter() {
-            proc.signal(sig);
+            proc.signal_info(sig, info);
         }
     }
 
@@ -0,0 +1,357 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use syscall_defs::resource::{RLimitKind, RLIM_INFINITY};
+use syscall_defs::{MMapProt, OpenFlags};
+
+use crate::arch::signal::UserRegs;
+use crate::drivers::elf::types::{
+    Abi, BinType, Class, Endianess, Machine, ProgramFlags, ProgramType,
+};
+use crate::drivers::elf::{ElfHeader, ProgramHeader};
+use crate::kernel::fs::path::Path;
+use crate::kernel::fs::vfs::FsError;
+use crate::kernel::fs::{lookup_by_path, LookupMode};
+use crate::kernel::mm::uaccess::copy_from_user;
+use crate::kernel::mm::{VirtAddr, PAGE_SIZE};
+use crate::kernel::sched::current_task_ref;
+use crate::kernel::task::filetable::FileHandle;
+use crate::kernel::task::vm::MappingInfo;
+use crate::kernel::utils::types::Align;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+
+const NOTE_NAME: &[u8] = b"CORE\0";
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct NoteHeader {
+    n_namesz: u32,
+    n_descsz: u32,
+    n_type: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+struct ElfPrStatus {
+    si_signo: i32,
+    si_code: i32,
+    si_errno: i32,
+    pr_cursig: i16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_times: [u64; 8],
+    pr_reg: UserRegs,
+    pr_fpvalid: i32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ElfPrPsInfo {
+    pr_state: u8,
+    pr_sname: u8,
+    pr_zomb: u8,
+    pr_nice: u8,
+    pr_flag: u64,
+    pr_uid: u32,
+    pr_gid: u32,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_fname: [u8; 16],
+    pr_psargs: [u8; 80],
+}
+
+fn as_bytes<T: Copy>(val: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(val as *const T as *const u8, size_of::<T>()) }
+}
+
+fn copy_str(dst: &mut [u8], src: &str) {
+    // Leave space for null terminator
+    let len = core::cmp::min(dst.len() - 1, src.len());
+
+    dst[..len].copy_from_slice(&src.as_bytes()[..len]);
+}
+
+fn note_size(desc_size: usize) -> usize {
+    size_of::<NoteHeader>() + NOTE_NAME.len().align_up(4) + desc_size.align_up(4)
+}
+
+/// Sequential writer into the core file, stops once RLIMIT_CORE is reached
+struct CoreWriter {
+    file: Arc<FileHandle>,
+    offset: usize,
+    limit: usize,
+}
+
+impl CoreWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<(), FsError> {
+        if self.offset + buf.len() > self.limit {
+            return Err(FsError::FileTooBig);
+        }
+
+        let mut done = 0;
+
+        while done < buf.len() {
+            let wrote = self.file.write_at(&buf[done..], self.offset + done)?;
+
+            if wrote == 0 {
+                return Err(FsError::NotSupported);
+            }
+
+            done += wrote;
+        }
+
+        self.offset += buf.len();
+
+        Ok(())
+    }
+
+    fn pad_to(&mut self, offset: usize) -> Result<(), FsError> {
+        let zeros = [0u8; 64];
+
+        while self.offset < offset {
+            let len = core::cmp::min(zeros.len(), offset - self.offset);
+
+            self.write(&zeros[..len])?;
+        }
+
+        Ok(())
+    }
+
+    fn write_note<T: Copy>(&mut self, typ: u32, desc: &T) -> Result<(), FsError> {
+        let hdr = NoteHeader {
+            n_namesz: NOTE_NAME.len() as u32,
+            n_descsz: size_of::<T>() as u32,
+            n_type: typ,
+        };
+
+        self.write(as_bytes(&hdr))?;
+        self.write(NOTE_NAME)?;
+        self.pad_to(self.offset.align_up(4))?;
+        self.write(as_bytes(desc))?;
+        self.pad_to(self.offset.align_up(4))
+    }
+
+    fn write_mapping(&mut self, map: &MappingInfo) -> Result<(), FsError> {
+        let mut page = vec![0u8; PAGE_SIZE];
+
+        for addr in (map.start.0..map.end.0).step_by(PAGE_SIZE) {
+            let addr = VirtAddr(addr);
+
+            // Don't populate anonymous memory that was never touched
+            let present = !map.anonymous || addr.to_phys_pagewalk().is_some();
+
+            if !present || copy_from_user(&mut page, addr).is_err() {
+                page.fill(0);
+            }
+
+            self.write(&page)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn prstatus(sig: usize, regs: &UserRegs) -> ElfPrStatus {
+    let task = current_task_ref();
+
+    ElfPrStatus {
+        si_signo: sig as i32,
+        pr_cursig: sig as i16,
+        pr_sigpend: task.signals().pending(),
+        pr_sighold: task.signals().blocked_mask(),
+        pr_pid: task.tid() as i32,
+        pr_ppid: task.get_parent().map_or(0, |p| p.pid()) as i32,
+        pr_pgrp: task.gid() as i32,
+        pr_sid: task.sid() as i32,
+        pr_reg: *regs,
+        ..Default::default()
+    }
+}
+
+fn prpsinfo() -> ElfPrPsInfo {
+    let task = current_task_ref();
+
+    let mut info = ElfPrPsInfo {
+        pr_state: 0,
+        pr_sname: b'R',
+        pr_zomb: 0,
+        pr_nice: 0,
+        pr_flag: 0,
+        pr_uid: 0,
+        pr_gid: 0,
+        pr_pid: task.pid() as i32,
+        pr_ppid: task.get_parent().map_or(0, |p| p.pid()) as i32,
+        pr_pgrp: task.gid() as i32,
+        pr_sid: task.sid() as i32,
+        pr_fname: [0; 16],
+        pr_psargs: [0; 80],
+    };
+
+    if let Some(exe) = task.exe() {
+        copy_str(&mut info.pr_fname, exe.name().as_str());
+        copy_str(&mut info.pr_psargs, exe.full_path().as_str());
+    }
+
+    info
+}
+
+fn write_core(writer: &mut CoreWriter, sig: usize, regs: &UserRegs) -> Result<(), FsError> {
+    let maps = current_task_ref().vm().mappings();
+
+    let phnum = 1 + maps.len();
+
+    let hdr = ElfHeader {
+        ei_magic: *b"\x7FELF",
+        ei_class: Class::Bit64,
+        ei_data: Endianess::Little,
+        ei_version: 1,
+        ei_osabi: Abi::SysV,
+        _pad: 0,
+        e_type: BinType::Core,
+        e_machine: Machine::X8664,
+        e_version: 1,
+        e_entry: 0,
+        e_phoff: size_of::<ElfHeader>() as u64,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: size_of::<ElfHeader>() as u16,
+        e_phentsize: size_of::<ProgramHeader>() as u16,
+        e_phnum: phnum as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+
+    let notes_offset = size_of::<ElfHeader>() + phnum * size_of::<ProgramHeader>();
+    let notes_size = note_size(size_of::<ElfPrStatus>()) + note_size(size_of::<ElfPrPsInfo>());
+
+    let mut phdrs = Vec::with_capacity(phnum);
+
+    phdrs.push(ProgramHeader {
+        p_type: ProgramType::Note,
+        p_flags: ProgramFlags::empty(),
+        p_offset: notes_offset as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes_size as u64,
+        p_memsz: 0,
+        p_align: 4,
+    });
+
+    let mut offset = (notes_offset + notes_size).align_up(PAGE_SIZE);
+
+    for map in maps.iter() {
+        let mut flags = ProgramFlags::empty();
+        flags.set(
+            ProgramFlags::READABLE,
+            map.prot.contains(MMapProt::PROT_READ),
+        );
+        flags.set(
+            ProgramFlags::WRITABLE,
+            map.prot.contains(MMapProt::PROT_WRITE),
+        );
+        flags.set(
+            ProgramFlags::EXECUTABLE,
+            map.prot.contains(MMapProt::PROT_EXEC),
+        );
+
+        let size = (map.end - map.start).0;
+
+        // Unreadable mappings (eg. guard pages) are recorded without contents
+        let filesz = if map.prot.contains(MMapProt::PROT_READ) {
+            size
+        } else {
+            0
+        };
+
+        phdrs.push(ProgramHeader {
+            p_type: ProgramType::Load,
+            p_flags: flags,
+            p_offset: offset as u64,
+            p_vaddr: map.start.0 as u64,
+            p_paddr: 0,
+            p_filesz: filesz as u64,
+            p_memsz: size as u64,
+            p_align: PAGE_SIZE as u64,
+        });
+
+        offset += filesz;
+    }
+
+    writer.write(as_bytes(&hdr))?;
+
+    for ph in phdrs.iter() {
+        writer.write(as_bytes(ph))?;
+    }
+
+    writer.write_note(NT_PRSTATUS, &prstatus(sig, regs))?;
+    writer.write_note(NT_PRPSINFO, &prpsinfo())?;
+
+    for (map, ph) in maps.iter().zip(phdrs.iter().skip(1)) {
+        if ph.p_filesz == 0 {
+            continue;
+        }
+
+        writer.pad_to(ph.p_offset as usize)?;
+        writer.write_mapping(map)?;
+    }
+
+    Ok(())
+}
+
+/// Writes ELF core file of the current process into its working directory
+pub fn dump(sig: usize, regs: &UserRegs) {
+    let task = current_task_ref();
+
+    let limit = task.rlimit(RLimitKind::Core).cur;
+
+    if limit == 0 {
+        return;
+    }
+
+    let name = format!("core.{}", task.pid());
+
+    let Ok(dentry) = lookup_by_path(&Path::new(name.as_str()), LookupMode::Create) else {
+        logln!("[ CORE ] Task {} failed to create {}", task.pid(), name);
+        return;
+    };
+
+    if dentry.inode().truncate(0).is_err() {
+        return;
+    }
+
+    let mut writer = CoreWriter {
+        file: FileHandle::new(0, dentry.clone(), OpenFlags::WRONLY, 0),
+        offset: 0,
+        limit: if limit == RLIM_INFINITY {
+            usize::MAX
+        } else {
+            limit as usize
+        },
+    };
+
+    let res = write_core(&mut writer, sig, regs);
+
+    let path: String = dentry.full_path();
+
+    match res {
+        Ok(()) => logln!("[ CORE ] Task {} dumped core to {}", task.pid(), path),
+        Err(e) => logln!(
+            "[ CORE ] Task {} core dump to {} truncated: {:?}",
+            task.pid(),
+            path,
+            e
+        ),
+    }
+}
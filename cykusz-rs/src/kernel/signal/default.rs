@@ -1,27 +1,30 @@
+use crate::arch::signal::UserRegs;
 use crate::kernel::task::ArcTask;
 
 #[derive(Copy, Clone)]
 pub enum Action {
     Ignore,
     Handle(fn(usize)),
+    // Write core dump and terminate
+    Core,
 }
 
 static DEFAULT_ACTIONS: [Action; super::SIGNAL_COUNT] = [
     Action::Ignore,                   // UNUSED
     Action::Handle(terminate),        // SIGHUP
     Action::Handle(terminate),        // SIGINT
-    Action::Handle(terminate),        // SIGQUIT
+    Action::Core,                     // SIGQUIT
     Action::Handle(terminate),        // SIGILL
-    Action::Ignore,                   // UNUSED
-    Action::Handle(terminate),        // SIGABRT
-    Action::Handle(terminate),        // SIGBUS
+    Action::Core,                     // SIGTRAP
+    Action::Core,                     // SIGABRT
+    Action::Core,                     // SIGBUS
     Action::Handle(terminate),        // SIGFPE
     Action::Handle(terminate),        // SIGKILL
     Action::Ignore,                   // UNUSED
-    Action::Handle(terminate),        // SIGSEGV
+    Action::Core,                     // SIGSEGV
     Action::Ignore,                   // UNUSED
     Action::Handle(terminate),        // SIGPIPE
-    Action::Ignore,                   // UNUSED
+    Action::Handle(terminate),        // SIGALRM
     Action::Handle(terminate),        // SIGTERM
     Action::Ignore,                   // UNUSED
     Action::Ignore,                   // SIGCHLD
@@ -31,10 +34,10 @@ static DEFAULT_ACTIONS: [Action; super::SIGNAL_COUNT] = [
     Action::Handle(stop),             // SIGTTIN
     Action::Handle(stop),             // SIGTTOU
     Action::Ignore,                   // UNUSED
-    Action::Ignore,                   // UNUSED
-    Action::Ignore,                   // UNUSED
-    Action::Ignore,                   // UNUSED
-    Action::Ignore,                   // UNUSED
+    Action::Handle(terminate),        // SIGXCPU
+    Action::Handle(terminate),        // SIGXFSZ
+    Action::Handle(terminate),        // SIGVTALRM
+    Action::Handle(terminate),        // SIGPROF
     Action::Ignore,                   // UNUSED
     Action::Ignore,                   // UNUSED
     Action::Ignore,                   // UNUSED
@@ -72,10 +75,14 @@ pub(in crate::kernel::signal) fn action(sig: usize) -> Action {
     DEFAULT_ACTIONS[sig]
 }
 
-pub(in crate::kernel::signal) fn handle_default(sig: usize) {
-    let action = DEFAULT_ACTIONS[sig];
+pub(in crate::kernel::signal) fn handle_default(sig: usize, regs: &UserRegs) {
+    match DEFAULT_ACTIONS[sig] {
+        Action::Handle(f) => (f)(sig),
+        Action::Core => {
+            super::coredump::dump(sig, regs);
 
-    if let Action::Handle(f) = action {
-        (f)(sig);
+            terminate(sig);
+        }
+        Action::Ignore => {}
     }
 }
@@ -9,14 +9,18 @@ use core::sync::atomic::{AtomicU64, Ordering};
 use bit_field::BitField;
 
 use syscall_defs::signal::SignalHandler;
-use syscall_defs::signal::{SigAction, SignalFlags};
+use syscall_defs::signal::{SigAction, SigAltStack, SigInfo, SignalFlags};
+use syscall_defs::signal::{MINSIGSTKSZ, SI_KERNEL, SS_DISABLE, SS_ONSTACK};
 use syscall_defs::{SyscallError, SyscallResult};
 
+use crate::arch::signal::UserFrame;
 use crate::kernel::fs::vfs::FsError;
+use crate::kernel::mm::VirtAddr;
 use crate::kernel::sched::current_task_ref;
 use crate::kernel::sync::{IrqGuard, LockApi, Spin, SpinGuard};
 use crate::kernel::task::ArcTask;
 
+mod coredump;
 mod default;
 
 pub const KSIGKILLTHR: usize = 32;
@@ -123,14 +127,19 @@ impl SignalEntry {
     pub fn sigreturn(&self) -> usize {
         self.sigreturn
     }
+
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
 }
 
-const SIGNAL_COUNT: usize = 34;
+pub const SIGNAL_COUNT: usize = 34;
 
 #[derive(Copy, Clone)]
 pub struct Entries {
     entries: [SignalEntry; SIGNAL_COUNT],
     pending_mask: u64,
+    pending_info: [SigInfo; SIGNAL_COUNT],
 }
 
 impl Default for Entries {
@@ -138,6 +147,7 @@ impl Default for Entries {
         Entries {
             entries: [SignalEntry::default(); SIGNAL_COUNT],
             pending_mask: 0,
+            pending_info: [SigInfo::default(); SIGNAL_COUNT],
         }
     }
 }
@@ -187,6 +197,55 @@ impl Entries {
         let sig = sig - 1;
         self.pending_mask.set_bit(sig as usize, true);
     }
+
+    /// Info of the first pending instance is kept, standard signals are not queued
+    pub fn set_pending_info(&mut self, sig: u64, info: SigInfo) {
+        if !self.is_pending(sig) {
+            self.pending_info[sig as usize] = info;
+        }
+
+        self.set_pending(sig);
+    }
+
+    pub fn pending_info(&self, sig: u64) -> SigInfo {
+        self.pending_info[sig as usize]
+    }
+}
+
+/// Alternate signal stack set with sigaltstack, disabled when size is 0
+#[derive(Default, Copy, Clone, Debug)]
+pub struct AltStack {
+    pub sp: u64,
+    pub size: u64,
+}
+
+impl AltStack {
+    pub fn is_enabled(&self) -> bool {
+        self.size > 0
+    }
+
+    pub fn contains(&self, sp: u64) -> bool {
+        self.is_enabled() && sp > self.sp && sp - self.sp <= self.size
+    }
+
+    /// Stack grows down from the end of the area
+    pub fn top(&self) -> u64 {
+        self.sp.saturating_add(self.size)
+    }
+
+    pub fn to_user(&self, sp: u64) -> SigAltStack {
+        SigAltStack {
+            ss_sp: self.sp,
+            ss_flags: if !self.is_enabled() {
+                SS_DISABLE
+            } else if self.contains(sp) {
+                SS_ONSTACK
+            } else {
+                0
+            },
+            ss_size: self.size,
+        }
+    }
 }
 
 pub type SigExecParam = Arc<dyn Any + Send + Sync>;
@@ -196,24 +255,43 @@ pub struct SigExec {
     param: SigExecParam,
 }
 
-#[derive(Default)]
 pub struct Signals {
     // Lock shared between threads of the same process
     shared_lock: Arc<Spin<()>>,
     entries: Arc<Spin<Entries>>,
     blocked_mask: AtomicU64,
     thread_pending_mask: AtomicU64,
+    thread_pending_info: Spin<[SigInfo; SIGNAL_COUNT]>,
+    alt_stack: Spin<AltStack>,
 
     sig_exec: Spin<Option<SigExec>>,
 }
 
+impl Default for Signals {
+    fn default() -> Self {
+        Signals {
+            shared_lock: Arc::default(),
+            entries: Arc::default(),
+            blocked_mask: AtomicU64::new(0),
+            thread_pending_mask: AtomicU64::new(0),
+            thread_pending_info: Spin::new([SigInfo::default(); SIGNAL_COUNT]),
+            alt_stack: Spin::new(AltStack::default()),
+
+            sig_exec: Spin::new(None),
+        }
+    }
+}
+
 impl Clone for Signals {
+    // New threads start without an alternate stack
     fn clone(&self) -> Self {
         Signals {
             shared_lock: self.shared_lock.clone(),
             entries: self.entries.clone(),
             blocked_mask: AtomicU64::new(self.blocked_mask.load(Ordering::SeqCst)),
             thread_pending_mask: AtomicU64::new(0),
+            thread_pending_info: Spin::new([SigInfo::default(); SIGNAL_COUNT]),
+            alt_stack: Spin::new(AltStack::default()),
 
             sig_exec: Spin::new(None),
         }
@@ -265,15 +343,37 @@ impl Signals {
     }
 
     pub fn set_pending(&self, sig: u64, thread_scope: bool) {
-        let sig = sig - 1;
+        self.set_pending_info(sig, thread_scope, SigInfo::new(sig as usize, SI_KERNEL));
+    }
+
+    pub fn set_pending_info(&self, sig: u64, thread_scope: bool, info: SigInfo) {
         if thread_scope {
+            let mut pending_info = self.thread_pending_info.lock_irq();
+
+            if !self.thread_pending().get_bit(sig as usize - 1) {
+                pending_info[sig as usize] = info;
+            }
+
             self.thread_pending_mask
-                .fetch_or(1u64 << sig, Ordering::SeqCst);
+                .fetch_or(1u64 << (sig - 1), Ordering::SeqCst);
         } else {
-            self.entries().set_pending(sig + 1);
+            self.entries().set_pending_info(sig, info);
         }
     }
 
+    /// Clears the pending signal and returns its info
+    pub fn take_pending(&self, sig: u64) -> SigInfo {
+        let info = if self.thread_pending().get_bit(sig as usize - 1) {
+            self.thread_pending_info.lock_irq()[sig as usize]
+        } else {
+            self.entries().pending_info(sig)
+        };
+
+        self.clear_pending(sig);
+
+        info
+    }
+
     pub fn has_pending(&self) -> bool {
         ((self.entries().pending() | self.thread_pending()) & !self.blocked_mask()) > 0
     }
@@ -317,11 +417,11 @@ impl Signals {
         }
     }
 
-    pub fn trigger(&self, signal: usize, this_thread: bool) -> TriggerResult {
+    pub fn trigger(&self, signal: usize, this_thread: bool, info: SigInfo) -> TriggerResult {
         assert!(signal < SIGNAL_COUNT);
 
         let set_pending = |trigger_result: TriggerResult| {
-            self.set_pending(signal as u64, this_thread);
+            self.set_pending_info(signal as u64, this_thread, info);
 
             if self.is_blocked(signal) {
                 TriggerResult::Blocked
@@ -340,6 +440,90 @@ impl Signals {
     pub fn clear(&self) {
         *self.entries.lock_irq() = Entries::default();
         self.blocked_mask.store(0, Ordering::SeqCst);
+        *self.alt_stack.lock_irq() = AltStack::default();
+    }
+
+    pub fn alt_stack(&self) -> AltStack {
+        *self.alt_stack.lock_irq()
+    }
+
+    /// Sets the alternate stack, sp is the current user stack pointer
+    pub fn set_alt_stack(
+        &self,
+        sp: u64,
+        new: Option<SigAltStack>,
+        old: Option<&mut SigAltStack>,
+    ) -> SyscallResult {
+        let mut alt_stack = self.alt_stack.lock_irq();
+
+        if let Some(old) = old {
+            *old = alt_stack.to_user(sp);
+        }
+
+        if let Some(new) = new {
+            if alt_stack.contains(sp) {
+                return Err(SyscallError::EPERM);
+            }
+
+            *alt_stack = match new.ss_flags {
+                SS_DISABLE => AltStack::default(),
+                0 | SS_ONSTACK if new.ss_size < MINSIGSTKSZ => {
+                    return Err(SyscallError::ENOMEM);
+                }
+                0 | SS_ONSTACK => {
+                    let end = new
+                        .ss_sp
+                        .checked_add(new.ss_size)
+                        .ok_or(SyscallError::EINVAL)?;
+
+                    if !VirtAddr(end as usize - 1).is_user() {
+                        return Err(SyscallError::EFAULT);
+                    }
+
+                    AltStack {
+                        sp: new.ss_sp,
+                        size: new.ss_size,
+                    }
+                }
+                _ => return Err(SyscallError::EINVAL),
+            };
+        }
+
+        Ok(0)
+    }
+
+    /// Blocked or ignored signal is unblocked and reset to the default action, so it can't
+    /// be skipped
+    pub fn force(&self, sig: usize) {
+        let blocked = self.is_blocked(sig);
+
+        {
+            let mut entries = self.entries();
+
+            if blocked || matches!(entries[sig].handler(), SignalHandler::Ignore) {
+                entries[sig] = SignalEntry::default();
+            }
+        }
+
+        if blocked {
+            self.blocked_mask
+                .fetch_and(!(1u64 << (sig - 1)), Ordering::SeqCst);
+        }
+    }
+
+    /// Signal handler mask and SA_RESETHAND are applied when the handler is entered
+    pub fn enter_handler(&self, sig: usize, entry: &SignalEntry) {
+        let mut mask = entry.mask();
+
+        if !entry.flags().contains(SignalFlags::NODEFER) {
+            mask |= 1u64 << (sig - 1);
+        }
+
+        self.set_mask(syscall_defs::signal::SigProcMask::Block, Some(mask), None);
+
+        if entry.flags().contains(SignalFlags::RESETHAND) && can_override(sig) {
+            self.entries()[sig] = SignalEntry::default();
+        }
     }
 
     pub fn set_signal(
@@ -371,6 +555,7 @@ impl Signals {
 
     pub fn copy_from(&self, signals: &Signals) {
         *self.entries() = *signals.entries();
+        *self.alt_stack.lock_irq() = signals.alt_stack();
 
         self.blocked_mask.store(
             signals.blocked_mask.load(Ordering::SeqCst),
@@ -430,12 +615,12 @@ impl Signals {
 
 pub enum DoSignalsResult {
     None,
-    Entry((usize, SignalEntry)),
+    Entry((usize, SignalEntry, SigInfo)),
     Default,
     Ignore,
 }
 
-pub fn do_signals(_from_syscall: bool, _syscall: usize) -> DoSignalsResult {
+pub fn do_signals(_from_syscall: bool, _syscall: usize, frame: &mut UserFrame) -> DoSignalsResult {
     let task = current_task_ref();
 
     let signals = task.signals();
@@ -470,16 +655,25 @@ pub fn do_signals(_from_syscall: bool, _syscall: usize) -> DoSignalsResult {
 
         signals.clear_pending(syscall_defs::signal::SIGABRT as u64);
 
-        crate::kernel::sched::exit(syscall_defs::waitpid::Status::Signaled(
-            syscall_defs::signal::SIGABRT as u64,
-        ));
+        default::handle_default(syscall_defs::signal::SIGABRT, &frame.user_regs());
     }
 
     signals.sig_exec();
 
     for s in 1..SIGNAL_COUNT {
         if !signals.is_blocked(s) && signals.is_pending(s as u64) {
-            signals.clear_pending(s as u64);
+            let info = signals.take_pending(s as u64);
+
+            // Signal delivery stop, tracer decides which signal is delivered if any
+            let (s, info) = if s < KSIGKILLTHR && task.ptrace().is_traced() {
+                match crate::kernel::task::ptrace::stop(s, frame) {
+                    0 => return DoSignalsResult::Ignore,
+                    sig if sig == s => (s, info),
+                    sig => (sig, SigInfo::new(sig, SI_KERNEL)),
+                }
+            } else {
+                (s, info)
+            };
 
             //drop(shared_lock);
 
@@ -491,10 +685,10 @@ pub fn do_signals(_from_syscall: bool, _syscall: usize) -> DoSignalsResult {
                 SignalHandler::Default => {
                     drop(entries);
                     dbgln!(task, "handle default sig task {}", task.tid());
-                    default::handle_default(s);
+                    default::handle_default(s, &frame.user_regs());
                     DoSignalsResult::Default
                 }
-                SignalHandler::Handle(_) => DoSignalsResult::Entry((s, entry)),
+                SignalHandler::Handle(_) => DoSignalsResult::Entry((s, entry, info)),
                 SignalHandler::Ignore => DoSignalsResult::Ignore,
             };
         }
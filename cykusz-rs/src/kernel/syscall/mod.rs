@@ -14,7 +14,7 @@ fn conditional_enable_int(sys: usize) {
     use syscall_defs::*;
     match sys {
         SYS_FUTEX_WAKE | SYS_FUTEX_WAIT | SYS_KILL | SYS_EXIT | SYS_EXIT_THREAD | SYS_FORK
-        | SYS_EXEC | SYS_SPAWN_THREAD => {
+        | SYS_EXEC | SYS_SPAWN_THREAD | SYS_CLONE => {
             assert!(!crate::int::is_enabled());
             return;
         }
@@ -28,6 +28,8 @@ fn conditional_enable_int(sys: usize) {
 pub fn syscall_handler(num: u64, a: u64, b: u64, c: u64, d: u64, e: u64, f: u64) -> SyscallResult {
     conditional_enable_int(num as usize);
 
+    crate::kernel::sched::current_task_ref().strace_entry(num as usize, [a, b, c, d, e, f]);
+
     use syscall_defs::*;
     let res = match num as usize {
         SYS_READ => sys::sys_read(a, b, c).maybe_into_erestartsys(),
@@ -64,16 +66,34 @@ pub fn syscall_handler(num: u64, a: u64, b: u64, c: u64, d: u64, e: u64, f: u64)
         SYS_LINK => sys::sys_link(a, b, c, d, e, f),
         SYS_RENAME => sys::sys_rename(a, b, c, d, e, f),
         SYS_FORK => sys::sys_fork(),
+        SYS_CLONE => sys::sys_clone(a, b, c, d, e),
+        SYS_PTRACE => sys::sys_ptrace(a, b, c, d),
+        SYS_STRACE => sys::sys_strace(a, b, c).maybe_into_erestartsys(),
+        SYS_GETPRIORITY => sys::sys_getpriority(a, b),
+        SYS_SETPRIORITY => sys::sys_setpriority(a, b, c),
+        SYS_NICE => sys::sys_nice(a),
+        SYS_SCHED_SETAFFINITY => sys::sys_sched_setaffinity(a, b, c),
+        SYS_SCHED_GETAFFINITY => sys::sys_sched_getaffinity(a, b, c),
+        SYS_SCHED_SETSCHEDULER => sys::sys_sched_setscheduler(a, b, c),
+        SYS_SCHED_GETSCHEDULER => sys::sys_sched_getscheduler(a),
+        SYS_SCHED_SETPARAM => sys::sys_sched_setparam(a, b),
+        SYS_SCHED_GETPARAM => sys::sys_sched_getparam(a, b),
         SYS_EXEC => sys::sys_exec(a, b, c, d, e, f),
         SYS_FCNTL => sys::sys_fcntl(a, b, c),
         SYS_MMAP => sys::sys_mmap(a, b, c, d, e, f),
         SYS_MPROTECT => sys::sys_mprotect(a, b, c),
+        SYS_PERSONALITY => sys::sys_personality(a),
         SYS_MUNMAP => sys::sys_munmap(a, b),
+        SYS_MLOCK => sys::sys_mlock(a, b),
+        SYS_MUNLOCK => sys::sys_munlock(a, b),
+        SYS_MLOCKALL => sys::sys_mlockall(a),
+        SYS_MUNLOCKALL => sys::sys_munlockall(),
         SYS_MAPS => sys::sys_maps(),
         SYS_SEEK => sys::sys_seek(a, b, c),
         SYS_PREAD => sys::sys_pread(a, b, c, d),
         SYS_PWRITE => sys::sys_pwrite(a, b, c, d),
         SYS_WAITPID => sys::sys_waitpid(a, b, c).maybe_into_erestartsys(),
+        SYS_WAIT4 => sys::sys_wait4(a, b, c, d).maybe_into_erestartsys(),
         SYS_IOCTL => sys::sys_ioctl(a, b, c).maybe_into_erestartsys(),
         SYS_SIGACTION => sys::sys_sigaction(a, b, c),
         SYS_SIGPROCMASK => sys::sys_sigprocmask(a, b, c),
@@ -91,6 +111,24 @@ pub fn syscall_handler(num: u64, a: u64, b: u64, c: u64, d: u64, e: u64, f: u64)
         SYS_DUP2 => sys::sys_dup2(a, b, c),
         SYS_STAT => sys::sys_stat(a, b, c, d, e),
         SYS_GETRLIMIT => sys::sys_getrlimit(a, b),
+        SYS_GETRUSAGE => sys::sys_getrusage(a, b),
+        SYS_TIMES => sys::sys_times(a),
+        SYS_ALARM => sys::sys_alarm(a),
+        SYS_SETITIMER => sys::sys_setitimer(a, b, c),
+        SYS_GETITIMER => sys::sys_getitimer(a, b),
+        SYS_TIMER_CREATE => sys::sys_timer_create(a, b, c),
+        SYS_TIMER_SETTIME => sys::sys_timer_settime(a, b, c, d),
+        SYS_TIMER_GETTIME => sys::sys_timer_gettime(a, b),
+        SYS_TIMER_DELETE => sys::sys_timer_delete(a),
+        SYS_CLOCK_GETTIME => sys::sys_clock_gettime(a, b),
+        SYS_CLOCK_SETTIME => sys::sys_clock_settime(a, b),
+        SYS_SETTIMEOFDAY => sys::sys_settimeofday(a, b),
+        SYS_CLOCK_NANOSLEEP => sys::sys_clock_nanosleep(a, b, c, d),
+        SYS_SIGALTSTACK => sys::sys_sigaltstack(a, b),
+        SYS_SLABINFO => sys::sys_slabinfo(a, b),
+        SYS_MEMINFO => sys::sys_meminfo(a),
+        SYS_SETRLIMIT => sys::sys_setrlimit(a, b),
+        SYS_PRLIMIT => sys::sys_prlimit(a, b, c, d),
         SYS_DEBUG => sys::sys_debug(a, b),
         SYS_ACCESS => sys::sys_access(a, b, c, d, e),
         SYS_KILL => sys::sys_kill(a, b),
@@ -111,5 +149,7 @@ pub fn syscall_handler(num: u64, a: u64, b: u64, c: u64, d: u64, e: u64, f: u64)
         }
     };
 
+    crate::kernel::sched::current_task_ref().strace_finish(res);
+
     res
 }
@@ -1,61 +1,115 @@
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::sync::Weak;
+use alloc::vec;
 use alloc::vec::Vec;
-use syscall_defs::net::{MsgFlags, MsgHdr, SockAddrPtr, SockDomain, SockOption, SockTypeFlags};
+use core::sync::atomic::{AtomicIsize, Ordering};
+use syscall_defs::exec::ExeArgs;
+use syscall_defs::meminfo::{MemInfo, SlabInfo};
+use syscall_defs::net::{
+    IoVec, MsgFlags, MsgHdr, SockAddrPtr, SockAddrStorage, SockDomain, SockOption, SockTypeFlags,
+};
 use syscall_defs::poll::{FdSet, PollEventFlags};
-use syscall_defs::signal::SigAction;
+use syscall_defs::ptrace::PtraceRequest;
+use syscall_defs::resource::{RLimit, RLimitKind, RUsageWho, RLIM_INFINITY};
+use syscall_defs::sched::{nice_to_prio, PrioWhich, SchedParam, SchedPolicy, NICE_MAX};
+use syscall_defs::signal::{SigAction, SigAltStack, SigInfo, SIGALRM, SI_USER};
 use syscall_defs::stat::Mode;
-use syscall_defs::time::Timespec;
+use syscall_defs::time::{
+    ns_to_clock_ticks, ClockId, ITimerSpec, ITimerVal, ITimerWhich, SigEvent, Timespec, Timeval,
+    Tms, SIGEV_NONE, SIGEV_SIGNAL, TIMER_ABSTIME,
+};
 use syscall_defs::{
-    AtFlags, FDFlags, FcntlCmd, FileType, MMapFlags, MMapProt, OpenFD, SyscallResult,
+    AtFlags, FDFlags, FcntlCmd, FileType, MLockAllFlags, MMapFlags, MMapProt, OpenFD, SyscallResult,
 };
-use syscall_defs::{OpenFlags, SyscallError};
+use syscall_defs::{CloneFlags, OpenFlags, SyscallError, CLONE_SIGNAL_MASK};
 
+use crate::arch::ipi::IpiKind;
 use crate::kernel::device::dev_t::DevId;
 use crate::kernel::fs::dirent::{DirEntry, DirEntryItem};
 use crate::kernel::fs::filesystem::FilesystemKind;
 use crate::kernel::fs::inode::INode;
 use crate::kernel::fs::path::Path;
 use crate::kernel::fs::poll::PollTable;
+use crate::kernel::fs::vfs::FsError;
 use crate::kernel::fs::{lookup_by_path, lookup_by_path_at, lookup_by_real_path, LookupMode};
+use crate::kernel::ipi::IpiTarget;
+use crate::kernel::mm::uaccess::{
+    check_user_access, copy_from_user, read_user, read_user_bytes, read_user_slice, write_user,
+    write_user_slice,
+};
 use crate::kernel::mm::VirtAddr;
 use crate::kernel::net::ip::Ip4;
 use crate::kernel::net::socket::SocketService;
 use crate::kernel::sched::{current_task, current_task_ref, SleepFlags};
-use crate::kernel::signal::SignalEntry;
+use crate::kernel::signal::{SignalEntry, SIGNAL_COUNT};
+use crate::kernel::task::itimer::ITimerValue;
+use crate::kernel::task::resource::Usage;
+use crate::kernel::task::{ArcTask, CloneArgs, Task};
 use crate::kernel::utils::node_map::NodeMapItem;
-use crate::kernel::utils::types::Prefault;
 
-//TODO: Check if the pointer from user is actually valid
-fn make_buf_mut(b: u64, len: u64) -> &'static mut [u8] {
-    let buf = unsafe { core::slice::from_raw_parts_mut(b as *mut u8, len as usize) };
+/// Maximum size of the kernel buffer user data is copied through, longer reads return short
+const MAX_BOUNCE: usize = 0x100000;
+
+/// Reads into a kernel buffer and copies the result to the user buffer
+fn read_buf(
+    b: u64,
+    len: u64,
+    read: impl FnOnce(&mut [u8]) -> Result<usize, FsError>,
+) -> SyscallResult {
+    let addr = VirtAddr(b as usize);
+
+    // Fail before consuming any data
+    check_user_access(addr, len as usize, true)?;
+
+    let mut buf = vec![0u8; (len as usize).min(MAX_BOUNCE)];
 
-    // Prefault userspace buffers, otherwise page fault might happen while we are holding a lock
-    buf.prefault();
+    let read = read(&mut buf)?;
 
-    buf
+    write_user_slice(addr, &buf[..read])?;
+
+    Ok(read)
 }
 
-//TODO: Check if the pointer from user is actually valid
-fn make_buf(b: u64, len: u64) -> &'static [u8] {
-    let buf = unsafe { core::slice::from_raw_parts(b as *const u8, len as usize) };
+/// Copies the user buffer to the kernel in chunks and writes them, stops on a short write
+fn write_buf(
+    b: u64,
+    len: u64,
+    mut write: impl FnMut(&[u8], usize) -> Result<usize, FsError>,
+) -> SyscallResult {
+    let len = len as usize;
+
+    let mut total = 0;
+
+    loop {
+        let buf = read_user_bytes(VirtAddr(b as usize + total), (len - total).min(MAX_BOUNCE))?;
+
+        let written = match write(&buf, total) {
+            Ok(written) => written,
+            Err(_) if total > 0 => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        total += written;
 
-    // Prefault userspace buffers, otherwise page fault might happen while we are holding a lock
-    buf.prefault();
+        if written < buf.len() || total == len {
+            break;
+        }
+    }
 
-    buf
+    Ok(total)
 }
 
-fn make_str<'a>(b: u64, len: u64) -> &'a str {
-    unsafe { core::str::from_utf8_unchecked(make_buf(b, len)) }
+fn make_str(b: u64, len: u64) -> Result<String, SyscallError> {
+    String::from_utf8(read_user_bytes(VirtAddr(b as usize), len as usize)?)
+        .map_err(|_| SyscallError::EINVAL)
 }
 
-fn make_path<'a>(path: u64, path_len: u64) -> Option<Path<'a>> {
+fn make_path(path: u64, path_len: u64) -> Result<Option<String>, SyscallError> {
     if path != 0 && path_len != 0 {
-        Some(Path::new(make_str(path, path_len)))
+        Ok(Some(make_str(path, path_len)?))
     } else {
-        None
+        Ok(None)
     }
 }
 
@@ -117,14 +171,15 @@ fn get_dir_entry(
 }
 
 pub fn sys_open(at: u64, path: u64, len: u64, mode: u64) -> SyscallResult {
-    dbgln!(sys_open, "sys_open {} {} {:x}", at, make_str(path, len), mode);
+    let path = make_path(path, len)?;
+    dbgln!(sys_open, "sys_open {} {:?} {:x}", at, path, mode);
     let flags = OpenFlags::from_bits(mode as usize).ok_or(SyscallError::EINVAL)?;
 
     let at = OpenFD::try_from(at)?;
 
     let inode = get_dir_entry(
         at,
-        make_path(path, len),
+        path.as_deref().map(Path::new),
         if flags.contains(OpenFlags::CREAT) {
             LookupMode::Create
         } else {
@@ -181,7 +236,7 @@ pub fn sys_write(fd: u64, buf: u64, len: u64) -> SyscallResult {
 
     return match task.get_handle(fd) { Some(f) => {
         if f.flags().is_writable() {
-            Ok(f.write(make_buf(buf, len))?)
+            write_buf(buf, len, |b, _| f.write(b))
         } else {
             logln4!("write fd {} = EACCESS", fd);
             Err(SyscallError::EACCES)
@@ -201,7 +256,7 @@ pub fn sys_read(fd: u64, buf: u64, len: u64) -> SyscallResult {
 
     return match task.get_handle(fd) { Some(f) => {
         if f.flags().is_readable() {
-            Ok(f.read(make_buf_mut(buf, len))?)
+            read_buf(buf, len, |b| f.read(b))
         } else {
             logln2!("eaccess");
             Err(SyscallError::EACCES)
@@ -222,7 +277,7 @@ pub fn sys_readlink(
 ) -> SyscallResult {
     let inode = get_dir_entry(
         OpenFD::try_from(at)?,
-        make_path(path, path_len),
+        make_path(path, path_len)?.as_deref().map(Path::new),
         LookupMode::None,
         true,
     )?;
@@ -237,13 +292,11 @@ pub fn sys_readlink(
         return Err(SyscallError::EFAULT);
     }
 
-    make_buf_mut(buf, link.len() as u64).copy_from_slice(link.as_bytes());
+    write_user_slice(VirtAddr(buf as usize), link.as_bytes())?;
 
     logln4!("read link read {}, len: {}", link, link.len());
 
-    unsafe {
-        *VirtAddr(len as usize).read_mut() = link.len();
-    }
+    write_user(VirtAddr(len as usize), &link.len())?;
 
     Ok(0)
 }
@@ -255,7 +308,7 @@ pub fn sys_pread(fd: u64, buf: u64, len: u64, offset: u64) -> SyscallResult {
 
     match task.get_handle(fd) { Some(f) => {
         if f.flags().is_readable() {
-            Ok(f.read_at(make_buf_mut(buf, len), offset as usize)?)
+            read_buf(buf, len, |b| f.read_at(b, offset as usize))
         } else {
             Err(SyscallError::EACCES)
         }
@@ -271,7 +324,7 @@ pub fn sys_pwrite(fd: u64, buf: u64, len: u64, offset: u64) -> SyscallResult {
     match task.get_handle(fd) { Some(f) => {
         if f.flags().is_writable() {
             logln4!("pwrite fd {}", fd);
-            Ok(f.write_at(make_buf(buf, len), offset as usize)?)
+            write_buf(buf, len, |b, off| f.write_at(b, offset as usize + off))
         } else {
             logln4!("pwrite fd {} = EACCESS", fd);
             Err(SyscallError::EACCES)
@@ -297,7 +350,12 @@ pub fn sys_seek(fd: u64, off: u64, whence: u64) -> SyscallResult {
 pub fn sys_access(at: u64, path: u64, path_len: u64, _mode: u64, _flags: u64) -> SyscallResult {
     let at = OpenFD::try_from(at)?;
 
-    get_dir_entry(at, make_path(path, path_len), LookupMode::None, false)?;
+    get_dir_entry(
+        at,
+        make_path(path, path_len)?.as_deref().map(Path::new),
+        LookupMode::None,
+        false,
+    )?;
 
     Ok(0)
 }
@@ -384,28 +442,25 @@ pub fn sys_mmap(addr: u64, len: u64, prot: u64, flags: u64, fd: u64, offset: u64
     };
     let offset = offset as usize;
 
-    if let Some(res) = task
+    let res = task
         .vm()
-        .mmap_vm(addr, len, prot, flags, file.clone(), offset)
-    {
-        dbgln!(
-            map | map_call,
-            "mmap at {} len: 0x{:X} | {:?} {}, fd: {}",
-            res,
-            len,
-            flags,
-            if let Some(f) = &file {
-                f.get_fs_dir_item().full_path()
-            } else {
-                "no_file".to_string()
-            },
-            fd
-        );
-        task.vm().log_vm();
-        Ok(res.0)
-    } else {
-        Err(SyscallError::EFAULT)
-    }
+        .mmap_vm(addr, len, prot, flags, file.clone(), offset)?;
+
+    dbgln!(
+        map | map_call,
+        "mmap at {} len: 0x{:X} | {:?} {}, fd: {}",
+        res,
+        len,
+        flags,
+        if let Some(f) = &file {
+            f.get_fs_dir_item().full_path()
+        } else {
+            "no_file".to_string()
+        },
+        fd
+    );
+    task.vm().log_vm();
+    Ok(res.0)
 }
 
 pub fn sys_mprotect(addr: u64, size: u64, prot: u64) -> SyscallResult {
@@ -433,6 +488,38 @@ pub fn sys_munmap(addr: u64, len: u64) -> SyscallResult {
     }
 }
 
+pub fn sys_mlock(addr: u64, len: u64) -> SyscallResult {
+    let addr = VirtAddr(addr as usize);
+
+    dbgln!(map_call, "mlock {} len: 0x{:X}", addr, len);
+
+    current_task_ref().vm().mlock(addr, len as usize)
+}
+
+pub fn sys_munlock(addr: u64, len: u64) -> SyscallResult {
+    let addr = VirtAddr(addr as usize);
+
+    dbgln!(map_call, "munlock {} len: 0x{:X}", addr, len);
+
+    current_task_ref().vm().munlock(addr, len as usize)
+}
+
+pub fn sys_mlockall(flags: u64) -> SyscallResult {
+    let flags = MLockAllFlags::from_bits(flags as usize).ok_or(SyscallError::EINVAL)?;
+
+    if flags.is_empty() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    current_task_ref().vm().mlockall(flags)
+}
+
+pub fn sys_munlockall() -> SyscallResult {
+    current_task_ref().vm().munlockall();
+
+    Ok(0)
+}
+
 pub fn sys_maps() -> SyscallResult {
     logln!(
         "free mem before fork: {}, used: {} heap: {}",
@@ -441,6 +528,17 @@ pub fn sys_maps() -> SyscallResult {
         crate::kernel::mm::heap::heap_mem(),
     );
 
+    crate::kernel::mm::slab::print_stats();
+
+    let rss = current_task_ref().vm().rss();
+
+    logln!(
+        "rss: anon {} kB, file {} kB, zero page shared {} kB",
+        rss.anon / 1024,
+        rss.file / 1024,
+        rss.zero / 1024,
+    );
+
     current_task_ref().vm().log_vm();
 
     //crate::kernel::fs::dirent::cache().print_stats();
@@ -450,10 +548,49 @@ pub fn sys_maps() -> SyscallResult {
     Ok(0)
 }
 
+pub fn sys_meminfo(info: u64) -> SyscallResult {
+    let rss = current_task_ref().vm().rss();
+
+    let out = MemInfo {
+        free: crate::kernel::mm::free_mem() as u64,
+        used: crate::kernel::mm::used_mem() as u64,
+        heap: crate::kernel::mm::heap::heap_mem() as u64,
+        slab: crate::kernel::mm::slab::used_mem() as u64,
+        rss_anon: rss.anon as u64,
+        rss_file: rss.file as u64,
+        rss_zero: rss.zero as u64,
+    };
+
+    write_user(VirtAddr(info as usize), &out)?;
+
+    Ok(0)
+}
+
+/// Writes the statistics of up to count slab size classes, returns the number written
+pub fn sys_slabinfo(buf: u64, count: u64) -> SyscallResult {
+    let stats = crate::kernel::mm::slab::stats();
+
+    let out = stats
+        .iter()
+        .take(count as usize)
+        .map(|s| SlabInfo {
+            obj_size: s.obj_size as u64,
+            slabs: s.slabs as u64,
+            inuse: s.inuse as u64,
+            allocs: s.allocs as u64,
+            frees: s.frees as u64,
+        })
+        .collect::<Vec<SlabInfo>>();
+
+    write_user_slice(VirtAddr(buf as usize), &out)?;
+
+    Ok(out.len())
+}
+
 pub fn sys_chdir(at: u64, path: u64, len: u64) -> SyscallResult {
     let dir = get_dir_entry(
         OpenFD::try_from(at)?,
-        make_path(path, len),
+        make_path(path, len)?.as_deref().map(Path::new),
         LookupMode::None,
         false,
     )?;
@@ -471,14 +608,12 @@ pub fn sys_chdir(at: u64, path: u64, len: u64) -> SyscallResult {
 
 pub fn sys_getcwd(buf: u64, len: u64) -> SyscallResult {
     logln!("getcwd len: {}", len);
-    let buf = make_buf_mut(buf, if len > 0 { len } else { 255 });
-
     if let Some(pwd) = current_task_ref().get_pwd() {
         if pwd.len() > len as usize {
             Err(SyscallError::EIO)
         } else {
             logln!("getcwd {}", pwd);
-            buf[..pwd.len()].copy_from_slice(pwd.as_bytes());
+            write_user_slice(VirtAddr(buf as usize), pwd.as_bytes())?;
             Ok(pwd.len())
         }
     } else {
@@ -489,7 +624,8 @@ pub fn sys_getcwd(buf: u64, len: u64) -> SyscallResult {
 pub fn sys_mkdir(at: u64, path: u64, path_len: u64) -> SyscallResult {
     let at = OpenFD::try_from(at)?;
 
-    let path = make_path(path, path_len).ok_or(SyscallError::EINVAL)?;
+    let path = make_path(path, path_len)?.ok_or(SyscallError::EINVAL)?;
+    let path = Path::new(&path);
 
     let (inode, name) = {
         let (dir, target) = path.containing_dir();
@@ -518,7 +654,7 @@ pub fn sys_getdents(fd: u64, buf: u64, len: u64) -> SyscallResult {
 
     let task = current_task_ref();
     match task.get_handle(fd) { Some(f) => {
-        Ok(f.get_dents(make_buf_mut(buf, len))?)
+        read_buf(buf, len, |b| f.get_dents(b))
     } _ => {
         Err(SyscallError::EBADFD)
     }}
@@ -531,10 +667,10 @@ pub fn sys_symlink(
     linkpath: u64,
     linkpath_len: u64,
 ) -> SyscallResult {
-    let target = make_str(target, target_len);
-    let path = make_str(linkpath, linkpath_len);
+    let target = make_str(target, target_len)?;
+    let path = make_str(linkpath, linkpath_len)?;
 
-    let path = Path::new(path);
+    let path = Path::new(&path);
 
     let (inode, name) = {
         let (dir, target) = path.containing_dir();
@@ -546,7 +682,7 @@ pub fn sys_symlink(
     };
 
     if inode.ftype()? == FileType::Dir {
-        inode.symlink(name.str(), target)?;
+        inode.symlink(name.str(), &target)?;
 
         Ok(0)
     } else {
@@ -569,7 +705,8 @@ fn remove_dir(file: &DirEntryItem, path: &Path) -> SyscallResult {
 }
 
 pub fn sys_rmdir(path: u64, path_len: u64) -> SyscallResult {
-    let path = Path::new(make_str(path, path_len));
+    let path = make_str(path, path_len)?;
+    let path = Path::new(&path);
     let dir = lookup_by_real_path(&path, LookupMode::None)?;
 
     remove_dir(&dir, &path)
@@ -578,9 +715,9 @@ pub fn sys_rmdir(path: u64, path_len: u64) -> SyscallResult {
 pub fn sys_unlink(at: u64, path: u64, path_len: u64, flags: u64) -> SyscallResult {
     let at = OpenFD::try_from(at)?;
 
-    let path_str = make_str(path, path_len);
+    let path_str = make_str(path, path_len)?;
 
-    let path = Path::new(path_str);
+    let path = Path::new(&path_str);
 
     dbgln!(unlink, "sys_unlink: {}, flags: {}", path.str(), flags);
 
@@ -591,7 +728,7 @@ pub fn sys_unlink(at: u64, path: u64, path_len: u64, flags: u64) -> SyscallResul
 
     let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
-    let path = Path::new(path_str);
+    let path = Path::new(&path_str);
     if flags.contains(AtFlags::REMOVEDIR) {
         return remove_dir(&file, &path);
     }
@@ -614,7 +751,8 @@ pub fn sys_unlink(at: u64, path: u64, path_len: u64, flags: u64) -> SyscallResul
 pub fn sys_mknode(at: u64, path: u64, path_len: u64, mode: u64, devid: u64) -> SyscallResult {
     let at = OpenFD::try_from(at)?;
 
-    let path = Path::new(make_str(path, path_len));
+    let path = make_str(path, path_len)?;
+    let path = Path::new(&path);
 
     let mode = Mode::from_bits_truncate(mode as u32);
 
@@ -644,13 +782,15 @@ pub fn sys_link(
 ) -> SyscallResult {
     let target_entry = get_dir_entry(
         target_at.try_into()?,
-        make_path(target, target_len),
+        make_path(target, target_len)?.as_deref().map(Path::new),
         LookupMode::None,
         true,
     )?;
 
+    let path = make_str(linkpath, linkpath_len)?;
+
     let (inode, name) = {
-        let path = Path::new(make_str(linkpath, linkpath_len));
+        let path = Path::new(&path);
 
         let (dir, name) = path.containing_dir();
 
@@ -675,15 +815,16 @@ pub fn sys_link(
 
 pub fn sys_chmod(at: u64, path: u64, path_len: u64, mode: u64, flags: u64) -> SyscallResult {
     let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+    let path = make_path(path, path_len)?;
     logln5!(
         "sys_chmod: {:?} {:?} {:#o}",
         OpenFD::try_from(at)?,
-        make_path(path, path_len),
+        path,
         mode
     );
     let inode = get_dir_entry(
         at.try_into()?,
-        make_path(path, path_len),
+        path.as_deref().map(Path::new),
         LookupMode::None,
         flags.contains(AtFlags::SYMLINK_NOFOLLOW),
     )?;
@@ -697,21 +838,22 @@ pub fn sys_chmod(at: u64, path: u64, path_len: u64, mode: u64, flags: u64) -> Sy
 
 pub fn sys_utime(at: u64, path: u64, path_len: u64, times: u64, flags: u64) -> SyscallResult {
     let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+    let path = make_path(path, path_len)?;
     logln5!(
         "sys_utime: {:?} {:?} {:#x}",
         OpenFD::try_from(at)?,
-        make_path(path, path_len),
+        path,
         times
     );
     let inode = get_dir_entry(
         at.try_into()?,
-        make_path(path, path_len),
+        path.as_deref().map(Path::new),
         LookupMode::None,
         flags.contains(AtFlags::SYMLINK_NOFOLLOW),
     )?;
 
     let times = &if times != 0 {
-        unsafe { VirtAddr(times as usize).read::<[Timespec; 2]>() }
+        read_user::<[Timespec; 2]>(VirtAddr(times as usize))?
     } else {
         let now = crate::kernel::time::unix_timestamp() as u32;
         [Timespec::from_secs(now as usize); 2]
@@ -732,13 +874,15 @@ pub fn sys_rename(
 ) -> SyscallResult {
     let old = get_dir_entry(
         old_at.try_into()?,
-        make_path(oldpath, oldpath_len),
+        make_path(oldpath, oldpath_len)?.as_deref().map(Path::new),
         LookupMode::None,
         true,
     )?;
 
+    let new_path = make_str(newpath, newpath_len)?;
+
     let (new, name) = {
-        let new_path = Path::new(make_str(newpath, newpath_len));
+        let new_path = Path::new(&new_path);
 
         let (dir, name) = new_path.containing_dir();
 
@@ -782,13 +926,11 @@ pub fn sys_rename(
 }
 
 pub fn sys_getaddrinfo(name: u64, nlen: u64, buf: u64, blen: u64) -> SyscallResult {
-    if let Ok(name) = core::str::from_utf8(make_buf(name, nlen)) {
+    if let Ok(name) = make_str(name, nlen) {
         let ip = crate::kernel::net::dns::get_ip_by_host(name.as_bytes())?;
 
         return if blen as usize >= core::mem::size_of::<Ip4>() {
-            let buf = make_buf_mut(buf, blen);
-
-            buf.copy_from_slice(&ip.v);
+            write_user_slice(VirtAddr(buf as usize), &ip.v)?;
 
             Ok(core::mem::size_of::<Ip4>())
         } else {
@@ -834,14 +976,152 @@ pub fn sys_socketpair(domain: u64, typ: u64, _protocol: u64, fds: u64) -> Syscal
     let fd1 = task.open_file(DirEntry::inode_wrap(s1.clone()), OpenFlags::RDWR)?;
     let fd2 = task.open_file(DirEntry::inode_wrap(s2.clone()), OpenFlags::RDWR)?;
 
-    let fds = unsafe { VirtAddr(fds as usize).read_mut::<[i32; 2]>() };
-
-    fds[0] = fd1 as i32;
-    fds[1] = fd2 as i32;
+    write_user(VirtAddr(fds as usize), &[fd1 as i32, fd2 as i32])?;
 
     Ok(0)
 }
 
+/// Maximum size of a socket option value
+const MAX_SOCK_OPT: usize = 0x1000;
+
+/// Kernel copy of a user socket address
+#[repr(C, align(8))]
+struct SockAddrBuf([u8; size_of::<SockAddrStorage>()]);
+
+impl SockAddrBuf {
+    fn new() -> SockAddrBuf {
+        SockAddrBuf([0; size_of::<SockAddrStorage>()])
+    }
+
+    fn read(addr: u64, len: usize) -> Result<SockAddrBuf, SyscallError> {
+        if len > size_of::<SockAddrStorage>() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let mut buf = SockAddrBuf::new();
+
+        if addr != 0 {
+            copy_from_user(&mut buf.0[..len], VirtAddr(addr as usize))?;
+        }
+
+        Ok(buf)
+    }
+
+    /// Null if there is no user address
+    fn ptr(&mut self, addr: u64) -> SockAddrPtr {
+        if addr != 0 {
+            SockAddrPtr::new(self.0.as_mut_ptr() as *mut ())
+        } else {
+            SockAddrPtr::new(core::ptr::null_mut())
+        }
+    }
+}
+
+/// Kernel copy of a user message header, the socket code only accesses kernel buffers
+struct MsgBuf {
+    user: MsgHdr,
+    name: SockAddrBuf,
+    iovecs: Vec<IoVec>,
+    data: Vec<Vec<u8>>,
+}
+
+impl MsgBuf {
+    fn new(addr: VirtAddr, send: bool) -> Result<MsgBuf, SyscallError> {
+        let user = read_user::<MsgHdr>(addr)?;
+
+        let name = if send {
+            SockAddrBuf::read(user.msg_name.addr() as u64, user.msg_namelen as usize)?
+        } else {
+            SockAddrBuf::new()
+        };
+
+        let mut data = Vec::new();
+        let mut left = MAX_BOUNCE;
+
+        for iov in read_user_slice::<IoVec>(VirtAddr(user.msg_iov as usize), user.msg_iovlen)? {
+            let len = iov.iov_len.min(left);
+
+            data.push(if send {
+                read_user_bytes(VirtAddr(iov.iov_base as usize), len)?
+            } else {
+                check_user_access(VirtAddr(iov.iov_base as usize), iov.iov_len, true)?;
+
+                vec![0u8; len]
+            });
+
+            left -= len;
+        }
+
+        let iovecs = data
+            .iter()
+            .map(|d| IoVec {
+                iov_base: d.as_ptr() as *const (),
+                iov_len: d.len(),
+            })
+            .collect();
+
+        Ok(MsgBuf {
+            user,
+            name,
+            iovecs,
+            data,
+        })
+    }
+
+    fn hdr(&mut self) -> MsgHdr {
+        MsgHdr {
+            msg_name: self.name.ptr(self.user.msg_name.addr() as u64),
+            msg_namelen: self
+                .user
+                .msg_namelen
+                .min(size_of::<SockAddrStorage>() as u32),
+            msg_iov: self.iovecs.as_ptr(),
+            msg_iovlen: self.iovecs.len(),
+            msg_control: core::ptr::null(),
+            msg_controllen: 0,
+            msg_flags: self.user.msg_flags,
+        }
+    }
+
+    /// Copies received data and the updated header back to the user
+    fn copy_out(
+        &self,
+        addr: VirtAddr,
+        hdr: &MsgHdr,
+        mut received: usize,
+    ) -> Result<(), SyscallError> {
+        let user_iovecs =
+            read_user_slice::<IoVec>(VirtAddr(self.user.msg_iov as usize), self.user.msg_iovlen)?;
+
+        for (iov, data) in user_iovecs.iter().zip(self.data.iter()) {
+            if received == 0 {
+                break;
+            }
+
+            let len = data.len().min(received);
+
+            write_user_slice(VirtAddr(iov.iov_base as usize), &data[..len])?;
+
+            received -= len;
+        }
+
+        if !self.user.msg_name.is_null() {
+            let len = (hdr.msg_namelen as usize)
+                .min(self.user.msg_namelen as usize)
+                .min(self.name.0.len());
+
+            write_user_slice(VirtAddr(self.user.msg_name.addr()), &self.name.0[..len])?;
+        }
+
+        let mut user = self.user;
+
+        user.msg_namelen = hdr.msg_namelen;
+        user.msg_flags = hdr.msg_flags;
+
+        Ok(write_user(addr, &user)?)
+    }
+}
+
 fn get_socket(fd: usize) -> Result<Arc<dyn SocketService>, SyscallError> {
     let task = current_task_ref();
 
@@ -856,34 +1136,44 @@ pub fn sys_bind(sockfd: u64, addr_ptr: u64, addrlen: u64) -> SyscallResult {
 
     logln!("found socket!");
 
-    let addr = SockAddrPtr::new(addr_ptr as *mut ());
+    let mut addr = SockAddrBuf::read(addr_ptr, addrlen as usize)?;
 
-    sock.bind(addr, addrlen as u32)
+    sock.bind(addr.ptr(addr_ptr), addrlen as u32)
 }
 
 pub fn sys_connect(sockfd: u64, addr_ptr: u64, addrlen: u64) -> SyscallResult {
     let sock = get_socket(sockfd as usize)?;
 
-    let addr = SockAddrPtr::new(addr_ptr as *mut ());
+    let mut addr = SockAddrBuf::read(addr_ptr, addrlen as usize)?;
 
-    sock.connect(addr, addrlen as u32)
+    sock.connect(addr.ptr(addr_ptr), addrlen as u32)
 }
 
 pub fn sys_accept(fd: u64, addr_ptr: u64, addr_len: u64, _flags: u64) -> SyscallResult {
     let sock = get_socket(fd as usize)?;
 
-    let (ptr, len) = if addr_ptr != 0 && addr_len != 0 {
-        unsafe {
-            (
-                SockAddrPtr::new(addr_ptr as *mut ()),
-                Some(VirtAddr(addr_len as usize).read_mut::<u32>()),
-            )
-        }
+    let mut len = if addr_ptr != 0 && addr_len != 0 {
+        let len = read_user::<u32>(VirtAddr(addr_len as usize))?;
+
+        check_user_access(VirtAddr(addr_ptr as usize), len as usize, true)?;
+
+        Some(len.min(size_of::<SockAddrStorage>() as u32))
     } else {
-        (SockAddrPtr::new(core::ptr::null_mut()), None)
+        None
     };
 
-    let sock = sock.accept(ptr, len)?;
+    let mut addr = SockAddrBuf::new();
+
+    let ptr = addr.ptr(if len.is_some() { addr_ptr } else { 0 });
+
+    let sock = sock.accept(ptr, len.as_mut())?;
+
+    if let Some(len) = len {
+        let copy = (len as usize).min(addr.0.len());
+
+        write_user_slice(VirtAddr(addr_ptr as usize), &addr.0[..copy])?;
+        write_user(VirtAddr(addr_len as usize), &len)?;
+    }
 
     let task = current_task_ref();
 
@@ -910,7 +1200,9 @@ pub fn sys_msg_recv(sockfd: u64, hdr: u64, flags: u64) -> SyscallResult {
         return Err(SyscallError::EINVAL);
     }
 
-    let hdr = unsafe { VirtAddr(hdr as usize).read_mut::<MsgHdr>() };
+    let hdr_addr = VirtAddr(hdr as usize);
+
+    let mut msg = MsgBuf::new(hdr_addr, false)?;
 
     let task = current_task_ref();
 
@@ -920,7 +1212,16 @@ pub fn sys_msg_recv(sockfd: u64, hdr: u64, flags: u64) -> SyscallResult {
 
     logln5!("msg_recv got socket!");
 
-    sock.msg_recv(hdr, MsgFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?)
+    let mut hdr = msg.hdr();
+
+    let res = sock.msg_recv(
+        &mut hdr,
+        MsgFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?,
+    );
+
+    msg.copy_out(hdr_addr, &hdr, *res.as_ref().unwrap_or(&0))?;
+
+    res
 }
 
 pub fn sys_msg_send(sockfd: u64, hdr: u64, flags: u64) -> SyscallResult {
@@ -929,12 +1230,14 @@ pub fn sys_msg_send(sockfd: u64, hdr: u64, flags: u64) -> SyscallResult {
         return Err(SyscallError::EINVAL);
     }
 
-    let hdr = unsafe { VirtAddr(hdr as usize).read_ref::<MsgHdr>() };
+    let mut msg = MsgBuf::new(VirtAddr(hdr as usize), true)?;
+
+    let hdr = msg.hdr();
 
     logln!(
         "hdr name len {} name addr {:#X}",
         hdr.msg_namelen,
-        hdr.msg_name.addr()
+        msg.user.msg_name.addr()
     );
 
     let task = current_task_ref();
@@ -949,17 +1252,27 @@ pub fn sys_msg_send(sockfd: u64, hdr: u64, flags: u64) -> SyscallResult {
 
     logln!("sys_msg_send flags {:?}", flags);
 
-    sock.msg_send(hdr, flags)
+    sock.msg_send(&hdr, flags)
 }
 
 pub fn sys_setsockopt(fd: u64, layer: u64, number: u64, buffer: u64, size: u64) -> SyscallResult {
     logln5!("setsockopt: {} {} {}", fd, layer, number);
     let sock = get_socket(fd as usize)?;
 
+    if size as usize > MAX_SOCK_OPT {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let value = read_user_slice::<u64>(VirtAddr(buffer as usize), (size as usize).div_ceil(8))?;
+
     sock.set_socket_option(
         layer as i32,
         SockOption::try_from(number)?,
-        buffer as *const (),
+        if buffer != 0 {
+            value.as_ptr() as *const ()
+        } else {
+            core::ptr::null()
+        },
         size as u32,
     )
 }
@@ -968,16 +1281,46 @@ pub fn sys_getsockopt(fd: u64, layer: u64, number: u64, buffer: u64, size: u64)
     logln5!("setsockopt: {} {} {}", fd, layer, number);
     let sock = get_socket(fd as usize)?;
 
-    sock.get_socket_option(
+    let mut len = if size == 0 {
+        None
+    } else {
+        let len = read_user::<u32>(VirtAddr(size as usize))?;
+
+        check_user_access(VirtAddr(buffer as usize), len as usize, true)?;
+
+        Some(len.min(MAX_SOCK_OPT as u32))
+    };
+
+    let user_len = len.unwrap_or(0) as usize;
+
+    let mut value = vec![0u64; user_len / 8 + 1];
+
+    let res = sock.get_socket_option(
         layer as i32,
         SockOption::try_from(number)?,
-        buffer as *mut (),
-        if size == 0 {
-            None
+        if buffer != 0 {
+            value.as_mut_ptr() as *mut ()
         } else {
-            unsafe { Some(VirtAddr(size as usize).read_mut::<u32>()) }
+            core::ptr::null_mut()
         },
-    )
+        len.as_mut(),
+    );
+
+    if let Some(len) = len {
+        let value =
+            unsafe { core::slice::from_raw_parts(value.as_ptr() as *const u8, value.len() * 8) };
+
+        if buffer != 0 {
+            write_user_slice(
+                VirtAddr(buffer as usize),
+                &value[..(len as usize).min(user_len)],
+            )?;
+        }
+
+        write_user(VirtAddr(size as usize), &len)?;
+    }
+
+    res
 }
 
 pub fn sys_select(
@@ -991,7 +1334,7 @@ pub fn sys_select(
     let timeout = if timeout == 0 {
         None
     } else {
-        unsafe { Some(VirtAddr(timeout as usize).read_ref::<Timespec>()) }
+        Some(read_user::<Timespec>(VirtAddr(timeout as usize))?)
     };
 
     logln!(
@@ -1005,23 +1348,25 @@ pub fn sys_select(
 
     current_task_ref().filetable().debug();
 
-    let mut input: [Option<(&mut FdSet, PollEventFlags)>; 3] = [None, None, None];
+    let mut input: [Option<(FdSet, PollEventFlags)>; 3] = [None, None, None];
 
-    let mut initfds = |idx: usize, addr: usize, flags: PollEventFlags| {
+    let mut initfds = |idx: usize, addr: usize, flags: PollEventFlags| -> Result<(), SyscallError> {
         if addr == 0 {
-            return;
+            return Ok(());
         }
 
-        let fdset = unsafe { VirtAddr(addr).read_mut::<FdSet>() };
+        let fdset = read_user::<FdSet>(VirtAddr(addr))?;
 
         logln!("select fdset {} {:?}", idx, fdset.fds);
 
         input[idx] = Some((fdset, flags));
+
+        Ok(())
     };
 
-    initfds(0, readfds as usize, PollEventFlags::READ);
-    initfds(1, writefds as usize, PollEventFlags::WRITE);
-    initfds(2, exceptfds as usize, PollEventFlags::empty());
+    initfds(0, readfds as usize, PollEventFlags::READ)?;
+    initfds(1, writefds as usize, PollEventFlags::WRITE)?;
+    initfds(2, exceptfds as usize, PollEventFlags::empty())?;
 
     let mut to_poll = Vec::<(usize, PollEventFlags)>::new();
 
@@ -1120,6 +1465,12 @@ pub fn sys_select(
 
     logln!("select found {}", found);
 
+    for (addr, inp) in [readfds, writefds, exceptfds].iter().zip(&input) {
+        if let Some((fdset, _)) = inp {
+            write_user(VirtAddr(*addr as usize), fdset)?;
+        }
+    }
+
     Ok(found)
 }
 
@@ -1130,8 +1481,10 @@ pub fn sys_poll(fds: u64, nfds: u64, timeout: u64) -> SyscallResult {
 
     let timeout_ms = timeout as i32;
 
-    let fds = if fds != 0 {
-        unsafe { VirtAddr(fds as usize).as_slice_mut::<syscall_defs::poll::PollFd>(nfds as usize) }
+    let fds_addr = VirtAddr(fds as usize);
+
+    let mut fds = if fds != 0 {
+        read_user_slice::<syscall_defs::poll::PollFd>(fds_addr, nfds as usize)?
     } else {
         return Err(SyscallError::EINVAL);
     };
@@ -1145,7 +1498,7 @@ pub fn sys_poll(fds: u64, nfds: u64, timeout: u64) -> SyscallResult {
     let mut timed_out = false;
 
     'search: loop {
-        for fd in &mut *fds {
+        for fd in fds.iter_mut() {
             if fd.fd < 0 {
                 fd.revents = PollEventFlags::empty();
                 continue;
@@ -1183,6 +1536,9 @@ pub fn sys_poll(fds: u64, nfds: u64, timeout: u64) -> SyscallResult {
     }
 
     dbgln!(poll, "poll ret: {:?}", fds);
+
+    write_user_slice(fds_addr, &fds)?;
+
     Ok(found)
 }
 
@@ -1194,16 +1550,16 @@ pub fn sys_mount(
     fs: u64,
     fs_len: u64,
 ) -> SyscallResult {
-    let dev_path = make_str(src, src_len);
-    let dest_path = make_str(dest, dest_len);
-    let fs = make_str(fs, fs_len);
+    let dev_path = make_str(src, src_len)?;
+    let dest_path = make_str(dest, dest_len)?;
+    let fs = make_str(fs, fs_len)?;
 
     if fs != "ext2" {
         return Err(SyscallError::EINVAL);
     }
 
-    let dev = lookup_by_path(&Path::new(dev_path), LookupMode::None)?.inode();
-    let dest = lookup_by_path(&Path::new(dest_path), LookupMode::None)?;
+    let dev = lookup_by_path(&Path::new(&dev_path), LookupMode::None)?.inode();
+    let dest = lookup_by_path(&Path::new(&dest_path), LookupMode::None)?;
 
     let dev = crate::kernel::block::get_blkdev_by_id(dev.device_id().ok_or(SyscallError::ENODEV)?)
         .ok_or(SyscallError::ENODEV)?;
@@ -1214,9 +1570,9 @@ pub fn sys_mount(
 }
 
 pub fn sys_umount(path: u64, path_len: u64) -> SyscallResult {
-    let path = make_str(path, path_len);
+    let path = make_str(path, path_len)?;
 
-    let node = lookup_by_path(&Path::new(path), LookupMode::None)?;
+    let node = lookup_by_path(&Path::new(&path), LookupMode::None)?;
 
     if let Err(e) = crate::kernel::fs::mount::umount(node) {
         Err(e)?
@@ -1234,6 +1590,8 @@ pub fn sys_ticksns() -> SyscallResult {
 }
 
 pub fn sys_exit(status: u64) -> ! {
+    current_task_ref().strace_finish(Ok(0));
+
     crate::kernel::sched::exit(syscall_defs::waitpid::Status::Exited(status));
 }
 
@@ -1244,12 +1602,115 @@ pub fn sys_sleep(time_ns: u64) -> SyscallResult {
     Ok(0)
 }
 
+/// Checks whether new task would exceed RLIMIT_NPROC
+fn check_nproc() -> Result<(), SyscallError> {
+    let limit = current_task_ref().rlimit(RLimitKind::NProc).cur;
+
+    if limit != RLIM_INFINITY && crate::kernel::sched::user_task_count() as u64 >= limit {
+        Err(SyscallError::EAGAIN)
+    } else {
+        Ok(())
+    }
+}
+
 pub fn sys_fork() -> SyscallResult {
+    check_nproc()?;
+
     let child = crate::kernel::sched::fork();
 
     Ok(child.tid())
 }
 
+pub fn sys_clone(
+    flags: u64,
+    stack: u64,
+    parent_tid: u64,
+    child_tid: u64,
+    tls: u64,
+) -> SyscallResult {
+    // Exit signal is ignored, parent is always notified with SIGCHLD
+    let flags = CloneFlags::from_bits(flags & !CLONE_SIGNAL_MASK).ok_or(SyscallError::EINVAL)?;
+
+    let has = |f| flags.contains(f);
+
+    if (has(CloneFlags::CLONE_THREAD) && !has(CloneFlags::CLONE_SIGHAND))
+        || (has(CloneFlags::CLONE_SIGHAND) && !has(CloneFlags::CLONE_VM))
+    {
+        return Err(SyscallError::EINVAL);
+    }
+
+    // Separate process can share the address space only until it calls exec or exits
+    if has(CloneFlags::CLONE_VM) && !has(CloneFlags::CLONE_THREAD) && !has(CloneFlags::CLONE_VFORK)
+    {
+        return Err(SyscallError::EINVAL);
+    }
+
+    check_nproc()?;
+
+    let addr = |set: bool, val: u64| (set && val != 0).then_some(VirtAddr(val as usize));
+
+    let child = crate::kernel::sched::clone_task(&CloneArgs {
+        flags,
+        stack: addr(true, stack),
+        tls: addr(has(CloneFlags::CLONE_SETTLS), tls),
+        parent_tid: addr(has(CloneFlags::CLONE_PARENT_SETTID), parent_tid),
+        child_tid: addr(has(CloneFlags::CLONE_CHILD_CLEARTID), child_tid),
+    });
+
+    if has(CloneFlags::CLONE_VFORK) {
+        child.wait_vfork();
+    }
+
+    Ok(child.tid())
+}
+
+pub fn sys_ptrace(request: u64, pid: u64, addr: u64, data: u64) -> SyscallResult {
+    crate::kernel::task::ptrace::ptrace(
+        PtraceRequest::from(request as usize),
+        pid as usize,
+        VirtAddr(addr as usize),
+        data as usize,
+    )
+}
+
+pub fn sys_strace(pid: u64, buf: u64, count: u64) -> SyscallResult {
+    let task = current_task_ref();
+
+    // Exited children keep their trace until they are reaped
+    let child = task
+        .children()
+        .iter()
+        .find(|c| c.tid() == pid as usize)
+        .map(|c| c.me());
+
+    let target = if pid as usize == task.pid() {
+        task.process_leader()
+    } else if let Some(child) = child {
+        child
+    } else {
+        // Tracer reads the trace enabled with PTRACE_O_STRACE
+        crate::kernel::sched::get_task(pid as usize)
+            .filter(|t| t.ptrace().is_traced_by(task))
+            .ok_or(SyscallError::ESRCH)?
+    };
+
+    let records = target.strace().read(count as usize)?;
+
+    write_user_slice(VirtAddr(buf as usize), &records)?;
+
+    Ok(records.len())
+}
+
+fn make_exe_args(args: u64, len: u64) -> Result<ExeArgs, SyscallError> {
+    let mut res = ExeArgs::new();
+
+    for [ptr, len] in read_user_slice::<[usize; 2]>(VirtAddr(args as usize), len as usize)? {
+        res.push_back(read_user_bytes(VirtAddr(ptr), len)?.into_boxed_slice());
+    }
+
+    Ok(res)
+}
+
 pub fn sys_exec(
     path: u64,
     path_len: u64,
@@ -1258,24 +1719,18 @@ pub fn sys_exec(
     envs: u64,
     envs_len: u64,
 ) -> SyscallResult {
-    let path = make_str(path, path_len);
+    let path = make_str(path, path_len)?;
 
-    let prog = lookup_by_path(&Path::new(path), LookupMode::None)?;
+    let prog = lookup_by_path(&Path::new(&path), LookupMode::None)?;
 
     let args = if args_len > 0 {
-        Some(syscall_defs::exec::from_syscall_slice(
-            args as usize,
-            args_len as usize,
-        ))
+        Some(make_exe_args(args, args_len)?)
     } else {
         None
     };
 
     let envs = if envs_len > 0 {
-        Some(syscall_defs::exec::from_syscall_slice(
-            envs as usize,
-            envs_len as usize,
-        ))
+        Some(make_exe_args(envs, envs_len)?)
     } else {
         None
     };
@@ -1284,6 +1739,8 @@ pub fn sys_exec(
 }
 
 pub fn sys_spawn_thread(entry: u64, stack: u64) -> SyscallResult {
+    check_nproc()?;
+
     let thread =
         crate::kernel::sched::spawn_thread(VirtAddr(entry as usize), VirtAddr(stack as usize));
 
@@ -1291,51 +1748,296 @@ pub fn sys_spawn_thread(entry: u64, stack: u64) -> SyscallResult {
 }
 
 pub fn sys_waitpid(pid: u64, status: u64, flags: u64) -> SyscallResult {
+    sys_wait4(pid, status, flags, 0)
+}
+
+/// waitpid also returning the resource usage of the reaped child
+pub fn sys_wait4(pid: u64, status: u64, flags: u64, rusage: u64) -> SyscallResult {
     use syscall_defs::waitpid::*;
 
     let current = current_task_ref();
 
-    let status = unsafe { VirtAddr(status as usize).read_mut::<u32>() };
-
     let mut st = Status::Invalid(0);
 
+    let mut usage = Usage::default();
+
     let res = current.wait_pid(
         pid as isize,
         &mut st,
         WaitPidFlags::from_bits_truncate(flags as usize) | WaitPidFlags::EXITED,
+        Some(&mut usage),
     )?;
 
-    *status = st.into();
+    if status != 0 {
+        write_user::<u32>(VirtAddr(status as usize), &st.into())?;
+    }
+
+    if rusage != 0 && res.is_ok() {
+        write_user(VirtAddr(rusage as usize), &usage.to_rusage())?;
+    }
 
     res
 }
 
-pub fn sys_getpid() -> SyscallResult {
-    Ok(current_task_ref().pid())
-}
+pub fn sys_getrusage(who: u64, rusage: u64) -> SyscallResult {
+    let resources = current_task_ref().resources();
 
-pub fn sys_getppid() -> SyscallResult {
-    match &current_task_ref().get_parent() { Some(p) => {
-        Ok(p.pid())
-    } _ => {
-        Ok(0)
-    }}
-}
+    let usage = match RUsageWho::try_from(who)? {
+        RUsageWho::SelfProcess => resources.usage(),
+        RUsageWho::Children => resources.children_usage(),
+        // Usage is accounted per process only
+        RUsageWho::Thread => return Err(SyscallError::EINVAL),
+    };
 
-pub fn sys_getpgid(_pid: u64) -> SyscallResult {
-    Ok(current_task_ref().gid())
-}
+    write_user(VirtAddr(rusage as usize), &usage.to_rusage())?;
 
-pub fn sys_gettid() -> SyscallResult {
-    Ok(current_task_ref().tid())
+    Ok(0)
 }
 
-pub fn sys_setsid() -> SyscallResult {
-    let task = current_task();
+/// Returns clock ticks since boot
+pub fn sys_times(buf: u64) -> SyscallResult {
+    let resources = current_task_ref().resources();
 
-    crate::kernel::session::sessions().set_sid(task.clone())?;
+    if buf != 0 {
+        let usage = resources.usage();
+        let children = resources.children_usage();
 
-    task.terminal().disconnect(None);
+        let tms = Tms {
+            tms_utime: ns_to_clock_ticks(usage.user_ns),
+            tms_stime: ns_to_clock_ticks(usage.system_ns),
+            tms_cutime: ns_to_clock_ticks(children.user_ns),
+            tms_cstime: ns_to_clock_ticks(children.system_ns),
+        };
+
+        write_user(VirtAddr(buf as usize), &tms)?;
+    }
+
+    Ok(ns_to_clock_ticks(crate::kernel::timer::current_ns()) as usize)
+}
+
+/// Returns seconds left to the previous alarm
+pub fn sys_alarm(secs: u64) -> SyscallResult {
+    let task = current_task_ref();
+
+    let old = task.timers().set_itimer(
+        task,
+        ITimerWhich::Real,
+        ITimerValue {
+            value: secs * 1_000_000_000,
+            interval: 0,
+        },
+    );
+
+    Ok(old.value.div_ceil(1_000_000_000) as usize)
+}
+
+pub fn sys_setitimer(which: u64, new: u64, old: u64) -> SyscallResult {
+    let which = ITimerWhich::try_from(which)?;
+
+    let value = ITimerValue::from_timeval(&read_user::<ITimerVal>(VirtAddr(new as usize))?)?;
+
+    let task = current_task_ref();
+
+    let prev = task.timers().set_itimer(task, which, value);
+
+    if old != 0 {
+        write_user(VirtAddr(old as usize), &prev.to_timeval())?;
+    }
+
+    Ok(0)
+}
+
+pub fn sys_getitimer(which: u64, curr: u64) -> SyscallResult {
+    let which = ITimerWhich::try_from(which)?;
+
+    let task = current_task_ref();
+
+    let value = task.timers().get_itimer(task, which);
+
+    write_user(VirtAddr(curr as usize), &value.to_timeval())?;
+
+    Ok(0)
+}
+
+/// Timers without sigevent send SIGALRM, only SIGEV_SIGNAL and SIGEV_NONE notifications are supported
+pub fn sys_timer_create(clock: u64, sevp: u64, timer_id: u64) -> SyscallResult {
+    let clock = ClockId::try_from(clock)?;
+
+    if clock.is_cpu() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let (signo, value) = if sevp == 0 {
+        (Some(SIGALRM), None)
+    } else {
+        let event = read_user::<SigEvent>(VirtAddr(sevp as usize))?;
+
+        match event.sigev_notify {
+            SIGEV_NONE => (None, None),
+            SIGEV_SIGNAL => match event.sigev_signo as usize {
+                sig if sig == 0 || sig >= SIGNAL_COUNT => return Err(SyscallError::EINVAL),
+                sig => (Some(sig), Some(event.sigev_value)),
+            },
+            _ => return Err(SyscallError::EINVAL),
+        }
+    };
+
+    let task = current_task_ref();
+
+    let id = task.timers().create(task, clock, signo, value)?;
+
+    if let Err(e) = write_user(VirtAddr(timer_id as usize), &(id as u32)) {
+        let _ = task.timers().delete(id);
+
+        return Err(e.into());
+    }
+
+    Ok(0)
+}
+
+pub fn sys_timer_settime(timer_id: u64, flags: u64, new: u64, old: u64) -> SyscallResult {
+    let timer = current_task_ref().timers().get(timer_id as usize)?;
+
+    let value = ITimerValue::from_timespec(&read_user::<ITimerSpec>(VirtAddr(new as usize))?)?;
+
+    let prev = if flags & TIMER_ABSTIME != 0 {
+        timer.set_abs(value)
+    } else {
+        timer.set(value)
+    };
+
+    if old != 0 {
+        write_user(VirtAddr(old as usize), &prev.to_timespec())?;
+    }
+
+    Ok(0)
+}
+
+pub fn sys_timer_gettime(timer_id: u64, curr: u64) -> SyscallResult {
+    let timer = current_task_ref().timers().get(timer_id as usize)?;
+
+    write_user(VirtAddr(curr as usize), &timer.get().to_timespec())?;
+
+    Ok(0)
+}
+
+pub fn sys_timer_delete(timer_id: u64) -> SyscallResult {
+    current_task_ref().timers().delete(timer_id as usize)?;
+
+    Ok(0)
+}
+
+pub fn sys_clock_gettime(clock: u64, ts: u64) -> SyscallResult {
+    let now = crate::kernel::time::clock_ns(ClockId::try_from(clock)?);
+
+    write_user(VirtAddr(ts as usize), &Timespec::from_nsecs(now))?;
+
+    Ok(0)
+}
+
+/// Only the wall clock can be set
+pub fn sys_clock_settime(clock: u64, ts: u64) -> SyscallResult {
+    if !ClockId::try_from(clock)?.is_realtime() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let ts = read_user::<Timespec>(VirtAddr(ts as usize))?;
+
+    if !ts.is_valid() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    crate::kernel::time::set_realtime_ns(ts.to_nanoseconds() as u64);
+
+    Ok(0)
+}
+
+/// Timezone is ignored, the clock is always in UTC
+pub fn sys_settimeofday(tv: u64, _tz: u64) -> SyscallResult {
+    if tv == 0 {
+        return Ok(0);
+    }
+
+    let tv = read_user::<Timeval>(VirtAddr(tv as usize))?;
+
+    if !tv.is_valid() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    crate::kernel::time::set_realtime_ns(tv.to_nanoseconds() as u64);
+
+    Ok(0)
+}
+
+/// Remaining time is reported on EINTR for relative sleeps, absolute sleeps are restarted
+pub fn sys_clock_nanosleep(clock: u64, flags: u64, req: u64, rem: u64) -> SyscallResult {
+    let clock = ClockId::try_from(clock)?;
+
+    if clock.is_cpu() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let req = read_user::<Timespec>(VirtAddr(req as usize))?;
+
+    if !req.is_valid() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    if flags & TIMER_ABSTIME != 0 {
+        return match crate::kernel::time::sleep_abs(clock, req.to_nanoseconds() as u64) {
+            Ok(()) => Ok(0),
+            Err(_) => Err(SyscallError::ERESTARTNOHAND),
+        };
+    }
+
+    let time = req.to_nanoseconds() as u64;
+
+    if time == 0 {
+        return Ok(0);
+    }
+
+    let deadline = crate::kernel::timer::current_ns() + time;
+
+    match current_task_ref().sleep(time as usize) {
+        Ok(()) => Ok(0),
+        Err(e) => {
+            if rem != 0 {
+                let left = deadline.saturating_sub(crate::kernel::timer::current_ns());
+
+                write_user(VirtAddr(rem as usize), &Timespec::from_nsecs(left))?;
+            }
+
+            Err(e.into())
+        }
+    }
+}
+
+pub fn sys_getpid() -> SyscallResult {
+    Ok(current_task_ref().pid())
+}
+
+pub fn sys_getppid() -> SyscallResult {
+    match &current_task_ref().get_parent() { Some(p) => {
+        Ok(p.pid())
+    } _ => {
+        Ok(0)
+    }}
+}
+
+pub fn sys_getpgid(_pid: u64) -> SyscallResult {
+    Ok(current_task_ref().gid())
+}
+
+pub fn sys_gettid() -> SyscallResult {
+    Ok(current_task_ref().tid())
+}
+
+pub fn sys_setsid() -> SyscallResult {
+    let task = current_task();
+
+    crate::kernel::session::sessions().set_sid(task.clone())?;
+
+    task.terminal().disconnect(None);
 
     Ok(0)
 }
@@ -1345,6 +2047,8 @@ pub fn sys_setpgid(pid: u64, pgid: u64) -> SyscallResult {
 }
 
 pub fn sys_exit_thread() -> ! {
+    current_task_ref().strace_finish(Ok(0));
+
     crate::kernel::sched::exit_thread();
 }
 
@@ -1367,7 +2071,7 @@ pub fn sys_sigaction(sig: u64, sigact: u64, old: u64) -> SyscallResult {
     let new = if sigact == 0 {
         None
     } else {
-        unsafe { Some(VirtAddr(sigact as usize).read_ref::<SigAction>()) }
+        Some(read_user::<SigAction>(VirtAddr(sigact as usize))?)
     };
 
     logln!(
@@ -1378,38 +2082,72 @@ pub fn sys_sigaction(sig: u64, sigact: u64, old: u64) -> SyscallResult {
     );
 
     let entry = if let Some(new) = new {
-        Some(SignalEntry::from_sigaction(*new)?)
+        Some(SignalEntry::from_sigaction(new)?)
     } else {
         None
     };
 
-    let old = if old == 0 {
+    let mut old_action = SigAction::default();
+
+    logln5!("sigaction: {} {:?}, old: {:#x}", sig, entry, old);
+
+    let res = current_task_ref().signals().set_signal(
+        sig as usize,
+        entry,
+        if old == 0 {
+            None
+        } else {
+            Some(&mut old_action)
+        },
+    )?;
+
+    if old != 0 {
+        write_user(VirtAddr(old as usize), &old_action)?;
+    }
+
+    Ok(res)
+}
+
+pub fn sys_sigaltstack(new: u64, old: u64) -> SyscallResult {
+    let task = current_task_ref();
+
+    let new = if new == 0 {
         None
     } else {
-        unsafe { Some(VirtAddr(old as usize).read_mut::<SigAction>()) }
+        Some(read_user::<SigAltStack>(VirtAddr(new as usize))?)
     };
 
-    logln5!("sigaction: {} {:?}, old: {:?}", sig, entry, old);
+    let mut old_stack = SigAltStack::default();
+
+    let sp = unsafe { task.arch_task() }.syscall_user_sp();
+
+    task.signals()
+        .set_alt_stack(sp, new, if old == 0 { None } else { Some(&mut old_stack) })?;
 
-    current_task_ref()
-        .signals()
-        .set_signal(sig as usize, entry, old)
+    if old != 0 {
+        write_user(VirtAddr(old as usize), &old_stack)?;
+    }
+
+    Ok(0)
 }
 
 pub fn sys_sigprocmask(how: u64, set: u64, old_set: u64) -> SyscallResult {
     logln2!("sigprocmask: {} {} {}", how, set, old_set);
     let how = syscall_defs::signal::SigProcMask::from(how);
     let set = if set > 0 {
-        Some(unsafe { VirtAddr(set as usize).read::<u64>() })
-    } else {
-        None
-    };
-    let old_set = if old_set > 0 {
-        Some(unsafe { VirtAddr(old_set as usize).read_mut::<u64>() })
+        Some(read_user::<u64>(VirtAddr(set as usize))?)
     } else {
         None
     };
-    current_task_ref().signals().set_mask(how, set, old_set);
+    let mut old = 0u64;
+    current_task_ref().signals().set_mask(
+        how,
+        set,
+        if old_set > 0 { Some(&mut old) } else { None },
+    );
+    if old_set > 0 {
+        write_user(VirtAddr(old_set as usize), &old)?;
+    }
     Ok(0)
 }
 
@@ -1420,11 +2158,13 @@ pub fn sys_kill(pid: u64, sig: u64) -> SyscallResult {
         pid as i64,
         sig
     );
+    let info = SigInfo::user(sig as usize, SI_USER, current_task_ref().pid(), 0);
+
     match pid as isize {
         a if a > 0 => {
             let task = crate::kernel::sched::get_task(a as usize).ok_or(SyscallError::ESRCH)?;
 
-            task.signal(sig as usize);
+            task.signal_info(sig as usize, info);
 
             Ok(0)
         }
@@ -1434,7 +2174,7 @@ pub fn sys_kill(pid: u64, sig: u64) -> SyscallResult {
             Ok(crate::kernel::session::sessions()
                 .get_group(task.sid(), task.gid())
                 .and_then(|g| {
-                    g.signal(sig as usize);
+                    g.signal_info(sig as usize, info);
 
                     Some(0)
                 })
@@ -1445,7 +2185,7 @@ pub fn sys_kill(pid: u64, sig: u64) -> SyscallResult {
         }
         a if a < -1 => Ok(crate::kernel::session::get_group((-a) as usize)
             .and_then(|g| {
-                g.signal(sig as usize);
+                g.signal_info(sig as usize, info);
 
                 Some(0)
             })
@@ -1457,7 +2197,9 @@ pub fn sys_kill(pid: u64, sig: u64) -> SyscallResult {
 }
 
 pub fn sys_pipe(fds: u64, flags: u64) -> SyscallResult {
-    let fds = unsafe { core::slice::from_raw_parts_mut(fds as *mut u32, 2) };
+    let fds = VirtAddr(fds as usize);
+
+    check_user_access(fds, size_of::<[u32; 2]>(), true)?;
 
     let pipe = crate::kernel::fs::pipe::Pipe::new(None);
 
@@ -1472,17 +2214,17 @@ pub fn sys_pipe(fds: u64, flags: u64) -> SyscallResult {
 
     if let Ok(fd1) = task.open_file(entry.clone(), f1) {
         if let Ok(fd2) = task.open_file(entry, f2) {
-            dbgln!(
-                sys_pipe,
-                "task {} fd1 {} fd2 {}",
-                task.tid(),
-                fds[0],
-                fds[1]
-            );
-            fds[0] = fd1 as u32;
-            fds[1] = fd2 as u32;
-
-            return Ok(0);
+            dbgln!(sys_pipe, "task {} fd1 {} fd2 {}", task.tid(), fd1, fd2);
+
+            return match write_user(fds, &[fd1 as u32, fd2 as u32]) {
+                Ok(_) => Ok(0),
+                Err(e) => {
+                    task.close_file(fd1);
+                    task.close_file(fd2);
+
+                    Err(e.into())
+                }
+            };
         } else {
             task.close_file(fd1);
         }
@@ -1531,7 +2273,6 @@ pub fn sys_truncate(fd: u64, size: u64) -> SyscallResult {
     task.filetable()
         .get_handle(fd as usize)
         .ok_or(SyscallError::EBADFD)?
-        .get_inode()
         .truncate(size as usize)
         .map(|_r| Ok(0))?
 }
@@ -1563,41 +2304,314 @@ pub fn sys_stat(fd: u64, path: u64, path_len: u64, stat: u64, flags: u64) -> Sys
 
     let file = get_dir_entry(
         fd,
-        make_path(path, path_len),
+        make_path(path, path_len)?.as_deref().map(Path::new),
         LookupMode::None,
         flags.contains(AtFlags::SYMLINK_NOFOLLOW),
     )?;
 
-    let stat = unsafe { VirtAddr(stat as usize).read_mut::<syscall_defs::stat::Stat>() };
+    let res = file.inode().stat()?;
 
-    *stat = file.inode().stat()?;
+    logln!("fstatat {:?}, {:?}", fd, res);
 
-    logln!("fstatat {:?}, {:?}", fd, stat);
+    write_user(VirtAddr(stat as usize), &res)?;
 
     Ok(0)
 }
 
 pub fn sys_getrlimit(resource: u64, rlimit: u64) -> SyscallResult {
-    let resource = syscall_defs::resource::RLimitKind::try_from(resource)?;
+    let resource = RLimitKind::try_from(resource)?;
+
+    let out = current_task_ref().rlimit(resource);
+
+    write_user(VirtAddr(rlimit as usize), &out)?;
+
+    Ok(0)
+}
+
+pub fn sys_setrlimit(resource: u64, rlimit: u64) -> SyscallResult {
+    let resource = RLimitKind::try_from(resource)?;
+
+    let limit = read_user::<RLimit>(VirtAddr(rlimit as usize))?;
+
+    logln4!("setrlimit {:?} {:?}", resource, limit);
+
+    current_task_ref().set_rlimit(resource, limit)?;
+
+    Ok(0)
+}
+
+fn set_task_nice(task: &Task, nice: isize) -> SyscallResult {
+    // There are no user ids, processes can renice themselves and their descendants
+    if !task.is_descendant_of(current_task_ref().pid()) {
+        return Err(SyscallError::EPERM);
+    }
+
+    if !crate::kernel::sched::prio::can_nice(task, nice) {
+        return Err(SyscallError::EACCES);
+    }
+
+    task.set_nice(nice);
+
+    Ok(0)
+}
+
+pub fn sys_getpriority(which: u64, who: u64) -> SyscallResult {
+    let nice = match PrioWhich::try_from(which)? {
+        PrioWhich::Process => {
+            if who == 0 {
+                current_task_ref().nice()
+            } else {
+                crate::kernel::sched::get_task(who as usize)
+                    .ok_or(SyscallError::ESRCH)?
+                    .nice()
+            }
+        }
+        PrioWhich::PGrp => {
+            let group = if who == 0 {
+                let task = current_task_ref();
+
+                crate::kernel::session::sessions().get_group(task.sid(), task.gid())
+            } else {
+                crate::kernel::session::get_group(who as usize)
+            }
+            .ok_or(SyscallError::ESRCH)?;
+
+            // Highest priority (lowest nice) in the group is reported
+            let nice = AtomicIsize::new(NICE_MAX + 1);
 
-    let out = unsafe { VirtAddr(rlimit as usize).read_mut::<syscall_defs::resource::RLimit>() };
+            group.for_each(&|p| {
+                nice.fetch_min(p.nice(), Ordering::Relaxed);
+            });
 
-    match resource {
-        syscall_defs::resource::RLimitKind::NOFile => {
-            out.cur = 256;
-            out.max = 256;
+            match nice.into_inner() {
+                n if n > NICE_MAX => return Err(SyscallError::ESRCH),
+                n => n,
+            }
         }
-        _ => {
-            out.cur = u64::MAX;
-            out.cur = u64::MAX;
+        PrioWhich::User => return Err(SyscallError::EINVAL),
+    };
+
+    Ok(nice_to_prio(nice))
+}
+
+pub fn sys_setpriority(which: u64, who: u64, nice: u64) -> SyscallResult {
+    let nice = crate::kernel::sched::prio::clamp_nice(nice as isize);
+
+    match PrioWhich::try_from(which)? {
+        PrioWhich::Process => {
+            if who == 0 {
+                set_task_nice(current_task_ref(), nice)
+            } else {
+                let task =
+                    crate::kernel::sched::get_task(who as usize).ok_or(SyscallError::ESRCH)?;
+
+                set_task_nice(&task, nice)
+            }
+        }
+        PrioWhich::PGrp => {
+            let group = if who == 0 {
+                let task = current_task_ref();
+
+                crate::kernel::session::sessions().get_group(task.sid(), task.gid())
+            } else {
+                crate::kernel::session::get_group(who as usize)
+            }
+            .ok_or(SyscallError::ESRCH)?;
+
+            let res = core::cell::Cell::new(Ok(0));
+
+            group.for_each(&|p| {
+                if let Err(e) = set_task_nice(p, nice) {
+                    res.set(Err(e));
+                }
+            });
+
+            res.into_inner()
+        }
+        PrioWhich::User => Err(SyscallError::EINVAL),
+    }
+}
+
+pub fn sys_nice(inc: u64) -> SyscallResult {
+    let task = current_task_ref();
+
+    let nice = crate::kernel::sched::prio::clamp_nice(task.nice().saturating_add(inc as isize));
+
+    set_task_nice(task, nice)
+}
+
+/// Cpu masks are limited to 64 cpus
+const CPU_MASK_SIZE: usize = size_of::<u64>();
+
+pub fn sys_sched_setaffinity(pid: u64, len: u64, mask: u64) -> SyscallResult {
+    let task = if pid == 0 {
+        current_task()
+    } else {
+        crate::kernel::sched::get_task(pid as usize).ok_or(SyscallError::ESRCH)?
+    };
+
+    let mut buf = [0u8; CPU_MASK_SIZE];
+
+    let len = core::cmp::min(len as usize, CPU_MASK_SIZE);
+
+    buf[..len].copy_from_slice(&read_user_bytes(VirtAddr(mask as usize), len)?);
+
+    let mask = u64::from_le_bytes(buf);
+
+    if mask & crate::kernel::sched::online_cpu_mask() == 0 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    task.set_cpu_mask(mask);
+
+    if !task.can_run_on(task.on_cpu()) {
+        if task.tid() == current_task_ref().tid() {
+            crate::kernel::sched::reschedule();
+        } else {
+            // Task is switched out on the next tick of its cpu and moved to an allowed one,
+            // queued and sleeping tasks are moved when they are picked or woken up
+            task.set_to_reschedule(true);
+
+            crate::kernel::ipi::exec_on_cpu(IpiTarget::Cpu(task.on_cpu()), IpiKind::IpiBalance);
         }
     }
 
     Ok(0)
 }
 
+pub fn sys_sched_getaffinity(pid: u64, len: u64, mask: u64) -> SyscallResult {
+    if (len as usize) < CPU_MASK_SIZE {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let task = if pid == 0 {
+        current_task()
+    } else {
+        crate::kernel::sched::get_task(pid as usize).ok_or(SyscallError::ESRCH)?
+    };
+
+    let out = task.cpu_mask() & crate::kernel::sched::online_cpu_mask();
+
+    write_user(VirtAddr(mask as usize), &out)?;
+
+    Ok(CPU_MASK_SIZE)
+}
+
+fn sched_task(pid: u64) -> Result<ArcTask, SyscallError> {
+    if pid == 0 {
+        Ok(current_task())
+    } else {
+        crate::kernel::sched::get_task(pid as usize).ok_or(SyscallError::ESRCH)
+    }
+}
+
+fn set_task_sched(task: &Task, policy: SchedPolicy, param: u64) -> SyscallResult {
+    let param = read_user::<SchedParam>(VirtAddr(param as usize))?;
+
+    let (min, max) = policy.priority_range();
+
+    let prio = match param.sched_priority {
+        p if p < 0 || (p as usize) < min || (p as usize) > max => {
+            return Err(SyscallError::EINVAL);
+        }
+        p => p as usize,
+    };
+
+    if !crate::kernel::sched::prio::can_set_sched(task, policy, prio) {
+        return Err(SyscallError::EPERM);
+    }
+
+    task.set_sched_policy(policy, prio);
+
+    crate::kernel::sched::requeue(task.me());
+
+    if task.tid() == current_task_ref().tid() {
+        crate::kernel::sched::reschedule();
+    }
+
+    Ok(0)
+}
+
+pub fn sys_sched_setscheduler(pid: u64, policy: u64, param: u64) -> SyscallResult {
+    let task = sched_task(pid)?;
+
+    set_task_sched(&task, SchedPolicy::try_from(policy)?, param)
+}
+
+pub fn sys_sched_getscheduler(pid: u64) -> SyscallResult {
+    Ok(sched_task(pid)?.sched_policy() as usize)
+}
+
+pub fn sys_sched_setparam(pid: u64, param: u64) -> SyscallResult {
+    let task = sched_task(pid)?;
+
+    set_task_sched(&task, task.sched_policy(), param)
+}
+
+pub fn sys_sched_getparam(pid: u64, param: u64) -> SyscallResult {
+    let task = sched_task(pid)?;
+
+    let out = SchedParam {
+        sched_priority: task.rt_priority() as i32,
+    };
+
+    write_user(VirtAddr(param as usize), &out)?;
+
+    Ok(0)
+}
+
+/// There are no user ids, so the limits of a process are accessible to it and its ancestors
+fn can_access_rlimit(task: &Task) -> bool {
+    task.is_descendant_of(current_task_ref().pid())
+}
+
+pub fn sys_prlimit(pid: u64, resource: u64, new_limit: u64, old_limit: u64) -> SyscallResult {
+    let resource = RLimitKind::try_from(resource)?;
+
+    let task = if pid == 0 {
+        current_task()
+    } else {
+        crate::kernel::sched::get_task(pid as usize).ok_or(SyscallError::ESRCH)?
+    };
+
+    if !can_access_rlimit(&task) {
+        return Err(SyscallError::EPERM);
+    }
+
+    let old = task.rlimit(resource);
+
+    if new_limit != 0 {
+        let limit = read_user::<RLimit>(VirtAddr(new_limit as usize))?;
+
+        logln4!("prlimit {} {:?} {:?}", pid, resource, limit);
+
+        task.set_rlimit(resource, limit)?;
+    }
+
+    if old_limit != 0 {
+        write_user(VirtAddr(old_limit as usize), &old)?;
+    }
+
+    Ok(0)
+}
+
+pub fn sys_personality(persona: u64) -> SyscallResult {
+    use syscall_defs::personality::{Personality, PERSONALITY_QUERY};
+
+    // Personality is shared by all the threads of the process
+    let task = current_task_ref().process_leader();
+
+    let old = task.personality();
+
+    if persona != PERSONALITY_QUERY {
+        task.set_personality(Personality::from_bits_truncate(persona));
+    }
+
+    Ok(old.bits() as usize)
+}
+
 pub fn sys_debug(str: u64, str_len: u64) -> SyscallResult {
-    dbgln!(debug, "{}", make_str(str, str_len));
+    dbgln!(debug, "{}", make_str(str, str_len)?);
 
     Ok(0)
 }
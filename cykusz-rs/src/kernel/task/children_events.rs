@@ -7,6 +7,7 @@ use crate::kernel::sched::current_task_ref;
 use crate::kernel::session::sessions;
 use crate::kernel::signal::SignalResult;
 use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::task::resource::Usage;
 use crate::kernel::task::{ArcTask, Task, WaitPidTaskAdapter};
 use crate::kernel::utils::wait_queue::{WaitQueue, WaitQueueFlags};
 
@@ -93,6 +94,26 @@ impl WaitPidEvents {
         }
     }
 
+    /// Drops pending event of the task, eg. when the tracer detaches
+    pub fn remove(&self, task: &Task) {
+        {
+            let mut list = self.tasks.lock_irq();
+
+            let mut cur = list.tasks.front_mut();
+
+            while let Some(t) = cur.get() {
+                if t.tid() == task.tid() {
+                    cur.remove();
+                    break;
+                }
+
+                cur.move_next();
+            }
+        }
+
+        self.wq.notify_all();
+    }
+
     fn wait_on(
         &self,
         wq: &WaitQueue,
@@ -100,7 +121,7 @@ impl WaitPidEvents {
         flags: WaitPidFlags,
         no_intr: bool,
         mut cond: impl FnMut(&Task) -> bool,
-    ) -> SignalResult<Option<(usize, syscall_defs::waitpid::Status)>> {
+    ) -> SignalResult<Option<(usize, syscall_defs::waitpid::Status, Usage)>> {
         let mut res = (0, syscall_defs::waitpid::Status::Invalid(0), None);
 
         let mut wq_flags: WaitQueueFlags = if flags.nohang() {
@@ -122,7 +143,8 @@ impl WaitPidEvents {
                     dbgln!(waitpid, "checking task {} = {:?}", t.tid(), status);
                     if ((flags.exited() && (status.is_exited() || status.is_signaled()))
                         || (flags.continued() && status.is_continued())
-                        || (flags.stopped() && status.is_stopped()))
+                        || ((flags.stopped() || t.ptrace().is_traced_by(me))
+                            && status.is_stopped()))
                         && cond(t)
                     {
                         res = (t.tid(), status, Some(t.me()));
@@ -144,16 +166,22 @@ impl WaitPidEvents {
                     }
                 }
 
-                !me.children_debug(2).iter().any(&mut cond)
+                !me.children_debug(2).iter().any(&mut cond) && !me.ptrace().any_tracee(&mut cond)
             })?
             .is_some();
 
         if found && !res.1.is_invalid() {
+            let mut usage = Usage::default();
+
             if res.1.is_exited() || res.1.is_signaled() {
                 let task = res.2.unwrap();
                 task.remove_from_parent();
 
                 if task.is_process_leader() {
+                    usage = task.resources().total_usage();
+
+                    me.resources().add_child(&usage);
+
                     if let Err(e) = sessions().remove_process(&task) {
                         panic!("Failed to remove process from a session {:?}", e);
                     }
@@ -175,7 +203,7 @@ impl WaitPidEvents {
                 );
             }
 
-            return Ok(Some((res.0, res.1)));
+            return Ok(Some((res.0, res.1, usage)));
         }
 
         Ok(None)
@@ -209,7 +237,7 @@ impl WaitPidEvents {
         });
 
         match res {
-            Ok(Some((tid, _st))) => Ok(Ok(tid)),
+            Ok(Some((tid, _st, _usage))) => Ok(Ok(tid)),
             Ok(None) => Ok(Err(SyscallError::ECHILD)),
             Err(e) => Err(e),
         }
@@ -221,6 +249,7 @@ impl WaitPidEvents {
         pid: isize,
         status: &mut syscall_defs::waitpid::Status,
         flags: WaitPidFlags,
+        usage: Option<&mut Usage>,
     ) -> SignalResult<SyscallResult> {
         dbgln!(waitpid, "task {} waitpid {} {:?}", me.tid(), pid, flags);
         let ret = self.wait_on(&self.wq, me, flags, false, |t| {
@@ -251,9 +280,13 @@ impl WaitPidEvents {
         );
 
         match ret {
-            Ok(Some((tid, st))) => {
+            Ok(Some((tid, st, u))) => {
                 *status = st;
 
+                if let Some(usage) = usage {
+                    *usage = u;
+                }
+
                 Ok(Ok(tid))
             }
             Ok(None) => Ok(Err(SyscallError::ECHILD)),
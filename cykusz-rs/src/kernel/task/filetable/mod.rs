@@ -4,6 +4,8 @@ use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use syscall_defs::net::{MsgFlags, MsgHdr};
 use syscall_defs::poll::PollEventFlags;
+use syscall_defs::resource::{RLimitKind, RLIM_INFINITY};
+use syscall_defs::signal::SIGXFSZ;
 use syscall_defs::{
     FDFlags, FileType, OpenFlags, SeekWhence, SysDirEntry, SyscallError, SyscallResult,
 };
@@ -12,12 +14,16 @@ use crate::kernel::fs::dirent::DirEntryItem;
 use crate::kernel::fs::inode::INode;
 use crate::kernel::fs::poll::PollTable;
 use crate::kernel::fs::vfs::{DirEntIter, FsError, Result};
+use crate::kernel::sched::current_task_ref;
 use crate::kernel::sync::{LockApi, Mutex, RwMutex};
 use crate::kernel::task::filetable::inode_wrap::INodeOpsWrap;
 
 mod inode_wrap;
 
-const FILE_NUM: usize = 256;
+/// Default limit of open files
+pub const FILE_NUM: usize = 256;
+/// Maximum value of the open files limit
+pub const FILE_MAX: usize = 4096;
 
 pub struct FileHandle {
     fd: usize,
@@ -89,16 +95,39 @@ impl FileHandle {
         let inode = &self.inode;
         Ok(match inode.as_cacheable() {
             Some(cacheable) => {
+                let buf = limit_file_size(buf, offset)?;
+
                 if let Some(w) = cacheable.write_cached(offset, buf) {
                     w
                 } else {
                     return Err(FsError::NotSupported);
                 }
             }
-            None => inode.write_at(offset, buf, self.flags())?,
+            None => {
+                // Devices, pipes and sockets have no size
+                let buf = if inode.ftype()? == FileType::File {
+                    limit_file_size(buf, offset)?
+                } else {
+                    buf
+                };
+
+                inode.write_at(offset, buf, self.flags())?
+            }
         })
     }
 
+    pub fn truncate(&self, size: usize) -> Result<()> {
+        let limit = current_task_ref().rlimit(RLimitKind::FSize).cur;
+
+        if limit != RLIM_INFINITY && size as u64 > limit {
+            current_task_ref().signal_thread(SIGXFSZ);
+
+            return Err(FsError::FileTooBig);
+        }
+
+        self.inode.truncate(size)
+    }
+
     pub fn msg_recv(&self, hdr: &mut MsgHdr, flags: MsgFlags) -> SyscallResult {
         let sock = self.get_inode().as_socket().ok_or(SyscallError::EINVAL)?;
 
@@ -315,8 +344,31 @@ impl Clone for FileDescriptor {
     }
 }
 
+/// Limits the write to RLIMIT_FSIZE, sends SIGXFSZ if nothing can be written
+fn limit_file_size(buf: &[u8], offset: usize) -> Result<&[u8]> {
+    let task = current_task_ref();
+
+    let limit = task.rlimit(RLimitKind::FSize).cur;
+
+    if limit == RLIM_INFINITY || buf.is_empty() {
+        return Ok(buf);
+    }
+
+    let limit = limit as usize;
+
+    if offset >= limit {
+        task.signal_thread(SIGXFSZ);
+
+        return Err(FsError::FileTooBig);
+    }
+
+    Ok(&buf[..core::cmp::min(buf.len(), limit - offset)])
+}
+
 pub struct FileTable {
     files: RwMutex<Vec<Option<FileDescriptor>>>,
+    // RLIMIT_NOFILE of the owning process
+    limit: AtomicUsize,
 }
 
 impl Clone for FileTable {
@@ -325,6 +377,7 @@ impl Clone for FileTable {
 
         FileTable {
             files: RwMutex::new(files),
+            limit: AtomicUsize::new(self.limit()),
         }
     }
 }
@@ -342,9 +395,19 @@ impl FileTable {
 
         FileTable {
             files: RwMutex::new(files),
+            limit: AtomicUsize::new(FILE_NUM),
         }
     }
 
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.limit
+            .store(core::cmp::min(limit, FILE_MAX), Ordering::Relaxed);
+    }
+
     pub fn debug(&self) {
         for (i, f) in (&*self.files.read()).iter().enumerate() {
             if let Some(f) = f {
@@ -380,7 +443,14 @@ impl FileTable {
             ))
         };
 
-        if let Some((idx, f)) = files.iter_mut().enumerate().find(|e| e.1.is_none()) {
+        let limit = self.limit();
+
+        if let Some((idx, f)) = files
+            .iter_mut()
+            .enumerate()
+            .take(limit)
+            .find(|e| e.1.is_none())
+        {
             let h = mk_handle(idx, dentry).ok_or(FsError::Busy)?;
 
             h.handle().open(flags)?;
@@ -388,7 +458,7 @@ impl FileTable {
             *f = Some(h);
 
             Ok(idx)
-        } else if files.len() < FILE_NUM {
+        } else if files.len() < limit {
             let len = files.len();
 
             let h = mk_handle(len, dentry).ok_or(FsError::Busy)?;
@@ -399,7 +469,7 @@ impl FileTable {
 
             Ok(len)
         } else {
-            Err(FsError::Busy)
+            Err(FsError::TooManyFiles)
         }
     }
 
@@ -458,35 +528,48 @@ impl FileTable {
 
         let mut files = self.files.write();
 
+        let limit = self.limit();
+
+        if min >= limit {
+            return Err(SyscallError::EINVAL);
+        }
+
         if let Some((idx, f)) = files
             .iter_mut()
             .enumerate()
+            .take(limit)
             .find(|e| e.0 >= min && e.1.is_none())
         {
             // inode.close() called on FileHandle Drop
             *f = Some(FileDescriptor::new(handle, flags));
 
             Ok(idx)
-        } else if files.len() < FILE_NUM {
-            let len = files.len();
+        } else if files.len() < limit {
+            let len = core::cmp::max(files.len(), min);
 
-            files.push(Some(FileDescriptor::new(handle, flags)));
+            files.resize(len + 1, None);
+
+            files[len] = Some(FileDescriptor::new(handle, flags));
 
             Ok(len)
         } else {
-            Err(SyscallError::EINVAL)
+            Err(SyscallError::EMFILE)
         }
     }
 
     pub fn duplicate_at(&self, fd: usize, at: usize, flags: FDFlags) -> SyscallResult {
-        if at >= FILE_NUM {
-            return Err(SyscallError::EINVAL);
+        if at >= self.limit() {
+            return Err(SyscallError::EBADFD);
         }
 
         let handle = self.get_handle(fd).ok_or(SyscallError::EBADFD)?;
 
         let mut files = self.files.write();
 
+        if at >= files.len() {
+            files.resize(at + 1, None);
+        }
+
         // inode.close() called on FileHandle Drop
         files[at] = Some(FileDescriptor::new(handle, flags));
 
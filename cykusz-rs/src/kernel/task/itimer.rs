@@ -0,0 +1,419 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use syscall_defs::signal::{SigInfo, SIGALRM, SIGPROF, SIGVTALRM, SI_KERNEL, SI_TIMER};
+use syscall_defs::time::{ClockId, ITimerSpec, ITimerVal, ITimerWhich, Timespec, Timeval};
+use syscall_defs::SyscallError;
+
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::task::{Task, WeakTask};
+use crate::kernel::time::realtime_ns;
+use crate::kernel::timer::{create_timer, current_ns, Timer, TimerCallback};
+
+/// Number of POSIX timers a process can create
+const TIMER_MAX: usize = 32;
+
+/// Timers armed with an absolute CLOCK_REALTIME time, moved when the wall clock is set
+static ABS_REALTIME: Spin<Vec<Weak<SignalTimer>>> = Spin::new(Vec::new());
+
+/// Time to the expiry and the reload interval in nanoseconds, 0 value means disarmed
+#[derive(Default, Debug, Copy, Clone)]
+pub struct ITimerValue {
+    pub value: u64,
+    pub interval: u64,
+}
+
+impl ITimerValue {
+    pub fn from_timeval(val: &ITimerVal) -> Result<ITimerValue, SyscallError> {
+        if !val.it_value.is_valid() || !val.it_interval.is_valid() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        Ok(ITimerValue {
+            value: val.it_value.to_nanoseconds() as u64,
+            interval: val.it_interval.to_nanoseconds() as u64,
+        })
+    }
+
+    pub fn to_timeval(&self) -> ITimerVal {
+        ITimerVal {
+            it_interval: Timeval::from_nsecs(self.interval),
+            it_value: Timeval::from_nsecs(self.value),
+        }
+    }
+
+    pub fn from_timespec(val: &ITimerSpec) -> Result<ITimerValue, SyscallError> {
+        if !val.it_value.is_valid() || !val.it_interval.is_valid() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        Ok(ITimerValue {
+            value: val.it_value.to_nanoseconds() as u64,
+            interval: val.it_interval.to_nanoseconds() as u64,
+        })
+    }
+
+    pub fn to_timespec(&self) -> ITimerSpec {
+        ITimerSpec {
+            it_interval: Timespec::from_nsecs(self.interval),
+            it_value: Timespec::from_nsecs(self.value),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SignalTimerState {
+    // Absolute expiry time, 0 when disarmed
+    deadline: u64,
+    interval: u64,
+    // Deadline follows the wall clock
+    abs_realtime: bool,
+}
+
+/// Timer signalling the process on expiry, driven by kernel::timer
+pub struct SignalTimer {
+    me: Weak<SignalTimer>,
+    timer: Arc<Timer>,
+    task: WeakTask,
+    clock: ClockId,
+    // Signal sent on expiry, None for SIGEV_NONE timers
+    info: Option<SigInfo>,
+    state: Spin<SignalTimerState>,
+}
+
+unsafe impl Sync for SignalTimer {}
+
+unsafe impl Send for SignalTimer {}
+
+impl SignalTimer {
+    fn new(task: &Task, clock: ClockId, info: Option<SigInfo>) -> Arc<SignalTimer> {
+        Arc::new_cyclic(|me| SignalTimer {
+            me: me.clone(),
+            timer: create_timer(TimerCallback::new(me.clone(), SignalTimer::expire)),
+            task: task.process_leader().sref.clone(),
+            clock,
+            info,
+            state: Spin::new(SignalTimerState::default()),
+        })
+    }
+
+    fn expire(&self) {
+        let now = current_ns();
+
+        let missed = {
+            let mut state = self.state.lock_irq();
+
+            // Timer was rearmed after it expired
+            if state.deadline == 0 || state.deadline > now {
+                return;
+            }
+
+            if state.interval > 0 {
+                // Skip the periods missed while the timer callback was delayed
+                let missed = (now - state.deadline) / state.interval + 1;
+
+                state.deadline += missed * state.interval;
+
+                self.timer.start_at(state.deadline);
+
+                missed
+            } else {
+                state.deadline = 0;
+
+                1
+            }
+        };
+
+        if let (Some(mut info), Some(task)) = (self.info, self.task.upgrade()) {
+            if info.si_code == SI_TIMER {
+                info = SigInfo::timer(
+                    info.si_signo as usize,
+                    info.timer_id(),
+                    missed as u32 - 1,
+                    info.value(),
+                );
+            }
+
+            task.signal_info(info.si_signo as usize, info);
+        }
+    }
+
+    fn value_locked(state: &SignalTimerState) -> ITimerValue {
+        ITimerValue {
+            value: match state.deadline {
+                0 => 0,
+                // Expired timer still waiting for the callback is reported as about to expire
+                d => d.saturating_sub(current_ns()).max(1),
+            },
+            interval: state.interval,
+        }
+    }
+
+    pub fn get(&self) -> ITimerValue {
+        Self::value_locked(&self.state.lock_irq())
+    }
+
+    /// Arms the timer to expire at the absolute time, 0 disarms it. Returns the previous value
+    pub fn set_at(&self, deadline: u64, interval: u64) -> ITimerValue {
+        let mut state = self.state.lock_irq();
+
+        let old = Self::value_locked(&state);
+
+        self.timer.disable();
+
+        state.deadline = deadline;
+        state.interval = if deadline == 0 { 0 } else { interval };
+        state.abs_realtime = false;
+
+        if deadline != 0 {
+            self.timer.start_at(deadline);
+        }
+
+        old
+    }
+
+    pub fn set(&self, value: ITimerValue) -> ITimerValue {
+        let deadline = match value.value {
+            0 => 0,
+            v => current_ns() + v,
+        };
+
+        self.set_at(deadline, value.interval)
+    }
+
+    /// Arms the timer to expire at the absolute time of the timer clock
+    pub fn set_abs(&self, value: ITimerValue) -> ITimerValue {
+        if value.value == 0 || !self.clock.is_realtime() {
+            return self.set_at(value.value, value.interval);
+        }
+
+        let deadline = (value.value + current_ns())
+            .saturating_sub(realtime_ns())
+            .max(1);
+
+        let old = self.set_at(deadline, value.interval);
+
+        self.state.lock_irq().abs_realtime = true;
+
+        let mut timers = ABS_REALTIME.lock_irq();
+
+        timers.retain(|t| t.strong_count() > 0);
+
+        if !timers.iter().any(|t| t.ptr_eq(&self.me)) {
+            timers.push(self.me.clone());
+        }
+
+        old
+    }
+
+    /// Moves the deadline by the wall clock change, returns false if the timer is no longer
+    /// armed with an absolute realtime
+    fn clock_was_set(&self, delta: i64) -> bool {
+        let mut state = self.state.lock_irq();
+
+        if !state.abs_realtime || state.deadline == 0 {
+            return false;
+        }
+
+        self.timer.disable();
+
+        state.deadline = state.deadline.saturating_add_signed(-delta).max(1);
+
+        self.timer.start_at(state.deadline);
+
+        true
+    }
+
+    fn disarm(&self) {
+        self.set_at(0, 0);
+    }
+}
+
+/// ITIMER_VIRTUAL and ITIMER_PROF count down the cpu time used by the process
+#[derive(Default, Copy, Clone)]
+struct CpuTimer {
+    value: u64,
+    interval: u64,
+}
+
+impl CpuTimer {
+    /// Returns true when the timer expired
+    fn account(&mut self, ns: u64) -> bool {
+        if self.value == 0 {
+            return false;
+        }
+
+        if ns < self.value {
+            self.value -= ns;
+            return false;
+        }
+
+        self.value = self.interval;
+
+        true
+    }
+}
+
+/// Interval and POSIX timers of the process, shared by all its threads
+#[derive(Default)]
+pub struct ProcessTimers {
+    real: Spin<Option<Arc<SignalTimer>>>,
+    cpu: Spin<[CpuTimer; 2]>,
+    cpu_armed: AtomicBool,
+    posix: Spin<BTreeMap<usize, Arc<SignalTimer>>>,
+    next_id: AtomicUsize,
+}
+
+impl ProcessTimers {
+    fn real(&self, task: &Task) -> Arc<SignalTimer> {
+        self.real
+            .lock_irq()
+            .get_or_insert_with(|| {
+                SignalTimer::new(
+                    task,
+                    ClockId::Monotonic,
+                    Some(SigInfo::new(SIGALRM, SI_KERNEL)),
+                )
+            })
+            .clone()
+    }
+
+    fn cpu_idx(which: ITimerWhich) -> usize {
+        match which {
+            ITimerWhich::Virtual => 0,
+            _ => 1,
+        }
+    }
+
+    pub fn get_itimer(&self, task: &Task, which: ITimerWhich) -> ITimerValue {
+        match which {
+            ITimerWhich::Real => self.real(task).get(),
+            which => {
+                let timer = self.cpu.lock_irq()[Self::cpu_idx(which)];
+
+                ITimerValue {
+                    value: timer.value,
+                    interval: timer.interval,
+                }
+            }
+        }
+    }
+
+    pub fn set_itimer(&self, task: &Task, which: ITimerWhich, value: ITimerValue) -> ITimerValue {
+        match which {
+            ITimerWhich::Real => self.real(task).set(value),
+            which => {
+                let mut timers = self.cpu.lock_irq();
+
+                let timer = &mut timers[Self::cpu_idx(which)];
+
+                let old = ITimerValue {
+                    value: timer.value,
+                    interval: timer.interval,
+                };
+
+                timer.value = value.value;
+                timer.interval = if value.value == 0 { 0 } else { value.interval };
+
+                self.cpu_armed
+                    .store(timers.iter().any(|t| t.value > 0), Ordering::SeqCst);
+
+                old
+            }
+        }
+    }
+
+    /// Counts down the cpu timers, user time is charged to both ITIMER_VIRTUAL and ITIMER_PROF
+    pub fn account(&self, ns: u64, user: bool, signal: impl Fn(usize)) {
+        if !self.cpu_armed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let (virt, prof) = {
+            let mut timers = self.cpu.lock_irq();
+
+            (user && timers[0].account(ns), timers[1].account(ns))
+        };
+
+        if virt {
+            signal(SIGVTALRM);
+        }
+
+        if prof {
+            signal(SIGPROF);
+        }
+    }
+
+    /// Value passed with the signal defaults to the timer id
+    pub fn create(
+        &self,
+        task: &Task,
+        clock: ClockId,
+        signo: Option<usize>,
+        value: Option<u64>,
+    ) -> Result<usize, SyscallError> {
+        let mut timers = self.posix.lock_irq();
+
+        if timers.len() >= TIMER_MAX {
+            return Err(SyscallError::EAGAIN);
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        let info = signo.map(|sig| SigInfo::timer(sig, id, 0, value.unwrap_or(id as u64)));
+
+        timers.insert(id, SignalTimer::new(task, clock, info));
+
+        Ok(id)
+    }
+
+    pub fn get(&self, id: usize) -> Result<Arc<SignalTimer>, SyscallError> {
+        self.posix
+            .lock_irq()
+            .get(&id)
+            .cloned()
+            .ok_or(SyscallError::EINVAL)
+    }
+
+    pub fn delete(&self, id: usize) -> Result<(), SyscallError> {
+        let timer = self
+            .posix
+            .lock_irq()
+            .remove(&id)
+            .ok_or(SyscallError::EINVAL)?;
+
+        timer.disarm();
+
+        Ok(())
+    }
+
+    /// POSIX timers are deleted on exec, interval timers are preserved
+    pub fn exec(&self) {
+        let timers = core::mem::take(&mut *self.posix.lock_irq());
+
+        timers.values().for_each(|t| t.disarm());
+    }
+
+    /// Called on process exit
+    pub fn disarm_all(&self) {
+        if let Some(real) = self.real.lock_irq().take() {
+            real.disarm();
+        }
+
+        self.cpu_armed.store(false, Ordering::SeqCst);
+
+        self.exec();
+    }
+}
+
+/// Called when the wall clock moved by delta nanoseconds, absolute realtime timers expire at
+/// the new time
+pub fn clock_was_set(delta: i64) {
+    ABS_REALTIME.lock_irq().retain(|timer| {
+        timer
+            .upgrade()
+            .is_some_and(|timer| timer.clock_was_set(delta))
+    });
+}
@@ -3,31 +3,45 @@ use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cell::UnsafeCell;
-use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicIsize, AtomicU64, AtomicUsize, Ordering};
 
 use intrusive_collections::{LinkedList, LinkedListLink};
 
 use syscall_defs::exec::ExeArgs;
-use syscall_defs::signal::SIGCHLD;
-use syscall_defs::{OpenFlags, SyscallError, SyscallResult};
+use syscall_defs::personality::Personality;
+use syscall_defs::resource::{RLimit, RLimitKind};
+use syscall_defs::sched::SchedPolicy;
+use syscall_defs::signal::{SigInfo, SIGCHLD, SIGCONT, SI_KERNEL};
+use syscall_defs::signal::{CLD_CONTINUED, CLD_EXITED, CLD_KILLED, CLD_STOPPED};
+use syscall_defs::{CloneFlags, OpenFlags, SyscallError, SyscallResult};
 
 use crate::arch::mm::VirtAddr;
 use crate::arch::task::Task as ArchTask;
 use crate::kernel::fs::dirent::DirEntryItem;
 use crate::kernel::fs::root_dentry;
+use crate::kernel::mm::uaccess::write_user;
 use crate::kernel::sched::{current_task_ref, new_task_tid, SleepFlags};
 use crate::kernel::signal::{SignalResult, Signals, KSIGSTOPTHR};
 use crate::kernel::sync::{LockApi, RwSpin, Spin, SpinGuard};
 use crate::kernel::task::children_events::WaitPidEvents;
 use crate::kernel::task::cwd::Cwd;
 use crate::kernel::task::filetable::FileHandle;
+use crate::kernel::task::itimer::ProcessTimers;
+use crate::kernel::task::ptrace::Ptrace;
+use crate::kernel::task::resource::Resources;
+use crate::kernel::task::strace::{SyscallTrace, TraceEntry};
 use crate::kernel::task::vm::{PageFaultReason, VM};
 use crate::kernel::tty::Terminal;
 use crate::kernel::utils::arc_type::{ArcType, Uid, WeakType};
+use crate::kernel::utils::wait_queue::{WaitQueue, WaitQueueFlags};
 
 pub mod children_events;
 pub mod cwd;
 pub mod filetable;
+pub mod itimer;
+pub mod ptrace;
+pub mod resource;
+pub mod strace;
 pub mod vm;
 #[macro_use]
 pub mod intrusive_adapter;
@@ -95,12 +109,45 @@ pub struct Task {
     filetable: Arc<filetable::FileTable>,
     vm: Arc<VM>,
     sleep_until: AtomicUsize,
-    cwd: RwSpin<Option<Cwd>>,
+    cwd: Arc<RwSpin<Option<Cwd>>>,
     sref: WeakTask,
     signals: Signals,
     terminal: Terminal,
     children_events: WaitPidEvents,
     waitpid_status: AtomicU64,
+    personality: AtomicU64,
+    resources: Arc<Resources>,
+    clear_child_tid: AtomicUsize,
+    vfork_pending: AtomicBool,
+    vfork_wq: WaitQueue,
+    ptrace: Ptrace,
+    strace: Arc<SyscallTrace>,
+    // Traced syscall in progress, only accessed by the task itself
+    strace_entry: UnsafeCell<Option<TraceEntry>>,
+    timers: Arc<ProcessTimers>,
+    nice: AtomicIsize,
+    sched_policy: AtomicUsize,
+    // Static priority of real-time tasks, 0 for normal tasks
+    rt_priority: AtomicUsize,
+    // Cpus the task is allowed to run on
+    cpu_mask: AtomicU64,
+    // Weighted cpu time used by the fair scheduler
+    vruntime: AtomicU64,
+    // Time used from the current time slice in nanoseconds
+    slice_used: AtomicU64,
+    // Time of the last user/kernel transition or context switch
+    acct_stamp: AtomicU64,
+    // Cpu time used by the thread
+    cpu_ns: AtomicU64,
+}
+
+#[derive(Default)]
+pub struct CloneArgs {
+    pub flags: CloneFlags,
+    pub stack: Option<VirtAddr>,
+    pub tls: Option<VirtAddr>,
+    pub parent_tid: Option<VirtAddr>,
+    pub child_tid: Option<VirtAddr>,
 }
 
 unsafe impl Sync for Task {}
@@ -124,6 +171,7 @@ impl Task {
 
         def.on_cpu
             .store(unsafe { crate::CPU_ID } as usize, Ordering::SeqCst);
+        def.set_cpu_mask(u64::MAX);
 
         if let Some(e) = root_dentry() {
             def.set_cwd(e.clone());
@@ -175,37 +223,134 @@ impl Task {
     }
 
     pub fn fork(&self) -> ArcTask {
+        self.clone_task(&CloneArgs::default())
+    }
+
+    /// Creates a child returning from the current syscall, sharing resources selected by flags
+    pub fn clone_task(&self, args: &CloneArgs) -> ArcTask {
+        let flags = args.flags;
+
+        let is_thread = flags.contains(CloneFlags::CLONE_THREAD);
+
         let mut task = Task::new();
 
-        task.set_gid(self.gid());
-        task.set_sid(self.sid());
+        let parent = if is_thread {
+            let leader = self.process_leader();
+
+            task.set_pid(leader.pid());
+
+            leader
+        } else {
+            self.me()
+        };
+
+        task.set_gid(parent.gid());
+        task.set_sid(parent.sid());
+        task.set_personality(parent.personality());
+        task.set_nice(self.nice());
+        task.set_sched_policy(self.sched_policy(), self.rt_priority());
+        task.set_cpu_mask(self.cpu_mask());
+
+        task.arch_task = UnsafeCell::new(unsafe {
+            self.arch_task().clone_task(
+                flags.contains(CloneFlags::CLONE_VM),
+                args.stack.map(|s| s.0),
+                args.tls.map(|t| t.0),
+            )
+        });
+
+        if is_thread {
+            task.vm = self.vm.clone();
+            task.resources = self.resources.clone();
+            task.strace = self.strace.clone();
+            task.timers = self.timers.clone();
+            task.signals = self.signals().clone();
+
+            self.terminal().share_with(&mut task.terminal);
+        } else {
+            if flags.contains(CloneFlags::CLONE_VM) {
+                task.vm().fork_shared(self.vm());
+            } else {
+                task.vm().fork(self.vm());
+            }
+
+            task.resources = Arc::new(self.resources.fork());
+        }
 
-        task.arch_task = UnsafeCell::new(unsafe { self.arch_task().fork() });
+        task.filetable = if flags.contains(CloneFlags::CLONE_FILES) {
+            self.filetable.clone()
+        } else {
+            Arc::new(self.filetable.as_ref().clone())
+        };
 
-        task.vm().fork(self.vm());
-        task.filetable = Arc::new(self.filetable.as_ref().clone());
-        if let Some(e) = self.get_dent() {
+        if flags.contains(CloneFlags::CLONE_FS) {
+            task.cwd = self.cwd.clone();
+        } else if let Some(e) = self.get_dent() {
             task.set_cwd(e);
         }
+
+        if let Some(addr) = args.child_tid {
+            task.clear_child_tid.store(addr.0, Ordering::SeqCst);
+        }
+
+        task.vfork_pending
+            .store(flags.contains(CloneFlags::CLONE_VFORK), Ordering::SeqCst);
+
         task.set_locks(self.locks());
 
+        if let Some(addr) = args.parent_tid {
+            // Written before the child runs, failures are ignored
+            let _ = write_user::<u32>(addr, &(task.tid() as u32));
+        }
+
         let task = Self::make_ptr(task);
 
-        self.add_child(task.clone());
+        parent.add_child(task.clone());
 
-        task.signals().copy_from(self.signals());
+        if !is_thread {
+            task.signals().copy_from(self.signals());
 
-        if let Some(term) = self.terminal().terminal() {
-            task.terminal().connect(term);
+            if let Some(term) = self.terminal().terminal() {
+                task.terminal().connect(term);
+            }
         }
 
         task.set_exe(self.exe());
 
-        logln2!("new fork task {}", task.pid());
+        logln2!("new clone task {} pid {}", task.tid(), task.pid());
 
         task
     }
 
+    /// Called once the task stops using its address space on exec or exit
+    pub fn release_vm(&self) {
+        let addr = self.clear_child_tid.swap(0, Ordering::SeqCst);
+
+        if addr != 0 {
+            let addr = VirtAddr(addr);
+
+            if write_user::<u32>(addr, &0).is_ok() {
+                let _ = crate::kernel::futex::futex().wake(addr);
+            }
+        }
+
+        if self.vfork_pending.swap(false, Ordering::SeqCst) {
+            self.vfork_wq.notify_all();
+        }
+    }
+
+    /// Detaches the exiting process from its tracer and from its tracees
+    pub fn release_ptrace(&self) {
+        ptrace::release(self);
+    }
+
+    /// Blocks until the vfork child calls exec or exits, or the parent is killed
+    pub fn wait_vfork(&self) {
+        let _ = self.vfork_wq.wait_for(WaitQueueFlags::KILLABLE, || {
+            !self.vfork_pending.load(Ordering::SeqCst)
+        });
+    }
+
     pub fn exec(
         &self,
         mut exe: DirEntryItem,
@@ -214,6 +359,11 @@ impl Task {
     ) -> Result<!, SyscallError> {
         dbgln!(task, "exec task {} {}", self.pid(), exe.full_path());
 
+        // Initial user stack has to fit within the stack limit
+        if self.rlimit(RLimitKind::Stack).cur < crate::kernel::mm::PAGE_SIZE as u64 {
+            return Err(SyscallError::E2BIG);
+        }
+
         self.set_terminating(false);
 
         let mut args = args.unwrap_or(ExeArgs::new());
@@ -221,7 +371,14 @@ impl Task {
         let vm = VM::new();
 
         let (base_addr, entry, elf_hdr, tls_vm, interpreter) =
-            vm.load_bin(exe.clone()).ok_or(SyscallError::EINVAL)?;
+            vm.load_bin(
+                exe.clone(),
+                !self
+                    .process_leader()
+                    .personality()
+                    .contains(Personality::ADDR_NO_RANDOMIZE),
+            )
+            .ok_or(SyscallError::EINVAL)?;
 
         if let Some((interp, additional_args)) = interpreter {
             // got a shebang interpreter line?? replace exe and pass script as a first param
@@ -234,8 +391,16 @@ impl Task {
             }
             exe = interp;
         }
+        // Map the user stack and tls within the new process limits
+        let tls_addr = crate::arch::task::mmap_exec(&vm, tls_vm.as_ref())?;
+
         vm.log_vm();
 
+        // Exec can't fail from now on
+        self.strace_finish(Ok(0));
+
+        self.release_vm();
+
         // Replace our new vm
         self.vm.fork(&vm);
 
@@ -244,6 +409,8 @@ impl Task {
         // New process does not inherits signals
         self.signals().clear();
 
+        self.timers().exec();
+
         // No locks
         self.set_locks(0);
 
@@ -275,6 +442,7 @@ impl Task {
                 &elf_hdr,
                 self.vm(),
                 tls_vm,
+                tls_addr,
                 exe,
                 Some(args),
                 envs,
@@ -305,9 +473,16 @@ impl Task {
         thread.set_pid(process_leader.pid());
         thread.set_gid(process_leader.gid());
         thread.set_sid(process_leader.sid());
+        thread.set_personality(process_leader.personality());
+        thread.set_nice(self.nice());
+        thread.set_sched_policy(self.sched_policy(), self.rt_priority());
+        thread.set_cpu_mask(self.cpu_mask());
 
         thread.filetable = process_leader.filetable.clone();
         thread.vm = process_leader.vm.clone();
+        thread.resources = process_leader.resources.clone();
+        thread.strace = process_leader.strace.clone();
+        thread.timers = process_leader.timers.clone();
         if let Some(d) = process_leader.get_dent() {
             thread.set_cwd(d);
         }
@@ -395,6 +570,22 @@ impl Task {
         *self.parent.lock() = parent;
     }
 
+    /// Whether the process is the process pid or one of its descendants
+    pub fn is_descendant_of(&self, pid: usize) -> bool {
+        let mut task = self.process_leader();
+
+        loop {
+            if task.pid() == pid {
+                return true;
+            }
+
+            match task.get_parent() {
+                Some(parent) => task = parent.process_leader(),
+                None => return false,
+            }
+        }
+    }
+
     pub fn children(&self) -> SpinGuard<'_, LinkedList<TaskAdapter>> {
         self.children.lock()
     }
@@ -499,6 +690,160 @@ impl Task {
         self.waitpid_status.store(status.into(), Ordering::SeqCst);
     }
 
+    pub fn personality(&self) -> Personality {
+        Personality::from_bits_truncate(self.personality.load(Ordering::SeqCst))
+    }
+
+    pub fn set_personality(&self, personality: Personality) {
+        self.personality.store(personality.bits(), Ordering::SeqCst);
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    pub fn rlimit(&self, kind: RLimitKind) -> RLimit {
+        self.resources.limit(kind)
+    }
+
+    pub fn set_rlimit(&self, kind: RLimitKind, limit: RLimit) -> Result<(), SyscallError> {
+        self.resources.set_limit(kind, limit)?;
+
+        if kind == RLimitKind::NOFile {
+            self.filetable.set_limit(limit.cur as usize);
+        }
+
+        Ok(())
+    }
+
+    /// Sends SIGXCPU or SIGKILL once the process goes over RLIMIT_CPU
+    pub fn check_cpu_limit(&self) {
+        if let Some(sig) = self.resources.check_cpu_time(self.process_cpu_ns()) {
+            self.signal_thread(sig);
+        }
+    }
+
+    /// Charges the time since the last transition to user or kernel mode
+    fn account_since_stamp(&self, user: bool) {
+        let now = crate::kernel::timer::current_ns();
+
+        let last = self.acct_stamp.swap(now, Ordering::Relaxed);
+
+        if last == 0 {
+            return;
+        }
+
+        self.cpu_ns.fetch_add(now - last, Ordering::Relaxed);
+
+        if user {
+            self.resources.account_user(now - last);
+        } else {
+            self.resources.account_system(now - last);
+        }
+
+        self.timers.account(now - last, user, |sig| {
+            self.signal(sig);
+        });
+    }
+
+    /// Called on syscall and interrupt entry from userspace
+    pub fn account_enter_kernel(&self) {
+        self.account_since_stamp(true);
+    }
+
+    /// Called on return to userspace
+    pub fn account_leave_kernel(&self) {
+        self.account_since_stamp(false);
+    }
+
+    /// Tasks are switched out in the kernel, the switch is voluntary unless still runnable
+    pub fn account_switch_out(&self) {
+        self.account_since_stamp(false);
+
+        self.resources
+            .account_switch(self.state() != TaskState::Runnable);
+    }
+
+    /// Time since the last accounting, only meaningful for the running task
+    fn unaccounted_ns(&self) -> u64 {
+        match self.acct_stamp.load(Ordering::Relaxed) {
+            0 => 0,
+            last => crate::kernel::timer::current_ns().saturating_sub(last),
+        }
+    }
+
+    pub fn thread_cpu_ns(&self) -> u64 {
+        self.cpu_ns.load(Ordering::Relaxed) + self.unaccounted_ns()
+    }
+
+    /// Cpu time used by all the threads of the process
+    pub fn process_cpu_ns(&self) -> u64 {
+        let usage = self.resources.usage();
+
+        usage.user_ns + usage.system_ns + self.unaccounted_ns()
+    }
+
+    pub fn account_switch_in(&self) {
+        self.acct_stamp
+            .store(crate::kernel::timer::current_ns(), Ordering::Relaxed);
+    }
+
+    pub fn nice(&self) -> isize {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    pub fn set_nice(&self, nice: isize) {
+        self.nice.store(
+            crate::kernel::sched::prio::clamp_nice(nice),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn vruntime(&self) -> u64 {
+        self.vruntime.load(Ordering::Relaxed)
+    }
+
+    pub fn set_vruntime(&self, vruntime: u64) {
+        self.vruntime.store(vruntime, Ordering::Relaxed);
+    }
+
+    pub fn sched_policy(&self) -> SchedPolicy {
+        SchedPolicy::try_from(self.sched_policy.load(Ordering::Relaxed) as u64).unwrap()
+    }
+
+    pub fn rt_priority(&self) -> usize {
+        self.rt_priority.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sched_policy(&self, policy: SchedPolicy, rt_priority: usize) {
+        self.sched_policy.store(policy as usize, Ordering::Relaxed);
+        self.rt_priority.store(rt_priority, Ordering::Relaxed);
+    }
+
+    pub fn is_rt(&self) -> bool {
+        self.sched_policy().is_rt()
+    }
+
+    /// Charges ns to the current time slice, returns true once the slice is used up
+    pub fn consume_slice(&self, ns: u64) -> bool {
+        let slice = match self.sched_policy() {
+            // FIFO tasks run until they block or a higher priority task shows up
+            SchedPolicy::Fifo => return false,
+            SchedPolicy::RoundRobin => crate::kernel::sched::prio::RR_SLICE_NS,
+            SchedPolicy::Other => crate::kernel::sched::prio::time_slice(self.nice()),
+        };
+
+        let used = self.slice_used.fetch_add(ns, Ordering::Relaxed) + ns;
+
+        if used >= slice {
+            self.slice_used.store(0, Ordering::Relaxed);
+
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn set_state(&self, state: TaskState) {
         self.state.store(state as usize, Ordering::SeqCst);
     }
@@ -623,6 +968,18 @@ impl Task {
         self.on_cpu.store(cpu, Ordering::Relaxed);
     }
 
+    pub fn cpu_mask(&self) -> u64 {
+        self.cpu_mask.load(Ordering::Relaxed)
+    }
+
+    pub fn set_cpu_mask(&self, mask: u64) {
+        self.cpu_mask.store(mask, Ordering::Relaxed);
+    }
+
+    pub fn can_run_on(&self, cpu: usize) -> bool {
+        cpu < 64 && self.cpu_mask() & (1 << cpu) != 0
+    }
+
     pub fn has_pending_io(&self) -> bool {
         self.pending_io.load(Ordering::SeqCst)
     }
@@ -736,6 +1093,7 @@ impl Task {
                     syscall_defs::waitpid::WaitPidFlags::EXITED
                         | syscall_defs::waitpid::WaitPidFlags::CONTINUED
                         | syscall_defs::waitpid::WaitPidFlags::STOPPED,
+                    None,
                 ) {
                     Ok(Err(SyscallError::ECHILD)) => {
                         if self
@@ -963,7 +1321,38 @@ impl Task {
         &self.signals
     }
 
-    fn do_signal(&self, sig: usize, this_thread: bool) -> bool {
+    pub fn ptrace(&self) -> &Ptrace {
+        &self.ptrace
+    }
+
+    pub fn strace(&self) -> &SyscallTrace {
+        &self.strace
+    }
+
+    pub fn strace_entry(&self, num: usize, args: [u64; 6]) {
+        if !self.strace.is_enabled() {
+            return;
+        }
+
+        unsafe {
+            *self.strace_entry.get() = Some(self.strace.entry(num, args));
+        }
+    }
+
+    /// Records the traced syscall, called on return or before exec and exit tear the task down
+    pub fn strace_finish(&self, res: SyscallResult) {
+        let entry = unsafe { (*self.strace_entry.get()).take() };
+
+        if let Some(entry) = entry {
+            self.strace.finish(entry, res);
+        }
+    }
+
+    pub fn timers(&self) -> &ProcessTimers {
+        &self.timers
+    }
+
+    fn do_signal(&self, sig: usize, this_thread: bool, info: SigInfo) -> bool {
         use crate::kernel::signal::TriggerResult;
 
         dbgln!(
@@ -974,7 +1363,7 @@ impl Task {
             this_thread
         );
 
-        match self.signals().trigger(sig, this_thread) {
+        match self.signals().trigger(sig, this_thread, info) {
             TriggerResult::Triggered => {
                 dbgln!(signal, "signal {} triggered", sig);
                 self.wake_up();
@@ -1017,6 +1406,10 @@ impl Task {
     }
 
     pub fn signal(&self, sig: usize) -> bool {
+        self.signal_info(sig, SigInfo::new(sig, SI_KERNEL))
+    }
+
+    pub fn signal_info(&self, sig: usize, info: SigInfo) -> bool {
         dbgln!(
             signal,
             "{}: signal {} sig: {}",
@@ -1025,20 +1418,42 @@ impl Task {
             sig
         );
         if self.state() != TaskState::Unused {
-            self.do_signal(sig, false)
+            self.do_signal(sig, false, info)
         } else {
             false
         }
     }
 
     pub fn signal_thread(&self, sig: usize) -> bool {
-        self.do_signal(sig, true)
+        self.do_signal(sig, true, SigInfo::new(sig, SI_KERNEL))
+    }
+
+    /// Signals the thread which caused the fault at the address, the signal can't be blocked
+    /// or ignored
+    pub fn signal_fault(&self, sig: usize, code: i32, addr: u64) -> bool {
+        self.signals().force(sig);
+
+        self.do_signal(sig, true, SigInfo::fault(sig, code, addr))
     }
 
     pub fn terminal(&self) -> &Terminal {
         &self.terminal
     }
 
+    fn sigchld_info(&self, status: syscall_defs::waitpid::Status) -> SigInfo {
+        use syscall_defs::waitpid::Status;
+
+        let (code, status) = match status {
+            Status::Exited(code) => (CLD_EXITED, code as i32),
+            Status::Signaled(sig) => (CLD_KILLED, sig as i32),
+            Status::Stopped(sig) => (CLD_STOPPED, sig as i32),
+            Status::Continued => (CLD_CONTINUED, SIGCONT as i32),
+            Status::Invalid(_) => (CLD_EXITED, 0),
+        };
+
+        SigInfo::child(code, self.pid(), 0, status)
+    }
+
     pub fn make_zombie(&self, status: syscall_defs::waitpid::Status) {
         self.terminal().disconnect(None);
 
@@ -1059,7 +1474,7 @@ impl Task {
                     self.tid(),
                     parent.tid()
                 );
-                parent.signal(SIGCHLD);
+                parent.signal_info(SIGCHLD, self.sigchld_info(status));
             }
         }
     }
@@ -1071,19 +1486,32 @@ impl Task {
 
             if self.is_process_leader() {
                 dbgln!(waitpid, "signal cont SIGCHILD by {}", self.tid());
-                parent.signal(SIGCHLD);
+                parent.signal_info(
+                    SIGCHLD,
+                    self.sigchld_info(syscall_defs::waitpid::Status::Continued),
+                );
             }
         }
     }
 
     pub fn notify_stopped(&self, sig: usize) {
-        if let Some(parent) = self.get_parent() {
+        // Traced process reports its stops to the tracer instead
+        let tracer = if self.is_process_leader() {
+            self.ptrace().tracer()
+        } else {
+            None
+        };
+
+        if let Some(parent) = tracer.or_else(|| self.get_parent()) {
             self.set_waitpid_status(syscall_defs::waitpid::Status::Stopped(sig as u64));
             parent.children_events.add_stopped(self.me());
 
             if self.is_process_leader() {
                 dbgln!(waitpid, "signal stop SIGCHILD by {}", self.tid());
-                parent.signal(SIGCHLD);
+                parent.signal_info(
+                    SIGCHLD,
+                    self.sigchld_info(syscall_defs::waitpid::Status::Stopped(sig as u64)),
+                );
             }
         }
     }
@@ -1093,8 +1521,10 @@ impl Task {
         pid: isize,
         status: &mut syscall_defs::waitpid::Status,
         flags: syscall_defs::waitpid::WaitPidFlags,
+        usage: Option<&mut resource::Usage>,
     ) -> SignalResult<SyscallResult> {
-        self.children_events.wait_pid(self, pid, status, flags)
+        self.children_events
+            .wait_pid(self, pid, status, flags, usage)
     }
 
     pub fn wait_thread(
@@ -0,0 +1,470 @@
+use alloc::vec::Vec;
+
+use syscall_defs::ptrace::{PtraceRequest, PTRACE_O_STRACE};
+use syscall_defs::signal::{SIGCHLD, SIGKILL, SIGSTOP, SIGTRAP};
+use syscall_defs::{SyscallError, SyscallResult};
+
+use crate::arch::signal::{UserFrame, UserRegs};
+use crate::kernel::mm::uaccess::{read_user, write_user};
+use crate::kernel::mm::VirtAddr;
+use crate::kernel::sched::{current_task_ref, get_task};
+use crate::kernel::signal::KSIGKILLTHR;
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::task::{ArcTask, Task, TaskState, WeakTask};
+use crate::kernel::utils::wait_queue::{WaitQueue, WaitQueueFlags};
+
+/// Tracee memory is accessed by the tracee itself, so page faults are handled in its vm
+enum MemRequest {
+    Peek(VirtAddr),
+    Poke(VirtAddr, u64),
+}
+
+impl MemRequest {
+    fn run(&self) -> SyscallResult {
+        match *self {
+            MemRequest::Peek(addr) => read_user::<usize>(addr).map_err(|_| SyscallError::EIO),
+            MemRequest::Poke(addr, val) => {
+                if current_task_ref()
+                    .vm()
+                    .write_force(addr, &val.to_ne_bytes())
+                {
+                    Ok(0)
+                } else {
+                    Err(SyscallError::EIO)
+                }
+            }
+        }
+    }
+}
+
+/// Tracee state while it is in the ptrace-stop
+struct Stop {
+    regs: UserRegs,
+    regs_changed: bool,
+    request: Option<MemRequest>,
+    response: Option<SyscallResult>,
+    // Signal to deliver after resume
+    resume: Option<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    tracer: Option<WeakTask>,
+    tracees: Vec<WeakTask>,
+    syscall_stops: bool,
+    single_step: bool,
+    stop: Option<Stop>,
+}
+
+#[derive(Default)]
+pub struct Ptrace {
+    state: Spin<State>,
+    wq: WaitQueue,
+}
+
+impl Ptrace {
+    pub fn is_traced(&self) -> bool {
+        self.state.lock_irq().tracer.is_some()
+    }
+
+    pub fn tracer(&self) -> Option<ArcTask> {
+        self.state.lock_irq().tracer.as_ref()?.upgrade()
+    }
+
+    pub fn is_traced_by(&self, task: &Task) -> bool {
+        self.tracer().is_some_and(|t| t.tid() == task.tid())
+    }
+
+    pub fn syscall_stops(&self) -> bool {
+        self.state.lock_irq().syscall_stops
+    }
+
+    pub fn any_tracee(&self, mut cond: impl FnMut(&Task) -> bool) -> bool {
+        self.state
+            .lock_irq()
+            .tracees
+            .iter()
+            .filter_map(|t| t.upgrade())
+            .any(|t| cond(&t))
+    }
+
+    fn set_tracer(&self, tracer: &Task) -> SyscallResult {
+        let mut state = self.state.lock_irq();
+
+        if state.tracer.is_some() {
+            return Err(SyscallError::EPERM);
+        }
+
+        state.tracer = Some(tracer.sref.clone());
+
+        Ok(0)
+    }
+
+    fn add_tracee(&self, tracee: &Task) {
+        self.state.lock_irq().tracees.push(tracee.sref.clone());
+    }
+
+    fn remove_tracee(&self, tracee: &Task) {
+        self.state
+            .lock_irq()
+            .tracees
+            .retain(|t| t.upgrade().is_some_and(|t| t.tid() != tracee.tid()));
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.state.lock_irq().stop.is_some()
+    }
+
+    fn regs(&self) -> Result<UserRegs, SyscallError> {
+        self.state
+            .lock_irq()
+            .stop
+            .as_ref()
+            .map(|s| s.regs)
+            .ok_or(SyscallError::ESRCH)
+    }
+
+    fn set_regs(&self, regs: UserRegs) -> SyscallResult {
+        if !VirtAddr(regs.rip as usize).is_user() || !VirtAddr(regs.rsp as usize).is_user() {
+            return Err(SyscallError::EIO);
+        }
+
+        let mut state = self.state.lock_irq();
+
+        let stop = state.stop.as_mut().ok_or(SyscallError::ESRCH)?;
+
+        stop.regs = regs;
+        stop.regs_changed = true;
+
+        Ok(0)
+    }
+
+    /// Runs memory request in the tracee context and waits for the result
+    fn request(&self, req: MemRequest) -> SyscallResult {
+        {
+            let mut state = self.state.lock_irq();
+
+            let stop = state.stop.as_mut().ok_or(SyscallError::ESRCH)?;
+
+            stop.request = Some(req);
+            stop.response = None;
+        }
+
+        self.wq.notify_all();
+
+        let mut state = self
+            .wq
+            .wait_lock_for(WaitQueueFlags::IRQ_DISABLE, &self.state, |s| {
+                s.stop.as_ref().is_none_or(|st| st.response.is_some())
+            })?
+            .unwrap();
+
+        state
+            .stop
+            .as_mut()
+            .and_then(|st| st.response.take())
+            .unwrap_or(Err(SyscallError::ESRCH))
+    }
+
+    fn resume(&self, sig: usize, syscall_stops: bool, single_step: bool) {
+        {
+            let mut state = self.state.lock_irq();
+
+            state.syscall_stops = syscall_stops;
+            state.single_step = single_step;
+
+            if let Some(stop) = state.stop.as_mut() {
+                stop.resume = Some(sig);
+            }
+        }
+
+        self.wq.notify_all();
+    }
+}
+
+/// Stops current task until the tracer resumes it, returns the signal to deliver
+pub fn stop(sig: usize, frame: &mut UserFrame) -> usize {
+    let task = current_task_ref();
+    let ptrace = task.ptrace();
+
+    let tracer = {
+        let mut state = ptrace.state.lock_irq();
+
+        let Some(tracer) = state.tracer.as_ref().and_then(|t| t.upgrade()) else {
+            return sig;
+        };
+
+        state.stop = Some(Stop {
+            regs: frame.user_regs(),
+            regs_changed: false,
+            request: None,
+            response: None,
+            resume: None,
+        });
+
+        tracer
+    };
+
+    dbgln!(ptrace, "task {} ptrace stop sig {}", task.tid(), sig);
+
+    if let Some(parent) = task.get_parent() {
+        parent.children_events.remove(task);
+    }
+
+    task.set_waitpid_status(syscall_defs::waitpid::Status::Stopped(sig as u64));
+    tracer.children_events.add_stopped(task.me());
+    tracer.signal(SIGCHLD);
+
+    drop(tracer);
+
+    loop {
+        let Ok(Some(mut state)) = ptrace.wq.wait_lock_for(
+            WaitQueueFlags::IRQ_DISABLE | WaitQueueFlags::NON_INTERRUPTIBLE,
+            &ptrace.state,
+            |s| {
+                s.tracer.is_none()
+                    || task.signals().is_pending(SIGKILL as u64)
+                    || s.stop
+                        .as_ref()
+                        .is_some_and(|st| st.resume.is_some() || st.request.is_some())
+            },
+        ) else {
+            continue;
+        };
+
+        let killed = state.tracer.is_none() || task.signals().is_pending(SIGKILL as u64);
+
+        let stop = state.stop.as_mut().expect("ptrace stop state missing");
+
+        if !killed && stop.resume.is_none() {
+            let req = stop.request.take().expect("ptrace request missing");
+
+            drop(state);
+
+            let res = req.run();
+
+            if let Some(stop) = ptrace.state.lock_irq().stop.as_mut() {
+                stop.response = Some(res);
+            }
+
+            ptrace.wq.notify_all();
+
+            continue;
+        }
+
+        let stop = state.stop.take().expect("ptrace stop state missing");
+        let single_step = state.single_step;
+
+        drop(state);
+
+        // Wake the tracer if it is still waiting for the request
+        ptrace.wq.notify_all();
+
+        if stop.regs_changed && !frame.set_user_regs(&stop.regs) {
+            logln!("[ PTRACE ] Task {} invalid registers set", task.tid());
+        }
+
+        frame.set_single_step(single_step);
+
+        dbgln!(
+            ptrace,
+            "task {} ptrace resume {:?}",
+            task.tid(),
+            stop.resume
+        );
+
+        return stop.resume.unwrap_or(0);
+    }
+}
+
+/// Syscall entry or exit stop
+pub fn syscall_stop(frame: &mut UserFrame) {
+    let sig = stop(SIGTRAP, frame);
+
+    if sig != 0 {
+        current_task_ref().signal(sig);
+    }
+}
+
+/// Exec stop, the new image is loaded but hasn't run yet
+pub fn exec_stop(frame: &mut UserFrame) {
+    syscall_stop(frame);
+}
+
+fn detach(tracee: &Task, sig: usize) {
+    let tracer = {
+        let mut state = tracee.ptrace().state.lock_irq();
+
+        state.syscall_stops = false;
+        state.single_step = false;
+
+        if let Some(stop) = state.stop.as_mut() {
+            stop.resume = Some(sig);
+        }
+
+        state.tracer.take()
+    };
+
+    tracee.ptrace().wq.notify_all();
+
+    if let Some(tracer) = tracer.and_then(|t| t.upgrade()) {
+        dbgln!(ptrace, "task {} detach {}", tracer.tid(), tracee.tid());
+
+        tracer.ptrace().remove_tracee(tracee);
+        tracer.children_events.remove(tracee);
+    }
+
+    if tracee.state() == TaskState::Stopped {
+        crate::kernel::sched::cont(tracee.me());
+    }
+}
+
+/// Detaches exiting process from its tracer and releases its own tracees
+pub fn release(task: &Task) {
+    if task.ptrace().is_traced() {
+        detach(task, 0);
+    }
+
+    let tracees = core::mem::take(&mut task.ptrace().state.lock_irq().tracees);
+
+    for tracee in tracees.iter().filter_map(|t| t.upgrade()) {
+        detach(&tracee, 0);
+    }
+}
+
+fn trace_me() -> SyscallResult {
+    let task = current_task_ref();
+
+    let parent = task.get_parent().ok_or(SyscallError::EPERM)?;
+
+    task.ptrace().set_tracer(&parent)?;
+    parent.ptrace().add_tracee(task);
+
+    Ok(0)
+}
+
+fn attach(pid: usize) -> SyscallResult {
+    let task = current_task_ref();
+
+    let tracee = get_task(pid).ok_or(SyscallError::ESRCH)?;
+
+    // There are no user ids, only descendants of the tracer can be attached, except init
+    if !tracee.is_process_leader()
+        || tracee.pid() == 1
+        || tracee.pid() == task.pid()
+        || !tracee.is_descendant_of(task.pid())
+        || !unsafe { tracee.arch_task() }.is_user()
+    {
+        return Err(SyscallError::EPERM);
+    }
+
+    tracee.ptrace().set_tracer(task)?;
+    task.ptrace().add_tracee(&tracee);
+
+    // Tracee reports the attach with the SIGSTOP signal delivery stop
+    tracee.signal(SIGSTOP);
+
+    Ok(0)
+}
+
+/// Tracee of the current task that is stopped
+fn stopped_tracee(pid: usize) -> Result<ArcTask, SyscallError> {
+    let tracee = get_task(pid).ok_or(SyscallError::ESRCH)?;
+
+    if !tracee.ptrace().is_traced_by(current_task_ref())
+        || (!tracee.ptrace().is_stopped() && tracee.state() != TaskState::Stopped)
+    {
+        return Err(SyscallError::ESRCH);
+    }
+
+    Ok(tracee)
+}
+
+fn resume_sig(data: usize) -> Result<usize, SyscallError> {
+    if data < KSIGKILLTHR {
+        Ok(data)
+    } else {
+        Err(SyscallError::EIO)
+    }
+}
+
+fn set_options(tracee: &Task, options: usize) -> SyscallResult {
+    if options & !PTRACE_O_STRACE != 0 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    tracee.strace().set_enabled(options & PTRACE_O_STRACE != 0);
+
+    Ok(0)
+}
+
+fn resume(tracee: &Task, data: usize, syscall_stops: bool, single_step: bool) -> SyscallResult {
+    let sig = resume_sig(data)?;
+
+    tracee.ptrace().resume(sig, syscall_stops, single_step);
+
+    // Tracee was stopped by a stop signal delivered by the tracer
+    if tracee.state() == TaskState::Stopped {
+        crate::kernel::sched::cont(tracee.me());
+    }
+
+    Ok(0)
+}
+
+pub fn ptrace(request: PtraceRequest, pid: usize, addr: VirtAddr, data: usize) -> SyscallResult {
+    dbgln!(
+        ptrace,
+        "task {} ptrace {:?} pid {} addr {} data {:#x}",
+        current_task_ref().tid(),
+        request,
+        pid,
+        addr,
+        data
+    );
+
+    match request {
+        PtraceRequest::TraceMe => return trace_me(),
+        PtraceRequest::Attach => return attach(pid),
+        PtraceRequest::Unknown => return Err(SyscallError::EIO),
+        _ => {}
+    }
+
+    let tracee = stopped_tracee(pid)?;
+    let ptrace = tracee.ptrace();
+
+    match request {
+        PtraceRequest::PeekText | PtraceRequest::PeekData => {
+            let val = ptrace.request(MemRequest::Peek(addr))?;
+
+            write_user(VirtAddr(data), &val)?;
+
+            Ok(0)
+        }
+        PtraceRequest::PokeText | PtraceRequest::PokeData => {
+            ptrace.request(MemRequest::Poke(addr, data as u64))
+        }
+        PtraceRequest::GetRegs => {
+            write_user(VirtAddr(data), &ptrace.regs()?)?;
+
+            Ok(0)
+        }
+        PtraceRequest::SetRegs => ptrace.set_regs(read_user::<UserRegs>(VirtAddr(data))?),
+        PtraceRequest::Cont => resume(&tracee, data, false, false),
+        PtraceRequest::Syscall => resume(&tracee, data, true, false),
+        PtraceRequest::SingleStep => resume(&tracee, data, false, true),
+        PtraceRequest::SetOptions => set_options(&tracee, data),
+        PtraceRequest::Kill => {
+            tracee.signal(SIGKILL);
+
+            Ok(0)
+        }
+        PtraceRequest::Detach => {
+            let sig = resume_sig(data)?;
+
+            detach(&tracee, sig);
+
+            Ok(0)
+        }
+        PtraceRequest::TraceMe | PtraceRequest::Attach | PtraceRequest::Unknown => unreachable!(),
+    }
+}
@@ -0,0 +1,191 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use syscall_defs::resource::{RLimit, RLimitKind, RUsage, RLIM_INFINITY};
+use syscall_defs::signal::{SIGKILL, SIGXCPU};
+use syscall_defs::time::Timeval;
+use syscall_defs::SyscallError;
+
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::task::filetable::{FILE_MAX, FILE_NUM};
+
+const RLIMIT_COUNT: usize = RLimitKind::NLimits as usize;
+
+const DEFAULT_STACK_LIMIT: u64 = 8 * 1024 * 1024;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// Snapshot of the resource usage
+#[derive(Default, Debug, Copy, Clone)]
+pub struct Usage {
+    pub user_ns: u64,
+    pub system_ns: u64,
+    pub minflt: u64,
+    pub nvcsw: u64,
+    pub nivcsw: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: &Usage) {
+        self.user_ns += other.user_ns;
+        self.system_ns += other.system_ns;
+        self.minflt += other.minflt;
+        self.nvcsw += other.nvcsw;
+        self.nivcsw += other.nivcsw;
+    }
+
+    pub fn to_rusage(&self) -> RUsage {
+        RUsage {
+            ru_utime: Timeval::from_nsecs(self.user_ns),
+            ru_stime: Timeval::from_nsecs(self.system_ns),
+            ru_minflt: self.minflt as i64,
+            ru_nvcsw: self.nvcsw as i64,
+            ru_nivcsw: self.nivcsw as i64,
+            ..RUsage::default()
+        }
+    }
+}
+
+/// Resource limits and usage shared by all the threads of the process
+pub struct Resources {
+    limits: Spin<[RLimit; RLIMIT_COUNT]>,
+    // Whole seconds of cpu time already checked against RLIMIT_CPU
+    cpu_secs: AtomicU64,
+    user_ns: AtomicU64,
+    system_ns: AtomicU64,
+    minflt: AtomicU64,
+    nvcsw: AtomicU64,
+    nivcsw: AtomicU64,
+    // Usage of the reaped children and their reaped descendants
+    children: Spin<Usage>,
+}
+
+impl Default for Resources {
+    fn default() -> Resources {
+        let mut limits = [RLimit::infinity(); RLIMIT_COUNT];
+
+        limits[RLimitKind::NOFile as usize] = RLimit::new(FILE_NUM as u64, FILE_MAX as u64);
+        limits[RLimitKind::Stack as usize] = RLimit::new(DEFAULT_STACK_LIMIT, RLIM_INFINITY);
+        // Core dumps are disabled until the program raises the soft limit
+        limits[RLimitKind::Core as usize] = RLimit::new(0, RLIM_INFINITY);
+        // Nice value can't be lowered below 0 until the soft limit is raised
+        limits[RLimitKind::Nice as usize] = RLimit::new(0, RLIM_INFINITY);
+        // Real-time policies can't be set until the soft limit is raised
+        limits[RLimitKind::RTPrio as usize] = RLimit::new(0, RLIM_INFINITY);
+
+        Resources::new(limits)
+    }
+}
+
+impl Resources {
+    fn new(limits: [RLimit; RLIMIT_COUNT]) -> Resources {
+        Resources {
+            limits: Spin::new(limits),
+            cpu_secs: AtomicU64::new(0),
+            user_ns: AtomicU64::new(0),
+            system_ns: AtomicU64::new(0),
+            minflt: AtomicU64::new(0),
+            nvcsw: AtomicU64::new(0),
+            nivcsw: AtomicU64::new(0),
+            children: Spin::new(Usage::default()),
+        }
+    }
+
+    /// Limits are inherited by the child process, usage starts from scratch
+    pub fn fork(&self) -> Resources {
+        Resources::new(*self.limits.lock_irq())
+    }
+
+    pub fn limit(&self, kind: RLimitKind) -> RLimit {
+        self.limits.lock_irq()[kind as usize]
+    }
+
+    pub fn set_limit(&self, kind: RLimitKind, limit: RLimit) -> Result<(), SyscallError> {
+        if limit.cur > limit.max {
+            return Err(SyscallError::EINVAL);
+        }
+
+        if kind == RLimitKind::NOFile && limit.max > FILE_MAX as u64 {
+            return Err(SyscallError::EPERM);
+        }
+
+        let mut limits = self.limits.lock_irq();
+
+        // Hard limit can only be lowered
+        if limit.max > limits[kind as usize].max {
+            return Err(SyscallError::EPERM);
+        }
+
+        limits[kind as usize] = limit;
+
+        Ok(())
+    }
+
+    /// Checks cpu time used by all the threads of the process. Returns signal to be sent if cpu
+    /// time limit is exceeded: SIGXCPU every second above the soft limit and SIGKILL above the
+    /// hard limit
+    pub fn check_cpu_time(&self, cpu_ns: u64) -> Option<usize> {
+        let secs = cpu_ns / NS_PER_SEC;
+
+        if self.cpu_secs.fetch_max(secs, Ordering::Relaxed) >= secs {
+            return None;
+        }
+
+        let limit = self.limit(RLimitKind::Cpu);
+
+        if limit.max != RLIM_INFINITY && secs >= limit.max {
+            Some(SIGKILL)
+        } else if limit.cur != RLIM_INFINITY && secs >= limit.cur {
+            Some(SIGXCPU)
+        } else {
+            None
+        }
+    }
+
+    pub fn account_user(&self, ns: u64) {
+        self.user_ns.fetch_add(ns, Ordering::Relaxed);
+    }
+
+    pub fn account_system(&self, ns: u64) {
+        self.system_ns.fetch_add(ns, Ordering::Relaxed);
+    }
+
+    pub fn account_fault(&self) {
+        self.minflt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn account_switch(&self, voluntary: bool) {
+        if voluntary {
+            self.nvcsw.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.nivcsw.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn usage(&self) -> Usage {
+        Usage {
+            user_ns: self.user_ns.load(Ordering::Relaxed),
+            system_ns: self.system_ns.load(Ordering::Relaxed),
+            minflt: self.minflt.load(Ordering::Relaxed),
+            nvcsw: self.nvcsw.load(Ordering::Relaxed),
+            nivcsw: self.nivcsw.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn children_usage(&self) -> Usage {
+        *self.children.lock_irq()
+    }
+
+    /// Usage of the process together with its reaped children, reported to the parent on reap
+    pub fn total_usage(&self) -> Usage {
+        let mut usage = self.usage();
+
+        usage.add(&self.children_usage());
+
+        usage
+    }
+
+    /// Folds the usage of a reaped child into the children totals
+    pub fn add_child(&self, child: &Usage) {
+        self.children.lock_irq().add(child);
+    }
+}
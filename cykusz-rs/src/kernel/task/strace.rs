@@ -0,0 +1,102 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use syscall_defs::strace::{path_arg, SyscallRecord, STRACE_PATH_MAX};
+use syscall_defs::{SyscallInto, SyscallResult};
+
+use crate::kernel::mm::uaccess::copy_from_user;
+use crate::kernel::mm::VirtAddr;
+use crate::kernel::sched::current_task_ref;
+use crate::kernel::signal::SignalResult;
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::utils::wait_queue::{WaitQueue, WaitQueueFlags};
+
+/// Number of records kept, oldest records are dropped when the reader falls behind
+const TRACE_CAPACITY: usize = 256;
+
+/// Syscall trace of the process, shared by all its threads
+#[derive(Default)]
+pub struct SyscallTrace {
+    enabled: AtomicBool,
+    closed: AtomicBool,
+    records: Spin<VecDeque<SyscallRecord>>,
+    wq: WaitQueue,
+}
+
+/// Syscall in progress, recorded on completion
+pub struct TraceEntry {
+    record: SyscallRecord,
+    start: u64,
+}
+
+impl SyscallTrace {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Captures syscall arguments on entry, path argument is copied before the syscall runs
+    pub fn entry(&self, num: usize, args: [u64; 6]) -> TraceEntry {
+        let mut record = SyscallRecord::empty();
+
+        record.tid = current_task_ref().tid() as u64;
+        record.num = num as u64;
+        record.args = args;
+
+        if let Some((ptr, len)) = path_arg(num) {
+            let len = core::cmp::min(args[len] as usize, STRACE_PATH_MAX);
+
+            if copy_from_user(&mut record.path[..len], VirtAddr(args[ptr] as usize)).is_ok() {
+                record.path_len = len as u64;
+            }
+        }
+
+        TraceEntry {
+            record,
+            start: crate::kernel::timer::current_ns(),
+        }
+    }
+
+    pub fn finish(&self, mut entry: TraceEntry, res: SyscallResult) {
+        entry.record.ret = res.syscall_into() as i64;
+        entry.record.duration_ns = crate::kernel::timer::current_ns() - entry.start;
+
+        {
+            let mut records = self.records.lock_irq();
+
+            if records.len() == TRACE_CAPACITY {
+                records.pop_front();
+            }
+
+            records.push_back(entry.record);
+        }
+
+        self.wq.notify_all();
+    }
+
+    /// Called on process exit, wakes up readers waiting for more records
+    pub fn close(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+        self.closed.store(true, Ordering::SeqCst);
+
+        self.wq.notify_all();
+    }
+
+    /// Blocks until records are available, returns empty vec once the process exited
+    pub fn read(&self, max: usize) -> SignalResult<Vec<SyscallRecord>> {
+        let mut records = self
+            .wq
+            .wait_lock_for(WaitQueueFlags::IRQ_DISABLE, &self.records, |r| {
+                !r.is_empty() || self.closed.load(Ordering::SeqCst)
+            })?
+            .unwrap();
+
+        let count = core::cmp::min(max, records.len());
+
+        Ok(records.drain(..count).collect())
+    }
+}
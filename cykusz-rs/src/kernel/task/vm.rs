@@ -5,9 +5,10 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use core::ops::Range;
 use syscall_defs::exec::ExeArgs;
-use syscall_defs::{MMapFlags, MMapProt, OpenFlags, SyscallError, SyscallResult};
+use syscall_defs::resource::{RLimitKind, RLIM_INFINITY};
+use syscall_defs::{MLockAllFlags, MMapFlags, MMapProt, OpenFlags, SyscallError, SyscallResult};
 
-use crate::arch::mm::{MMAP_USER_ADDR, PAGE_SIZE};
+use crate::arch::mm::{HUGE_PAGE_SIZE, MMAP_USER_ADDR, PAGE_SIZE};
 use crate::arch::raw::mm::UserAddr;
 use crate::drivers::elf::types::{BinType, ProgramFlags, ProgramType};
 use crate::drivers::elf::ElfHeader;
@@ -20,7 +21,9 @@ use crate::kernel::fs::pcache::{
 use crate::kernel::fs::{lookup_by_path, LookupMode};
 use crate::kernel::mm::virt::PageFlags;
 use crate::kernel::mm::{
-    allocate_order, map_flags, map_to_flags, unmap, update_flags, PhysAddr, VirtAddr, MAX_USER_ADDR,
+    allocate_order, collapse_hugepage, insert_flags, is_hugepage, is_zero_page, map_flags,
+    map_hugepage_flags, map_to_flags, remove_flags, split_hugepage, unmap, unmap_hugepage,
+    update_flags, zero_page, PhysAddr, VirtAddr, MAX_USER_ADDR,
 };
 use crate::kernel::sched::current_task_ref;
 use crate::kernel::sync::{LockApi, Mutex};
@@ -236,6 +239,8 @@ pub struct Mapping {
     flags: MMapFlags,
     start: VirtAddr,
     end: VirtAddr,
+    // Pages are kept resident (mlock)
+    locked: bool,
 }
 
 impl Mapping {
@@ -253,6 +258,7 @@ impl Mapping {
             flags,
             start: addr,
             end: addr + len.align_up(PAGE_SIZE),
+            locked: false,
         }
     }
 
@@ -262,6 +268,7 @@ impl Mapping {
         prot: MMapProt,
         flags: MMapFlags,
         file: Option<MMapedFile>,
+        locked: bool,
     ) -> Mapping {
         Mapping {
             mmaped_file: file,
@@ -269,6 +276,7 @@ impl Mapping {
             flags,
             start: addr,
             end: addr + len,
+            locked,
         }
     }
 
@@ -300,6 +308,7 @@ impl Mapping {
         if self.mmaped_file.is_none() != other.mmaped_file.is_none()
             || self.flags != other.flags
             || self.prot != other.prot
+            || self.locked != other.locked
         {
             return false;
         }
@@ -327,18 +336,90 @@ impl Mapping {
         res
     }
 
+    fn is_huge_eligible(&self) -> bool {
+        self.flags
+            .contains(MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS)
+    }
+
+    /// Returns start of the 2MB aligned region containing addr if it fits in this mapping
+    fn huge_range(&self, addr: VirtAddr) -> Option<VirtAddr> {
+        if !self.is_huge_eligible() {
+            return None;
+        }
+
+        let start = addr.align_down(HUGE_PAGE_SIZE);
+
+        if start >= self.start && start + HUGE_PAGE_SIZE <= self.end {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    fn try_collapse(&self, addr: VirtAddr) {
+        if let Some(start) = self.huge_range(addr) {
+            if collapse_hugepage(start, PageFlags::USER | self.prot.into()) {
+                dbgln!(vm, "collapsed hugepage {}", start);
+            }
+        }
+    }
+
     fn handle_pf_private_anon(&mut self, reason: PageFaultReason, addr: VirtAddr) -> bool {
         let addr_aligned = addr.align_down(PAGE_SIZE);
 
         if !reason.contains(PageFaultReason::PRESENT) {
+            if !reason.contains(PageFaultReason::WRITE) {
+                // Nothing was written yet, share the zero page until the first write
+                dbgln!(vm, "private read zero page");
+                let mut flags = PageFlags::USER | self.prot.into();
+                flags.remove(PageFlags::WRITABLE);
+
+                map_to_flags(addr_aligned, zero_page(), flags);
+
+                return true;
+            }
+
+            if let Some(start) = self.huge_range(addr) {
+                if map_hugepage_flags(start, PageFlags::USER | self.prot.into()) {
+                    dbgln!(vm, "private write hugepage {}", start);
+
+                    return true;
+                }
+            }
+
             // Page not present so just make it available
-            dbgln!(vm, "private read");
+            dbgln!(vm, "private write");
             map_flags(addr_aligned, PageFlags::USER | self.prot.into());
 
+            self.try_collapse(addr);
+
             true
         } else if reason.contains(PageFaultReason::WRITE) {
+            if is_hugepage(addr_aligned) {
+                if self.huge_range(addr).is_some() {
+                    // Huge pages are never shared, so just make it writable
+                    dbgln!(vm, "hugepage update flags");
+                    return update_flags(addr_aligned, PageFlags::USER | self.prot.into());
+                }
+
+                // Part of the huge page belongs to another mapping with its own protection
+                split_hugepage(addr_aligned.align_down(HUGE_PAGE_SIZE));
+            }
+
+            // No need to copy the zero page, fresh page is already cleared
+            let bytes = match addr_aligned.to_phys_pagewalk() {
+                Some(phys) if is_zero_page(phys) => 0,
+                _ => PAGE_SIZE,
+            };
+
             dbgln!(vm, "handle cow anon");
-            return self.handle_cow(addr_aligned, false, PAGE_SIZE);
+            let res = self.handle_cow(addr_aligned, false, bytes);
+
+            if res {
+                self.try_collapse(addr);
+            }
+
+            res
         } else {
             dbgln!(vm, "present read fail");
             false
@@ -433,7 +514,7 @@ impl Mapping {
                 // Otherwise, this page is not shared with anyone, so just make it writable
                 if phys_page.vm_use_count() > 1 || do_copy {
                     logln_disabled!("mmap cow: map_copy {}", bytes);
-                    Self::map_copy(addr_aligned, addr_aligned, bytes, self.prot);
+                    Self::map_copy(addr_aligned, phys.to_virt(), bytes, self.prot);
                 } else {
                     logln_disabled!("mmap cow: update flags");
                     if !update_flags(addr_aligned, PageFlags::USER | self.prot.into()) {
@@ -515,7 +596,14 @@ impl Mapping {
             None
         };
 
-        let new_split = Mapping::new_split(addr, (self.end - addr).0, self.prot, self.flags, new_f);
+        let new_split = Mapping::new_split(
+            addr,
+            (self.end - addr).0,
+            self.prot,
+            self.flags,
+            new_f,
+            self.locked,
+        );
         dbgln!(map_v, "self [{} {}]", self.start, addr);
         dbgln!(map_v, "new  [{} {}]", addr, addr + (self.end - addr));
 
@@ -549,12 +637,27 @@ impl Mapping {
         dbgln!(unmap, "unmapping region {} - {}", self.start, self.end);
 
         let unmap_range = |range: Range<VirtAddr>, f: &mut Option<MMapedFile>| {
-            for v in range.step_by(PAGE_SIZE) {
+            let mut v = range.start;
+
+            while v < range.end {
+                // Whole 2MB page is being unmapped, drop it at once
+                if f.is_none()
+                    && v.0.is_multiple_of(HUGE_PAGE_SIZE)
+                    && v + HUGE_PAGE_SIZE <= range.end
+                    && unmap_hugepage(v)
+                {
+                    v += HUGE_PAGE_SIZE;
+
+                    continue;
+                }
+
                 if let Some(f) = f {
                     f.unmap(v);
                 }
 
                 unmap(v);
+
+                v += PAGE_SIZE;
             }
         };
 
@@ -629,9 +732,104 @@ impl Mapping {
             return Ok(MProtectResult::Full);
         }
 
+        // Huge page crossing the new mapping boundary can't hold two protections
+        for boundary in [start, end] {
+            if boundary > self.start
+                && boundary < self.end
+                && !boundary.0.is_multiple_of(HUGE_PAGE_SIZE)
+                && is_hugepage(boundary)
+            {
+                split_hugepage(boundary.align_down(HUGE_PAGE_SIZE));
+            }
+        }
+
+        let (old_prot, map_start, map_end) = (self.prot, self.start, self.end);
+
+        let res = self.update_range(start, end, |m| m.prot = prot);
+
+        if !matches!(res, MProtectResult::None) {
+            Self::protect_present(start.max(map_start), end.min(map_end), old_prot, prot);
+        }
+
+        Ok(res)
+    }
+
+    /// Applies the protection change to the pages already mapped in the range. Write access is
+    /// granted lazily by the write fault, so cow pages stay read-only
+    fn protect_present(start: VirtAddr, end: VirtAddr, old: MMapProt, new: MMapProt) {
+        let mut remove = PageFlags::empty();
+        let mut insert = PageFlags::empty();
+
+        if old.contains(MMapProt::PROT_WRITE) && !new.contains(MMapProt::PROT_WRITE) {
+            remove.insert(PageFlags::WRITABLE);
+        }
+
+        match (
+            old.contains(MMapProt::PROT_EXEC),
+            new.contains(MMapProt::PROT_EXEC),
+        ) {
+            (true, false) => insert.insert(PageFlags::NO_EXECUTE),
+            (false, true) => remove.insert(PageFlags::NO_EXECUTE),
+            _ => {}
+        }
+
+        // PROT_NONE pages keep their content but are not accessible from the user space
+        match (old.is_empty(), new.is_empty()) {
+            (false, true) => remove.insert(PageFlags::USER),
+            (true, false) => insert.insert(PageFlags::USER),
+            _ => {}
+        }
+
+        if remove.is_empty() && insert.is_empty() {
+            return;
+        }
+
+        let mut addr = start;
+
+        while addr < end {
+            if !remove.is_empty() {
+                remove_flags(addr, remove);
+            }
+
+            if !insert.is_empty() {
+                insert_flags(addr, insert);
+            }
+
+            // Huge pages are split at the range boundaries, so they are fully covered
+            addr += if addr.0.is_multiple_of(HUGE_PAGE_SIZE) && is_hugepage(addr) {
+                HUGE_PAGE_SIZE
+            } else {
+                PAGE_SIZE
+            };
+        }
+    }
+
+    fn mlock(&mut self, start: VirtAddr, end: VirtAddr, locked: bool) -> MProtectResult {
+        assert!(start.0.is_multiple_of(PAGE_SIZE));
+        assert!(end.0.is_multiple_of(PAGE_SIZE));
+
+        //....>--<..############..>--<
+        if end <= self.start || start >= self.end {
+            return MProtectResult::None;
+        }
+
+        if self.locked == locked {
+            return MProtectResult::Full;
+        }
+
+        self.update_range(start, end, |m| m.locked = locked)
+    }
+
+    /// Applies update to the part of the mapping within start..end, splitting it if needed
+    fn update_range(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        update: impl Fn(&mut Mapping),
+    ) -> MProtectResult {
         //....>--<..############..>--<
         if end <= self.start || start >= self.end {
-            return Ok(MProtectResult::None);
+            return MProtectResult::None;
         }
 
         //..........###>----<###......
@@ -639,35 +837,35 @@ impl Mapping {
             let mut split1 = self.split_from(start);
             let split2 = split1.split_from(end);
 
-            split1.prot = prot;
+            update(&mut split1);
             //MID
 
-            return Ok(MProtectResult::Mid(split1, split2));
+            return MProtectResult::Mid(split1, split2);
         }
 
         //..........>----------<.....
         if start <= self.start && end >= self.end {
             //FULL
-            self.prot = prot;
+            update(self);
 
-            return Ok(MProtectResult::Full);
+            return MProtectResult::Full;
         }
 
         //..........>--------<###......
         if start <= self.start && end < self.end {
             //BEGIN
-            self.prot = prot;
             let split = self.split_from(end);
+            update(self);
 
-            return Ok(MProtectResult::Begin(split));
+            return MProtectResult::Begin(split);
         }
 
         //..........###>--------<......
         if start > self.start && end >= self.end {
             let mut split = self.split_from(start);
-            split.prot = prot;
+            update(&mut split);
 
-            return Ok(MProtectResult::End(split));
+            return MProtectResult::End(split);
             //END
         }
 
@@ -675,9 +873,77 @@ impl Mapping {
     }
 }
 
+/// Snapshot of a single mapping, used to inspect address space without holding the vm lock
+#[derive(Copy, Clone)]
+pub struct MappingInfo {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub prot: MMapProt,
+    pub flags: MMapFlags,
+    pub anonymous: bool,
+}
+
+/// Resident memory of the address space in bytes
+#[derive(Copy, Clone, Default)]
+pub struct Rss {
+    pub anon: usize,
+    pub file: usize,
+    // Untouched private anonymous memory backed by the shared zero page, not counted as resident
+    pub zero: usize,
+}
+
 #[derive(Clone)]
 struct VMData {
     maps: LinkedList<Mapping>,
+    layout: Layout,
+    // Address of the vdso elf image
+    vdso: Option<VirtAddr>,
+    // Lock all new mappings (mlockall MCL_FUTURE)
+    lock_future: bool,
+}
+
+const DYN_EXE_BASE: VirtAddr = VirtAddr(0x7500_0000_0000);
+const DYN_INTERP_BASE: VirtAddr = VirtAddr(0x7600_0000_0000);
+
+// Number of random bits in page granularity, 28 bits gives 1TB range
+const MMAP_RANDOM_BITS: usize = 28;
+const DYN_RANDOM_BITS: usize = 28;
+const STACK_RANDOM_BITS: usize = 22;
+
+/// Base addresses of the process address space regions, randomised on exec unless disabled
+/// with ADDR_NO_RANDOMIZE personality
+#[derive(Copy, Clone)]
+struct Layout {
+    mmap_base: VirtAddr,
+    exe_base: VirtAddr,
+    interp_base: VirtAddr,
+    stack_top: VirtAddr,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            mmap_base: MMAP_USER_ADDR,
+            exe_base: DYN_EXE_BASE,
+            interp_base: DYN_INTERP_BASE,
+            stack_top: MAX_USER_ADDR,
+        }
+    }
+}
+
+impl Layout {
+    fn random_pages(bits: usize) -> usize {
+        crate::kernel::random::next_below(1u64 << bits) as usize * PAGE_SIZE
+    }
+
+    fn new_random() -> Layout {
+        Layout {
+            mmap_base: MMAP_USER_ADDR + Self::random_pages(MMAP_RANDOM_BITS),
+            exe_base: DYN_EXE_BASE + Self::random_pages(DYN_RANDOM_BITS),
+            interp_base: DYN_INTERP_BASE + Self::random_pages(DYN_RANDOM_BITS),
+            stack_top: MAX_USER_ADDR - Self::random_pages(STACK_RANDOM_BITS),
+        }
+    }
 }
 
 pub struct TlsVmInfo {
@@ -773,6 +1039,9 @@ impl VMData {
     fn new() -> VMData {
         VMData {
             maps: LinkedList::new(),
+            layout: Layout::default(),
+            vdso: None,
+            lock_future: false,
         }
     }
 
@@ -857,6 +1126,16 @@ impl VMData {
         None
     }
 
+    fn find_any_above_aligned(
+        &mut self,
+        addr: VirtAddr,
+        len: usize,
+        align: usize,
+    ) -> Option<(VirtAddr, CursorMut<'_, Mapping>)> {
+        self.find_any_above(addr, len + align - PAGE_SIZE)
+            .map(|(start, cur)| (start.align_up(align), cur))
+    }
+
     fn mmap(
         &mut self,
         addr: Option<VirtAddr>,
@@ -933,7 +1212,20 @@ impl VMData {
                     self.find_any_above(addr, len)
                 }
             }
-            None => self.find_any_above(MMAP_USER_ADDR, len.align_up(PAGE_SIZE)),
+            None => {
+                // Align large anonymous mappings so that they can be backed by huge pages
+                if flags.contains(MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS)
+                    && len >= HUGE_PAGE_SIZE
+                {
+                    self.find_any_above_aligned(
+                        self.layout.mmap_base,
+                        len.align_up(PAGE_SIZE),
+                        HUGE_PAGE_SIZE,
+                    )
+                } else {
+                    self.find_any_above(self.layout.mmap_base, len.align_up(PAGE_SIZE))
+                }
+            }
         }
         .and_then(|(addr, cur)| {
             let mapping = Mapping::new(addr, len, prot, flags, file, offset);
@@ -985,9 +1277,148 @@ impl VMData {
         }
     }
 
+    /// Writes to user memory ignoring mapping protection, private mappings get their own copy
+    fn write_force(&mut self, addr: VirtAddr, buf: &[u8]) -> bool {
+        let mut done = 0;
+
+        while done < buf.len() {
+            let cur = addr + done;
+            let len = core::cmp::min(buf.len() - done, PAGE_SIZE - cur.0 % PAGE_SIZE);
+
+            let Some(map) = self.maps.iter_mut().find(|e| cur >= e.start && cur < e.end) else {
+                return false;
+            };
+
+            let is_private = map.flags.contains(MMapFlags::MAP_PRIVATE);
+            let is_anonymous = map.flags.contains(MMapFlags::MAP_ANONYOMUS);
+
+            // Read-only shared mapping would leak the change into the underlying file
+            if !is_private && !map.prot.contains(MMapProt::PROT_WRITE) {
+                return false;
+            }
+
+            let mut reason = PageFaultReason::WRITE | PageFaultReason::USER;
+
+            if cur.to_phys_pagewalk().is_some() {
+                reason.insert(PageFaultReason::PRESENT);
+            }
+
+            let handled = match (is_private, is_anonymous) {
+                (false, _) => map.handle_pf_shared_file(reason, cur),
+                (true, false) => map.handle_pf_private_file(reason, cur),
+                (true, true) => map.handle_pf_private_anon(reason, cur),
+            };
+
+            if !handled {
+                return false;
+            }
+
+            let Some(phys) = cur.to_phys_pagewalk() else {
+                return false;
+            };
+
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    buf[done..].as_ptr(),
+                    phys.to_virt().0 as *mut u8,
+                    len,
+                );
+            }
+
+            done += len;
+        }
+
+        true
+    }
+
+    fn mapped_size(&self, filter: impl Fn(&Mapping) -> bool) -> usize {
+        self.maps
+            .iter()
+            .filter(|m| filter(m))
+            .map(|m| (m.end - m.start).0)
+            .sum()
+    }
+
+    fn is_stack(&self, map: &Mapping) -> bool {
+        map.end == self.layout.stack_top
+    }
+
+    fn is_data(&self, map: &Mapping) -> bool {
+        map.flags.contains(MMapFlags::MAP_PRIVATE)
+            && map.prot.contains(MMapProt::PROT_WRITE)
+            && !self.is_stack(map)
+    }
+
+    /// Checks whether new mapping fits within RLIMIT_AS, RLIMIT_DATA and RLIMIT_STACK
+    fn check_limits(
+        &self,
+        addr: Option<VirtAddr>,
+        len: usize,
+        prot: MMapProt,
+        flags: MMapFlags,
+    ) -> bool {
+        let task = current_task_ref();
+
+        let len = len.align_up(PAGE_SIZE);
+
+        let fits = |kind: RLimitKind, used: usize| {
+            let limit = task.rlimit(kind).cur;
+
+            limit == RLIM_INFINITY || (used + len) as u64 <= limit
+        };
+
+        if !fits(RLimitKind::As, self.mapped_size(|_| true)) {
+            return false;
+        }
+
+        if self.lock_future && !fits(RLimitKind::Memlock, self.mapped_size(|m| m.locked)) {
+            return false;
+        }
+
+        if addr.is_some_and(|a| a + len == self.layout.stack_top) {
+            fits(RLimitKind::Stack, self.mapped_size(|m| self.is_stack(m)))
+        } else if flags.contains(MMapFlags::MAP_PRIVATE) && prot.contains(MMapProt::PROT_WRITE) {
+            fits(RLimitKind::Data, self.mapped_size(|m| self.is_data(m)))
+        } else {
+            true
+        }
+    }
+
+    fn check_access(&self, addr: VirtAddr, len: usize, write: bool) -> bool {
+        let end = addr + len;
+        let mut cur = addr;
+
+        if len == 0 {
+            return true;
+        }
+
+        for map in self.maps.iter() {
+            if map.end <= cur {
+                continue;
+            }
+
+            if map.start > cur || !map.prot.contains(MMapProt::PROT_READ) {
+                return false;
+            }
+
+            if write && !map.prot.contains(MMapProt::PROT_WRITE) {
+                return false;
+            }
+
+            cur = map.end;
+
+            if cur >= end {
+                return true;
+            }
+        }
+
+        false
+    }
+
     fn load_bin(
         &mut self,
         exe: DirEntryItem,
+        is_interp: bool,
     ) -> Option<(
         VirtAddr,
         VirtAddr,
@@ -1028,7 +1459,7 @@ impl VMData {
 
                 let interp = lookup_by_path(&Path::new(path[0]), LookupMode::None).ok()?;
 
-                let (base_addr, entry, elf, tls, _) = self.load_bin(interp.clone())?;
+                let (base_addr, entry, elf, tls, _) = self.load_bin(interp.clone(), false)?;
 
                 let exe_args = if path.len() > 1 {
                     let mut ea = ExeArgs::new();
@@ -1045,11 +1476,13 @@ impl VMData {
 
             let hdr = hdr.unwrap();
 
-            let load_offset = VirtAddr(if hdr.e_type == BinType::Dyn {
-                0x7500_0000_0000usize
+            let load_offset = if hdr.e_type != BinType::Dyn {
+                VirtAddr(0)
+            } else if is_interp {
+                self.layout.interp_base
             } else {
-                0usize
-            });
+                self.layout.exe_base
+            };
 
             let mut entry_addr = VirtAddr(hdr.e_entry as usize) + load_offset;
 
@@ -1062,7 +1495,7 @@ impl VMData {
             {
                 if p.p_type == ProgramType::Interp {
                     match lookup_by_path(&Path::new("/usr/lib/ld.so"), LookupMode::None) { Ok(interp) => {
-                        if let Some((_base_addr, entry, _elf, _tls, _)) = self.load_bin(interp) {
+                        if let Some((_base_addr, entry, _elf, _tls, _)) = self.load_bin(interp, true) {
                             entry_addr = entry;
                         }
                     } _ => {
@@ -1146,6 +1579,31 @@ impl VMData {
         None
     }
 
+    // Walks page tables of the active address space
+    fn rss(&self) -> Rss {
+        let mut rss = Rss::default();
+
+        for m in self.maps.iter() {
+            for addr in (m.start.0..m.end.0).step_by(PAGE_SIZE) {
+                let Some(phys) = VirtAddr(addr).to_phys_pagewalk() else {
+                    continue;
+                };
+
+                if is_zero_page(phys) {
+                    rss.zero += PAGE_SIZE;
+                } else if m.mmaped_file.is_some()
+                    && phys.to_phys_page().is_none_or(|p| p.page_item().is_some())
+                {
+                    rss.file += PAGE_SIZE;
+                } else {
+                    rss.anon += PAGE_SIZE;
+                }
+            }
+        }
+
+        rss
+    }
+
     fn print_vm(&self) {
         for e in self.maps.iter() {
             if let Some(f) = &e.mmaped_file {
@@ -1189,6 +1647,50 @@ impl VMData {
         let other = vm.data.lock();
 
         self.maps = other.maps.clone();
+        self.layout = other.layout;
+        self.vdso = other.vdso;
+
+        // Memory locks are not inherited
+        for m in self.maps.iter_mut() {
+            m.locked = false;
+        }
+    }
+
+    // Page table stays shared with the parent until exec, so leave the page cache reverse
+    // mappings owned by the parent
+    fn fork_shared(&mut self, vm: &VM) {
+        self.fork(vm);
+
+        for m in self.maps.iter_mut() {
+            if let Some(f) = m.mmaped_file.as_mut() {
+                f.active_mappings.clear();
+            }
+        }
+    }
+
+    /// Maps vdso data pages followed by the read only, executable vdso image
+    fn map_vdso(&mut self) -> Option<VirtAddr> {
+        let vdso = crate::kernel::vdso::mapping()?;
+
+        let base = self.mmap(
+            None,
+            vdso.data_len + vdso.image_len,
+            MMapProt::PROT_READ,
+            MMapFlags::MAP_SHARED,
+            Some(vdso.file),
+            0,
+        )?;
+
+        let image = base + vdso.data_len;
+
+        self.mprotect(
+            image,
+            vdso.image_len,
+            MMapProt::PROT_READ | MMapProt::PROT_EXEC,
+        )
+        .ok()?;
+
+        Some(image)
     }
 
     fn unmap(&mut self, addr: VirtAddr, len: usize) -> bool {
@@ -1238,6 +1740,14 @@ impl VMData {
     }
 
     fn mprotect(&mut self, addr: VirtAddr, len: usize, prot: MMapProt) -> SyscallResult {
+        self.update_range(addr, len, |m, start, end| m.mprotect(start, end, prot))
+    }
+
+    /// Applies update to all the mappings within the range, splitting and merging them as needed
+    fn update_range<F>(&mut self, addr: VirtAddr, len: usize, mut update: F) -> SyscallResult
+    where
+        F: FnMut(&mut Mapping, VirtAddr, VirtAddr) -> Result<MProtectResult, SyscallError>,
+    {
         if !addr.0.is_multiple_of(PAGE_SIZE) {
             return Err(SyscallError::EINVAL);
         }
@@ -1251,7 +1761,7 @@ impl VMData {
             if c.end <= start {
                 cursor.move_next();
             } else {
-                match c.mprotect(start, end, prot)? {
+                match update(c, start, end)? {
                     MProtectResult::None => {
                         return Ok(0);
                     }
@@ -1280,6 +1790,124 @@ impl VMData {
 
         Ok(0)
     }
+
+    /// Checks whether the whole range is covered by mappings
+    fn is_mapped(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let mut cur = start;
+
+        for map in self.maps.iter() {
+            if cur >= end {
+                break;
+            }
+
+            if map.end <= cur {
+                continue;
+            }
+
+            if map.start > cur {
+                return false;
+            }
+
+            cur = map.end;
+        }
+
+        cur >= end
+    }
+
+    fn memlock_fits(&self, len: usize) -> bool {
+        let limit = current_task_ref().rlimit(RLimitKind::Memlock).cur;
+
+        limit == RLIM_INFINITY || (self.mapped_size(|m| m.locked) + len) as u64 <= limit
+    }
+
+    /// Faults in all the pages in the range, so they are resident before being accessed
+    fn populate(&mut self, start: VirtAddr, end: VirtAddr) {
+        let ranges = self
+            .maps
+            .iter()
+            .filter(|m| m.end > start && m.start < end && !m.prot.is_empty())
+            .map(|m| {
+                let mut reason = PageFaultReason::USER;
+
+                // Break copy on write of private writable pages upfront
+                if m.flags.contains(MMapFlags::MAP_PRIVATE) && m.prot.contains(MMapProt::PROT_WRITE)
+                {
+                    reason.insert(PageFaultReason::WRITE);
+                }
+
+                (
+                    core::cmp::max(m.start, start),
+                    core::cmp::min(m.end, end),
+                    reason,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for (start, end, reason) in ranges {
+            for addr in (start..end).step_by(PAGE_SIZE) {
+                if addr.to_phys_pagewalk().is_none() {
+                    self.handle_pagefault(reason, addr);
+                }
+            }
+        }
+    }
+
+    fn mlock(&mut self, addr: VirtAddr, len: usize, locked: bool) -> SyscallResult {
+        let start = addr.align_down(PAGE_SIZE);
+        let end = (addr + len).align_up(PAGE_SIZE);
+
+        if !self.is_mapped(start, end) {
+            return Err(SyscallError::ENOMEM);
+        }
+
+        if locked && !self.memlock_fits((end - start).0) {
+            return Err(SyscallError::ENOMEM);
+        }
+
+        self.update_range(start, (end - start).0, |m, start, end| {
+            Ok(m.mlock(start, end, locked))
+        })?;
+
+        if locked {
+            self.populate(start, end);
+        }
+
+        Ok(0)
+    }
+
+    fn mlockall(&mut self, flags: MLockAllFlags) -> SyscallResult {
+        if flags.contains(MLockAllFlags::MCL_CURRENT) {
+            if !self.memlock_fits(self.mapped_size(|m| !m.locked)) {
+                return Err(SyscallError::ENOMEM);
+            }
+
+            for m in self.maps.iter_mut() {
+                m.locked = true;
+            }
+
+            let ranges = self
+                .maps
+                .iter()
+                .map(|m| (m.start, m.end))
+                .collect::<Vec<_>>();
+
+            for (start, end) in ranges {
+                self.populate(start, end);
+            }
+        }
+
+        self.lock_future = flags.contains(MLockAllFlags::MCL_FUTURE);
+
+        Ok(0)
+    }
+
+    fn munlockall(&mut self) {
+        for m in self.maps.iter_mut() {
+            m.locked = false;
+        }
+
+        self.lock_future = false;
+    }
 }
 
 impl Drop for VMData {
@@ -1326,6 +1954,10 @@ impl VM {
         self.data.lock_irq().fork(vm);
     }
 
+    pub fn fork_shared(&self, vm: &VM) {
+        self.data.lock_irq().fork_shared(vm);
+    }
+
     pub fn mmap_vm(
         &self,
         addr: Option<VirtAddr>,
@@ -1334,14 +1966,28 @@ impl VM {
         flags: MMapFlags,
         file: Option<Arc<FileHandle>>,
         offset: usize,
-    ) -> Option<VirtAddr> {
+    ) -> Result<VirtAddr, SyscallError> {
         let mut data = self.data.lock_irq();
 
-        let res = data.mmap(addr, len, prot, flags, file, offset);
+        if !data.check_limits(addr, len, prot, flags) {
+            return Err(SyscallError::ENOMEM);
+        }
+
+        let res = data
+            .mmap(addr, len, prot, flags, file, offset)
+            .ok_or(SyscallError::EFAULT)?;
+
+        if data.lock_future {
+            data.update_range(res, len, |m, start, end| Ok(m.mlock(start, end, true)))?;
+        }
+
+        if data.lock_future || flags.contains(MMapFlags::MAP_POPULATE) {
+            data.populate(res, res + len.align_up(PAGE_SIZE));
+        }
 
         data.log_vm();
 
-        res
+        Ok(res)
     }
 
     pub fn munmap(&self, addr: VirtAddr, len: usize) -> bool {
@@ -1356,6 +2002,22 @@ impl VM {
         data.mprotect(addr, len, prot)
     }
 
+    pub fn mlock(&self, addr: VirtAddr, len: usize) -> SyscallResult {
+        self.data.lock_irq().mlock(addr, len, true)
+    }
+
+    pub fn munlock(&self, addr: VirtAddr, len: usize) -> SyscallResult {
+        self.data.lock_irq().mlock(addr, len, false)
+    }
+
+    pub fn mlockall(&self, flags: MLockAllFlags) -> SyscallResult {
+        self.data.lock_irq().mlockall(flags)
+    }
+
+    pub fn munlockall(&self) {
+        self.data.lock_irq().munlockall()
+    }
+
     pub fn handle_pagefault(&self, reason: PageFaultReason, addr: VirtAddr) -> bool {
         if current_task_ref().locks() > 0 {
             logln!("handle_pagefault: locks > 0");
@@ -1370,9 +2032,20 @@ impl VM {
         ret
     }
 
+    /// Used by the tracer to modify read-only memory, eg. to insert breakpoints
+    pub fn write_force(&self, addr: VirtAddr, buf: &[u8]) -> bool {
+        self.data.lock_irq().write_force(addr, buf)
+    }
+
+    /// Check whether whole user range is mapped with required permissions
+    pub fn check_access(&self, addr: VirtAddr, len: usize, write: bool) -> bool {
+        self.data.lock_irq().check_access(addr, len, write)
+    }
+
     pub fn load_bin(
         &self,
         exe: DirEntryItem,
+        randomize: bool,
     ) -> Option<(
         VirtAddr,
         VirtAddr,
@@ -1380,11 +2053,53 @@ impl VM {
         Option<TlsVmInfo>,
         Option<(DirEntryItem, Option<ExeArgs>)>,
     )> {
-        self.data.lock_irq().load_bin(exe)
+        let mut data = self.data.lock_irq();
+
+        data.layout = if randomize {
+            Layout::new_random()
+        } else {
+            Layout::default()
+        };
+
+        let res = data.load_bin(exe, false)?;
+
+        data.vdso = data.map_vdso();
+
+        Some(res)
+    }
+
+    pub fn stack_top(&self) -> VirtAddr {
+        self.data.lock_irq().layout.stack_top
+    }
+
+    pub fn mappings(&self) -> Vec<MappingInfo> {
+        self.data
+            .lock_irq()
+            .maps
+            .iter()
+            .map(|m| MappingInfo {
+                start: m.start,
+                end: m.end,
+                prot: m.prot,
+                flags: m.flags,
+                anonymous: m.mmaped_file.is_none(),
+            })
+            .collect()
+    }
+
+    pub fn rss(&self) -> Rss {
+        self.data.lock_irq().rss()
+    }
+
+    pub fn vdso(&self) -> Option<VirtAddr> {
+        self.data.lock_irq().vdso
     }
 
     pub fn clear(&self) {
-        self.data.lock_irq().maps.clear();
+        let mut data = self.data.lock_irq();
+
+        data.maps.clear();
+        data.vdso = None;
     }
 
     pub fn print_vm(&self) {
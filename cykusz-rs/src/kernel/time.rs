@@ -1 +1,110 @@
-pub use crate::arch::time::unix_timestamp;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+
+use syscall_defs::time::ClockId;
+
+use crate::kernel::sched::current_task_ref;
+use crate::kernel::signal::SignalResult;
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::task::ArcTask;
+use crate::kernel::timer::current_ns;
+
+const NS_PER_SEC: i64 = 1_000_000_000;
+
+/// Difference between the wall clock and the monotonic clock in nanoseconds
+static WALL_OFFSET: AtomicI64 = AtomicI64::new(0);
+static WALL_SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// Tasks sleeping until an absolute wall clock time, woken up when the clock is set
+static ABS_REALTIME_SLEEPERS: Spin<Vec<ArcTask>> = Spin::new(Vec::new());
+
+fn set_wall_offset(offset: i64) {
+    let prev = wall_offset();
+
+    WALL_OFFSET.store(offset, Ordering::SeqCst);
+    WALL_SYNCED.store(true, Ordering::SeqCst);
+
+    crate::kernel::vdso::set_wall_offset(offset);
+
+    crate::kernel::task::itimer::clock_was_set(offset - prev);
+
+    let sleepers = ABS_REALTIME_SLEEPERS.lock_irq().clone();
+
+    for task in sleepers {
+        task.wake_up();
+    }
+}
+
+/// Called by the rtc driver on the second boundary, wall clock is synced with it once on boot
+pub fn rtc_update(unix_ts: i64) {
+    if !WALL_SYNCED.load(Ordering::SeqCst) {
+        set_wall_offset(unix_ts * NS_PER_SEC - current_ns() as i64);
+    }
+}
+
+pub fn wall_offset() -> i64 {
+    if WALL_SYNCED.load(Ordering::SeqCst) {
+        WALL_OFFSET.load(Ordering::SeqCst)
+    } else {
+        crate::arch::time::unix_timestamp() * NS_PER_SEC - current_ns() as i64
+    }
+}
+
+/// Wall clock time in nanoseconds
+pub fn realtime_ns() -> u64 {
+    (current_ns() as i64 + wall_offset()) as u64
+}
+
+pub fn unix_timestamp() -> i64 {
+    realtime_ns() as i64 / NS_PER_SEC
+}
+
+/// Sets the wall clock, the time is also stored in the rtc
+pub fn set_realtime_ns(ns: u64) {
+    set_wall_offset(ns as i64 - current_ns() as i64);
+
+    crate::arch::time::set_unix_timestamp(ns as i64 / NS_PER_SEC);
+}
+
+/// Time of the clock in nanoseconds, cpu time clocks are measured for the current task
+pub fn clock_ns(clock: ClockId) -> u64 {
+    match clock {
+        ClockId::Realtime | ClockId::RealtimeCoarse => realtime_ns(),
+        ClockId::Monotonic
+        | ClockId::MonotonicRaw
+        | ClockId::MonotonicCoarse
+        | ClockId::Boottime => current_ns(),
+        ClockId::ProcessCputime => current_task_ref().process_cpu_ns(),
+        ClockId::ThreadCputime => current_task_ref().thread_cpu_ns(),
+    }
+}
+
+/// Sleeps until the clock reaches `ns`, the deadline is checked again after every wakeup
+/// as the wall clock can be set in the meantime
+pub fn sleep_abs(clock: ClockId, ns: u64) -> SignalResult<()> {
+    let task = current_task_ref();
+
+    if clock.is_realtime() {
+        ABS_REALTIME_SLEEPERS.lock_irq().push(task.me());
+    }
+
+    let res = loop {
+        let left = ns.saturating_sub(clock_ns(clock));
+
+        if left == 0 {
+            break Ok(());
+        }
+
+        if let Err(e) = task.sleep(left as usize) {
+            break Err(e);
+        }
+    };
+
+    if clock.is_realtime() {
+        ABS_REALTIME_SLEEPERS
+            .lock_irq()
+            .retain(|t| t.tid() != task.tid());
+    }
+
+    res
+}
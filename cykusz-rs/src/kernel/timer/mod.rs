@@ -0,0 +1,319 @@
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+
+use intrusive_collections::LinkedListLink;
+
+use crate::kernel::sched::{current_task, SleepFlags};
+use crate::kernel::sync::{LockApi, Spin};
+use crate::kernel::utils::percpu::PerCpu;
+use crate::kernel::utils::wait_queue::{WaitQueue, WaitQueueGuard};
+
+use self::wheel::{to_jiffies, Wheel};
+
+mod wheel;
+
+/// Longest time an idle cpu goes without the timer interrupt
+const MAX_IDLE_NS: u64 = 1_000_000_000;
+/// Deadlines closer than that are rounded up to avoid interrupt storms
+const MIN_EVENT_NS: u64 = 10_000;
+/// Period of the scheduler tick
+const TICK_NS: u64 = 1_000_000;
+
+const NO_CPU: usize = usize::MAX;
+
+pub trait TimerObject: Send + Sync {
+    fn call(&self);
+}
+
+pub struct Timer {
+    timeout: AtomicU64,
+    obj: Arc<dyn TimerObject>,
+    self_ref: Weak<Timer>,
+    link: LinkedListLink,
+    /// Serializes start and disable of the timer
+    lock: Spin<()>,
+    /// Cpu whose wheel holds the timer, changed under that wheel lock
+    cpu: AtomicUsize,
+    slot: AtomicUsize,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        logln_disabled!("[ TCP ] Timer dropped");
+    }
+}
+
+unsafe impl Sync for Timer {}
+
+unsafe impl Send for Timer {}
+
+impl Timer {
+    fn new(obj: Arc<dyn TimerObject>) -> Arc<Timer> {
+        Arc::new_cyclic(|me| Timer {
+            timeout: AtomicU64::new(0),
+            obj,
+            self_ref: me.clone(),
+            link: LinkedListLink::new(),
+            lock: Spin::new(()),
+            cpu: AtomicUsize::new(NO_CPU),
+            slot: AtomicUsize::new(0),
+        })
+    }
+
+    fn call(&self) {
+        self.obj.call();
+    }
+
+    fn expires(&self) -> u64 {
+        to_jiffies(self.timeout())
+    }
+
+    fn slot(&self) -> usize {
+        self.slot.load(Ordering::SeqCst)
+    }
+
+    fn set_location(&self, cpu: usize, slot: usize) {
+        self.slot.store(slot, Ordering::SeqCst);
+        self.cpu.store(cpu, Ordering::SeqCst);
+    }
+
+    fn clear_location(&self) {
+        self.cpu.store(NO_CPU, Ordering::SeqCst);
+    }
+
+    fn cancel(&self) {
+        loop {
+            let cpu = self.cpu.load(Ordering::SeqCst);
+
+            if cpu == NO_CPU {
+                return;
+            }
+
+            let mut wheel = WHEELS.cpu(cpu).lock_irq();
+
+            // Timer may have expired meanwhile
+            if self.cpu.load(Ordering::SeqCst) == cpu {
+                wheel.remove(self);
+                return;
+            }
+        }
+    }
+
+    pub fn disable(&self) {
+        let _lock = self.lock.lock_irq();
+
+        self.cancel();
+    }
+
+    fn timeout(&self) -> u64 {
+        self.timeout.load(Ordering::SeqCst)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.cpu.load(Ordering::SeqCst) != NO_CPU
+    }
+
+    pub fn start_with_timeout(&self, timeout: u64) {
+        self.start_at(current_ns() + timeout * 1_000_000);
+    }
+
+    /// Starts the timer expiring at the absolute time in nanoseconds, the timer is queued on the
+    /// wheel of the current cpu
+    pub fn start_at(&self, deadline: u64) {
+        let _lock = self.lock.lock_irq();
+
+        self.cancel();
+
+        if let Some(timer) = self.self_ref.upgrade() {
+            timer.timeout.store(deadline, Ordering::SeqCst);
+
+            let cpu = unsafe { crate::CPU_ID } as usize;
+
+            WHEELS.cpu(cpu).lock_irq().add(timer, cpu);
+        }
+    }
+}
+
+intrusive_adapter!(TimerAdapter = Arc<Timer>: Timer {link => LinkedListLink});
+
+struct Wheels {
+    wheels: PerCpu<Spin<Wheel>>,
+}
+
+unsafe impl Sync for Wheels {}
+unsafe impl Send for Wheels {}
+
+impl Wheels {
+    fn cpu(&self, cpu: usize) -> &Spin<Wheel> {
+        self.wheels.cpu(cpu as isize)
+    }
+}
+
+lazy_static! {
+    static ref WHEELS: Wheels = Wheels {
+        wheels: PerCpu::new_fn(|| Spin::new(Wheel::new())),
+    };
+}
+
+static TIMERS_WQ: WaitQueue = WaitQueue::new();
+
+/// Runs callbacks of the expired timers of all cpus
+fn run_expired() {
+    for cpu in 0..crate::kernel::smp::cpu_count() {
+        loop {
+            let timer = WHEELS.cpu(cpu).lock_irq().pop_expired();
+
+            match timer {
+                Some(timer) => timer.call(),
+                None => break,
+            }
+        }
+    }
+}
+
+fn timer_fun() {
+    let task = current_task();
+    loop {
+        // Timers expiring while we run the callbacks wake us up
+        let _guard = WaitQueueGuard::new(&TIMERS_WQ, &task);
+
+        run_expired();
+
+        crate::kernel::sched::sleep(None, SleepFlags::NON_INTERRUPTIBLE)
+            .expect("Unexpected signal in timer thread");
+    }
+}
+
+pub struct TimerCallback<T: Send + Sync> {
+    obj: Weak<T>,
+    fun: fn(&T),
+}
+
+impl<T: Send + Sync> TimerObject for TimerCallback<T> {
+    fn call(&self) {
+        if let Some(s) = self.obj.upgrade() {
+            (self.fun)(&s)
+        }
+    }
+}
+
+impl<T: Send + Sync> TimerCallback<T> {
+    pub fn new(obj: Weak<T>, fun: fn(&T)) -> Arc<TimerCallback<T>> {
+        Arc::new(TimerCallback { obj, fun })
+    }
+}
+
+pub fn create_timer(obj: Arc<dyn TimerObject>) -> Arc<Timer> {
+    let timer = Timer::new(obj);
+
+    return timer;
+}
+
+pub fn setup() {
+    crate::kernel::sched::create_task(timer_fun);
+
+    dbgln!(timer, "timer task created");
+
+    crate::arch::timer::setup(timer_handler);
+
+    dbgln!(timer, "timer task setup");
+}
+
+pub fn setup_ap() {
+    crate::arch::timer::setup(timer_handler);
+}
+
+pub fn start() {
+    crate::arch::timer::start();
+}
+
+#[thread_local]
+static TICK_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Earliest sleep deadline of the tasks on this cpu, 0 if none
+#[thread_local]
+static SLEEP_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+/// Scheduler reports the earliest sleep deadline, the timer is programmed to fire on time
+pub fn set_sleep_deadline(deadline: Option<u64>) {
+    SLEEP_DEADLINE.store(deadline.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Time left to the sleep deadline, deadlines already passed were handled by the tick
+fn sleep_deadline_in(now: u64) -> Option<u64> {
+    match SLEEP_DEADLINE.load(Ordering::Relaxed) {
+        d if d > now => Some(d - now),
+        _ => None,
+    }
+}
+
+/// Restarts the periodic tick, short sleeps get the interrupt before the tick
+pub fn reset_counter() {
+    // Timekeeping data is not updated while the tick is stopped
+    if TICK_STOPPED.swap(false, Ordering::Relaxed) {
+        crate::kernel::vdso::update();
+    }
+
+    match sleep_deadline_in(current_ns()) {
+        Some(ns) if ns < TICK_NS => crate::arch::timer::set_next_event(ns.max(MIN_EVENT_NS)),
+        _ => crate::arch::timer::reset_counter(),
+    }
+}
+
+/// Stops the periodic tick on idle cpu, the timer fires at the next sleep deadline or timer
+/// expiry instead
+pub fn stop_tick() {
+    TICK_STOPPED.store(true, Ordering::Relaxed);
+
+    let now = current_ns();
+
+    let wheel = WHEELS
+        .cpu(unsafe { crate::CPU_ID } as usize)
+        .lock_irq()
+        .next_event()
+        .map(|t| t.saturating_sub(now));
+
+    let ns = match (sleep_deadline_in(now), wheel) {
+        (Some(s), Some(w)) => s.min(w),
+        (s, w) => s.or(w).unwrap_or(MAX_IDLE_NS),
+    };
+
+    crate::arch::timer::set_next_event(ns.clamp(MIN_EVENT_NS, MAX_IDLE_NS));
+}
+
+#[thread_local]
+static LAST_TICK: AtomicU64 = AtomicU64::new(0);
+
+fn timer_handler() {
+    let now = current_ns();
+
+    // Time since the last tick is charged to the slice of the running task
+    let last = LAST_TICK.swap(now, Ordering::Relaxed);
+
+    let elapsed = if last > 0 { now - last } else { 0 };
+
+    crate::kernel::sched::current_task_ref().check_cpu_limit();
+
+    crate::kernel::vdso::update();
+
+    let cpu = unsafe { crate::CPU_ID } as usize;
+
+    if WHEELS.cpu(cpu).lock_irq().run(now, cpu) {
+        TIMERS_WQ.notify_one();
+    }
+
+    crate::kernel::sched::tick(elapsed);
+}
+
+pub fn early_sleep(ms: u64) {
+    crate::arch::timer::early_sleep(ms);
+}
+
+pub fn busy_sleep(ns: u64) {
+    crate::arch::timer::busy_sleep(ns)
+}
+
+pub fn current_ns() -> u64 {
+    crate::arch::timer::current_ns()
+}
@@ -0,0 +1,173 @@
+use alloc::sync::Arc;
+
+use intrusive_collections::LinkedList;
+
+use super::{current_ns, Timer, TimerAdapter};
+
+/// Resolution of the wheel
+pub const WHEEL_TICK_NS: u64 = 1_000_000;
+
+const LVL_BITS: u64 = 6;
+const LVL_SIZE: usize = 1 << LVL_BITS;
+const LVL_MASK: u64 = LVL_SIZE as u64 - 1;
+const LEVELS: usize = 4;
+
+/// Timers further away are parked in the last level and requeued when it cascades
+const MAX_DELTA: u64 = (1 << (LVL_BITS * LEVELS as u64)) - 1;
+
+/// Slot of the timers waiting for their callback to run
+pub const EXPIRED_SLOT: usize = LEVELS * LVL_SIZE;
+
+/// Rounded up, so timers never fire early
+pub fn to_jiffies(ns: u64) -> u64 {
+    ns.div_ceil(WHEEL_TICK_NS)
+}
+
+/// Hierarchical timer wheel, each level has 64 slots, 64 times coarser than the level below.
+/// Timers are moved to a lower level when the clock reaches their slot (cascade).
+pub struct Wheel {
+    slots: [LinkedList<TimerAdapter>; LEVELS * LVL_SIZE],
+    expired: LinkedList<TimerAdapter>,
+    /// Next jiffy to be processed
+    clk: u64,
+    /// Number of timers in the slots
+    pending: usize,
+}
+
+impl Wheel {
+    pub fn new() -> Wheel {
+        Wheel {
+            slots: core::array::from_fn(|_| LinkedList::new(TimerAdapter::new())),
+            expired: LinkedList::new(TimerAdapter::new()),
+            clk: current_ns() / WHEEL_TICK_NS,
+            pending: 0,
+        }
+    }
+
+    fn slot_for(&self, expires: u64) -> usize {
+        let expires = expires.clamp(self.clk, self.clk + MAX_DELTA);
+        let delta = expires - self.clk;
+
+        let mut level = 0;
+
+        while level < LEVELS - 1 && delta >> (LVL_BITS * (level as u64 + 1)) != 0 {
+            level += 1;
+        }
+
+        level * LVL_SIZE + ((expires >> (LVL_BITS * level as u64)) & LVL_MASK) as usize
+    }
+
+    pub fn add(&mut self, timer: Arc<Timer>, cpu: usize) {
+        let slot = self.slot_for(timer.expires());
+
+        timer.set_location(cpu, slot);
+
+        self.slots[slot].push_back(timer);
+        self.pending += 1;
+    }
+
+    pub fn remove(&mut self, timer: &Timer) {
+        let list = match timer.slot() {
+            EXPIRED_SLOT => &mut self.expired,
+            slot => {
+                self.pending -= 1;
+                &mut self.slots[slot]
+            }
+        };
+
+        unsafe { list.cursor_mut_from_ptr(timer as *const Timer) }.remove();
+
+        timer.clear_location();
+    }
+
+    fn cascade(&mut self, cpu: usize) {
+        for level in 1..LEVELS {
+            let idx = ((self.clk >> (LVL_BITS * level as u64)) & LVL_MASK) as usize;
+
+            let mut list = self.slots[level * LVL_SIZE + idx].take();
+
+            while let Some(timer) = list.pop_front() {
+                self.pending -= 1;
+                self.add(timer, cpu);
+            }
+
+            if idx != 0 {
+                break;
+            }
+        }
+    }
+
+    /// Moves timers due up to now to the expired list, returns true if there is any
+    pub fn run(&mut self, now: u64, cpu: usize) -> bool {
+        let now = now / WHEEL_TICK_NS;
+
+        while self.clk <= now {
+            if self.pending == 0 {
+                self.clk = now + 1;
+                break;
+            }
+
+            let idx = (self.clk & LVL_MASK) as usize;
+
+            if idx == 0 {
+                self.cascade(cpu);
+            }
+
+            let mut list = self.slots[idx].take();
+
+            while let Some(timer) = list.pop_front() {
+                self.pending -= 1;
+
+                if timer.expires() > self.clk {
+                    self.add(timer, cpu);
+                } else {
+                    timer.set_location(cpu, EXPIRED_SLOT);
+                    self.expired.push_back(timer);
+                }
+            }
+
+            self.clk += 1;
+        }
+
+        !self.expired.is_empty()
+    }
+
+    pub fn pop_expired(&mut self) -> Option<Arc<Timer>> {
+        let timer = self.expired.pop_front()?;
+
+        timer.clear_location();
+
+        Some(timer)
+    }
+
+    /// Time the wheel needs to run next, either to expire or to cascade timers
+    pub fn next_event(&self) -> Option<u64> {
+        if self.pending == 0 {
+            return None;
+        }
+
+        let mut next: Option<u64> = None;
+
+        for level in 0..LEVELS {
+            let shift = LVL_BITS * level as u64;
+            let base = self.clk >> shift;
+
+            // Slot of the current clock on upper levels was already cascaded
+            let range = if level == 0 {
+                0..LVL_SIZE as u64
+            } else {
+                1..LVL_SIZE as u64 + 1
+            };
+
+            if let Some(i) = range.into_iter().find(|i| {
+                !self.slots[level * LVL_SIZE + ((base + i) & LVL_MASK) as usize].is_empty()
+            }) {
+                let at = (base + i) << shift;
+
+                next = Some(next.map_or(at, |n| n.min(at)));
+            }
+        }
+
+        next.map(|j| j * WHEEL_TICK_NS)
+    }
+}
@@ -1,9 +1,10 @@
 use alloc::vec::Vec;
 use syscall_defs::net::MsgFlags;
+use syscall_defs::signal::SIGKILL;
 use syscall_defs::OpenFlags;
 
 use crate::kernel::sched::{current_task, SleepFlags};
-use crate::kernel::signal::{SignalError, SignalResult};
+use crate::kernel::signal::{SignalError, SignalResult, KSIGKILLTHR};
 use crate::kernel::sync::{IrqGuard, LockApi, LockGuard, Spin};
 use crate::kernel::task::ArcTask;
 
@@ -22,13 +23,15 @@ bitflags! {
         const IRQ_DISABLE = (1u64 << 0);
         const NON_INTERRUPTIBLE = (1u64 << 1);
         const NO_HANG = (1u64 << 2);
+        /// Non interruptible wait ended only by SIGKILL
+        const KILLABLE = (1u64 << 3);
     }
 }
 
 impl From<WaitQueueFlags> for SleepFlags {
     fn from(value: WaitQueueFlags) -> Self {
         let mut flags = SleepFlags::empty();
-        if value.contains(WaitQueueFlags::NON_INTERRUPTIBLE) {
+        if value.intersects(WaitQueueFlags::NON_INTERRUPTIBLE | WaitQueueFlags::KILLABLE) {
             flags.insert(SleepFlags::NON_INTERRUPTIBLE);
         }
 
@@ -225,12 +228,24 @@ impl WaitQueue {
     }
 
     fn do_await_io(flags: WaitQueueFlags, task: &ArcTask) -> SignalResult<()> {
+        let killed = || {
+            flags.contains(WaitQueueFlags::KILLABLE)
+                && (task.signals().is_pending(SIGKILL as u64)
+                    || task.signals().is_pending(KSIGKILLTHR as u64))
+        };
+
+        if killed() {
+            return Err(SignalError::Interrupted);
+        }
+
         let res = task.await_io(flags.into());
 
         return match res {
             Ok(()) => Ok(()),
             res @ Err(SignalError::Interrupted) => {
-                if flags.contains(WaitQueueFlags::NON_INTERRUPTIBLE) {
+                if flags.contains(WaitQueueFlags::NON_INTERRUPTIBLE)
+                    || (flags.contains(WaitQueueFlags::KILLABLE) && !killed())
+                {
                     Ok(())
                 } else {
                     res
@@ -0,0 +1 @@
+pub use crate::arch::vdso::{init, mapping, set_wall_offset, update, VdsoMapping};
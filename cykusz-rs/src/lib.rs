@@ -103,6 +103,8 @@ pub fn rust_main(stack_top: VirtAddr) {
 
     kernel::smp::start();
 
+    kernel::mm::slab::init_percpu();
+
     println!(
         "[ OK ] SMP Initialized (CPU count: {})",
         kernel::smp::cpu_count()
@@ -112,6 +114,10 @@ pub fn rust_main(stack_top: VirtAddr) {
 
     println!("[ OK ] Syscall Initialized");
 
+    kernel::vdso::init();
+
+    println!("[ OK ] vDSO Initialized");
+
     kernel::timer::setup();
 
     println!("[ OK ] Timer Setup");
@@ -22,6 +22,7 @@ cp -f cykusz-rs/src/arch/x86_64/asm/grub.cfg mnt/grub/
 
 sed -i "s/{ROOT_UUID}/$(blkid -s UUID -o value "$lo"p2)/g" mnt/grub/grub.cfg
 sed -i "s/{LOGS}/$CYKUSZ_LOGS/g" mnt/grub/grub.cfg
+sed -i "s/{SCHED}/$CYKUSZ_SCHED/g" mnt/grub/grub.cfg
 
 RUST_PROG_MODE=release
 
@@ -33,7 +34,7 @@ fi
 sudo umount mnt
 
 PROGS="test testgcc testcpp hello stack nyancat ttytest fork poweroff stat fbdoom doom1.wad open_sleep"
-RUST_PROGS="init shell mount umount unixsocket-server unixsocket-client forktest mprotecttest play playmidi threads sound-daemon doom"
+RUST_PROGS="init shell mount umount strace unixsocket-server unixsocket-client forktest meminfo mprotecttest ptracetest rlimitexec clocktest mlocktest itimertest sigaltstack play playmidi threads sound-daemon doom"
 
 sudo mount "$lo"p2 mnt
 sudo chown -R $u:$u mnt
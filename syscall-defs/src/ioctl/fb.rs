@@ -1,6 +1,6 @@
 pub const GFBINFO: usize = 0x3415;
 
-#[derive(Default)]
+#[derive(Default, Copy, Clone)]
 pub struct FbInfo {
     pub width: u64,
     pub height: u64,
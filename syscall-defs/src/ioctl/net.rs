@@ -55,18 +55,21 @@ pub union IfReqU {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct IfReq {
     pub ifr_name: [u8; IFNAMSIZ],
     pub ifrequ: IfReqU,
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub union IfConfU {
     pub ifcu_buf: *mut u8,
     pub ifcu_req: *mut IfReq,
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct IfConf {
     pub ifc_len: i32,
     pub ifc_ifcu: IfConfU,
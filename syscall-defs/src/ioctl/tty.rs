@@ -12,6 +12,7 @@ pub const TIOCGWINSZ: usize = 0x5413;
 pub const TIOCSWINSZ: usize = 0x5414;
 
 #[repr(C)]
+#[derive(Default, Copy, Clone)]
 pub struct WinSize {
     pub ws_row: u16,
     pub ws_col: u16,
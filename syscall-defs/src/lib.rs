@@ -14,12 +14,17 @@ use crate::stat::Mode;
 pub mod events;
 pub mod exec;
 pub mod ioctl;
+pub mod meminfo;
 pub mod net;
+pub mod personality;
 pub mod poll;
 pub mod prctl;
+pub mod ptrace;
 pub mod resource;
+pub mod sched;
 pub mod signal;
 pub mod stat;
+pub mod strace;
 pub mod time;
 pub mod waitpid;
 
@@ -107,8 +112,44 @@ pub const SYS_UTIME: usize = 72;
 pub const SYS_MKNODE: usize = 73;
 pub const SYS_SOCKETPAIR: usize = 74;
 pub const SYS_MPROTECT: usize = 75;
-
-pub const SYSCALL_STRING: [&'static str; 76] = [
+pub const SYS_PERSONALITY: usize = 76;
+pub const SYS_SETRLIMIT: usize = 77;
+pub const SYS_PRLIMIT: usize = 78;
+pub const SYS_MLOCK: usize = 79;
+pub const SYS_MUNLOCK: usize = 80;
+pub const SYS_MLOCKALL: usize = 81;
+pub const SYS_MUNLOCKALL: usize = 82;
+pub const SYS_CLONE: usize = 83;
+pub const SYS_PTRACE: usize = 84;
+pub const SYS_STRACE: usize = 85;
+pub const SYS_GETPRIORITY: usize = 86;
+pub const SYS_SETPRIORITY: usize = 87;
+pub const SYS_NICE: usize = 88;
+pub const SYS_SCHED_SETAFFINITY: usize = 89;
+pub const SYS_SCHED_GETAFFINITY: usize = 90;
+pub const SYS_SCHED_SETSCHEDULER: usize = 91;
+pub const SYS_SCHED_GETSCHEDULER: usize = 92;
+pub const SYS_SCHED_SETPARAM: usize = 93;
+pub const SYS_SCHED_GETPARAM: usize = 94;
+pub const SYS_GETRUSAGE: usize = 95;
+pub const SYS_TIMES: usize = 96;
+pub const SYS_WAIT4: usize = 97;
+pub const SYS_ALARM: usize = 98;
+pub const SYS_SETITIMER: usize = 99;
+pub const SYS_GETITIMER: usize = 100;
+pub const SYS_TIMER_CREATE: usize = 101;
+pub const SYS_TIMER_SETTIME: usize = 102;
+pub const SYS_TIMER_GETTIME: usize = 103;
+pub const SYS_TIMER_DELETE: usize = 104;
+pub const SYS_CLOCK_GETTIME: usize = 105;
+pub const SYS_CLOCK_SETTIME: usize = 106;
+pub const SYS_SETTIMEOFDAY: usize = 107;
+pub const SYS_CLOCK_NANOSLEEP: usize = 108;
+pub const SYS_SIGALTSTACK: usize = 109;
+pub const SYS_SLABINFO: usize = 110;
+pub const SYS_MEMINFO: usize = 111;
+
+pub const SYSCALL_STRING: [&'static str; 112] = [
     "SYS_READ",
     "SYS_WRITE",
     "SYS_OPEN",
@@ -185,6 +226,42 @@ pub const SYSCALL_STRING: [&'static str; 76] = [
     "SYS_MKNODE",
     "SYS_SOCKETPAIR",
     "SYS_MPROTECT",
+    "SYS_PERSONALITY",
+    "SYS_SETRLIMIT",
+    "SYS_PRLIMIT",
+    "SYS_MLOCK",
+    "SYS_MUNLOCK",
+    "SYS_MLOCKALL",
+    "SYS_MUNLOCKALL",
+    "SYS_CLONE",
+    "SYS_PTRACE",
+    "SYS_STRACE",
+    "SYS_GETPRIORITY",
+    "SYS_SETPRIORITY",
+    "SYS_NICE",
+    "SYS_SCHED_SETAFFINITY",
+    "SYS_SCHED_GETAFFINITY",
+    "SYS_SCHED_SETSCHEDULER",
+    "SYS_SCHED_GETSCHEDULER",
+    "SYS_SCHED_SETPARAM",
+    "SYS_SCHED_GETPARAM",
+    "SYS_GETRUSAGE",
+    "SYS_TIMES",
+    "SYS_WAIT4",
+    "SYS_ALARM",
+    "SYS_SETITIMER",
+    "SYS_GETITIMER",
+    "SYS_TIMER_CREATE",
+    "SYS_TIMER_SETTIME",
+    "SYS_TIMER_GETTIME",
+    "SYS_TIMER_DELETE",
+    "SYS_CLOCK_GETTIME",
+    "SYS_CLOCK_SETTIME",
+    "SYS_SETTIMEOFDAY",
+    "SYS_CLOCK_NANOSLEEP",
+    "SYS_SIGALTSTACK",
+    "SYS_SLABINFO",
+    "SYS_MEMINFO",
 ];
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -520,6 +597,33 @@ bitflags! {
         const MAP_SHARED = 0x2;
         const MAP_FIXED = 0x4;
         const MAP_ANONYOMUS = 0x8;
+        const MAP_POPULATE = 0x100;
+    }
+}
+
+bitflags! {
+    #[derive(Copy, Clone, Debug)]
+    pub struct MLockAllFlags: usize {
+        const MCL_CURRENT = 0x1;
+        const MCL_FUTURE = 0x2;
+    }
+}
+
+/// Low byte of the clone flags holds the signal sent to the parent on exit
+pub const CLONE_SIGNAL_MASK: u64 = 0xff;
+
+bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Default)]
+    pub struct CloneFlags: u64 {
+        const CLONE_VM = 0x100;
+        const CLONE_FS = 0x200;
+        const CLONE_FILES = 0x400;
+        const CLONE_SIGHAND = 0x800;
+        const CLONE_VFORK = 0x4000;
+        const CLONE_THREAD = 0x10000;
+        const CLONE_SETTLS = 0x80000;
+        const CLONE_PARENT_SETTID = 0x100000;
+        const CLONE_CHILD_CLEARTID = 0x200000;
     }
 }
 
@@ -0,0 +1,25 @@
+/// System memory usage and the resident memory of the calling process, as returned by
+/// SYS_MEMINFO. All sizes are in bytes.
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct MemInfo {
+    pub free: u64,
+    pub used: u64,
+    pub heap: u64,
+    pub slab: u64,
+    pub rss_anon: u64,
+    pub rss_file: u64,
+    // Private anonymous memory backed by the shared zero page, not counted as resident
+    pub rss_zero: u64,
+}
+
+/// Statistics of a slab size class, as returned by SYS_SLABINFO
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct SlabInfo {
+    pub obj_size: u64,
+    pub slabs: u64,
+    pub inuse: u64,
+    pub allocs: u64,
+    pub frees: u64,
+}
@@ -134,6 +134,7 @@ impl TryFrom<u64> for SockDomain {
         }
     }
 }
+#[derive(Copy, Clone)]
 pub struct SockAddrPtr(*mut ());
 
 #[repr(C)]
@@ -310,6 +311,7 @@ pub struct Ucred {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct IoVec {
     pub iov_base: *const (),
     pub iov_len: usize,
@@ -326,6 +328,7 @@ impl IoVec {
 }
 
 #[repr(C)]
+#[derive(Copy, Clone)]
 pub struct MsgHdr {
     pub msg_name: SockAddrPtr,
     pub msg_namelen: u32,
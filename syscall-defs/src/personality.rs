@@ -0,0 +1,9 @@
+bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub struct Personality: u64 {
+        const ADDR_NO_RANDOMIZE = 0x0040000;
+    }
+}
+
+/// Passing this value to the personality syscall only queries the current personality
+pub const PERSONALITY_QUERY: u64 = 0xffff_ffff;
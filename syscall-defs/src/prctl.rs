@@ -2,12 +2,17 @@
 pub enum PrctlCmd {
     Unknown = 0x0,
     ArchSetFs = 0x1002,
+    // Enable (1) or disable (0) syscall tracing of the calling process
+    SetSyscallTrace = 0x5301,
+    GetSyscallTrace = 0x5302,
 }
 
 impl From<usize> for PrctlCmd {
     fn from(v: usize) -> Self {
         match v {
             0x1002 => PrctlCmd::ArchSetFs,
+            0x5301 => PrctlCmd::SetSyscallTrace,
+            0x5302 => PrctlCmd::GetSyscallTrace,
             _ => PrctlCmd::Unknown,
         }
     }
@@ -0,0 +1,44 @@
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(usize)]
+pub enum PtraceRequest {
+    TraceMe = 0,
+    PeekText = 1,
+    PeekData = 2,
+    PokeText = 4,
+    PokeData = 5,
+    Cont = 7,
+    Kill = 8,
+    SingleStep = 9,
+    GetRegs = 12,
+    SetRegs = 13,
+    Attach = 16,
+    Detach = 17,
+    Syscall = 24,
+    SetOptions = 0x4200,
+    Unknown = usize::MAX,
+}
+
+/// Records the tracee syscalls into its syscall trace, read with the strace syscall
+pub const PTRACE_O_STRACE: usize = 1 << 24;
+
+impl From<usize> for PtraceRequest {
+    fn from(v: usize) -> Self {
+        match v {
+            0 => PtraceRequest::TraceMe,
+            1 => PtraceRequest::PeekText,
+            2 => PtraceRequest::PeekData,
+            4 => PtraceRequest::PokeText,
+            5 => PtraceRequest::PokeData,
+            7 => PtraceRequest::Cont,
+            8 => PtraceRequest::Kill,
+            9 => PtraceRequest::SingleStep,
+            12 => PtraceRequest::GetRegs,
+            13 => PtraceRequest::SetRegs,
+            16 => PtraceRequest::Attach,
+            17 => PtraceRequest::Detach,
+            24 => PtraceRequest::Syscall,
+            0x4200 => PtraceRequest::SetOptions,
+            _ => PtraceRequest::Unknown,
+        }
+    }
+}
@@ -1,4 +1,9 @@
+use crate::time::Timeval;
 use crate::SyscallError;
+
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum RLimitKind {
     Core = 1,
     Cpu = 2,
@@ -19,11 +24,22 @@ pub enum RLimitKind {
 }
 
 #[repr(C)]
+#[derive(Debug, Copy, Clone)]
 pub struct RLimit {
     pub cur: u64,
     pub max: u64,
 }
 
+impl RLimit {
+    pub const fn new(cur: u64, max: u64) -> RLimit {
+        RLimit { cur, max }
+    }
+
+    pub const fn infinity() -> RLimit {
+        RLimit::new(RLIM_INFINITY, RLIM_INFINITY)
+    }
+}
+
 impl TryFrom<u64> for RLimitKind {
     type Error = SyscallError;
 
@@ -48,3 +64,44 @@ impl TryFrom<u64> for RLimitKind {
         }
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RUsageWho {
+    SelfProcess = 0,
+    Children = -1,
+    Thread = 1,
+}
+
+impl TryFrom<u64> for RUsageWho {
+    type Error = SyscallError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value as i64 {
+            0 => Ok(RUsageWho::SelfProcess),
+            -1 => Ok(RUsageWho::Children),
+            1 => Ok(RUsageWho::Thread),
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct RUsage {
+    pub ru_utime: Timeval,
+    pub ru_stime: Timeval,
+    pub ru_maxrss: i64,
+    pub ru_ixrss: i64,
+    pub ru_idrss: i64,
+    pub ru_isrss: i64,
+    pub ru_minflt: i64,
+    pub ru_majflt: i64,
+    pub ru_nswap: i64,
+    pub ru_inblock: i64,
+    pub ru_oublock: i64,
+    pub ru_msgsnd: i64,
+    pub ru_msgrcv: i64,
+    pub ru_nsignals: i64,
+    pub ru_nvcsw: i64,
+    pub ru_nivcsw: i64,
+}
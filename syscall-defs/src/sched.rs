@@ -0,0 +1,77 @@
+use crate::SyscallError;
+
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PrioWhich {
+    Process = 0,
+    PGrp = 1,
+    User = 2,
+}
+
+impl TryFrom<u64> for PrioWhich {
+    type Error = SyscallError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(PrioWhich::Process),
+            1 => Ok(PrioWhich::PGrp),
+            2 => Ok(PrioWhich::User),
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+}
+
+/// getpriority returns the nice value biased to 1..40 range, so it is never negative
+pub fn nice_to_prio(nice: isize) -> usize {
+    (20 - nice) as usize
+}
+
+pub fn prio_to_nice(prio: usize) -> isize {
+    20 - prio as isize
+}
+
+pub const RT_PRIO_MIN: usize = 1;
+pub const RT_PRIO_MAX: usize = 99;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SchedPolicy {
+    Other = 0,
+    Fifo = 1,
+    RoundRobin = 2,
+}
+
+impl SchedPolicy {
+    pub fn is_rt(&self) -> bool {
+        *self != SchedPolicy::Other
+    }
+
+    /// Valid static priorities of the policy, normal tasks always use 0
+    pub fn priority_range(&self) -> (usize, usize) {
+        if self.is_rt() {
+            (RT_PRIO_MIN, RT_PRIO_MAX)
+        } else {
+            (0, 0)
+        }
+    }
+}
+
+impl TryFrom<u64> for SchedPolicy {
+    type Error = SyscallError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SchedPolicy::Other),
+            1 => Ok(SchedPolicy::Fifo),
+            2 => Ok(SchedPolicy::RoundRobin),
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SchedParam {
+    pub sched_priority: i32,
+}
@@ -2,12 +2,14 @@ pub const SIGHUP: usize = 1;
 pub const SIGINT: usize = 2;
 pub const SIGQUIT: usize = 3;
 pub const SIGILL: usize = 4;
+pub const SIGTRAP: usize = 5;
 pub const SIGABRT: usize = 6;
 pub const SIGBUS: usize = 7;
 pub const SIGFPE: usize = 8;
 pub const SIGKILL: usize = 9;
 pub const SIGSEGV: usize = 11;
 pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
 pub const SIGTERM: usize = 15;
 pub const SIGCHLD: usize = 17;
 pub const SIGCONT: usize = 18;
@@ -15,6 +17,10 @@ pub const SIGSTOP: usize = 19;
 pub const SIGTSTP: usize = 20;
 pub const SIGTTIN: usize = 21;
 pub const SIGTTOU: usize = 22;
+pub const SIGXCPU: usize = 24;
+pub const SIGXFSZ: usize = 25;
+pub const SIGVTALRM: usize = 26;
+pub const SIGPROF: usize = 27;
 
 #[derive(Debug, Copy, Clone)]
 pub enum SignalHandler {
@@ -24,7 +30,7 @@ pub enum SignalHandler {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, Debug)]
 pub struct SigAction {
     pub sa_handler: u64,
     pub sa_flags: u32,
@@ -46,10 +52,181 @@ impl SigAction {
 bitflags! {
     #[derive(Default, Copy, Clone, Debug)]
     pub struct SignalFlags: u32 {
+        const SIGINFO = 0x4;
+        const RESTORER = 0x04000000;
+        const ONSTACK = 0x08000000;
         const RESTART = 0x10000000;
+        const NODEFER = 0x40000000;
+        const RESETHAND = 0x80000000;
     }
 }
 
+// si_code values
+pub const SI_USER: i32 = 0;
+pub const SI_KERNEL: i32 = 0x80;
+pub const SI_QUEUE: i32 = -1;
+pub const SI_TIMER: i32 = -2;
+pub const SI_TKILL: i32 = -6;
+
+pub const ILL_ILLOPN: i32 = 2;
+pub const FPE_INTDIV: i32 = 1;
+pub const FPE_INTOVF: i32 = 2;
+pub const FPE_FLTINV: i32 = 7;
+pub const SEGV_MAPERR: i32 = 1;
+pub const SEGV_ACCERR: i32 = 2;
+pub const BUS_ADRALN: i32 = 1;
+pub const TRAP_BRKPT: i32 = 1;
+pub const TRAP_TRACE: i32 = 2;
+pub const CLD_EXITED: i32 = 1;
+pub const CLD_KILLED: i32 = 2;
+pub const CLD_DUMPED: i32 = 3;
+pub const CLD_STOPPED: i32 = 5;
+pub const CLD_CONTINUED: i32 = 6;
+
+/// Signal information in the siginfo_t layout, the union fields are accessed with the helpers
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct SigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    fields: [u64; 14],
+}
+
+impl SigInfo {
+    pub fn new(signo: usize, code: i32) -> SigInfo {
+        SigInfo {
+            si_signo: signo as i32,
+            si_code: code,
+            ..Default::default()
+        }
+    }
+
+    /// Signal sent by a process
+    pub fn user(signo: usize, code: i32, pid: usize, uid: u32) -> SigInfo {
+        let mut info = SigInfo::new(signo, code);
+
+        info.fields[0] = pid as u32 as u64 | (uid as u64) << 32;
+
+        info
+    }
+
+    /// Hardware fault at the address
+    pub fn fault(signo: usize, code: i32, addr: u64) -> SigInfo {
+        let mut info = SigInfo::new(signo, code);
+
+        info.fields[0] = addr;
+
+        info
+    }
+
+    pub fn timer(signo: usize, id: usize, overrun: u32, value: u64) -> SigInfo {
+        let mut info = SigInfo::new(signo, SI_TIMER);
+
+        info.fields[0] = id as u32 as u64 | (overrun as u64) << 32;
+        info.fields[1] = value;
+
+        info
+    }
+
+    pub fn child(code: i32, pid: usize, uid: u32, status: i32) -> SigInfo {
+        let mut info = SigInfo::user(SIGCHLD, code, pid, uid);
+
+        info.fields[1] = status as u32 as u64;
+
+        info
+    }
+
+    pub fn pid(&self) -> usize {
+        self.fields[0] as u32 as usize
+    }
+
+    pub fn uid(&self) -> u32 {
+        (self.fields[0] >> 32) as u32
+    }
+
+    pub fn addr(&self) -> u64 {
+        self.fields[0]
+    }
+
+    pub fn timer_id(&self) -> usize {
+        self.fields[0] as u32 as usize
+    }
+
+    pub fn overrun(&self) -> u32 {
+        (self.fields[0] >> 32) as u32
+    }
+
+    pub fn value(&self) -> u64 {
+        self.fields[1]
+    }
+
+    pub fn status(&self) -> i32 {
+        self.fields[1] as i32
+    }
+}
+
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+
+pub const MINSIGSTKSZ: u64 = 2048;
+pub const SIGSTKSZ: u64 = 8192;
+
+/// stack_t of sigaltstack
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct SigAltStack {
+    pub ss_sp: u64,
+    pub ss_flags: i32,
+    pub ss_size: u64,
+}
+
+/// Registers of the interrupted context in the x86_64 sigcontext layout
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct MContext {
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rsp: u64,
+    pub rip: u64,
+    pub eflags: u64,
+    pub cs: u16,
+    pub gs: u16,
+    pub fs: u16,
+    pub ss: u16,
+    pub err: u64,
+    pub trapno: u64,
+    pub oldmask: u64,
+    pub cr2: u64,
+    pub fpstate: u64,
+    pub reserved: [u64; 8],
+}
+
+/// Context passed to SA_SIGINFO handlers, changes are applied by sigreturn
+#[repr(C)]
+#[derive(Default, Copy, Clone, Debug)]
+pub struct UContext {
+    pub uc_flags: u64,
+    pub uc_link: u64,
+    pub uc_stack: SigAltStack,
+    pub uc_mcontext: MContext,
+    pub uc_sigmask: u64,
+}
+
 #[repr(u64)]
 #[derive(Debug)]
 pub enum SigProcMask {
@@ -61,7 +61,7 @@ impl From<FileType> for Mode {
 }
 
 #[repr(C)]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Copy, Clone)]
 pub struct Stat {
     pub st_dev: u64,
     pub st_ino: u64,
@@ -0,0 +1,53 @@
+use crate::*;
+
+/// Maximum number of path bytes captured for a traced syscall
+pub const STRACE_PATH_MAX: usize = 64;
+
+/// Single traced syscall, as returned by SYS_STRACE
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SyscallRecord {
+    pub tid: u64,
+    pub num: u64,
+    pub args: [u64; 6],
+    // Raw syscall return value, negative errno on failure
+    pub ret: i64,
+    pub duration_ns: u64,
+    pub path_len: u64,
+    pub path: [u8; STRACE_PATH_MAX],
+}
+
+impl SyscallRecord {
+    pub const fn empty() -> SyscallRecord {
+        SyscallRecord {
+            tid: 0,
+            num: 0,
+            args: [0; 6],
+            ret: 0,
+            duration_ns: 0,
+            path_len: 0,
+            path: [0; STRACE_PATH_MAX],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        SYSCALL_STRING
+            .get(self.num as usize)
+            .copied()
+            .unwrap_or("SYS_UNKNOWN")
+    }
+
+    pub fn path(&self) -> Option<&[u8]> {
+        path_arg(self.num as usize).map(|_| &self.path[..self.path_len as usize])
+    }
+}
+
+/// Indexes of the path pointer and path length arguments of a syscall
+pub fn path_arg(num: usize) -> Option<(usize, usize)> {
+    match num {
+        SYS_OPEN | SYS_CHDIR | SYS_MKDIR | SYS_UNLINK | SYS_LINK | SYS_RENAME | SYS_STAT
+        | SYS_ACCESS | SYS_READLINK | SYS_CHMOD | SYS_UTIME | SYS_MKNODE => Some((1, 2)),
+        SYS_RMDIR | SYS_EXEC | SYS_SYMLINK | SYS_MOUNT => Some((0, 1)),
+        _ => None,
+    }
+}
@@ -1,3 +1,5 @@
+use crate::SyscallError;
+
 pub const UTIME_NOW: u64 = (1u64 << 30) - 1;
 pub const UTIME_OMIT: u64 = (1u64 << 30) - 2;
 
@@ -27,10 +29,21 @@ impl Timespec {
     pub fn is_omit(&self) -> bool {
         self.nsecs == UTIME_OMIT
     }
+
+    pub fn from_nsecs(nsecs: u64) -> Timespec {
+        Timespec {
+            secs: nsecs / 1000_000_000,
+            nsecs: nsecs % 1000_000_000,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.nsecs < 1000_000_000
+    }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone)]
 pub struct Timeval {
     pub secs: u64,
     pub usecs: u64,
@@ -47,4 +60,115 @@ impl Timeval {
     pub fn to_nanoseconds(&self) -> usize {
         self.secs as usize * 1000000000usize + self.usecs as usize * 1000usize
     }
+
+    pub fn is_valid(&self) -> bool {
+        self.usecs < 1000_000
+    }
+}
+
+/// Clock ticks per second reported by times
+pub const CLK_TCK: u64 = 100;
+
+pub fn ns_to_clock_ticks(ns: u64) -> u64 {
+    ns / (1_000_000_000 / CLK_TCK)
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct Tms {
+    pub tms_utime: u64,
+    pub tms_stime: u64,
+    pub tms_cutime: u64,
+    pub tms_cstime: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct ITimerVal {
+    pub it_interval: Timeval,
+    pub it_value: Timeval,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct ITimerSpec {
+    pub it_interval: Timespec,
+    pub it_value: Timespec,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ITimerWhich {
+    Real = 0,
+    Virtual = 1,
+    Prof = 2,
+}
+
+impl TryFrom<u64> for ITimerWhich {
+    type Error = SyscallError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ITimerWhich::Real),
+            1 => Ok(ITimerWhich::Virtual),
+            2 => Ok(ITimerWhich::Prof),
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ClockId {
+    Realtime = 0,
+    Monotonic = 1,
+    ProcessCputime = 2,
+    ThreadCputime = 3,
+    MonotonicRaw = 4,
+    RealtimeCoarse = 5,
+    MonotonicCoarse = 6,
+    Boottime = 7,
+}
+
+impl ClockId {
+    /// Cpu time clocks of the calling process or thread
+    pub fn is_cpu(&self) -> bool {
+        matches!(self, ClockId::ProcessCputime | ClockId::ThreadCputime)
+    }
+
+    pub fn is_realtime(&self) -> bool {
+        matches!(self, ClockId::Realtime | ClockId::RealtimeCoarse)
+    }
+}
+
+impl TryFrom<u64> for ClockId {
+    type Error = SyscallError;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ClockId::Realtime),
+            1 => Ok(ClockId::Monotonic),
+            2 => Ok(ClockId::ProcessCputime),
+            3 => Ok(ClockId::ThreadCputime),
+            4 => Ok(ClockId::MonotonicRaw),
+            5 => Ok(ClockId::RealtimeCoarse),
+            6 => Ok(ClockId::MonotonicCoarse),
+            7 => Ok(ClockId::Boottime),
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+}
+
+/// timer_settime flag, the expiry time is absolute
+pub const TIMER_ABSTIME: u64 = 1;
+
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+pub const SIGEV_THREAD: i32 = 2;
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone)]
+pub struct SigEvent {
+    pub sigev_value: u64,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    pub _pad: [i32; 12],
 }
@@ -0,0 +1,178 @@
+use std::process::ExitCode;
+
+use syscall_defs::prctl::PrctlCmd;
+use syscall_defs::ptrace::{PTRACE_O_STRACE, PtraceRequest};
+use syscall_defs::signal::{SIGCHLD, SigAction, SignalFlags, SignalHandler};
+use syscall_defs::strace::{SyscallRecord, path_arg};
+use syscall_defs::waitpid::{Status, WaitPidFlags};
+use syscall_defs::{SyscallError, SyscallFrom, SyscallResult};
+
+fn print_record(rec: &SyscallRecord) {
+    let name = rec.name().trim_start_matches("SYS_").to_lowercase();
+
+    let path = path_arg(rec.num as usize);
+
+    let mut args = Vec::new();
+
+    for (i, arg) in rec.args.iter().enumerate() {
+        match path {
+            Some((ptr, _)) if i == ptr => {
+                let path = String::from_utf8_lossy(rec.path().unwrap_or(&[]));
+
+                args.push(format!("{:?}", path));
+            }
+            Some((_, len)) if i == len => {}
+            _ => args.push(format!("{:#x}", arg)),
+        }
+    }
+
+    let ret = match SyscallResult::syscall_from(rec.ret as isize) {
+        Ok(v) => format!("{:#x}", v),
+        Err(e) => format!("-1 {:?}", e),
+    };
+
+    println!(
+        "[{}] {}({}) = {} <{}.{:06}>",
+        rec.tid,
+        name,
+        args.join(", "),
+        ret,
+        rec.duration_ns / 1_000_000_000,
+        rec.duration_ns % 1_000_000_000 / 1000
+    );
+}
+
+fn sigchld_handler(_sig: usize) {}
+
+/// Resumes the attached process after a signal stop, passing the signal on
+fn resume_stopped(pid: usize) {
+    let mut status = 0u32;
+
+    while let Ok(p) = syscall_user::waitpid(
+        pid as isize,
+        &mut status,
+        WaitPidFlags::NOHANG | WaitPidFlags::STOPPED,
+    ) {
+        if p == 0 {
+            break;
+        }
+
+        if let Status::Stopped(sig) = Status::from(status) {
+            if let Err(e) = syscall_user::ptrace(PtraceRequest::Cont, pid, 0, sig as usize) {
+                println!("strace: failed to resume {}: {:?}", pid, e);
+                break;
+            }
+        }
+    }
+}
+
+fn print_trace(pid: usize, attached: bool) {
+    let mut records = [SyscallRecord::empty(); 32];
+
+    loop {
+        // Attached process stops on every signal, reading the trace is interrupted by SIGCHLD
+        if attached {
+            resume_stopped(pid);
+        }
+
+        match syscall_user::strace(pid, &mut records) {
+            Ok(0) => break,
+            Ok(n) => records[..n].iter().for_each(print_record),
+            Err(SyscallError::EINTR) => {}
+            Err(e) => {
+                println!("strace: failed to read trace: {:?}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Attaches to a running process and enables its syscall trace
+fn attach(pid: usize) -> Result<(), SyscallError> {
+    syscall_user::sigaction(
+        SIGCHLD,
+        Some(&mut SigAction::new(
+            SignalHandler::Handle(sigchld_handler),
+            0,
+            SignalFlags::empty(),
+        )),
+        None,
+    )?;
+
+    syscall_user::ptrace(PtraceRequest::Attach, pid, 0, 0)?;
+
+    let mut status = 0u32;
+
+    while let Err(SyscallError::EINTR) =
+        syscall_user::waitpid(pid as isize, &mut status, WaitPidFlags::STOPPED)
+    {}
+
+    syscall_user::ptrace(PtraceRequest::SetOptions, pid, 0, PTRACE_O_STRACE)?;
+    syscall_user::ptrace(PtraceRequest::Cont, pid, 0, 0)?;
+
+    Ok(())
+}
+
+fn main() -> Result<(), ExitCode> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+    if args.is_empty() {
+        println!("Usage: strace <program> [args...] | strace -p <pid>");
+        return Err(ExitCode::from(1));
+    }
+
+    if args[0] == "-p" {
+        let Some(pid) = args.get(1).and_then(|p| p.parse::<usize>().ok()) else {
+            println!("strace: invalid pid");
+            return Err(ExitCode::from(1));
+        };
+
+        if let Err(e) = attach(pid) {
+            println!("strace: failed to attach to {}: {:?}", pid, e);
+            return Err(ExitCode::from(1));
+        }
+
+        print_trace(pid, true);
+
+        return Ok(());
+    }
+
+    let path = if args[0].contains('/') {
+        args[0].clone()
+    } else {
+        format!("/bin/{}", args[0])
+    };
+
+    let pid = syscall_user::fork().map_err(|_e| ExitCode::from(1))?;
+
+    if pid == 0 {
+        let argv = args.iter().map(|a| a.as_str()).collect::<Vec<&str>>();
+        let env = std::env::vars()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<String>>();
+        let env = env.iter().map(|e| e.as_str()).collect::<Vec<&str>>();
+
+        syscall_user::arch_prctl(PrctlCmd::SetSyscallTrace, 1).expect("Failed to enable trace");
+
+        if let Err(e) = syscall_user::exec(path.as_str(), Some(&argv), Some(&env)) {
+            println!("strace: {}: {:?}", path, e);
+        }
+
+        syscall_user::exit(127);
+    }
+
+    print_trace(pid, false);
+
+    let mut status = 0u32;
+
+    while let Err(SyscallError::EINTR) =
+        syscall_user::waitpid(pid as isize, &mut status, WaitPidFlags::EXITED)
+    {}
+
+    println!(
+        "strace: process exit with status: {:?}",
+        syscall_defs::waitpid::Status::from(status)
+    );
+
+    Ok(())
+}
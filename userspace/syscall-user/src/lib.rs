@@ -7,8 +7,14 @@ use std::sync::atomic::AtomicU32;
 
 use syscall_defs::net::{MsgHdr, SockAddrPtr, SockDomain, SockTypeFlags};
 use syscall_defs::poll::FdSet;
+use syscall_defs::ptrace::PtraceRequest;
+use syscall_defs::resource::{RLimit, RLimitKind, RUsage, RUsageWho};
+use syscall_defs::sched::{PrioWhich, SchedParam, SchedPolicy};
 use syscall_defs::signal::SigAction;
-use syscall_defs::time::Timespec;
+use syscall_defs::strace::SyscallRecord;
+use syscall_defs::time::{
+    ClockId, ITimerSpec, ITimerVal, ITimerWhich, SigEvent, Timespec, Timeval, Tms,
+};
 use syscall_defs::*;
 
 pub unsafe fn syscall0(mut a: usize) -> SyscallResult { unsafe {
@@ -205,6 +211,184 @@ pub fn mprotect(addr: usize, size: usize, prot: MMapProt) -> SyscallResult {
     unsafe { syscall3(SYS_MPROTECT, addr, size, prot.bits()) }
 }
 
+pub fn mlock(addr: usize, len: usize) -> SyscallResult {
+    unsafe { syscall2(SYS_MLOCK, addr, len) }
+}
+
+pub fn munlock(addr: usize, len: usize) -> SyscallResult {
+    unsafe { syscall2(SYS_MUNLOCK, addr, len) }
+}
+
+pub fn mlockall(flags: MLockAllFlags) -> SyscallResult {
+    unsafe { syscall1(SYS_MLOCKALL, flags.bits()) }
+}
+
+pub fn munlockall() -> SyscallResult {
+    unsafe { syscall0(SYS_MUNLOCKALL) }
+}
+
+pub fn personality(persona: u64) -> SyscallResult {
+    unsafe { syscall1(SYS_PERSONALITY, persona as usize) }
+}
+
+pub fn getrlimit(resource: RLimitKind, rlimit: &mut RLimit) -> SyscallResult {
+    unsafe {
+        syscall2(
+            SYS_GETRLIMIT,
+            resource as usize,
+            rlimit as *mut RLimit as usize,
+        )
+    }
+}
+
+pub fn setrlimit(resource: RLimitKind, rlimit: &RLimit) -> SyscallResult {
+    unsafe {
+        syscall2(
+            SYS_SETRLIMIT,
+            resource as usize,
+            rlimit as *const RLimit as usize,
+        )
+    }
+}
+
+pub fn getrusage(who: RUsageWho) -> Result<RUsage, SyscallError> {
+    let mut usage = RUsage::default();
+
+    unsafe {
+        syscall2(
+            SYS_GETRUSAGE,
+            who as isize as usize,
+            &raw mut usage as usize,
+        )?;
+    }
+
+    Ok(usage)
+}
+
+/// Returns clock ticks since boot
+pub fn times(tms: &mut Tms) -> SyscallResult {
+    unsafe { syscall1(SYS_TIMES, tms as *mut Tms as usize) }
+}
+
+/// Returns seconds left to the previous alarm
+pub fn alarm(secs: usize) -> SyscallResult {
+    unsafe { syscall1(SYS_ALARM, secs) }
+}
+
+pub fn setitimer(
+    which: ITimerWhich,
+    new: &ITimerVal,
+    old: Option<&mut ITimerVal>,
+) -> SyscallResult {
+    unsafe {
+        syscall3(
+            SYS_SETITIMER,
+            which as usize,
+            new as *const ITimerVal as usize,
+            old.map_or(0, |o| o as *mut ITimerVal as usize),
+        )
+    }
+}
+
+pub fn getitimer(which: ITimerWhich) -> Result<ITimerVal, SyscallError> {
+    let mut value = ITimerVal::default();
+
+    unsafe {
+        syscall2(SYS_GETITIMER, which as usize, &raw mut value as usize)?;
+    }
+
+    Ok(value)
+}
+
+/// Returns id of the new timer, timer without event sends SIGALRM
+pub fn timer_create(clock: ClockId, event: Option<&SigEvent>) -> Result<u32, SyscallError> {
+    let mut id = 0u32;
+
+    unsafe {
+        syscall3(
+            SYS_TIMER_CREATE,
+            clock as usize,
+            event.map_or(0, |e| e as *const SigEvent as usize),
+            &raw mut id as usize,
+        )?;
+    }
+
+    Ok(id)
+}
+
+pub fn timer_settime(
+    id: u32,
+    flags: usize,
+    new: &ITimerSpec,
+    old: Option<&mut ITimerSpec>,
+) -> SyscallResult {
+    unsafe {
+        syscall4(
+            SYS_TIMER_SETTIME,
+            id as usize,
+            flags,
+            new as *const ITimerSpec as usize,
+            old.map_or(0, |o| o as *mut ITimerSpec as usize),
+        )
+    }
+}
+
+pub fn timer_gettime(id: u32) -> Result<ITimerSpec, SyscallError> {
+    let mut value = ITimerSpec::default();
+
+    unsafe {
+        syscall2(SYS_TIMER_GETTIME, id as usize, &raw mut value as usize)?;
+    }
+
+    Ok(value)
+}
+
+pub fn timer_delete(id: u32) -> SyscallResult {
+    unsafe { syscall1(SYS_TIMER_DELETE, id as usize) }
+}
+
+pub fn clock_gettime(clock: ClockId) -> Result<Timespec, SyscallError> {
+    let mut ts = Timespec::default();
+
+    unsafe {
+        syscall2(SYS_CLOCK_GETTIME, clock as usize, &raw mut ts as usize)?;
+    }
+
+    Ok(ts)
+}
+
+pub fn clock_settime(clock: ClockId, ts: &Timespec) -> SyscallResult {
+    unsafe {
+        syscall2(
+            SYS_CLOCK_SETTIME,
+            clock as usize,
+            ts as *const Timespec as usize,
+        )
+    }
+}
+
+pub fn settimeofday(tv: &Timeval) -> SyscallResult {
+    unsafe { syscall2(SYS_SETTIMEOFDAY, tv as *const Timeval as usize, 0) }
+}
+
+/// Remaining time is stored in rem when a relative sleep is interrupted
+pub fn clock_nanosleep(
+    clock: ClockId,
+    flags: usize,
+    req: &Timespec,
+    rem: Option<&mut Timespec>,
+) -> SyscallResult {
+    unsafe {
+        syscall4(
+            SYS_CLOCK_NANOSLEEP,
+            clock as usize,
+            flags,
+            req as *const Timespec as usize,
+            rem.map_or(0, |r| r as *mut Timespec as usize),
+        )
+    }
+}
+
 pub fn munmap(addr: usize, len: usize) -> SyscallResult {
     unsafe { syscall2(SYS_MUNMAP, addr, len) }
 }
@@ -440,6 +624,122 @@ pub fn fork() -> SyscallResult {
     unsafe { syscall0(SYS_FORK) }
 }
 
+pub fn clone(
+    flags: CloneFlags,
+    stack: usize,
+    parent_tid: usize,
+    child_tid: usize,
+    tls: usize,
+) -> SyscallResult {
+    unsafe {
+        syscall5(
+            SYS_CLONE,
+            flags.bits() as usize,
+            stack,
+            parent_tid,
+            child_tid,
+            tls,
+        )
+    }
+}
+
+pub fn ptrace(request: PtraceRequest, pid: usize, addr: usize, data: usize) -> SyscallResult {
+    unsafe { syscall4(SYS_PTRACE, request as usize, pid, addr, data) }
+}
+
+/// Returns the nice value
+pub fn getpriority(which: PrioWhich, who: usize) -> Result<isize, SyscallError> {
+    let prio = unsafe { syscall2(SYS_GETPRIORITY, which as usize, who)? };
+
+    Ok(syscall_defs::sched::prio_to_nice(prio))
+}
+
+pub fn setpriority(which: PrioWhich, who: usize, nice: isize) -> SyscallResult {
+    unsafe { syscall3(SYS_SETPRIORITY, which as usize, who, nice as usize) }
+}
+
+pub fn nice(inc: isize) -> SyscallResult {
+    unsafe { syscall1(SYS_NICE, inc as usize) }
+}
+
+/// Bit n of the mask allows running on cpu n
+pub fn sched_setaffinity(pid: usize, mask: u64) -> SyscallResult {
+    unsafe {
+        syscall3(
+            SYS_SCHED_SETAFFINITY,
+            pid,
+            size_of::<u64>(),
+            &raw const mask as usize,
+        )
+    }
+}
+
+pub fn sched_getaffinity(pid: usize) -> Result<u64, SyscallError> {
+    let mut mask = 0u64;
+
+    unsafe {
+        syscall3(
+            SYS_SCHED_GETAFFINITY,
+            pid,
+            size_of::<u64>(),
+            &raw mut mask as usize,
+        )?;
+    }
+
+    Ok(mask)
+}
+
+pub fn sched_setscheduler(pid: usize, policy: SchedPolicy, priority: i32) -> SyscallResult {
+    let param = SchedParam {
+        sched_priority: priority,
+    };
+
+    unsafe {
+        syscall3(
+            SYS_SCHED_SETSCHEDULER,
+            pid,
+            policy as usize,
+            &raw const param as usize,
+        )
+    }
+}
+
+pub fn sched_getscheduler(pid: usize) -> Result<SchedPolicy, SyscallError> {
+    let policy = unsafe { syscall1(SYS_SCHED_GETSCHEDULER, pid)? };
+
+    SchedPolicy::try_from(policy as u64)
+}
+
+pub fn sched_setparam(pid: usize, priority: i32) -> SyscallResult {
+    let param = SchedParam {
+        sched_priority: priority,
+    };
+
+    unsafe { syscall2(SYS_SCHED_SETPARAM, pid, &raw const param as usize) }
+}
+
+/// Returns the static priority
+pub fn sched_getparam(pid: usize) -> Result<i32, SyscallError> {
+    let mut param = SchedParam::default();
+
+    unsafe {
+        syscall2(SYS_SCHED_GETPARAM, pid, &raw mut param as usize)?;
+    }
+
+    Ok(param.sched_priority)
+}
+
+pub fn strace(pid: usize, records: &mut [SyscallRecord]) -> SyscallResult {
+    unsafe {
+        syscall3(
+            SYS_STRACE,
+            pid,
+            records.as_mut_ptr() as usize,
+            records.len(),
+        )
+    }
+}
+
 pub fn exec(path: &str, args: Option<&[&str]>, env: Option<&[&str]>) -> SyscallResult {
     let args = if let Some(args) = args {
         syscall_defs::exec::into_syscall_slice(args)
@@ -476,6 +776,24 @@ pub fn waitpid(pid: isize, status: &mut u32, flags: waitpid::WaitPidFlags) -> Sy
     }
 }
 
+/// waitpid returning the resource usage of the reaped child
+pub fn wait4(
+    pid: isize,
+    status: &mut u32,
+    flags: waitpid::WaitPidFlags,
+    rusage: &mut RUsage,
+) -> SyscallResult {
+    unsafe {
+        syscall4(
+            SYS_WAIT4,
+            pid as usize,
+            status as *const u32 as usize,
+            flags.bits(),
+            rusage as *mut RUsage as usize,
+        )
+    }
+}
+
 pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> SyscallResult {
     unsafe { syscall3(SYS_IOCTL, fd, cmd, arg) }
 }
@@ -521,6 +839,33 @@ pub fn sigprocmask(
     unsafe { syscall3(SYS_SIGPROCMASK, how as usize, set, old_set) }
 }
 
+pub fn sigaltstack(
+    new: Option<&syscall_defs::signal::SigAltStack>,
+    old: Option<&mut syscall_defs::signal::SigAltStack>,
+) -> SyscallResult {
+    unsafe {
+        syscall2(
+            SYS_SIGALTSTACK,
+            new.map_or(0, |s| s as *const _ as usize),
+            old.map_or(0, |s| s as *mut _ as usize),
+        )
+    }
+}
+
+pub fn meminfo() -> Result<syscall_defs::meminfo::MemInfo, SyscallError> {
+    let mut info = syscall_defs::meminfo::MemInfo::default();
+
+    unsafe {
+        syscall1(SYS_MEMINFO, &raw mut info as usize)?;
+    }
+
+    Ok(info)
+}
+
+pub fn slabinfo(info: &mut [syscall_defs::meminfo::SlabInfo]) -> SyscallResult {
+    unsafe { syscall2(SYS_SLABINFO, info.as_mut_ptr() as usize, info.len()) }
+}
+
 #[allow(unused)]
 pub fn bochs() {
     unsafe {
@@ -0,0 +1,111 @@
+use syscall_defs::time::{ClockId, TIMER_ABSTIME, Timespec};
+use syscall_defs::waitpid::{Status, WaitPidFlags};
+use syscall_defs::{SyscallError, SyscallResult};
+use syscall_user::{clock_gettime, clock_nanosleep, clock_settime, exit, fork, waitpid};
+
+const MS: u64 = 1_000_000;
+
+fn now(clock: ClockId) -> u64 {
+    clock_gettime(clock)
+        .expect("clock_gettime failed")
+        .to_nanoseconds() as u64
+}
+
+fn check(name: &str, ok: bool) {
+    println!("{}: {}", name, if ok { "ok" } else { "FAILED" });
+}
+
+fn sleep_until(clock: ClockId, ns: u64) -> SyscallResult {
+    clock_nanosleep(
+        clock,
+        TIMER_ABSTIME as usize,
+        &Timespec::from_nsecs(ns),
+        None,
+    )
+}
+
+fn test_gettime() {
+    let a = now(ClockId::Monotonic);
+    let b = now(ClockId::Monotonic);
+
+    check("monotonic", a <= b);
+    check("realtime", now(ClockId::Realtime) > 0);
+    check("cputime", clock_gettime(ClockId::ProcessCputime).is_ok());
+}
+
+fn test_relative() {
+    let start = now(ClockId::Monotonic);
+
+    clock_nanosleep(ClockId::Monotonic, 0, &Timespec::from_nsecs(50 * MS), None)
+        .expect("clock_nanosleep failed");
+
+    check("relative", now(ClockId::Monotonic) - start >= 50 * MS);
+
+    check(
+        "cpu clock sleep",
+        clock_nanosleep(ClockId::ProcessCputime, 0, &Timespec::from_nsecs(MS), None)
+            == Err(SyscallError::EINVAL),
+    );
+}
+
+fn test_absolute() {
+    let target = now(ClockId::Monotonic) + 50 * MS;
+    sleep_until(ClockId::Monotonic, target).expect("clock_nanosleep failed");
+
+    check("absolute monotonic", now(ClockId::Monotonic) >= target);
+
+    let target = now(ClockId::Realtime) + 50 * MS;
+    sleep_until(ClockId::Realtime, target).expect("clock_nanosleep failed");
+
+    check("absolute realtime", now(ClockId::Realtime) >= target);
+
+    check(
+        "absolute past",
+        sleep_until(ClockId::Realtime, now(ClockId::Realtime) - 1000 * MS).is_ok(),
+    );
+}
+
+/// Absolute realtime sleep wakes up when the wall clock is set past its deadline
+fn test_clock_set() {
+    let target = now(ClockId::Realtime) + 10_000 * MS;
+
+    let pid = fork().expect("fork failed");
+
+    if pid == 0 {
+        let res = sleep_until(ClockId::Realtime, target);
+
+        exit(if res.is_ok() { 0 } else { 1 });
+    }
+
+    let start = now(ClockId::Monotonic);
+
+    clock_nanosleep(ClockId::Monotonic, 0, &Timespec::from_nsecs(100 * MS), None)
+        .expect("clock_nanosleep failed");
+
+    let wall = now(ClockId::Realtime);
+
+    clock_settime(ClockId::Realtime, &Timespec::from_nsecs(wall + 20_000 * MS))
+        .expect("clock_settime failed");
+
+    let mut status = 0;
+    while let Err(SyscallError::EINTR) = waitpid(pid as isize, &mut status, WaitPidFlags::EXITED) {}
+
+    let elapsed = now(ClockId::Monotonic) - start;
+
+    // Restore the wall clock
+    let wall = now(ClockId::Realtime);
+    clock_settime(ClockId::Realtime, &Timespec::from_nsecs(wall - 20_000 * MS))
+        .expect("clock_settime failed");
+
+    check(
+        "clock set",
+        matches!(Status::from(status), Status::Exited(0)) && elapsed < 5_000 * MS,
+    );
+}
+
+fn main() {
+    test_gettime();
+    test_relative();
+    test_absolute();
+    test_clock_set();
+}
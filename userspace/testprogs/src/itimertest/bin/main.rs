@@ -0,0 +1,158 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use syscall_defs::SyscallError;
+use syscall_defs::signal::{SIGALRM, SIGPROF, SIGVTALRM, SigAction, SignalFlags, SignalHandler};
+use syscall_defs::time::{ClockId, ITimerSpec, ITimerVal, ITimerWhich, Timespec, Timeval};
+use syscall_user::{
+    alarm, clock_gettime, getitimer, setitimer, sigaction, sleep, timer_create, timer_delete,
+    timer_gettime, timer_settime,
+};
+
+const MS: u64 = 1_000_000;
+
+static ALRM: AtomicUsize = AtomicUsize::new(0);
+static VTALRM: AtomicUsize = AtomicUsize::new(0);
+static PROF: AtomicUsize = AtomicUsize::new(0);
+
+fn handler(sig: usize) {
+    match sig {
+        SIGALRM => &ALRM,
+        SIGVTALRM => &VTALRM,
+        SIGPROF => &PROF,
+        _ => return,
+    }
+    .fetch_add(1, Ordering::SeqCst);
+}
+
+fn check(name: &str, ok: bool) {
+    println!("{}: {}", name, if ok { "ok" } else { "FAILED" });
+}
+
+fn now() -> u64 {
+    clock_gettime(ClockId::Monotonic)
+        .expect("clock_gettime failed")
+        .to_nanoseconds() as u64
+}
+
+/// Waits until the counter reaches count, sleeping or burning cpu time, gives up after 5s
+fn wait_for(counter: &AtomicUsize, count: usize, busy: bool) -> bool {
+    let start = now();
+
+    while counter.load(Ordering::SeqCst) < count {
+        if now() - start > 5_000 * MS {
+            return false;
+        }
+
+        if !busy {
+            let _ = sleep(1);
+        }
+    }
+
+    true
+}
+
+fn itimer(value: u64, interval: u64) -> ITimerVal {
+    ITimerVal {
+        it_interval: Timeval::from_nsecs(interval),
+        it_value: Timeval::from_nsecs(value),
+    }
+}
+
+fn is_zero(val: &Timeval) -> bool {
+    val.secs == 0 && val.usecs == 0
+}
+
+/// Periodic real timer keeps firing until disarmed
+fn test_real() {
+    setitimer(ITimerWhich::Real, &itimer(20 * MS, 20 * MS), None).expect("setitimer failed");
+
+    check("real periodic", wait_for(&ALRM, 3, false));
+
+    let val = getitimer(ITimerWhich::Real).expect("getitimer failed");
+    check("real get", !is_zero(&val.it_interval));
+
+    let mut old = ITimerVal::default();
+    setitimer(ITimerWhich::Real, &itimer(0, 0), Some(&mut old)).expect("setitimer failed");
+    check("real old", !is_zero(&old.it_interval));
+
+    let val = getitimer(ITimerWhich::Real).expect("getitimer failed");
+    check("real disarm", is_zero(&val.it_value));
+}
+
+/// Virtual and prof timers only run while the process uses cpu
+fn test_cpu() {
+    setitimer(ITimerWhich::Virtual, &itimer(50 * MS, 0), None).expect("setitimer failed");
+    check("virtual", wait_for(&VTALRM, 1, true));
+
+    setitimer(ITimerWhich::Prof, &itimer(50 * MS, 0), None).expect("setitimer failed");
+    check("prof", wait_for(&PROF, 1, true));
+
+    check(
+        "bad timeval",
+        setitimer(
+            ITimerWhich::Virtual,
+            &ITimerVal {
+                it_interval: Timeval::default(),
+                it_value: Timeval {
+                    secs: 0,
+                    usecs: 1_000_000,
+                },
+            },
+            None,
+        ) == Err(SyscallError::EINVAL),
+    );
+}
+
+fn test_alarm() {
+    check("alarm", alarm(10) == Ok(0));
+    check(
+        "alarm left",
+        alarm(0).is_ok_and(|left| left > 0 && left <= 10),
+    );
+}
+
+/// Timer without an event sends SIGALRM
+fn test_posix() {
+    ALRM.store(0, Ordering::SeqCst);
+
+    let id = timer_create(ClockId::Monotonic, None).expect("timer_create failed");
+
+    let spec = ITimerSpec {
+        it_interval: Timespec::from_nsecs(10 * MS),
+        it_value: Timespec::from_nsecs(10 * MS),
+    };
+
+    timer_settime(id, 0, &spec, None).expect("timer_settime failed");
+
+    check("posix periodic", wait_for(&ALRM, 3, false));
+    check(
+        "posix get",
+        timer_gettime(id).is_ok_and(|s| s.it_interval.to_nanoseconds() as u64 == 10 * MS),
+    );
+
+    check("posix delete", timer_delete(id).is_ok());
+    check(
+        "posix delete again",
+        timer_delete(id) == Err(SyscallError::EINVAL),
+    );
+}
+
+fn main() {
+    for sig in [SIGALRM, SIGVTALRM, SIGPROF] {
+        sigaction(
+            sig,
+            Some(&mut SigAction::new(
+                SignalHandler::Handle(handler),
+                0,
+                SignalFlags::RESTART,
+            )),
+            None,
+        )
+        .expect("sigaction failed");
+    }
+
+    test_real();
+    test_cpu();
+    test_alarm();
+    test_posix();
+}
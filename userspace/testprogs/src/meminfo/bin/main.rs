@@ -0,0 +1,67 @@
+use syscall_defs::meminfo::SlabInfo;
+use syscall_defs::{MMapFlags, MMapProt};
+use syscall_user::{meminfo, mmap, slabinfo};
+
+fn print_rss(when: &str) {
+    let info = meminfo().expect("meminfo failed");
+
+    println!(
+        "{}: rss anon {} kB, file {} kB, zero page {} kB",
+        when,
+        info.rss_anon / 1024,
+        info.rss_file / 1024,
+        info.rss_zero / 1024
+    );
+}
+
+fn main() {
+    let info = meminfo().expect("meminfo failed");
+
+    println!(
+        "free {} kB, used {} kB, heap {} kB, slab {} kB",
+        info.free / 1024,
+        info.used / 1024,
+        info.heap / 1024,
+        info.slab / 1024
+    );
+
+    let mut slabs = [SlabInfo::default(); 32];
+
+    let count = slabinfo(&mut slabs).expect("slabinfo failed");
+
+    for s in &slabs[..count] {
+        println!(
+            "slab {:>5}: slabs {:>6} inuse {:>8} allocs {:>10} frees {:>10}",
+            s.obj_size, s.slabs, s.inuse, s.allocs, s.frees
+        );
+    }
+
+    // Reads are backed by the zero page, only writes make the memory resident
+    let len = 0x40000;
+
+    let addr = mmap(
+        None,
+        len,
+        MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+        MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
+        None,
+        0,
+    )
+    .expect("mmap failed");
+
+    print_rss("mapped");
+
+    let mut sum = 0u64;
+
+    for off in (0..len).step_by(0x1000) {
+        sum += unsafe { ((addr + off) as *const u64).read_volatile() };
+    }
+
+    print_rss("read");
+
+    for off in (0..len).step_by(0x1000) {
+        unsafe { ((addr + off) as *mut u64).write_volatile(sum + 1) };
+    }
+
+    print_rss("written");
+}
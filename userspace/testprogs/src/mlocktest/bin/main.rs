@@ -0,0 +1,90 @@
+use syscall_defs::resource::{RLimit, RLimitKind};
+use syscall_defs::{MLockAllFlags, MMapFlags, MMapProt, SyscallError};
+use syscall_user::{
+    getrlimit, meminfo, mlock, mlockall, mmap, munlock, munlockall, munmap, setrlimit,
+};
+
+const LEN: usize = 0x40000;
+
+fn check(name: &str, ok: bool) {
+    println!("{}: {}", name, if ok { "ok" } else { "FAILED" });
+}
+
+fn rss_anon() -> u64 {
+    meminfo().expect("meminfo failed").rss_anon
+}
+
+fn map() -> usize {
+    mmap(
+        None,
+        LEN,
+        MMapProt::PROT_READ | MMapProt::PROT_WRITE,
+        MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
+        None,
+        0,
+    )
+    .expect("mmap failed")
+}
+
+/// Locked pages are made resident without being touched
+fn test_mlock() {
+    let addr = map();
+
+    let before = rss_anon();
+
+    check("mlock", mlock(addr, LEN).is_ok());
+    check("mlock resident", rss_anon() >= before + LEN as u64);
+    check("munlock", munlock(addr, LEN).is_ok());
+
+    munmap(addr, LEN).expect("munmap failed");
+
+    check(
+        "mlock unmapped",
+        mlock(addr, LEN) == Err(SyscallError::ENOMEM),
+    );
+}
+
+/// Locking more than RLIMIT_MEMLOCK fails
+fn test_limit() {
+    let addr = map();
+
+    let mut old = RLimit::infinity();
+    getrlimit(RLimitKind::Memlock, &mut old).expect("getrlimit failed");
+
+    setrlimit(RLimitKind::Memlock, &RLimit::new(0x1000, old.max)).expect("setrlimit failed");
+
+    check(
+        "mlock over limit",
+        mlock(addr, LEN) == Err(SyscallError::ENOMEM),
+    );
+    check("mlock within limit", mlock(addr, 0x1000).is_ok());
+
+    setrlimit(RLimitKind::Memlock, &old).expect("setrlimit failed");
+
+    munmap(addr, LEN).expect("munmap failed");
+}
+
+/// MCL_FUTURE locks new mappings as they are created
+fn test_mlockall() {
+    check("mlockall", mlockall(MLockAllFlags::MCL_FUTURE).is_ok());
+
+    let before = rss_anon();
+
+    let addr = map();
+
+    check("mlockall resident", rss_anon() >= before + LEN as u64);
+    check("munlockall", munlockall().is_ok());
+
+    munmap(addr, LEN).expect("munmap failed");
+
+    check(
+        "mlockall no flags",
+        mlockall(MLockAllFlags::empty()) == Err(SyscallError::EINVAL),
+    );
+}
+
+fn main() {
+    test_mlock();
+    test_limit();
+    test_mlockall();
+}
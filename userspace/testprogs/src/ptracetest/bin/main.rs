@@ -0,0 +1,94 @@
+use syscall_defs::SyscallError;
+use syscall_defs::ptrace::PtraceRequest;
+use syscall_defs::signal::{SIGKILL, SIGSTOP, SIGTRAP};
+use syscall_defs::waitpid::{Status, WaitPidFlags};
+use syscall_user::{exec, exit, fork, getpid, ptrace, sleep, waitpid};
+
+fn wait(pid: usize, flags: WaitPidFlags) -> Status {
+    let mut status = 0;
+
+    while let Err(SyscallError::EINTR) = waitpid(pid as isize, &mut status, flags) {}
+
+    Status::from(status)
+}
+
+fn check(name: &str, ok: bool) {
+    println!("{}: {}", name, if ok { "ok" } else { "FAILED" });
+}
+
+/// Only descendants can be attached, init and the tracer itself are refused
+fn test_attach_perm() {
+    let me = getpid().expect("getpid failed");
+
+    check(
+        "attach self",
+        ptrace(PtraceRequest::Attach, me, 0, 0) == Err(SyscallError::EPERM),
+    );
+    check(
+        "attach init",
+        ptrace(PtraceRequest::Attach, 1, 0, 0) == Err(SyscallError::EPERM),
+    );
+}
+
+/// Traced child stops with SIGTRAP after exec, before the new image runs
+fn test_exec_stop() {
+    let pid = fork().expect("fork failed");
+
+    if pid == 0 {
+        ptrace(PtraceRequest::TraceMe, 0, 0, 0).expect("traceme failed");
+
+        let _ = exec("/bin/forktest", None, None);
+
+        exit(127);
+    }
+
+    let status = wait(pid, WaitPidFlags::STOPPED);
+
+    check(
+        "exec stop",
+        matches!(status, Status::Stopped(sig) if sig as usize == SIGTRAP),
+    );
+
+    ptrace(PtraceRequest::Cont, pid, 0, 0).expect("cont failed");
+
+    check(
+        "exec exit",
+        matches!(wait(pid, WaitPidFlags::EXITED), Status::Exited(0)),
+    );
+}
+
+/// Attach reports the SIGSTOP stop, stopped tracee can be killed
+fn test_attach_child() {
+    let pid = fork().expect("fork failed");
+
+    if pid == 0 {
+        loop {
+            let _ = sleep(1000);
+        }
+    }
+
+    check(
+        "attach child",
+        ptrace(PtraceRequest::Attach, pid, 0, 0).is_ok(),
+    );
+
+    let status = wait(pid, WaitPidFlags::STOPPED);
+
+    check(
+        "attach stop",
+        matches!(status, Status::Stopped(sig) if sig as usize == SIGSTOP),
+    );
+
+    ptrace(PtraceRequest::Kill, pid, 0, 0).expect("kill failed");
+
+    check(
+        "kill",
+        matches!(wait(pid, WaitPidFlags::EXITED), Status::Signaled(sig) if sig as usize == SIGKILL),
+    );
+}
+
+fn main() {
+    test_attach_perm();
+    test_exec_stop();
+    test_attach_child();
+}
@@ -0,0 +1,36 @@
+use syscall_defs::SyscallError;
+use syscall_defs::resource::{RLimit, RLimitKind};
+use syscall_defs::waitpid::{Status, WaitPidFlags};
+use syscall_user::{exec, exit, fork, getrlimit, setrlimit, waitpid};
+
+fn main() {
+    let pid = fork().expect("fork failed");
+
+    if pid == 0 {
+        let mut old = RLimit::infinity();
+        getrlimit(RLimitKind::As, &mut old).expect("getrlimit failed");
+
+        // Too small for the user stack of the new image
+        setrlimit(RLimitKind::As, &RLimit::new(0x10000, old.max)).expect("setrlimit failed");
+
+        let res = exec("/bin/forktest", None, None);
+        println!("exec under tiny RLIMIT_AS: {:?}", res);
+
+        if res != Err(SyscallError::ENOMEM) {
+            exit(1);
+        }
+
+        // We are still alive, exec should work again with the old limit
+        setrlimit(RLimitKind::As, &old).expect("setrlimit failed");
+
+        let res = exec("/bin/forktest", None, None);
+        println!("exec failed: {:?}", res);
+
+        exit(2);
+    }
+
+    let mut status = 0;
+    waitpid(pid as isize, &mut status, WaitPidFlags::EXITED).expect("waitpid failed");
+
+    println!("rlimitexec: {:?}", Status::from(status));
+}
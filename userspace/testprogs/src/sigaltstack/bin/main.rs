@@ -0,0 +1,173 @@
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+
+use syscall_defs::SyscallError;
+use syscall_defs::signal::{
+    MINSIGSTKSZ, SIGALRM, SIGSEGV, SIGSTKSZ, SS_DISABLE, SS_ONSTACK, SigAction, SigAltStack,
+    SignalFlags, SignalHandler,
+};
+use syscall_defs::time::{ITimerVal, ITimerWhich, Timeval};
+use syscall_defs::waitpid::{Status, WaitPidFlags};
+use syscall_user::{exit, fork, setitimer, sigaction, sigaltstack, sleep, waitpid};
+
+static mut STACK: [u8; SIGSTKSZ as usize] = [0; SIGSTKSZ as usize];
+
+static HANDLER_SP: AtomicU64 = AtomicU64::new(0);
+static HANDLER_FLAGS: AtomicI32 = AtomicI32::new(0);
+static HANDLER_EPERM: AtomicBool = AtomicBool::new(false);
+
+fn check(name: &str, ok: bool) {
+    println!("{}: {}", name, if ok { "ok" } else { "FAILED" });
+}
+
+fn stack() -> SigAltStack {
+    SigAltStack {
+        ss_sp: &raw mut STACK as u64,
+        ss_flags: 0,
+        ss_size: SIGSTKSZ,
+    }
+}
+
+fn on_stack(sp: u64) -> bool {
+    let s = stack();
+
+    sp > s.ss_sp && sp <= s.ss_sp + s.ss_size
+}
+
+fn alarm_handler(_sig: usize) {
+    let local = 0u8;
+
+    HANDLER_SP.store(&raw const local as u64, Ordering::SeqCst);
+
+    let mut old = SigAltStack::default();
+    let _ = sigaltstack(None, Some(&mut old));
+    HANDLER_FLAGS.store(old.ss_flags, Ordering::SeqCst);
+
+    // Alternate stack can't be changed while it is in use
+    HANDLER_EPERM.store(
+        sigaltstack(Some(&stack()), None) == Err(SyscallError::EPERM),
+        Ordering::SeqCst,
+    );
+}
+
+fn segv_handler(_sig: usize) {
+    let local = 0u8;
+
+    exit(if on_stack(&raw const local as u64) {
+        0
+    } else {
+        1
+    });
+}
+
+fn handle(sig: usize, handler: fn(usize)) {
+    sigaction(
+        sig,
+        Some(&mut SigAction::new(
+            SignalHandler::Handle(handler),
+            0,
+            SignalFlags::ONSTACK,
+        )),
+        None,
+    )
+    .expect("sigaction failed");
+}
+
+fn test_args() {
+    let mut small = stack();
+    small.ss_size = MINSIGSTKSZ - 1;
+
+    check(
+        "too small",
+        sigaltstack(Some(&small), None) == Err(SyscallError::ENOMEM),
+    );
+
+    let mut bad = stack();
+    bad.ss_flags = 0x100;
+
+    check(
+        "bad flags",
+        sigaltstack(Some(&bad), None) == Err(SyscallError::EINVAL),
+    );
+
+    let mut old = SigAltStack::default();
+    sigaltstack(None, Some(&mut old)).expect("sigaltstack failed");
+
+    check("disabled", old.ss_flags == SS_DISABLE);
+}
+
+/// Handler with ONSTACK runs on the alternate stack, which is reported as in use
+fn test_handler() {
+    sigaltstack(Some(&stack()), None).expect("sigaltstack failed");
+
+    handle(SIGALRM, alarm_handler);
+
+    let val = ITimerVal {
+        it_interval: Timeval::default(),
+        it_value: Timeval::from_nsecs(10_000_000),
+    };
+
+    setitimer(ITimerWhich::Real, &val, None).expect("setitimer failed");
+
+    while HANDLER_SP.load(Ordering::SeqCst) == 0 {
+        let _ = sleep(1);
+    }
+
+    check(
+        "handler on stack",
+        on_stack(HANDLER_SP.load(Ordering::SeqCst)),
+    );
+    check(
+        "handler onstack flag",
+        HANDLER_FLAGS.load(Ordering::SeqCst) == SS_ONSTACK,
+    );
+    check("handler change", HANDLER_EPERM.load(Ordering::SeqCst));
+
+    let mut old = SigAltStack::default();
+    sigaltstack(None, Some(&mut old)).expect("sigaltstack failed");
+
+    check("after handler", old.ss_flags == 0);
+
+    let disable = SigAltStack {
+        ss_flags: SS_DISABLE,
+        ..Default::default()
+    };
+
+    check("disable", sigaltstack(Some(&disable), None).is_ok());
+}
+
+#[allow(unconditional_recursion)]
+fn recurse(depth: u64) -> u64 {
+    let buf = black_box([depth; 64]);
+
+    recurse(depth + 1) + buf[0]
+}
+
+/// SIGSEGV of a stack overflow can only be handled on the alternate stack
+fn test_overflow() {
+    let pid = fork().expect("fork failed");
+
+    if pid == 0 {
+        sigaltstack(Some(&stack()), None).expect("sigaltstack failed");
+
+        handle(SIGSEGV, segv_handler);
+
+        black_box(recurse(0));
+
+        exit(2);
+    }
+
+    let mut status = 0;
+    while let Err(SyscallError::EINTR) = waitpid(pid as isize, &mut status, WaitPidFlags::EXITED) {}
+
+    check(
+        "overflow",
+        matches!(Status::from(status), Status::Exited(0)),
+    );
+}
+
+fn main() {
+    test_args();
+    test_handler();
+    test_overflow();
+}